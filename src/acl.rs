@@ -0,0 +1,311 @@
+use std::collections::BTreeSet;
+
+use serde::Deserialize;
+
+use crate::admin::{any_host, AclBinding, AclOperation, AclPattern, AclPermission, AclResource};
+
+/// ACLs a cluster is expected to have, read from a YAML file such as:
+///
+/// ```yaml
+/// acls:
+///   - principal: User:billing
+///     resource_type: topic
+///     resource_name: orders
+///     operations: [read, describe]
+///   - principal: User:billing
+///     resource_type: group
+///     resource_name: billing-
+///     pattern_type: prefixed
+///     operations: [read]
+/// ```
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct AclFile {
+    #[serde(default)]
+    pub acls: Vec<AclRule>,
+}
+
+/// One entry of an ACL file, granting or denying several operations on one resource.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct AclRule {
+    pub principal: String,
+    pub resource_type: AclResource,
+    pub resource_name: String,
+    #[serde(default)]
+    pub pattern_type: AclPattern,
+    #[serde(default = "any_host")]
+    pub host: String,
+    pub operations: Vec<AclOperation>,
+    #[serde(default)]
+    pub permission: AclPermission,
+}
+
+impl AclFile {
+    /// The bindings of every rule, one per operation, without duplicates.
+    pub fn bindings(&self) -> BTreeSet<AclBinding> {
+        self.acls
+            .iter()
+            .flat_map(|rule| {
+                rule.operations.iter().map(|operation| AclBinding {
+                    resource_type: rule.resource_type,
+                    resource_name: rule.resource_name.clone(),
+                    pattern_type: rule.pattern_type,
+                    principal: rule.principal.clone(),
+                    host: rule.host.clone(),
+                    operation: *operation,
+                    permission: rule.permission,
+                })
+            })
+            .collect()
+    }
+}
+
+/// The ACLs to create so the cluster has every binding of `desired`, and with `prune` the ones
+/// to delete because the file does not list them. Pruning only touches principals that appear
+/// in the file, so ACLs of other services are never deleted.
+pub fn plan_acls<'a>(
+    desired: &'a BTreeSet<AclBinding>,
+    current: &'a [AclBinding],
+    prune: bool,
+) -> (Vec<&'a AclBinding>, Vec<&'a AclBinding>) {
+    let existing: BTreeSet<&AclBinding> = current.iter().collect();
+    let create = desired
+        .iter()
+        .filter(|acl| !existing.contains(acl))
+        .collect();
+    if !prune {
+        return (create, vec![]);
+    }
+    let principals: BTreeSet<&str> = desired.iter().map(|acl| acl.principal.as_str()).collect();
+    let delete = existing
+        .into_iter()
+        .filter(|acl| principals.contains(acl.principal.as_str()) && !desired.contains(*acl))
+        .collect();
+    (create, delete)
+}
+
+/// Whether `acl` applies to `principal`, by name or through the `User:*` wildcard.
+pub fn applies_to(acl: &AclBinding, principal: &str) -> bool {
+    acl.principal == principal || acl.principal == "User:*"
+}
+
+/// Whether `acl` covers the resource `name`: by name, through the `*` wildcard, or as a prefix.
+fn covers(acl: &AclBinding, resource_type: AclResource, name: &str) -> bool {
+    acl.resource_type == resource_type
+        && match acl.pattern_type {
+            AclPattern::Literal => acl.resource_name == name || acl.resource_name == "*",
+            AclPattern::Prefixed => name.starts_with(&acl.resource_name),
+        }
+}
+
+/// Operations worth reporting on each kind of resource.
+fn resource_operations(resource_type: AclResource) -> &'static [AclOperation] {
+    match resource_type {
+        AclResource::Topic => &[
+            AclOperation::Read,
+            AclOperation::Write,
+            AclOperation::Create,
+            AclOperation::Delete,
+            AclOperation::Alter,
+            AclOperation::Describe,
+            AclOperation::DescribeConfigs,
+            AclOperation::AlterConfigs,
+        ],
+        AclResource::Group => &[
+            AclOperation::Read,
+            AclOperation::Delete,
+            AclOperation::Describe,
+        ],
+        AclResource::Cluster => &[
+            AclOperation::Create,
+            AclOperation::Alter,
+            AclOperation::Describe,
+            AclOperation::ClusterAction,
+            AclOperation::DescribeConfigs,
+            AclOperation::AlterConfigs,
+            AclOperation::IdempotentWrite,
+        ],
+        AclResource::TransactionalId => &[AclOperation::Write, AclOperation::Describe],
+    }
+}
+
+/// Operations the `acls` of a principal allow on the resource `name`, the way the broker
+/// authorizes them: a deny wins over any allow, `all` stands for every operation, and allowing
+/// read, write, delete or alter implies describe as alter-configs implies describe-configs.
+pub fn effective_operations(
+    acls: &[&AclBinding],
+    resource_type: AclResource,
+    name: &str,
+) -> Vec<AclOperation> {
+    let matching: Vec<&&AclBinding> = acls
+        .iter()
+        .filter(|acl| covers(acl, resource_type, name))
+        .collect();
+    let granted = |permission: AclPermission, operations: &[AclOperation]| {
+        matching.iter().any(|acl| {
+            acl.permission == permission
+                && (acl.operation == AclOperation::All || operations.contains(&acl.operation))
+        })
+    };
+    resource_operations(resource_type)
+        .iter()
+        .copied()
+        .filter(|operation| {
+            let implied: &[AclOperation] = match operation {
+                AclOperation::Describe => &[
+                    AclOperation::Describe,
+                    AclOperation::Read,
+                    AclOperation::Write,
+                    AclOperation::Delete,
+                    AclOperation::Alter,
+                ],
+                AclOperation::DescribeConfigs => {
+                    &[AclOperation::DescribeConfigs, AclOperation::AlterConfigs]
+                }
+                operation => std::slice::from_ref(operation),
+            };
+            !granted(AclPermission::Deny, &[*operation]) && granted(AclPermission::Allow, implied)
+        })
+        .collect()
+}
+
+/// Whether `principal` can read and whether it can write `topic`.
+fn read_write(acls: &[AclBinding], principal: &str, topic: &str) -> (bool, bool) {
+    let applicable: Vec<&AclBinding> = acls
+        .iter()
+        .filter(|acl| applies_to(acl, principal))
+        .collect();
+    let operations = effective_operations(&applicable, AclResource::Topic, topic);
+    (
+        operations.contains(&AclOperation::Read),
+        operations.contains(&AclOperation::Write),
+    )
+}
+
+/// Who can read or write `topic`, as `(principal, read, write)`. What `User:*` grants is listed
+/// once under that name, and other principals only when their access differs from it, such as
+/// a principal denied what everyone else is allowed.
+pub fn topic_access(acls: &[AclBinding], topic: &str) -> Vec<(String, bool, bool)> {
+    let everyone = read_write(acls, "User:*", topic);
+    let principals: BTreeSet<&str> = acls.iter().map(|acl| acl.principal.as_str()).collect();
+    principals
+        .into_iter()
+        .filter_map(|principal| {
+            let (read, write) = read_write(acls, principal, topic);
+            let listed = if principal == "User:*" {
+                read || write
+            } else {
+                (read, write) != everyone
+            };
+            listed.then(|| (principal.to_string(), read, write))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{effective_operations, plan_acls, topic_access, AclFile};
+    use crate::admin::{AclBinding, AclOperation, AclPattern, AclPermission, AclResource};
+
+    fn acl(principal: &str, resource_name: &str, operation: AclOperation) -> AclBinding {
+        AclBinding {
+            resource_type: AclResource::Topic,
+            resource_name: resource_name.to_string(),
+            pattern_type: AclPattern::Literal,
+            principal: principal.to_string(),
+            host: "*".to_string(),
+            operation,
+            permission: AclPermission::Allow,
+        }
+    }
+
+    #[test]
+    fn test_plan_acls() {
+        let file: AclFile = serde_yaml::from_str(
+            r#"
+            acls:
+              - principal: User:billing
+                resource_type: topic
+                resource_name: orders
+                operations: [read, describe]
+              - principal: User:billing
+                resource_type: topic
+                resource_name: invoices
+                operations: [write]
+            "#,
+        )
+        .unwrap();
+        let desired = file.bindings();
+        assert_eq!(desired.len(), 3);
+
+        let current = vec![
+            acl("User:billing", "orders", AclOperation::Read),
+            acl("User:billing", "legacy", AclOperation::Read),
+            acl("User:shipping", "orders", AclOperation::Read),
+        ];
+        let (create, delete) = plan_acls(&desired, &current, false);
+        assert_eq!(
+            create,
+            vec![
+                &acl("User:billing", "invoices", AclOperation::Write),
+                &acl("User:billing", "orders", AclOperation::Describe),
+            ]
+        );
+        assert!(delete.is_empty());
+
+        let (_, delete) = plan_acls(&desired, &current, true);
+        assert_eq!(delete, vec![&current[1]]);
+    }
+
+    #[test]
+    fn test_effective_operations() {
+        let mut prefixed = acl("User:*", "orders.", AclOperation::Write);
+        prefixed.pattern_type = AclPattern::Prefixed;
+        let mut denied = acl("User:billing", "orders.audit", AclOperation::Write);
+        denied.permission = AclPermission::Deny;
+        let all_topics = acl("User:billing", "*", AclOperation::DescribeConfigs);
+        let acls = vec![&prefixed, &denied, &all_topics];
+
+        assert_eq!(
+            effective_operations(&acls, AclResource::Topic, "orders.eu"),
+            vec![
+                AclOperation::Write,
+                AclOperation::Describe,
+                AclOperation::DescribeConfigs
+            ]
+        );
+        assert_eq!(
+            effective_operations(&acls, AclResource::Topic, "orders.audit"),
+            vec![AclOperation::Describe, AclOperation::DescribeConfigs]
+        );
+        assert_eq!(
+            effective_operations(&acls, AclResource::Topic, "payments"),
+            vec![AclOperation::DescribeConfigs]
+        );
+        assert!(effective_operations(&acls, AclResource::Group, "orders.eu").is_empty());
+    }
+
+    #[test]
+    fn test_topic_access() {
+        let mut prefixed = acl("User:billing", "orders", AclOperation::Read);
+        prefixed.pattern_type = AclPattern::Prefixed;
+        let mut denied = acl("User:audit", "orders.eu", AclOperation::Write);
+        denied.permission = AclPermission::Deny;
+        let acls = vec![
+            prefixed,
+            acl("User:shipping", "orders.eu", AclOperation::All),
+            acl("User:*", "orders.eu", AclOperation::Write),
+            denied,
+            acl("User:other", "payments", AclOperation::Read),
+        ];
+        assert_eq!(
+            topic_access(&acls, "orders.eu"),
+            vec![
+                ("User:*".to_string(), false, true),
+                ("User:audit".to_string(), false, false),
+                ("User:billing".to_string(), true, true),
+                ("User:shipping".to_string(), true, true),
+            ]
+        );
+        assert!(topic_access(&acls, "invoices").is_empty());
+    }
+}
@@ -0,0 +1,2063 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::{c_char, CStr, CString},
+    fmt,
+    io::{Cursor, Read, Write},
+    net::TcpStream,
+    ptr,
+    time::{Duration, Instant},
+};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use futures_executor::block_on;
+use rdkafka::{
+    admin::{
+        AdminClient, AdminOptions, ConfigEntry, GroupResult, NewPartitions, NewTopic,
+        OwnedResourceSpecifier, ResourceSpecifier, TopicResult,
+    },
+    bindings::{
+        rd_kafka_AclBindingFilter_new, rd_kafka_AclBinding_destroy, rd_kafka_AclBinding_host,
+        rd_kafka_AclBinding_name, rd_kafka_AclBinding_new, rd_kafka_AclBinding_operation,
+        rd_kafka_AclBinding_permission_type, rd_kafka_AclBinding_principal,
+        rd_kafka_AclBinding_resource_pattern_type, rd_kafka_AclBinding_restype,
+        rd_kafka_AclBinding_t, rd_kafka_AclOperation_t, rd_kafka_AclPermissionType_t,
+        rd_kafka_AdminOptions_destroy, rd_kafka_AdminOptions_new, rd_kafka_AdminOptions_set_broker,
+        rd_kafka_AdminOptions_set_request_timeout, rd_kafka_AdminOptions_t,
+        rd_kafka_AlterConfigOpType_t, rd_kafka_ConfigEntry_name, rd_kafka_ConfigEntry_value,
+        rd_kafka_ConfigResource_add_incremental_config, rd_kafka_ConfigResource_configs,
+        rd_kafka_ConfigResource_destroy, rd_kafka_ConfigResource_error,
+        rd_kafka_ConfigResource_error_string, rd_kafka_ConfigResource_new,
+        rd_kafka_ConfigResource_t, rd_kafka_ConsumerGroupDescription_coordinator,
+        rd_kafka_ConsumerGroupDescription_error, rd_kafka_ConsumerGroupDescription_member,
+        rd_kafka_ConsumerGroupDescription_member_count, rd_kafka_CreateAcls,
+        rd_kafka_CreateAcls_result_acls, rd_kafka_DeleteAcls,
+        rd_kafka_DeleteAcls_result_response_error, rd_kafka_DeleteAcls_result_responses,
+        rd_kafka_DescribeAcls, rd_kafka_DescribeAcls_result_acls, rd_kafka_DescribeCluster,
+        rd_kafka_DescribeCluster_result_controller, rd_kafka_DescribeCluster_result_nodes,
+        rd_kafka_DescribeConsumerGroups, rd_kafka_DescribeConsumerGroups_result_groups,
+        rd_kafka_IncrementalAlterConfigs, rd_kafka_IncrementalAlterConfigs_result_resources,
+        rd_kafka_ListConsumerGroupOffsets, rd_kafka_ListConsumerGroupOffsets_destroy,
+        rd_kafka_ListConsumerGroupOffsets_new, rd_kafka_ListConsumerGroupOffsets_result_groups,
+        rd_kafka_MemberDescription_consumer_id, rd_kafka_MemberDescription_group_instance_id,
+        rd_kafka_Node_host, rd_kafka_Node_id, rd_kafka_Node_port, rd_kafka_Node_rack,
+        rd_kafka_Node_t, rd_kafka_ResourcePatternType_t, rd_kafka_ResourceType_t,
+        rd_kafka_acl_result_error, rd_kafka_admin_op_t, rd_kafka_error_destroy,
+        rd_kafka_error_string, rd_kafka_event_CreateAcls_result, rd_kafka_event_DeleteAcls_result,
+        rd_kafka_event_DescribeAcls_result, rd_kafka_event_DescribeCluster_result,
+        rd_kafka_event_DescribeConsumerGroups_result,
+        rd_kafka_event_IncrementalAlterConfigs_result,
+        rd_kafka_event_ListConsumerGroupOffsets_result, rd_kafka_event_destroy,
+        rd_kafka_event_error, rd_kafka_event_error_string, rd_kafka_event_t,
+        rd_kafka_group_result_error, rd_kafka_group_result_partitions, rd_kafka_queue_destroy,
+        rd_kafka_queue_new, rd_kafka_queue_poll, rd_kafka_queue_t, rd_kafka_t,
+    },
+    consumer::{BaseConsumer, Consumer},
+    types::RDKafkaRespErr,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    client::{self, ClientAuth},
+    kafka::KafkaError,
+};
+
+const ADMIN_TIMEOUT_MS: i32 = 10_000;
+
+/// Resource type of a broker's log4j loggers in DescribeConfigs and IncrementalAlterConfigs.
+/// librdkafka's `rd_kafka_ResourceType_t` has no such type and would send it as UNKNOWN.
+const RESOURCE_BROKER_LOGGER: i8 = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClusterNode {
+    pub id: i32,
+    pub host: String,
+    pub port: u16,
+    pub rack: Option<String>,
+}
+
+impl fmt::Display for ClusterNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}:{})", self.id, self.host, self.port)
+    }
+}
+
+/// What DescribeConsumerGroups reports about a group on top of the group list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupDescription {
+    /// Broker hosting the group, as resolved with FindCoordinator
+    pub coordinator: Option<ClusterNode>,
+    /// `group.instance.id` of the static members, by member id
+    pub instance_ids: HashMap<String, String>,
+}
+
+/// Copies a nullable C string owned by librdkafka.
+///
+/// # Safety
+/// `value` must be null or point to a valid NUL terminated string.
+unsafe fn optional_string(value: *const c_char) -> Option<String> {
+    if value.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(value).to_string_lossy().into_owned())
+    }
+}
+
+/// Runs an admin request that librdkafka supports but rdkafka does not wrap yet. The request is
+/// sent with `send` on a dedicated queue of `client` and the result event is handed to `read`.
+/// `broker` pins the request to one broker instead of letting librdkafka pick the target.
+fn run_admin_request<T, S, R>(
+    client: &BaseConsumer<ClientAuth>,
+    operation: rd_kafka_admin_op_t,
+    broker: Option<i32>,
+    send: S,
+    read: R,
+) -> Result<T, KafkaError>
+where
+    S: FnOnce(*mut rd_kafka_t, *const rd_kafka_AdminOptions_t, *mut rd_kafka_queue_t),
+    R: FnOnce(*mut rd_kafka_event_t) -> Result<T, KafkaError>,
+{
+    let rk = client.client().native_ptr();
+    unsafe {
+        let queue = rd_kafka_queue_new(rk);
+        let options = rd_kafka_AdminOptions_new(rk, operation);
+        rd_kafka_AdminOptions_set_request_timeout(options, ADMIN_TIMEOUT_MS, ptr::null_mut(), 0);
+        if let Some(broker_id) = broker {
+            rd_kafka_AdminOptions_set_broker(options, broker_id, ptr::null_mut(), 0);
+        }
+
+        tracing::debug!(?operation, ?broker, "Sending admin request");
+        let started = Instant::now();
+        send(rk, options, queue);
+        let event = rd_kafka_queue_poll(queue, ADMIN_TIMEOUT_MS + 5_000);
+
+        let result = if event.is_null() {
+            Err(KafkaError::Admin(
+                "Timed out waiting for the admin request".to_string(),
+            ))
+        } else if rd_kafka_event_error(event) != RDKafkaRespErr::RD_KAFKA_RESP_ERR_NO_ERROR {
+            Err(KafkaError::Admin(
+                optional_string(rd_kafka_event_error_string(event))
+                    .unwrap_or_else(|| "Admin request failed".to_string()),
+            ))
+        } else {
+            read(event)
+        };
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+        match &result {
+            Ok(_) => tracing::debug!(?operation, elapsed_ms, "Admin request done"),
+            Err(er) => tracing::warn!(?operation, elapsed_ms, error = %er, "Admin request failed"),
+        }
+
+        if !event.is_null() {
+            rd_kafka_event_destroy(event);
+        }
+        rd_kafka_AdminOptions_destroy(options);
+        rd_kafka_queue_destroy(queue);
+        result
+    }
+}
+
+/// Lists the brokers of the cluster including their rack, which plain metadata does not carry.
+pub fn describe_cluster_nodes(
+    client: &BaseConsumer<ClientAuth>,
+) -> Result<Vec<ClusterNode>, KafkaError> {
+    run_admin_request(
+        client,
+        rd_kafka_admin_op_t::RD_KAFKA_ADMIN_OP_DESCRIBECLUSTER,
+        None,
+        |rk, options, queue| unsafe { rd_kafka_DescribeCluster(rk, options, queue) },
+        |event| unsafe {
+            let result = rd_kafka_event_DescribeCluster_result(event);
+            if result.is_null() {
+                return Err(KafkaError::Admin(
+                    "Unexpected response to DescribeCluster".to_string(),
+                ));
+            }
+            let mut count = 0;
+            let nodes = rd_kafka_DescribeCluster_result_nodes(result, &mut count);
+            Ok((0..count).map(|i| read_node(*nodes.add(i))).collect())
+        },
+    )
+}
+
+/// Finds the active controller, which partition reassignments have to be sent to.
+pub fn describe_controller(client: &BaseConsumer<ClientAuth>) -> Result<ClusterNode, KafkaError> {
+    run_admin_request(
+        client,
+        rd_kafka_admin_op_t::RD_KAFKA_ADMIN_OP_DESCRIBECLUSTER,
+        None,
+        |rk, options, queue| unsafe { rd_kafka_DescribeCluster(rk, options, queue) },
+        |event| unsafe {
+            let result = rd_kafka_event_DescribeCluster_result(event);
+            if result.is_null() {
+                return Err(KafkaError::Admin(
+                    "Unexpected response to DescribeCluster".to_string(),
+                ));
+            }
+            let controller = rd_kafka_DescribeCluster_result_controller(result);
+            if controller.is_null() {
+                return Err(KafkaError::Admin(
+                    "The cluster has no active controller".to_string(),
+                ));
+            }
+            Ok(read_node(controller))
+        },
+    )
+}
+
+/// Copies a broker node out of a result event.
+///
+/// # Safety
+/// `node` must point to a node owned by a live result event.
+unsafe fn read_node(node: *const rd_kafka_Node_t) -> ClusterNode {
+    ClusterNode {
+        id: rd_kafka_Node_id(node),
+        host: optional_string(rd_kafka_Node_host(node)).unwrap_or_default(),
+        port: rd_kafka_Node_port(node),
+        rack: optional_string(rd_kafka_Node_rack(node)),
+    }
+}
+
+/// Describes a consumer group through the admin API, which also finds its coordinator.
+pub fn describe_consumer_group(
+    client: &BaseConsumer<ClientAuth>,
+    group: &str,
+) -> Result<GroupDescription, KafkaError> {
+    let name = CString::new(group)
+        .map_err(|_| KafkaError::Generic(format!("Invalid group name: {}", group)))?;
+    run_admin_request(
+        client,
+        rd_kafka_admin_op_t::RD_KAFKA_ADMIN_OP_DESCRIBECONSUMERGROUPS,
+        None,
+        |rk, options, queue| unsafe {
+            let mut groups = [name.as_ptr()];
+            rd_kafka_DescribeConsumerGroups(rk, groups.as_mut_ptr(), 1, options, queue);
+        },
+        |event| unsafe {
+            let result = rd_kafka_event_DescribeConsumerGroups_result(event);
+            if result.is_null() {
+                return Err(KafkaError::Admin(
+                    "Unexpected response to DescribeConsumerGroups".to_string(),
+                ));
+            }
+            let mut count = 0;
+            let groups = rd_kafka_DescribeConsumerGroups_result_groups(result, &mut count);
+            if count == 0 {
+                return Err(KafkaError::Admin(format!(
+                    "Group {} was not described",
+                    group
+                )));
+            }
+            let description = *groups;
+            let error = rd_kafka_ConsumerGroupDescription_error(description);
+            if !error.is_null() {
+                return Err(KafkaError::Admin(
+                    optional_string(rd_kafka_error_string(error))
+                        .unwrap_or_else(|| "Describing the group failed".to_string()),
+                ));
+            }
+            let coordinator = rd_kafka_ConsumerGroupDescription_coordinator(description);
+            let instance_ids = (0..rd_kafka_ConsumerGroupDescription_member_count(description))
+                .filter_map(|i| {
+                    let member = rd_kafka_ConsumerGroupDescription_member(description, i);
+                    Some((
+                        optional_string(rd_kafka_MemberDescription_consumer_id(member))?,
+                        optional_string(rd_kafka_MemberDescription_group_instance_id(member))?,
+                    ))
+                })
+                .collect();
+            Ok(GroupDescription {
+                coordinator: (!coordinator.is_null()).then(|| read_node(coordinator)),
+                instance_ids,
+            })
+        },
+    )
+}
+
+/// Lists every offset `group` has committed, including those on topics that were deleted since,
+/// which an offset fetch for the current topics cannot return.
+pub fn list_group_offsets(
+    client: &BaseConsumer<ClientAuth>,
+    group: &str,
+) -> Result<Vec<(String, i32, i64)>, KafkaError> {
+    let name = CString::new(group)
+        .map_err(|_| KafkaError::Generic(format!("Invalid group name: {}", group)))?;
+    run_admin_request(
+        client,
+        rd_kafka_admin_op_t::RD_KAFKA_ADMIN_OP_LISTCONSUMERGROUPOFFSETS,
+        None,
+        |rk, options, queue| unsafe {
+            let request = rd_kafka_ListConsumerGroupOffsets_new(name.as_ptr(), ptr::null());
+            let mut requests = [request];
+            rd_kafka_ListConsumerGroupOffsets(rk, requests.as_mut_ptr(), 1, options, queue);
+            rd_kafka_ListConsumerGroupOffsets_destroy(request);
+        },
+        |event| unsafe {
+            let result = rd_kafka_event_ListConsumerGroupOffsets_result(event);
+            if result.is_null() {
+                return Err(KafkaError::Admin(
+                    "Unexpected response to ListConsumerGroupOffsets".to_string(),
+                ));
+            }
+            let mut count = 0;
+            let groups = rd_kafka_ListConsumerGroupOffsets_result_groups(result, &mut count);
+            if count == 0 {
+                return Ok(vec![]);
+            }
+            let error = rd_kafka_group_result_error(*groups);
+            if !error.is_null() {
+                return Err(KafkaError::Admin(
+                    optional_string(rd_kafka_error_string(error))
+                        .unwrap_or_else(|| "Listing the group offsets failed".to_string()),
+                ));
+            }
+            let partitions = rd_kafka_group_result_partitions(*groups);
+            if partitions.is_null() {
+                return Ok(vec![]);
+            }
+            let partitions = &*partitions;
+            Ok((0..partitions.cnt as usize)
+                .map(|i| &*partitions.elems.add(i))
+                .filter(|p| p.offset >= 0)
+                .map(|p| {
+                    (
+                        optional_string(p.topic).unwrap_or_default(),
+                        p.partition,
+                        p.offset,
+                    )
+                })
+                .collect())
+        },
+    )
+}
+
+/// Deletes the given consumer groups, which must have no members, and returns the outcome per
+/// group.
+pub fn delete_groups(
+    admin: &AdminClient<ClientAuth>,
+    groups: &[&str],
+) -> Result<Vec<GroupResult>, KafkaError> {
+    let options = AdminOptions::new().operation_timeout(Some(Duration::from_secs(30)));
+    block_on(admin.delete_groups(groups, &options))
+        .map_err(|er| KafkaError::Admin(format!("Error while deleting groups: {}", er)))
+}
+
+/// Kind of resource an ACL grants access to. `cluster` is the cluster itself, which is always
+/// named `kafka-cluster`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum AclResource {
+    Topic,
+    Group,
+    Cluster,
+    TransactionalId,
+}
+
+/// Whether the resource name of an ACL is a full name or a prefix of names.
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum AclPattern {
+    #[default]
+    Literal,
+    Prefixed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum AclOperation {
+    All,
+    Read,
+    Write,
+    Create,
+    Delete,
+    Alter,
+    Describe,
+    ClusterAction,
+    DescribeConfigs,
+    AlterConfigs,
+    IdempotentWrite,
+}
+
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum AclPermission {
+    #[default]
+    Allow,
+    Deny,
+}
+
+/// Native value and name of each ACL setting, named the way the serde attributes above do.
+const ACL_RESOURCES: [(AclResource, rd_kafka_ResourceType_t, &str); 4] = [
+    (
+        AclResource::Topic,
+        rd_kafka_ResourceType_t::RD_KAFKA_RESOURCE_TOPIC,
+        "topic",
+    ),
+    (
+        AclResource::Group,
+        rd_kafka_ResourceType_t::RD_KAFKA_RESOURCE_GROUP,
+        "group",
+    ),
+    (
+        AclResource::Cluster,
+        rd_kafka_ResourceType_t::RD_KAFKA_RESOURCE_BROKER,
+        "cluster",
+    ),
+    (
+        AclResource::TransactionalId,
+        rd_kafka_ResourceType_t::RD_KAFKA_RESOURCE_TRANSACTIONAL_ID,
+        "transactional_id",
+    ),
+];
+const ACL_PATTERNS: [(AclPattern, rd_kafka_ResourcePatternType_t, &str); 2] = [
+    (
+        AclPattern::Literal,
+        rd_kafka_ResourcePatternType_t::RD_KAFKA_RESOURCE_PATTERN_LITERAL,
+        "literal",
+    ),
+    (
+        AclPattern::Prefixed,
+        rd_kafka_ResourcePatternType_t::RD_KAFKA_RESOURCE_PATTERN_PREFIXED,
+        "prefixed",
+    ),
+];
+const ACL_OPERATIONS: [(AclOperation, rd_kafka_AclOperation_t, &str); 11] = [
+    (
+        AclOperation::All,
+        rd_kafka_AclOperation_t::RD_KAFKA_ACL_OPERATION_ALL,
+        "all",
+    ),
+    (
+        AclOperation::Read,
+        rd_kafka_AclOperation_t::RD_KAFKA_ACL_OPERATION_READ,
+        "read",
+    ),
+    (
+        AclOperation::Write,
+        rd_kafka_AclOperation_t::RD_KAFKA_ACL_OPERATION_WRITE,
+        "write",
+    ),
+    (
+        AclOperation::Create,
+        rd_kafka_AclOperation_t::RD_KAFKA_ACL_OPERATION_CREATE,
+        "create",
+    ),
+    (
+        AclOperation::Delete,
+        rd_kafka_AclOperation_t::RD_KAFKA_ACL_OPERATION_DELETE,
+        "delete",
+    ),
+    (
+        AclOperation::Alter,
+        rd_kafka_AclOperation_t::RD_KAFKA_ACL_OPERATION_ALTER,
+        "alter",
+    ),
+    (
+        AclOperation::Describe,
+        rd_kafka_AclOperation_t::RD_KAFKA_ACL_OPERATION_DESCRIBE,
+        "describe",
+    ),
+    (
+        AclOperation::ClusterAction,
+        rd_kafka_AclOperation_t::RD_KAFKA_ACL_OPERATION_CLUSTER_ACTION,
+        "cluster_action",
+    ),
+    (
+        AclOperation::DescribeConfigs,
+        rd_kafka_AclOperation_t::RD_KAFKA_ACL_OPERATION_DESCRIBE_CONFIGS,
+        "describe_configs",
+    ),
+    (
+        AclOperation::AlterConfigs,
+        rd_kafka_AclOperation_t::RD_KAFKA_ACL_OPERATION_ALTER_CONFIGS,
+        "alter_configs",
+    ),
+    (
+        AclOperation::IdempotentWrite,
+        rd_kafka_AclOperation_t::RD_KAFKA_ACL_OPERATION_IDEMPOTENT_WRITE,
+        "idempotent_write",
+    ),
+];
+const ACL_PERMISSIONS: [(AclPermission, rd_kafka_AclPermissionType_t, &str); 2] = [
+    (
+        AclPermission::Allow,
+        rd_kafka_AclPermissionType_t::RD_KAFKA_ACL_PERMISSION_TYPE_ALLOW,
+        "allow",
+    ),
+    (
+        AclPermission::Deny,
+        rd_kafka_AclPermissionType_t::RD_KAFKA_ACL_PERMISSION_TYPE_DENY,
+        "deny",
+    ),
+];
+
+fn acl_native<T: PartialEq, N: Copy>(table: &[(T, N, &str)], value: T) -> N {
+    table
+        .iter()
+        .find(|(v, _, _)| *v == value)
+        .map(|(_, native, _)| *native)
+        .expect("every ACL setting has a native value")
+}
+
+/// The setting of a native value, `None` for the ones kfcli does not handle such as UNKNOWN.
+fn acl_setting<T: Copy, N: PartialEq>(table: &[(T, N, &str)], native: N) -> Option<T> {
+    table
+        .iter()
+        .find(|(_, n, _)| *n == native)
+        .map(|(value, _, _)| *value)
+}
+
+fn acl_name<T: PartialEq>(table: &[(T, impl Copy, &'static str)], value: T) -> &'static str {
+    table
+        .iter()
+        .find(|(v, _, _)| *v == value)
+        .map(|(_, _, name)| *name)
+        .expect("every ACL setting has a name")
+}
+
+/// One ACL: `principal` connecting from `host` is allowed or denied `operation` on a resource.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AclBinding {
+    pub resource_type: AclResource,
+    pub resource_name: String,
+    #[serde(default)]
+    pub pattern_type: AclPattern,
+    /// e.g. `User:billing`
+    pub principal: String,
+    /// `*` for any host
+    #[serde(default = "any_host")]
+    pub host: String,
+    pub operation: AclOperation,
+    #[serde(default)]
+    pub permission: AclPermission,
+}
+
+pub fn any_host() -> String {
+    "*".to_string()
+}
+
+impl fmt::Display for AclBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} on {} {}",
+            acl_name(&ACL_PERMISSIONS, self.permission),
+            self.principal,
+            acl_name(&ACL_OPERATIONS, self.operation),
+            acl_name(&ACL_RESOURCES, self.resource_type),
+            self.resource_name
+        )?;
+        if self.pattern_type == AclPattern::Prefixed {
+            write!(f, " (prefixed)")?;
+        }
+        if self.host != "*" {
+            write!(f, " from {}", self.host)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for AclResource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(acl_name(&ACL_RESOURCES, *self))
+    }
+}
+
+impl fmt::Display for AclPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(acl_name(&ACL_PATTERNS, *self))
+    }
+}
+
+impl fmt::Display for AclOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(acl_name(&ACL_OPERATIONS, *self))
+    }
+}
+
+impl AclBinding {
+    /// Builds the native binding, or with `filter` a filter matching exactly this binding. The
+    /// caller has to destroy it.
+    fn to_native(&self, filter: bool) -> Result<*mut rd_kafka_AclBinding_t, KafkaError> {
+        let invalid = || KafkaError::Admin(format!("Invalid ACL: {}", self));
+        let name = CString::new(self.resource_name.as_str()).map_err(|_| invalid())?;
+        let principal = CString::new(self.principal.as_str()).map_err(|_| invalid())?;
+        let host = CString::new(self.host.as_str()).map_err(|_| invalid())?;
+        let mut errstr = [0 as c_char; 512];
+        let new = if filter {
+            rd_kafka_AclBindingFilter_new
+        } else {
+            rd_kafka_AclBinding_new
+        };
+        let binding = unsafe {
+            new(
+                acl_native(&ACL_RESOURCES, self.resource_type),
+                name.as_ptr(),
+                acl_native(&ACL_PATTERNS, self.pattern_type),
+                principal.as_ptr(),
+                host.as_ptr(),
+                acl_native(&ACL_OPERATIONS, self.operation),
+                acl_native(&ACL_PERMISSIONS, self.permission),
+                errstr.as_mut_ptr(),
+                errstr.len(),
+            )
+        };
+        if binding.is_null() {
+            let reason = unsafe { CStr::from_ptr(errstr.as_ptr()) };
+            return Err(KafkaError::Admin(format!(
+                "Invalid ACL {}: {}",
+                self,
+                reason.to_string_lossy()
+            )));
+        }
+        Ok(binding)
+    }
+
+    /// Copies a binding out of a result event, `None` for settings kfcli does not handle.
+    ///
+    /// # Safety
+    /// `binding` must point to a binding owned by a live result event.
+    unsafe fn from_native(binding: *const rd_kafka_AclBinding_t) -> Option<Self> {
+        Some(AclBinding {
+            resource_type: acl_setting(&ACL_RESOURCES, rd_kafka_AclBinding_restype(binding))?,
+            resource_name: optional_string(rd_kafka_AclBinding_name(binding))?,
+            pattern_type: acl_setting(
+                &ACL_PATTERNS,
+                rd_kafka_AclBinding_resource_pattern_type(binding),
+            )?,
+            principal: optional_string(rd_kafka_AclBinding_principal(binding))?,
+            host: optional_string(rd_kafka_AclBinding_host(binding))?,
+            operation: acl_setting(&ACL_OPERATIONS, rd_kafka_AclBinding_operation(binding))?,
+            permission: acl_setting(
+                &ACL_PERMISSIONS,
+                rd_kafka_AclBinding_permission_type(binding),
+            )?,
+        })
+    }
+}
+
+/// Lists every ACL of the cluster. Fails on clusters without an authorizer.
+pub fn describe_acls(client: &BaseConsumer<ClientAuth>) -> Result<Vec<AclBinding>, KafkaError> {
+    let mut errstr = [0 as c_char; 512];
+    let filter = unsafe {
+        rd_kafka_AclBindingFilter_new(
+            rd_kafka_ResourceType_t::RD_KAFKA_RESOURCE_ANY,
+            ptr::null(),
+            rd_kafka_ResourcePatternType_t::RD_KAFKA_RESOURCE_PATTERN_ANY,
+            ptr::null(),
+            ptr::null(),
+            rd_kafka_AclOperation_t::RD_KAFKA_ACL_OPERATION_ANY,
+            rd_kafka_AclPermissionType_t::RD_KAFKA_ACL_PERMISSION_TYPE_ANY,
+            errstr.as_mut_ptr(),
+            errstr.len(),
+        )
+    };
+    if filter.is_null() {
+        return Err(KafkaError::Admin(
+            "Could not build the ACL filter".to_string(),
+        ));
+    }
+    let result = run_admin_request(
+        client,
+        rd_kafka_admin_op_t::RD_KAFKA_ADMIN_OP_DESCRIBEACLS,
+        None,
+        |rk, options, queue| unsafe { rd_kafka_DescribeAcls(rk, filter, options, queue) },
+        |event| unsafe {
+            let result = rd_kafka_event_DescribeAcls_result(event);
+            if result.is_null() {
+                return Err(KafkaError::Admin(
+                    "Unexpected response to DescribeAcls".to_string(),
+                ));
+            }
+            let mut count = 0;
+            let acls = rd_kafka_DescribeAcls_result_acls(result, &mut count);
+            let mut bindings: Vec<AclBinding> = (0..count)
+                .filter_map(|i| AclBinding::from_native(*acls.add(i)))
+                .collect();
+            bindings.sort();
+            Ok(bindings)
+        },
+    );
+    unsafe { rd_kafka_AclBinding_destroy(filter) };
+    result
+}
+
+/// Builds the native bindings or filters of `acls`, which the caller has to destroy.
+fn native_acls(
+    acls: &[AclBinding],
+    filter: bool,
+) -> Result<Vec<*mut rd_kafka_AclBinding_t>, KafkaError> {
+    let mut bindings = Vec::with_capacity(acls.len());
+    for acl in acls {
+        match acl.to_native(filter) {
+            Ok(binding) => bindings.push(binding),
+            Err(er) => {
+                for binding in bindings {
+                    unsafe { rd_kafka_AclBinding_destroy(binding) };
+                }
+                return Err(er);
+            }
+        }
+    }
+    Ok(bindings)
+}
+
+/// Creates the given ACLs and returns the ones that failed with the reason.
+pub fn create_acls(
+    client: &BaseConsumer<ClientAuth>,
+    acls: &[AclBinding],
+) -> Result<Vec<(AclBinding, String)>, KafkaError> {
+    let mut bindings = native_acls(acls, false)?;
+    let result = run_admin_request(
+        client,
+        rd_kafka_admin_op_t::RD_KAFKA_ADMIN_OP_CREATEACLS,
+        None,
+        |rk, options, queue| unsafe {
+            rd_kafka_CreateAcls(rk, bindings.as_mut_ptr(), bindings.len(), options, queue)
+        },
+        |event| unsafe {
+            let result = rd_kafka_event_CreateAcls_result(event);
+            if result.is_null() {
+                return Err(KafkaError::Admin(
+                    "Unexpected response to CreateAcls".to_string(),
+                ));
+            }
+            let mut count = 0;
+            let results = rd_kafka_CreateAcls_result_acls(result, &mut count);
+            // Results come in the order of the request
+            Ok((0..count)
+                .zip(acls)
+                .filter_map(|(i, acl)| {
+                    let error = rd_kafka_acl_result_error(*results.add(i));
+                    (!error.is_null()).then(|| {
+                        (
+                            acl.clone(),
+                            optional_string(rd_kafka_error_string(error))
+                                .unwrap_or_else(|| "ACL not created".to_string()),
+                        )
+                    })
+                })
+                .collect())
+        },
+    );
+    for binding in bindings {
+        unsafe { rd_kafka_AclBinding_destroy(binding) };
+    }
+    result
+}
+
+/// Deletes the given ACLs and returns the ones that failed with the reason.
+pub fn delete_acls(
+    client: &BaseConsumer<ClientAuth>,
+    acls: &[AclBinding],
+) -> Result<Vec<(AclBinding, String)>, KafkaError> {
+    let mut filters = native_acls(acls, true)?;
+    let result = run_admin_request(
+        client,
+        rd_kafka_admin_op_t::RD_KAFKA_ADMIN_OP_DELETEACLS,
+        None,
+        |rk, options, queue| unsafe {
+            rd_kafka_DeleteAcls(rk, filters.as_mut_ptr(), filters.len(), options, queue)
+        },
+        |event| unsafe {
+            let result = rd_kafka_event_DeleteAcls_result(event);
+            if result.is_null() {
+                return Err(KafkaError::Admin(
+                    "Unexpected response to DeleteAcls".to_string(),
+                ));
+            }
+            let mut count = 0;
+            let responses = rd_kafka_DeleteAcls_result_responses(result, &mut count);
+            // Responses come in the order of the filters
+            Ok((0..count)
+                .zip(acls)
+                .filter_map(|(i, acl)| {
+                    let error = rd_kafka_DeleteAcls_result_response_error(*responses.add(i));
+                    (!error.is_null()).then(|| {
+                        (
+                            acl.clone(),
+                            optional_string(rd_kafka_error_string(error))
+                                .unwrap_or_else(|| "ACL not deleted".to_string()),
+                        )
+                    })
+                })
+                .collect())
+        },
+    );
+    for filter in filters {
+        unsafe { rd_kafka_AclBinding_destroy(filter) };
+    }
+    result
+}
+
+const LEAVE_GROUP_API_KEY: i16 = 13;
+/// First LeaveGroup version that takes members by `group.instance.id`, and the last one before
+/// the flexible encoding
+const LEAVE_GROUP_VERSION: i16 = 3;
+const DESCRIBE_LOG_DIRS_API_KEY: i16 = 35;
+/// Last DescribeLogDirs version before the flexible encoding
+const DESCRIBE_LOG_DIRS_VERSION: i16 = 1;
+const ALTER_PARTITION_REASSIGNMENTS_API_KEY: i16 = 45;
+/// The only AlterPartitionReassignments version, which uses the flexible encoding
+const ALTER_PARTITION_REASSIGNMENTS_VERSION: i16 = 0;
+const DESCRIBE_CLIENT_QUOTAS_API_KEY: i16 = 48;
+/// Last DescribeClientQuotas version before the flexible encoding
+const DESCRIBE_CLIENT_QUOTAS_VERSION: i16 = 0;
+const API_VERSIONS_API_KEY: i16 = 18;
+/// ApiVersions v0 is understood by every broker since Kafka 0.10
+const API_VERSIONS_VERSION: i16 = 0;
+const INCREMENTAL_ALTER_CONFIGS_API_KEY: i16 = 44;
+/// Last IncrementalAlterConfigs version before the flexible encoding
+const INCREMENTAL_ALTER_CONFIGS_VERSION: i16 = 0;
+const DESCRIBE_CONFIGS_API_KEY: i16 = 32;
+/// Oldest DescribeConfigs version Kafka 4 still accepts, from before the flexible encoding
+const DESCRIBE_CONFIGS_VERSION: i16 = 1;
+const CLIENT_ID: &str = "kfcli";
+
+/// Writes a Kafka protocol string, or null for `None`.
+fn write_string(buffer: &mut Vec<u8>, value: Option<&str>) {
+    match value {
+        Some(value) => {
+            buffer.write_i16::<BigEndian>(value.len() as i16).unwrap();
+            buffer.extend_from_slice(value.as_bytes());
+        }
+        None => buffer.write_i16::<BigEndian>(-1).unwrap(),
+    }
+}
+
+pub fn read_string(cursor: &mut Cursor<&[u8]>) -> std::io::Result<Option<String>> {
+    let len = cursor.read_i16::<BigEndian>()?;
+    if len < 0 {
+        return Ok(None);
+    }
+    let mut bytes = vec![0; len as usize];
+    cursor.read_exact(&mut bytes)?;
+    Ok(Some(String::from_utf8_lossy(&bytes).into_owned()))
+}
+
+/// Starts a request with the v1 request header.
+fn request_header(api_key: i16, version: i16, correlation_id: i32) -> Vec<u8> {
+    let mut body = Vec::new();
+    body.write_i16::<BigEndian>(api_key).unwrap();
+    body.write_i16::<BigEndian>(version).unwrap();
+    body.write_i32::<BigEndian>(correlation_id).unwrap();
+    write_string(&mut body, Some(CLIENT_ID));
+    body
+}
+
+/// Starts a request with the v2 request header of flexible versions, which ends in tagged fields.
+fn flexible_request_header(api_key: i16, version: i16, correlation_id: i32) -> Vec<u8> {
+    let mut body = request_header(api_key, version, correlation_id);
+    body.push(0);
+    body
+}
+
+fn write_unsigned_varint(buffer: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        buffer.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+fn read_unsigned_varint(cursor: &mut Cursor<&[u8]>) -> std::io::Result<u32> {
+    let mut value = 0;
+    for shift in (0..35).step_by(7) {
+        let byte = cursor.read_u8()?;
+        value |= ((byte & 0x7f) as u32) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "varint is longer than 5 bytes",
+    ))
+}
+
+/// Writes the length of a compact array or string, which is stored plus one so 0 means null.
+fn write_compact_len(buffer: &mut Vec<u8>, len: Option<usize>) {
+    write_unsigned_varint(buffer, len.map_or(0, |len| len as u32 + 1));
+}
+
+pub fn read_compact_len(cursor: &mut Cursor<&[u8]>) -> std::io::Result<Option<usize>> {
+    Ok(read_unsigned_varint(cursor)?
+        .checked_sub(1)
+        .map(|len| len as usize))
+}
+
+pub fn read_compact_string(cursor: &mut Cursor<&[u8]>) -> std::io::Result<Option<String>> {
+    let Some(len) = read_compact_len(cursor)? else {
+        return Ok(None);
+    };
+    let mut bytes = vec![0; len];
+    cursor.read_exact(&mut bytes)?;
+    Ok(Some(String::from_utf8_lossy(&bytes).into_owned()))
+}
+
+/// Skips the tagged fields that end every structure of a flexible version.
+pub fn skip_tagged_fields(cursor: &mut Cursor<&[u8]>) -> std::io::Result<()> {
+    for _ in 0..read_unsigned_varint(cursor)? {
+        let _tag = read_unsigned_varint(cursor)?;
+        let size = read_unsigned_varint(cursor)?;
+        cursor.set_position(cursor.position() + size as u64);
+    }
+    Ok(())
+}
+
+fn size_prefixed(body: Vec<u8>) -> Vec<u8> {
+    let mut request = Vec::with_capacity(body.len() + 4);
+    request.write_i32::<BigEndian>(body.len() as i32).unwrap();
+    request.extend(body);
+    request
+}
+
+/// Sends a size prefixed request to `node` and returns the response without its size prefix.
+fn send_request(node: &ClusterNode, request: &[u8]) -> Result<Vec<u8>, KafkaError> {
+    if client::uses_secure_connection() {
+        return Err(KafkaError::Admin(format!(
+            "This command talks to broker {} directly, which is not supported with SASL or TLS yet",
+            node
+        )));
+    }
+    let address = format!("{}:{}", node.host, node.port);
+    // The API key follows the 4 byte size prefix
+    let api_key = request
+        .get(4..6)
+        .map(|key| i16::from_be_bytes([key[0], key[1]]));
+    tracing::debug!(broker = %node, ?api_key, bytes = request.len(), "Sending request");
+    let started = Instant::now();
+    let io_error = |er: std::io::Error| {
+        tracing::warn!(broker = %node, ?api_key, error = %er, "Request failed");
+        KafkaError::Io(format!("Error while talking to broker {}", node), er)
+    };
+    let mut stream = TcpStream::connect(&address).map_err(io_error)?;
+    let timeout = Some(Duration::from_millis(ADMIN_TIMEOUT_MS as u64));
+    stream.set_read_timeout(timeout).map_err(io_error)?;
+    stream.set_write_timeout(timeout).map_err(io_error)?;
+
+    stream.write_all(request).map_err(io_error)?;
+    let size = stream.read_i32::<BigEndian>().map_err(io_error)?;
+    let mut response = vec![0; size.max(0) as usize];
+    stream.read_exact(&mut response).map_err(io_error)?;
+    tracing::debug!(
+        broker = %node,
+        ?api_key,
+        bytes = response.len(),
+        elapsed_ms = started.elapsed().as_millis() as u64,
+        "Received response"
+    );
+    Ok(response)
+}
+
+/// The (min, max) version a broker supports of each API key.
+type ApiVersions = BTreeMap<i16, (i16, i16)>;
+
+/// A request some commands need that older brokers do not know.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Feature {
+    pub name: &'static str,
+    api_key: i16,
+    version: i16,
+    /// First Kafka release whose brokers accept the request
+    pub since: &'static str,
+}
+
+impl Feature {
+    pub const INCREMENTAL_ALTER_CONFIGS: Feature = Feature {
+        name: "incremental alter configs",
+        api_key: INCREMENTAL_ALTER_CONFIGS_API_KEY,
+        version: INCREMENTAL_ALTER_CONFIGS_VERSION,
+        since: "Kafka 2.3",
+    };
+    pub const PARTITION_REASSIGNMENTS: Feature = Feature {
+        name: "partition reassignments",
+        api_key: ALTER_PARTITION_REASSIGNMENTS_API_KEY,
+        version: ALTER_PARTITION_REASSIGNMENTS_VERSION,
+        since: "Kafka 2.4",
+    };
+    pub const STATIC_MEMBER_REMOVAL: Feature = Feature {
+        name: "removing static members",
+        api_key: LEAVE_GROUP_API_KEY,
+        version: LEAVE_GROUP_VERSION,
+        since: "Kafka 2.4",
+    };
+    pub const CLIENT_QUOTAS: Feature = Feature {
+        name: "describing client quotas",
+        api_key: DESCRIBE_CLIENT_QUOTAS_API_KEY,
+        version: DESCRIBE_CLIENT_QUOTAS_VERSION,
+        since: "Kafka 2.6",
+    };
+    pub const LOG_DIRS: Feature = Feature {
+        name: "describing log dirs",
+        api_key: DESCRIBE_LOG_DIRS_API_KEY,
+        version: DESCRIBE_LOG_DIRS_VERSION,
+        since: "Kafka 2.0",
+    };
+
+    /// Whether a broker with the given API versions accepts the request.
+    fn supported_by(&self, versions: &ApiVersions) -> bool {
+        versions
+            .get(&self.api_key)
+            .is_some_and(|(min, max)| (*min..=*max).contains(&self.version))
+    }
+}
+
+/// Size prefixed ApiVersions v0 request, which has no fields besides the header.
+fn encode_api_versions(correlation_id: i32) -> Vec<u8> {
+    size_prefixed(request_header(
+        API_VERSIONS_API_KEY,
+        API_VERSIONS_VERSION,
+        correlation_id,
+    ))
+}
+
+/// Reads an ApiVersions v0 response, without its size prefix, into the error code and the
+/// supported versions.
+fn decode_api_versions(response: &[u8]) -> std::io::Result<(i16, ApiVersions)> {
+    let mut cursor = Cursor::new(response);
+    let _correlation_id = cursor.read_i32::<BigEndian>()?;
+    let error_code = cursor.read_i16::<BigEndian>()?;
+    let count = cursor.read_i32::<BigEndian>()?.max(0);
+    let mut versions = BTreeMap::new();
+    for _ in 0..count {
+        let api_key = cursor.read_i16::<BigEndian>()?;
+        let min = cursor.read_i16::<BigEndian>()?;
+        let max = cursor.read_i16::<BigEndian>()?;
+        versions.insert(api_key, (min, max));
+    }
+    Ok((error_code, versions))
+}
+
+/// The API versions `node` supports.
+fn api_versions(node: &ClusterNode) -> Result<ApiVersions, KafkaError> {
+    let response = send_request(node, &encode_api_versions(1))?;
+    let (error_code, versions) = decode_api_versions(&response).map_err(|er| {
+        KafkaError::Deserialize(
+            "Error while reading the ApiVersions response".to_string(),
+            er,
+        )
+    })?;
+    if error_code != 0 {
+        return Err(KafkaError::Admin(format!(
+            "Broker {} rejected the ApiVersions request with error code {}",
+            node, error_code
+        )));
+    }
+    Ok(versions)
+}
+
+/// Checks that every broker of the cluster supports `feature` before a command relies on it,
+/// so an old cluster gets a clear message instead of an opaque protocol error halfway through.
+/// Brokers that cannot be asked, including every broker over SASL or TLS, are given the
+/// benefit of the doubt and left to the request itself, which is logged at info level.
+pub fn check_feature(
+    client: &BaseConsumer<ClientAuth>,
+    feature: Feature,
+) -> Result<(), KafkaError> {
+    if client::uses_secure_connection() {
+        tracing::info!(
+            feature = feature.name,
+            "Skipped the API version check, which needs a plaintext connection"
+        );
+        return Ok(());
+    }
+    let mut too_old = vec![];
+    for node in describe_cluster_nodes(client)? {
+        match api_versions(&node) {
+            Ok(versions) if !feature.supported_by(&versions) => too_old.push(node.id.to_string()),
+            Ok(_) => {}
+            Err(er) => {
+                tracing::info!(broker = %node, error = %er, "Skipped the API version check")
+            }
+        }
+    }
+    if too_old.is_empty() {
+        return Ok(());
+    }
+    Err(KafkaError::Admin(format!(
+        "The cluster is too old for {}: broker{} {} do{} not support it, it needs {} or newer",
+        feature.name,
+        if too_old.len() == 1 { "" } else { "s" },
+        too_old.join(", "),
+        if too_old.len() == 1 { "es" } else { "" },
+        feature.since
+    )))
+}
+
+/// Size prefixed LeaveGroup v3 request removing the static members `instance_ids` from `group`.
+fn encode_leave_group(correlation_id: i32, group: &str, instance_ids: &[&str]) -> Vec<u8> {
+    let mut body = request_header(LEAVE_GROUP_API_KEY, LEAVE_GROUP_VERSION, correlation_id);
+    write_string(&mut body, Some(group));
+    body.write_i32::<BigEndian>(instance_ids.len() as i32)
+        .unwrap();
+    for instance_id in instance_ids {
+        // Static members are addressed by instance id, with an empty member id
+        write_string(&mut body, Some(""));
+        write_string(&mut body, Some(instance_id));
+    }
+    size_prefixed(body)
+}
+
+/// Reads a LeaveGroup v3 response, without its size prefix, into the group error code and the
+/// error code of each instance id.
+fn decode_leave_group(response: &[u8]) -> std::io::Result<(i16, Vec<(String, i16)>)> {
+    let mut cursor = Cursor::new(response);
+    let _correlation_id = cursor.read_i32::<BigEndian>()?;
+    let _throttle_time_ms = cursor.read_i32::<BigEndian>()?;
+    let error_code = cursor.read_i16::<BigEndian>()?;
+    let count = cursor.read_i32::<BigEndian>()?.max(0);
+    let mut members = Vec::new();
+    for _ in 0..count {
+        let _member_id = read_string(&mut cursor)?;
+        let instance_id = read_string(&mut cursor)?.unwrap_or_default();
+        members.push((instance_id, cursor.read_i16::<BigEndian>()?));
+    }
+    Ok((error_code, members))
+}
+
+/// Names the error codes a LeaveGroup for static members can come back with.
+fn describe_error_code(code: i16) -> String {
+    match code {
+        14 => "the coordinator is loading the group".to_string(),
+        15 => "the coordinator is not available".to_string(),
+        16 => "the broker is not the group coordinator".to_string(),
+        25 => "no member has this instance id".to_string(),
+        30 => "not authorized to change the group".to_string(),
+        69 => "the group does not exist".to_string(),
+        82 => "the instance id is used by another member".to_string(),
+        code => format!("error code {}", code),
+    }
+}
+
+/// Removes static members from `group` by their `group.instance.id`, the way the Java admin
+/// client's removeMembersFromConsumerGroup does. librdkafka has no API for it, so a LeaveGroup
+/// request is sent straight to the group coordinator. Returns the instance ids that failed
+/// with the reason.
+pub fn remove_static_members(
+    coordinator: &ClusterNode,
+    group: &str,
+    instance_ids: &[&str],
+) -> Result<Vec<(String, String)>, KafkaError> {
+    let response = send_request(coordinator, &encode_leave_group(1, group, instance_ids))?;
+    let (error_code, members) = decode_leave_group(&response).map_err(|er| {
+        KafkaError::Deserialize(
+            "Error while reading the LeaveGroup response".to_string(),
+            er,
+        )
+    })?;
+    if error_code != 0 {
+        return Err(KafkaError::Admin(format!(
+            "Could not remove members from {}: {}",
+            group,
+            describe_error_code(error_code)
+        )));
+    }
+    Ok(members
+        .into_iter()
+        .filter(|(_, code)| *code != 0)
+        .map(|(instance_id, code)| (instance_id, describe_error_code(code)))
+        .collect())
+}
+
+/// Bytes a replica of a partition takes in one log directory of a broker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplicaSize {
+    pub broker: i32,
+    pub log_dir: String,
+    pub topic: String,
+    pub partition: i32,
+    pub size: i64,
+}
+
+/// Size prefixed DescribeLogDirs v1 request for the given partitions, or for every partition
+/// on the broker when `topics` is `None`.
+fn encode_describe_log_dirs(correlation_id: i32, topics: Option<&[(&str, Vec<i32>)]>) -> Vec<u8> {
+    let mut body = request_header(
+        DESCRIBE_LOG_DIRS_API_KEY,
+        DESCRIBE_LOG_DIRS_VERSION,
+        correlation_id,
+    );
+    match topics {
+        Some(topics) => {
+            body.write_i32::<BigEndian>(topics.len() as i32).unwrap();
+            for (topic, partitions) in topics {
+                write_string(&mut body, Some(topic));
+                body.write_i32::<BigEndian>(partitions.len() as i32)
+                    .unwrap();
+                for partition in partitions {
+                    body.write_i32::<BigEndian>(*partition).unwrap();
+                }
+            }
+        }
+        None => body.write_i32::<BigEndian>(-1).unwrap(),
+    }
+    size_prefixed(body)
+}
+
+/// Reads a DescribeLogDirs v1 response, without its size prefix, into the replica sizes of
+/// `broker`. Log directories with an error, which are offline, and future replicas still being
+/// moved between directories are left out.
+fn decode_describe_log_dirs(broker: i32, response: &[u8]) -> std::io::Result<Vec<ReplicaSize>> {
+    let mut cursor = Cursor::new(response);
+    let _correlation_id = cursor.read_i32::<BigEndian>()?;
+    let _throttle_time_ms = cursor.read_i32::<BigEndian>()?;
+    let mut replicas = Vec::new();
+    for _ in 0..cursor.read_i32::<BigEndian>()?.max(0) {
+        let error_code = cursor.read_i16::<BigEndian>()?;
+        let log_dir = read_string(&mut cursor)?.unwrap_or_default();
+        for _ in 0..cursor.read_i32::<BigEndian>()?.max(0) {
+            let topic = read_string(&mut cursor)?.unwrap_or_default();
+            for _ in 0..cursor.read_i32::<BigEndian>()?.max(0) {
+                let partition = cursor.read_i32::<BigEndian>()?;
+                let size = cursor.read_i64::<BigEndian>()?;
+                let _offset_lag = cursor.read_i64::<BigEndian>()?;
+                let is_future = cursor.read_u8()? != 0;
+                if error_code == 0 && !is_future {
+                    replicas.push(ReplicaSize {
+                        broker,
+                        log_dir: log_dir.clone(),
+                        topic: topic.clone(),
+                        partition,
+                        size,
+                    });
+                }
+            }
+        }
+    }
+    Ok(replicas)
+}
+
+/// Lists the size of the replicas `broker` holds, for the given partitions or all of them.
+/// librdkafka has no DescribeLogDirs, so the request is sent to the broker directly.
+pub fn describe_log_dirs(
+    broker: &ClusterNode,
+    topics: Option<&[(&str, Vec<i32>)]>,
+) -> Result<Vec<ReplicaSize>, KafkaError> {
+    let response = send_request(broker, &encode_describe_log_dirs(1, topics))?;
+    decode_describe_log_dirs(broker.id, &response).map_err(|er| {
+        KafkaError::Deserialize(
+            format!("Error while reading the log dirs of broker {}", broker),
+            er,
+        )
+    })
+}
+
+/// Size prefixed AlterPartitionReassignments v0 request moving the partitions of `topic` to the
+/// given replicas.
+fn encode_alter_partition_reassignments(
+    correlation_id: i32,
+    topic: &str,
+    assignments: &[(i32, Vec<i32>)],
+) -> Vec<u8> {
+    let mut body = flexible_request_header(
+        ALTER_PARTITION_REASSIGNMENTS_API_KEY,
+        ALTER_PARTITION_REASSIGNMENTS_VERSION,
+        correlation_id,
+    );
+    body.write_i32::<BigEndian>(ADMIN_TIMEOUT_MS).unwrap();
+    write_compact_len(&mut body, Some(1));
+    write_compact_len(&mut body, Some(topic.len()));
+    body.extend_from_slice(topic.as_bytes());
+    write_compact_len(&mut body, Some(assignments.len()));
+    for (partition, replicas) in assignments {
+        body.write_i32::<BigEndian>(*partition).unwrap();
+        write_compact_len(&mut body, Some(replicas.len()));
+        for replica in replicas {
+            body.write_i32::<BigEndian>(*replica).unwrap();
+        }
+        body.push(0);
+    }
+    body.push(0);
+    body.push(0);
+    size_prefixed(body)
+}
+
+/// Reads an AlterPartitionReassignments v0 response, without its size prefix, into the request
+/// error and the error code and message of each partition.
+#[allow(clippy::type_complexity)]
+fn decode_alter_partition_reassignments(
+    response: &[u8],
+) -> std::io::Result<((i16, Option<String>), Vec<(i32, i16, Option<String>)>)> {
+    let mut cursor = Cursor::new(response);
+    let _correlation_id = cursor.read_i32::<BigEndian>()?;
+    skip_tagged_fields(&mut cursor)?;
+    let _throttle_time_ms = cursor.read_i32::<BigEndian>()?;
+    let error_code = cursor.read_i16::<BigEndian>()?;
+    let error_message = read_compact_string(&mut cursor)?;
+    let mut partitions = Vec::new();
+    for _ in 0..read_compact_len(&mut cursor)?.unwrap_or(0) {
+        let _topic = read_compact_string(&mut cursor)?;
+        for _ in 0..read_compact_len(&mut cursor)?.unwrap_or(0) {
+            let partition = cursor.read_i32::<BigEndian>()?;
+            let code = cursor.read_i16::<BigEndian>()?;
+            let message = read_compact_string(&mut cursor)?;
+            skip_tagged_fields(&mut cursor)?;
+            partitions.push((partition, code, message));
+        }
+        skip_tagged_fields(&mut cursor)?;
+    }
+    Ok(((error_code, error_message), partitions))
+}
+
+/// Starts moving the partitions of `topic` to new replicas. librdkafka has no API for it, so the
+/// request is sent to the controller directly. The brokers copy the data in the background;
+/// returns the partitions that could not be reassigned with the reason.
+pub fn alter_partition_reassignments(
+    controller: &ClusterNode,
+    topic: &str,
+    assignments: &[(i32, Vec<i32>)],
+) -> Result<Vec<(i32, String)>, KafkaError> {
+    let response = send_request(
+        controller,
+        &encode_alter_partition_reassignments(1, topic, assignments),
+    )?;
+    let ((error_code, error_message), partitions) = decode_alter_partition_reassignments(&response)
+        .map_err(|er| {
+            KafkaError::Deserialize(
+                "Error while reading the AlterPartitionReassignments response".to_string(),
+                er,
+            )
+        })?;
+    if error_code != 0 {
+        return Err(KafkaError::Admin(format!(
+            "Could not reassign partitions of {}: {}",
+            topic,
+            error_message.unwrap_or_else(|| format!("error code {}", error_code))
+        )));
+    }
+    Ok(partitions
+        .into_iter()
+        .filter(|(_, code, _)| *code != 0)
+        .map(|(partition, code, message)| {
+            (
+                partition,
+                message.unwrap_or_else(|| format!("error code {}", code)),
+            )
+        })
+        .collect())
+}
+
+/// Quotas configured for one entity, such as a user, a client id or both together.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientQuota {
+    /// Entity type (`user`, `client-id` or `ip`) and name, `None` for the default entity
+    pub entity: Vec<(String, Option<String>)>,
+    /// Quota name, such as `consumer_byte_rate`, and its value
+    pub values: Vec<(String, f64)>,
+}
+
+impl ClientQuota {
+    /// The client id the quota is set on, when it is set on a named client id alone rather than
+    /// on a user and client id together, which only applies to that user's connections.
+    pub fn client_id(&self) -> Option<&str> {
+        match &self.entity[..] {
+            [(entity_type, Some(name))] if entity_type == "client-id" => Some(name),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ClientQuota {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self
+            .entity
+            .iter()
+            .map(|(entity_type, name)| {
+                format!("{}={}", entity_type, name.as_deref().unwrap_or("<default>"))
+            })
+            .collect();
+        write!(f, "{}", parts.join(","))
+    }
+}
+
+/// Size prefixed DescribeClientQuotas v0 request matching every configured quota.
+fn encode_describe_client_quotas(correlation_id: i32) -> Vec<u8> {
+    let mut body = request_header(
+        DESCRIBE_CLIENT_QUOTAS_API_KEY,
+        DESCRIBE_CLIENT_QUOTAS_VERSION,
+        correlation_id,
+    );
+    // No filter components, and not strict so entities with any components match
+    body.write_i32::<BigEndian>(0).unwrap();
+    body.write_u8(0).unwrap();
+    size_prefixed(body)
+}
+
+/// Reads a DescribeClientQuotas v0 response, without its size prefix, into the top-level
+/// error code and message and the quotas.
+#[allow(clippy::type_complexity)]
+fn decode_describe_client_quotas(
+    response: &[u8],
+) -> std::io::Result<((i16, Option<String>), Vec<ClientQuota>)> {
+    let mut cursor = Cursor::new(response);
+    let _correlation_id = cursor.read_i32::<BigEndian>()?;
+    let _throttle_time_ms = cursor.read_i32::<BigEndian>()?;
+    let error_code = cursor.read_i16::<BigEndian>()?;
+    let error_message = read_string(&mut cursor)?;
+    let mut quotas = Vec::new();
+    for _ in 0..cursor.read_i32::<BigEndian>()?.max(0) {
+        let mut entity = Vec::new();
+        for _ in 0..cursor.read_i32::<BigEndian>()?.max(0) {
+            let entity_type = read_string(&mut cursor)?.unwrap_or_default();
+            entity.push((entity_type, read_string(&mut cursor)?));
+        }
+        let mut values = Vec::new();
+        for _ in 0..cursor.read_i32::<BigEndian>()?.max(0) {
+            let key = read_string(&mut cursor)?.unwrap_or_default();
+            values.push((key, cursor.read_f64::<BigEndian>()?));
+        }
+        quotas.push(ClientQuota { entity, values });
+    }
+    Ok(((error_code, error_message), quotas))
+}
+
+/// Lists the client quotas configured on the cluster. librdkafka has no DescribeClientQuotas,
+/// so the request is sent to `broker` directly.
+pub fn describe_client_quotas(broker: &ClusterNode) -> Result<Vec<ClientQuota>, KafkaError> {
+    let response = send_request(broker, &encode_describe_client_quotas(1))?;
+    let ((error_code, error_message), quotas) =
+        decode_describe_client_quotas(&response).map_err(|er| {
+            KafkaError::Deserialize(
+                "Error while reading the DescribeClientQuotas response".to_string(),
+                er,
+            )
+        })?;
+    if error_code != 0 {
+        return Err(KafkaError::Admin(format!(
+            "Could not describe client quotas: {}",
+            error_message.unwrap_or_else(|| format!("error code {}", error_code))
+        )));
+    }
+    Ok(quotas)
+}
+
+/// Checks the per-resource error of a config result and returns its entries as name/value pairs.
+///
+/// # Safety
+/// `resource` must point to a config resource owned by a live result event.
+unsafe fn read_config_resource(
+    resource: *const rd_kafka_ConfigResource_t,
+) -> Result<Vec<(String, String)>, KafkaError> {
+    if rd_kafka_ConfigResource_error(resource) != RDKafkaRespErr::RD_KAFKA_RESP_ERR_NO_ERROR {
+        return Err(KafkaError::Admin(
+            optional_string(rd_kafka_ConfigResource_error_string(resource))
+                .unwrap_or_else(|| "Config request failed".to_string()),
+        ));
+    }
+    let mut count = 0;
+    let entries = rd_kafka_ConfigResource_configs(resource, &mut count);
+    Ok((0..count)
+        .map(|i| {
+            let entry = *entries.add(i);
+            (
+                optional_string(rd_kafka_ConfigEntry_name(entry)).unwrap_or_default(),
+                optional_string(rd_kafka_ConfigEntry_value(entry)).unwrap_or_default(),
+            )
+        })
+        .collect())
+}
+
+/// Size prefixed DescribeConfigs v1 request for every config of one resource.
+fn encode_describe_configs(correlation_id: i32, resource_type: i8, resource_name: &str) -> Vec<u8> {
+    let mut body = request_header(
+        DESCRIBE_CONFIGS_API_KEY,
+        DESCRIBE_CONFIGS_VERSION,
+        correlation_id,
+    );
+    body.write_i32::<BigEndian>(1).unwrap();
+    body.write_i8(resource_type).unwrap();
+    write_string(&mut body, Some(resource_name));
+    // All configs, without their synonyms
+    body.write_i32::<BigEndian>(-1).unwrap();
+    body.write_u8(0).unwrap();
+    size_prefixed(body)
+}
+
+/// Reads a DescribeConfigs v1 response for one resource, without its size prefix, into the
+/// resource's error code and message and its configs as name/value pairs.
+#[allow(clippy::type_complexity)]
+fn decode_describe_configs(
+    response: &[u8],
+) -> std::io::Result<((i16, Option<String>), Vec<(String, String)>)> {
+    let mut cursor = Cursor::new(response);
+    let _correlation_id = cursor.read_i32::<BigEndian>()?;
+    let _throttle_time_ms = cursor.read_i32::<BigEndian>()?;
+    if cursor.read_i32::<BigEndian>()? < 1 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "the response has no resource",
+        ));
+    }
+    let error_code = cursor.read_i16::<BigEndian>()?;
+    let error_message = read_string(&mut cursor)?;
+    let _resource_type = cursor.read_i8()?;
+    let _resource_name = read_string(&mut cursor)?;
+    let mut configs = Vec::new();
+    for _ in 0..cursor.read_i32::<BigEndian>()?.max(0) {
+        let name = read_string(&mut cursor)?.unwrap_or_default();
+        let value = read_string(&mut cursor)?.unwrap_or_default();
+        let _read_only = cursor.read_u8()?;
+        let _config_source = cursor.read_i8()?;
+        let _is_sensitive = cursor.read_u8()?;
+        for _ in 0..cursor.read_i32::<BigEndian>()?.max(0) {
+            let _synonym_name = read_string(&mut cursor)?;
+            let _synonym_value = read_string(&mut cursor)?;
+            let _synonym_source = cursor.read_i8()?;
+        }
+        configs.push((name, value));
+    }
+    Ok(((error_code, error_message), configs))
+}
+
+/// Size prefixed IncrementalAlterConfigs v0 request setting `configs` on one resource.
+fn encode_incremental_alter_configs(
+    correlation_id: i32,
+    resource_type: i8,
+    resource_name: &str,
+    configs: &[(String, String)],
+) -> Vec<u8> {
+    let mut body = request_header(
+        INCREMENTAL_ALTER_CONFIGS_API_KEY,
+        INCREMENTAL_ALTER_CONFIGS_VERSION,
+        correlation_id,
+    );
+    body.write_i32::<BigEndian>(1).unwrap();
+    body.write_i8(resource_type).unwrap();
+    write_string(&mut body, Some(resource_name));
+    body.write_i32::<BigEndian>(configs.len() as i32).unwrap();
+    for (name, value) in configs {
+        write_string(&mut body, Some(name));
+        // Operation 0 is SET
+        body.write_i8(0).unwrap();
+        write_string(&mut body, Some(value));
+    }
+    // Not validate_only
+    body.write_u8(0).unwrap();
+    size_prefixed(body)
+}
+
+/// Reads an IncrementalAlterConfigs v0 response for one resource, without its size prefix,
+/// into the resource's error code and message.
+fn decode_incremental_alter_configs(response: &[u8]) -> std::io::Result<(i16, Option<String>)> {
+    let mut cursor = Cursor::new(response);
+    let _correlation_id = cursor.read_i32::<BigEndian>()?;
+    let _throttle_time_ms = cursor.read_i32::<BigEndian>()?;
+    if cursor.read_i32::<BigEndian>()? < 1 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "the response has no resource",
+        ));
+    }
+    let error_code = cursor.read_i16::<BigEndian>()?;
+    Ok((error_code, read_string(&mut cursor)?))
+}
+
+/// The broker with id `broker_id`, to send it requests directly.
+fn broker_node(
+    client: &BaseConsumer<ClientAuth>,
+    broker_id: i32,
+) -> Result<ClusterNode, KafkaError> {
+    describe_cluster_nodes(client)?
+        .into_iter()
+        .find(|node| node.id == broker_id)
+        .ok_or_else(|| KafkaError::Admin(format!("The cluster has no broker {}", broker_id)))
+}
+
+/// Lists the log4j loggers of a broker with their current level. librdkafka turns the broker
+/// logger resource type into an unknown one, so the request is sent to the broker directly.
+pub fn describe_broker_loggers(
+    client: &BaseConsumer<ClientAuth>,
+    broker_id: i32,
+) -> Result<Vec<(String, String)>, KafkaError> {
+    let node = broker_node(client, broker_id)?;
+    let request = encode_describe_configs(1, RESOURCE_BROKER_LOGGER, &broker_id.to_string());
+    let response = send_request(&node, &request)?;
+    let ((error_code, error_message), loggers) =
+        decode_describe_configs(&response).map_err(|er| {
+            KafkaError::Deserialize(
+                "Error while reading the DescribeConfigs response".to_string(),
+                er,
+            )
+        })?;
+    if error_code != 0 {
+        return Err(KafkaError::Admin(format!(
+            "Could not describe the loggers of broker {}: {}",
+            broker_id,
+            error_message.unwrap_or_else(|| format!("error code {}", error_code))
+        )));
+    }
+    Ok(loggers)
+}
+
+/// Sets logger levels on a broker. Only the given loggers change, the others keep their level.
+pub fn set_broker_loggers(
+    client: &BaseConsumer<ClientAuth>,
+    broker_id: i32,
+    levels: &[(String, String)],
+) -> Result<(), KafkaError> {
+    let node = broker_node(client, broker_id)?;
+    let request =
+        encode_incremental_alter_configs(1, RESOURCE_BROKER_LOGGER, &broker_id.to_string(), levels);
+    let response = send_request(&node, &request)?;
+    let (error_code, error_message) =
+        decode_incremental_alter_configs(&response).map_err(|er| {
+            KafkaError::Deserialize(
+                "Error while reading the IncrementalAlterConfigs response".to_string(),
+                er,
+            )
+        })?;
+    if error_code != 0 {
+        return Err(KafkaError::Admin(format!(
+            "Could not set the loggers of broker {}: {}",
+            broker_id,
+            error_message.unwrap_or_else(|| format!("error code {}", error_code))
+        )));
+    }
+    Ok(())
+}
+
+/// Sets configs of a topic. Only the given configs change, the others keep their value.
+pub fn set_topic_configs(
+    client: &BaseConsumer<ClientAuth>,
+    topic: &str,
+    configs: &[(String, String)],
+) -> Result<(), KafkaError> {
+    incremental_alter_configs(
+        client,
+        rd_kafka_ResourceType_t::RD_KAFKA_RESOURCE_TOPIC,
+        topic,
+        None,
+        configs,
+    )
+}
+
+/// Sets the given configs of one resource with IncrementalAlterConfigs.
+fn incremental_alter_configs(
+    client: &BaseConsumer<ClientAuth>,
+    resource_type: rd_kafka_ResourceType_t,
+    resource_name: &str,
+    broker: Option<i32>,
+    configs: &[(String, String)],
+) -> Result<(), KafkaError> {
+    let name = CString::new(resource_name)
+        .map_err(|_| KafkaError::Admin(format!("Invalid resource name {}", resource_name)))?;
+    let mut c_configs = Vec::with_capacity(configs.len());
+    for (config, value) in configs {
+        match (CString::new(config.as_str()), CString::new(value.as_str())) {
+            (Ok(config), Ok(value)) => c_configs.push((config, value)),
+            _ => {
+                return Err(KafkaError::Admin(format!(
+                    "Invalid setting {}={}",
+                    config, value
+                )))
+            }
+        }
+    }
+
+    let mut resource = unsafe { rd_kafka_ConfigResource_new(resource_type, name.as_ptr()) };
+    for ((config, value), (c_config, c_value)) in configs.iter().zip(&c_configs) {
+        unsafe {
+            let error = rd_kafka_ConfigResource_add_incremental_config(
+                resource,
+                c_config.as_ptr(),
+                rd_kafka_AlterConfigOpType_t::RD_KAFKA_ALTER_CONFIG_OP_TYPE_SET,
+                c_value.as_ptr(),
+            );
+            if !error.is_null() {
+                let message = optional_string(rd_kafka_error_string(error))
+                    .unwrap_or_else(|| "rejected by the client".to_string());
+                rd_kafka_error_destroy(error);
+                rd_kafka_ConfigResource_destroy(resource);
+                return Err(KafkaError::Admin(format!(
+                    "Invalid setting {}={}: {}",
+                    config, value, message
+                )));
+            }
+        }
+    }
+
+    let result = run_admin_request(
+        client,
+        rd_kafka_admin_op_t::RD_KAFKA_ADMIN_OP_INCREMENTALALTERCONFIGS,
+        broker,
+        |rk, options, queue| unsafe {
+            rd_kafka_IncrementalAlterConfigs(rk, &mut resource, 1, options, queue);
+        },
+        |event| unsafe {
+            let result = rd_kafka_event_IncrementalAlterConfigs_result(event);
+            if result.is_null() {
+                return Err(KafkaError::Admin(
+                    "Unexpected response to IncrementalAlterConfigs".to_string(),
+                ));
+            }
+            let mut count = 0;
+            let resources = rd_kafka_IncrementalAlterConfigs_result_resources(result, &mut count);
+            if count > 0 {
+                read_config_resource(*resources)?;
+            }
+            Ok(())
+        },
+    );
+    unsafe { rd_kafka_ConfigResource_destroy(resource) };
+    result
+}
+
+pub fn get_admin_client(bootstrap_servers: &str) -> Result<AdminClient<ClientAuth>, KafkaError> {
+    client::admin(&client::config(bootstrap_servers))
+}
+
+/// Creates the given topics and returns the outcome per topic.
+pub fn create_topics(
+    admin: &AdminClient<ClientAuth>,
+    topics: &[NewTopic],
+) -> Result<Vec<TopicResult>, KafkaError> {
+    let options = AdminOptions::new().operation_timeout(Some(Duration::from_secs(30)));
+    block_on(admin.create_topics(topics, &options))
+        .map_err(|er| KafkaError::Admin(format!("Error while creating topics: {}", er)))
+}
+
+/// Raises the partition count of the given topics and returns the outcome per topic.
+pub fn create_partitions(
+    admin: &AdminClient<ClientAuth>,
+    partitions: &[NewPartitions],
+) -> Result<Vec<TopicResult>, KafkaError> {
+    let options = AdminOptions::new().operation_timeout(Some(Duration::from_secs(30)));
+    block_on(admin.create_partitions(partitions, &options))
+        .map_err(|er| KafkaError::Admin(format!("Error while adding partitions: {}", er)))
+}
+
+/// Deletes the given topics and returns the outcome per topic.
+pub fn delete_topics(
+    admin: &AdminClient<ClientAuth>,
+    topics: &[&str],
+) -> Result<Vec<TopicResult>, KafkaError> {
+    let options = AdminOptions::new().operation_timeout(Some(Duration::from_secs(30)));
+    block_on(admin.delete_topics(topics, &options))
+        .map_err(|er| KafkaError::Admin(format!("Error while deleting topics: {}", er)))
+}
+
+/// Fetches the configuration of the given resources, keyed by resource name.
+pub fn describe_configs(
+    admin: &AdminClient<ClientAuth>,
+    resources: &[ResourceSpecifier],
+) -> Result<HashMap<String, Vec<ConfigEntry>>, KafkaError> {
+    let options = AdminOptions::new().request_timeout(Some(Duration::from_secs(10)));
+    let results = block_on(admin.describe_configs(resources, &options))
+        .map_err(|er| KafkaError::Admin(format!("Error while describing configs: {}", er)))?;
+
+    let mut configs = HashMap::new();
+    for result in results {
+        let resource = result.map_err(|code| {
+            KafkaError::Admin(format!("Error while describing configs: {}", code))
+        })?;
+        let name = match resource.specifier {
+            OwnedResourceSpecifier::Topic(name) | OwnedResourceSpecifier::Group(name) => name,
+            OwnedResourceSpecifier::Broker(id) => id.to_string(),
+        };
+        configs.insert(name, resource.entries);
+    }
+    Ok(configs)
+}
+
+/// Fetches the configuration of `topics`, a batch of topics per request.
+pub fn describe_topic_configs(
+    admin: &AdminClient<ClientAuth>,
+    topics: &[&str],
+) -> Result<HashMap<String, Vec<ConfigEntry>>, KafkaError> {
+    let mut configs = HashMap::new();
+    for batch in topics.chunks(100) {
+        let resources: Vec<ResourceSpecifier> =
+            batch.iter().map(|t| ResourceSpecifier::Topic(t)).collect();
+        configs.extend(describe_configs(admin, &resources)?);
+    }
+    Ok(configs)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::{
+        decode_alter_partition_reassignments, decode_api_versions, decode_describe_client_quotas,
+        decode_describe_configs, decode_describe_log_dirs, decode_incremental_alter_configs,
+        decode_leave_group, encode_alter_partition_reassignments, encode_api_versions,
+        encode_describe_client_quotas, encode_describe_configs, encode_describe_log_dirs,
+        encode_incremental_alter_configs, encode_leave_group, ClientQuota, Feature, ReplicaSize,
+        RESOURCE_BROKER_LOGGER,
+    };
+
+    #[test]
+    fn test_encode_api_versions() {
+        assert_eq!(
+            encode_api_versions(3),
+            [
+                &[0, 0, 0, 15][..],
+                &[0, 18, 0, 0, 0, 0, 0, 3],
+                &[0, 5, b'k', b'f', b'c', b'l', b'i'],
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn test_decode_api_versions() {
+        let response = [
+            &[0, 0, 0, 1, 0, 0, 0, 0, 0, 2][..],
+            &[0, 13, 0, 0, 0, 2],
+            &[0, 44, 0, 0, 0, 1],
+        ]
+        .concat();
+        let (error_code, versions) = decode_api_versions(&response).unwrap();
+        assert_eq!(error_code, 0);
+        assert_eq!(versions, BTreeMap::from([(13, (0, 2)), (44, (0, 1))]));
+        assert!(decode_api_versions(&response[..13]).is_err());
+
+        assert!(Feature::INCREMENTAL_ALTER_CONFIGS.supported_by(&versions));
+        assert!(!Feature::STATIC_MEMBER_REMOVAL.supported_by(&versions));
+        assert!(!Feature::CLIENT_QUOTAS.supported_by(&versions));
+    }
+
+    #[test]
+    fn test_encode_describe_configs() {
+        let request = encode_describe_configs(4, RESOURCE_BROKER_LOGGER, "1");
+        assert_eq!(
+            request,
+            [
+                &[0, 0, 0, 28][..],
+                &[0, 32, 0, 1, 0, 0, 0, 4],
+                &[0, 5, b'k', b'f', b'c', b'l', b'i'],
+                // One resource of type 8, the broker loggers, named after the broker id
+                &[0, 0, 0, 1, 8, 0, 1, b'1'],
+                &[0xff, 0xff, 0xff, 0xff, 0],
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn test_decode_describe_configs() {
+        let response = [
+            &[0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0, 1][..],
+            &[0, 0, 0xff, 0xff, 8, 0, 1, b'1'],
+            &[0, 0, 0, 1],
+            &[0, 4, b'r', b'o', b'o', b't', 0, 4, b'I', b'N', b'F', b'O'],
+            &[0, 5, 0, 0, 0, 0, 0],
+        ]
+        .concat();
+        assert_eq!(
+            decode_describe_configs(&response).unwrap(),
+            ((0, None), vec![("root".to_string(), "INFO".to_string())])
+        );
+        assert!(decode_describe_configs(&response[..20]).is_err());
+    }
+
+    #[test]
+    fn test_encode_incremental_alter_configs() {
+        let levels = [("kafka.server".to_string(), "DEBUG".to_string())];
+        let request = encode_incremental_alter_configs(5, RESOURCE_BROKER_LOGGER, "1", &levels);
+        assert_eq!(
+            request,
+            [
+                &[0, 0, 0, 50][..],
+                &[0, 44, 0, 0, 0, 0, 0, 5],
+                &[0, 5, b'k', b'f', b'c', b'l', b'i'],
+                &[0, 0, 0, 1, 8, 0, 1, b'1'],
+                &[0, 0, 0, 1, 0, 12],
+                b"kafka.server",
+                &[0, 0, 5, b'D', b'E', b'B', b'U', b'G'],
+                &[0],
+            ]
+            .concat()
+        );
+        let response = [
+            &[0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 1][..],
+            &[0, 42, 0, 3, b'b', b'a', b'd', 8, 0, 1, b'1'],
+        ]
+        .concat();
+        assert_eq!(
+            decode_incremental_alter_configs(&response).unwrap(),
+            (42, Some("bad".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_encode_leave_group() {
+        let request = encode_leave_group(7, "g", &["i-1"]);
+        assert_eq!(
+            request,
+            [
+                &[0, 0, 0, 29][..],
+                &[0, 13, 0, 3, 0, 0, 0, 7],
+                &[0, 5, b'k', b'f', b'c', b'l', b'i'],
+                &[0, 1, b'g'],
+                &[0, 0, 0, 1, 0, 0, 0, 3, b'i', b'-', b'1'],
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn test_decode_leave_group() {
+        let response = [
+            &[0, 0, 0, 7, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2][..],
+            &[0, 0, 0, 3, b'i', b'-', b'1', 0, 0],
+            &[0, 0, 0, 3, b'i', b'-', b'2', 0, 25],
+        ]
+        .concat();
+        assert_eq!(
+            decode_leave_group(&response).unwrap(),
+            (0, vec![("i-1".to_string(), 0), ("i-2".to_string(), 25)])
+        );
+        assert!(decode_leave_group(&response[..12]).is_err());
+        // A member count the response does not hold is an error, not an allocation
+        assert!(
+            decode_leave_group(&[0, 0, 0, 7, 0, 0, 0, 0, 0, 0, 0x7f, 0xff, 0xff, 0xff]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_encode_alter_partition_reassignments() {
+        assert_eq!(
+            encode_alter_partition_reassignments(2, "t", &[(0, vec![1, 2])]),
+            [
+                &[0, 0, 0, 40][..],
+                &[0, 45, 0, 0, 0, 0, 0, 2, 0, 5, b'k', b'f', b'c', b'l', b'i', 0],
+                &[0, 0, 0x27, 0x10],
+                &[2, 2, b't', 2],
+                &[0, 0, 0, 0, 3, 0, 0, 0, 1, 0, 0, 0, 2, 0],
+                &[0, 0],
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn test_decode_alter_partition_reassignments() {
+        let response = [
+            &[0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0][..],
+            &[2, 2, b't', 3],
+            &[0, 0, 0, 0, 0, 0, 0, 0],
+            &[0, 0, 0, 1, 0, 39, 4, b'b', b'a', b'd', 0],
+            &[0, 0],
+        ]
+        .concat();
+        assert_eq!(
+            decode_alter_partition_reassignments(&response).unwrap(),
+            (
+                (0, None),
+                vec![(0, 0, None), (1, 39, Some("bad".to_string()))]
+            )
+        );
+    }
+
+    #[test]
+    fn test_encode_describe_log_dirs() {
+        let header = [
+            &[0, 35, 0, 1, 0, 0, 0, 3][..],
+            &[0, 5, b'k', b'f', b'c', b'l', b'i'],
+        ]
+        .concat();
+        assert_eq!(
+            encode_describe_log_dirs(3, None),
+            [&[0, 0, 0, 19][..], &header, &[0xff, 0xff, 0xff, 0xff]].concat()
+        );
+        assert_eq!(
+            encode_describe_log_dirs(3, Some(&[("t", vec![0, 1])])),
+            [
+                &[0, 0, 0, 34][..],
+                &header,
+                &[0, 0, 0, 1, 0, 1, b't'],
+                &[0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 1],
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn test_decode_describe_log_dirs() {
+        let partition = |partition: u8, size: u8, future: u8| {
+            [
+                &[0, 0, 0, partition][..],
+                &[0, 0, 0, 0, 0, 0, 0, size],
+                &[0; 8],
+                &[future],
+            ]
+            .concat()
+        };
+        let response = [
+            &[0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 2][..],
+            // A healthy log dir with a current and a future replica
+            &[0, 0, 0, 2, b'/', b'a', 0, 0, 0, 1, 0, 1, b't', 0, 0, 0, 2],
+            &partition(0, 100, 0),
+            &partition(1, 50, 1),
+            // An offline log dir
+            &[0, 57, 0, 2, b'/', b'b', 0, 0, 0, 1, 0, 1, b't', 0, 0, 0, 1],
+            &partition(2, 10, 0),
+        ]
+        .concat();
+        assert_eq!(
+            decode_describe_log_dirs(4, &response).unwrap(),
+            vec![ReplicaSize {
+                broker: 4,
+                log_dir: "/a".to_string(),
+                topic: "t".to_string(),
+                partition: 0,
+                size: 100,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_encode_describe_client_quotas() {
+        assert_eq!(
+            encode_describe_client_quotas(2),
+            [
+                &[0, 0, 0, 20, 0, 48, 0, 0, 0, 0, 0, 2][..],
+                &[0, 5, b'k', b'f', b'c', b'l', b'i'],
+                &[0, 0, 0, 0, 0],
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn test_decode_describe_client_quotas() {
+        let response = [
+            &[0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0, 0, 0, 2][..],
+            // client-id=etl with a consumer byte rate
+            &[0, 0, 0, 1, 0, 9],
+            b"client-id",
+            &[0, 3, b'e', b't', b'l', 0, 0, 0, 1, 0, 18],
+            b"consumer_byte_rate",
+            &1_048_576f64.to_be_bytes(),
+            // The default user
+            &[
+                0, 0, 0, 1, 0, 4, b'u', b's', b'e', b'r', 0xff, 0xff, 0, 0, 0, 0,
+            ],
+        ]
+        .concat();
+        let ((error_code, _), quotas) = decode_describe_client_quotas(&response).unwrap();
+        assert_eq!(error_code, 0);
+        assert_eq!(
+            quotas,
+            vec![
+                ClientQuota {
+                    entity: vec![("client-id".to_string(), Some("etl".to_string()))],
+                    values: vec![("consumer_byte_rate".to_string(), 1_048_576.0)],
+                },
+                ClientQuota {
+                    entity: vec![("user".to_string(), None)],
+                    values: vec![],
+                },
+            ]
+        );
+        assert_eq!(quotas[0].client_id(), Some("etl"));
+        assert_eq!(quotas[1].client_id(), None);
+        assert_eq!(quotas[1].to_string(), "user=<default>");
+    }
+}
@@ -0,0 +1,178 @@
+use std::error::Error;
+
+use rdkafka::error::RDKafkaErrorCode;
+
+use crate::client;
+
+/// A short suggestion of what to check, for the failures people run into most. The error and
+/// its sources are searched for a librdkafka error code, or for its message where only the
+/// message survived, as with admin results.
+pub fn hint(error: &(dyn Error + 'static)) -> Option<String> {
+    hint_for(error, &client::principal())
+}
+
+fn hint_for(error: &(dyn Error + 'static), principal: &str) -> Option<String> {
+    let mut current = Some(error);
+    while let Some(error) = current {
+        let code = error
+            .downcast_ref::<rdkafka::error::KafkaError>()
+            .and_then(|er| er.rdkafka_error_code())
+            .or_else(|| message_code(&error.to_string()));
+        if let Some(hint) = code.and_then(|code| code_hint(code, principal)) {
+            return Some(hint);
+        }
+        current = error.source();
+    }
+    None
+}
+
+/// The error code of a librdkafka error message such as "Broker: Not coordinator".
+fn message_code(message: &str) -> Option<RDKafkaErrorCode> {
+    let message = message.to_lowercase();
+    [
+        (
+            "topic authorization failed",
+            RDKafkaErrorCode::TopicAuthorizationFailed,
+        ),
+        (
+            "group authorization failed",
+            RDKafkaErrorCode::GroupAuthorizationFailed,
+        ),
+        (
+            "cluster authorization failed",
+            RDKafkaErrorCode::ClusterAuthorizationFailed,
+        ),
+        (
+            "sasl authentication failed",
+            RDKafkaErrorCode::SaslAuthenticationFailed,
+        ),
+        ("ssl handshake failed", RDKafkaErrorCode::SSL),
+        ("certificate verify failed", RDKafkaErrorCode::SSL),
+        ("not coordinator", RDKafkaErrorCode::NotCoordinator),
+        (
+            "coordinator not available",
+            RDKafkaErrorCode::CoordinatorNotAvailable,
+        ),
+        (
+            "coordinator load in progress",
+            RDKafkaErrorCode::CoordinatorLoadInProgress,
+        ),
+        ("policy violation", RDKafkaErrorCode::PolicyViolation),
+        (
+            "all broker connections are down",
+            RDKafkaErrorCode::AllBrokersDown,
+        ),
+        (
+            "connection refused",
+            RDKafkaErrorCode::BrokerTransportFailure,
+        ),
+    ]
+    .into_iter()
+    .find(|(text, _)| message.contains(text))
+    .map(|(_, code)| code)
+}
+
+fn code_hint(code: RDKafkaErrorCode, principal: &str) -> Option<String> {
+    let hint = match code {
+        RDKafkaErrorCode::TopicAuthorizationFailed => format!(
+            "Check that the ACLs of {} allow this on the topic; `kfcli acl whoami` shows what \
+             they allow",
+            principal
+        ),
+        RDKafkaErrorCode::GroupAuthorizationFailed => format!(
+            "Check that the ACLs of {} allow Read on the consumer group; `kfcli acl whoami` \
+             shows what they allow",
+            principal
+        ),
+        RDKafkaErrorCode::ClusterAuthorizationFailed => format!(
+            "Check that the ACLs of {} allow this on the cluster resource, such as Describe or \
+             Alter",
+            principal
+        ),
+        RDKafkaErrorCode::TransactionalIdAuthorizationFailed => format!(
+            "Check that the ACLs of {} allow Write on the transactional id",
+            principal
+        ),
+        RDKafkaErrorCode::SaslAuthenticationFailed | RDKafkaErrorCode::Authentication => {
+            "The brokers rejected the credentials; check the sasl or oauth settings of the \
+             environment in the config file"
+                .to_string()
+        }
+        RDKafkaErrorCode::SSL => "The TLS handshake failed; check that ca_location holds the CA \
+             of the brokers and that the brokers expect TLS on this port"
+            .to_string(),
+        RDKafkaErrorCode::NotCoordinator
+        | RDKafkaErrorCode::CoordinatorNotAvailable
+        | RDKafkaErrorCode::CoordinatorLoadInProgress
+        | RDKafkaErrorCode::WaitingForCoordinator => "The group coordinator moved or is still \
+             loading, as happens while brokers restart; retry in a few seconds"
+            .to_string(),
+        RDKafkaErrorCode::PolicyViolation => "A create or alter policy of the brokers rejected \
+             the request; ask the cluster admins which partition counts, replication factors \
+             and configs it allows"
+            .to_string(),
+        RDKafkaErrorCode::AllBrokersDown
+        | RDKafkaErrorCode::BrokerTransportFailure
+        | RDKafkaErrorCode::Resolve => "No broker could be reached; check the brokers of the \
+             environment and that this host can connect to them and to their advertised \
+             listeners"
+            .to_string(),
+        RDKafkaErrorCode::UnknownTopicOrPartition | RDKafkaErrorCode::UnknownTopic => format!(
+            "The topic does not exist, or {} is not allowed to describe it",
+            principal
+        ),
+        RDKafkaErrorCode::MessageSizeTooLarge | RDKafkaErrorCode::InvalidMessageSize => {
+            "The record is larger than max.message.bytes of the topic or message.max.bytes of \
+             the brokers"
+                .to_string()
+        }
+        RDKafkaErrorCode::InvalidReplicationFactor => {
+            "The replication factor is higher than the number of brokers".to_string()
+        }
+        RDKafkaErrorCode::NonEmptyGroup => {
+            "The group still has members; stop its consumers first".to_string()
+        }
+        RDKafkaErrorCode::UnsupportedVersion => {
+            "The brokers are too old for this request".to_string()
+        }
+        RDKafkaErrorCode::RequestTimedOut
+        | RDKafkaErrorCode::OperationTimedOut
+        | RDKafkaErrorCode::MessageTimedOut => "The brokers did not answer in time; they may be \
+             overloaded, or a partition may have no leader"
+            .to_string(),
+        _ => return None,
+    };
+    Some(hint)
+}
+
+#[cfg(test)]
+mod test {
+    use rdkafka::error::{KafkaError as RdKafkaError, RDKafkaErrorCode};
+
+    use super::hint_for;
+    use crate::kafka::KafkaError;
+
+    #[test]
+    fn test_hint() {
+        let denied = KafkaError::MetadataFetch(
+            "Error while fetching topic metadata".to_string(),
+            RdKafkaError::MetadataFetch(RDKafkaErrorCode::TopicAuthorizationFailed),
+        );
+        let hint = hint_for(&denied, "User:billing").unwrap();
+        assert!(
+            hint.starts_with("Check that the ACLs of User:billing"),
+            "{}",
+            hint
+        );
+
+        let moved = KafkaError::Admin("Broker: Not coordinator".to_string());
+        assert!(hint_for(&moved, "User:billing")
+            .unwrap()
+            .contains("coordinator moved"));
+        let tls = KafkaError::Generic("ssl://b1:9093: SSL handshake failed".to_string());
+        assert!(hint_for(&tls, "User:billing").unwrap().contains("TLS"));
+
+        let other = KafkaError::Generic("Invalid filter".to_string());
+        assert_eq!(hint_for(&other, "User:billing"), None);
+    }
+}
@@ -0,0 +1,145 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+};
+
+use chrono::Utc;
+use prettytable::{row, Table};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{config_path, ConfigError},
+    output,
+};
+
+const AUDIT_FILE: &str = "audit.log";
+
+/// One command that changed a cluster. The audit log is a JSON Lines file next to the config,
+/// appended to and never rewritten.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    /// Epoch milliseconds
+    pub time: i64,
+    pub environment: String,
+    /// The project file that chose the environment, when it was not the active one or `-e`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment_source: Option<String>,
+    pub arguments: Vec<String>,
+    /// `ok`, or the error the command failed with
+    pub result: String,
+}
+
+impl AuditEntry {
+    pub fn new(
+        environment: &str,
+        environment_source: Option<String>,
+        arguments: Vec<String>,
+        result: Result<(), String>,
+    ) -> Self {
+        AuditEntry {
+            time: Utc::now().timestamp_millis(),
+            environment: environment.to_string(),
+            environment_source,
+            arguments,
+            result: match result {
+                Ok(()) => "ok".to_string(),
+                Err(er) => format!("error: {}", er),
+            },
+        }
+    }
+}
+
+/// Appends an entry to the audit log, creating the log on first use.
+pub fn record(entry: &AuditEntry) -> Result<(), ConfigError> {
+    let path = config_path(AUDIT_FILE)?;
+    let write_error = |er| ConfigError::ConfigWrite(format!("Failed to write {:?}", path), er);
+    let line = serde_json::to_string(entry).expect("audit entries serialize");
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| writeln!(file, "{}", line))
+        .map_err(write_error)
+}
+
+/// Reads the entries of an audit log, skipping lines that are not entries.
+fn parse_entries(content: &str) -> Vec<AuditEntry> {
+    content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// Prints the last `limit` entries of the audit log, oldest first.
+pub fn show_audit_log(limit: usize) -> Result<(), ConfigError> {
+    let path = config_path(AUDIT_FILE)?;
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(er) if er.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(er) => {
+            return Err(ConfigError::ConfigRead(
+                format!("Failed to read {:?}", path),
+                er,
+            ))
+        }
+    };
+    let entries = parse_entries(&content);
+    if entries.is_empty() {
+        println!("No commands have been audited yet");
+        return Ok(());
+    }
+
+    let mut table = Table::new();
+    table.add_row(row!["Time", "Environment", "Command", "Result"]);
+    for entry in &entries[entries.len().saturating_sub(limit)..] {
+        let command = format!("kfcli {}", entry.arguments.join(" "));
+        let environment = match &entry.environment_source {
+            Some(source) => format!("{} (set by {})", entry.environment, source),
+            None => entry.environment.clone(),
+        };
+        if entry.result == "ok" {
+            table.add_row(row![
+                output::timestamp(entry.time),
+                environment,
+                command,
+                entry.result
+            ]);
+        } else {
+            table.add_row(row![
+                output::timestamp(entry.time),
+                environment,
+                command,
+                Fr->entry.result
+            ]);
+        }
+    }
+    output::print_table(&table);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_entries, AuditEntry};
+
+    #[test]
+    fn test_audit_entries_round_trip() {
+        let entry = AuditEntry::new(
+            "prod",
+            Some("/work/orders/.kfcli.toml".to_string()),
+            vec!["admin".to_string(), "create-topic".to_string()],
+            Err("Topic already exists".to_string()),
+        );
+        assert_eq!(entry.result, "error: Topic already exists");
+        let older: AuditEntry = serde_json::from_str(
+            r#"{"time": 1, "environment": "dev", "arguments": [], "result": "ok"}"#,
+        )
+        .unwrap();
+        assert_eq!(older.environment_source, None);
+        let content = format!(
+            "{}\nnot an entry\n{}\n",
+            serde_json::to_string(&entry).unwrap(),
+            serde_json::to_string(&entry).unwrap()
+        );
+        assert_eq!(parse_entries(&content), vec![entry.clone(), entry]);
+    }
+}
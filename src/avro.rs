@@ -0,0 +1,91 @@
+use std::{fs, io::Cursor, path::Path};
+
+use apache_avro::{reader::datum::GenericDatumReader, Schema};
+use serde_json::Value;
+
+use crate::kafka::{KafkaError, PayloadDecoder};
+
+/// Decodes raw Avro payloads, written without a schema id prefix, against a local schema.
+pub struct AvroDecoder {
+    schema: Schema,
+    /// Named types the schema refers to, as listed in Schema Registry references
+    references: Vec<Schema>,
+}
+
+impl AvroDecoder {
+    pub fn load(schema: &Path) -> Result<Self, KafkaError> {
+        let text = fs::read_to_string(schema).map_err(|er| {
+            KafkaError::Io(format!("Failed to read Avro schema: {:?}", schema), er)
+        })?;
+        Self::from_str(&text)
+    }
+
+    pub fn from_str(schema: &str) -> Result<Self, KafkaError> {
+        Self::with_references(schema, &[])
+    }
+
+    pub fn with_references(schema: &str, references: &[String]) -> Result<Self, KafkaError> {
+        let (schema, references) = Schema::parse_str_with_list(schema, references)
+            .map_err(|er| KafkaError::Generic(format!("Invalid Avro schema: {}", er)))?;
+        Ok(AvroDecoder { schema, references })
+    }
+}
+
+impl PayloadDecoder for AvroDecoder {
+    fn to_json(&self, payload: &[u8]) -> Result<Value, String> {
+        let mut reader = Cursor::new(payload);
+        let schemata = std::iter::once(&self.schema)
+            .chain(&self.references)
+            .collect();
+        let value = GenericDatumReader::builder(&self.schema)
+            .writer_schemata(schemata)
+            .and_then(|builder| builder.build())
+            .and_then(|datum_reader| datum_reader.read_value(&mut reader))
+            .map_err(|er| format!("Invalid Avro payload: {}", er))?;
+        if reader.position() != payload.len() as u64 {
+            return Err(format!(
+                "Invalid Avro payload: {} trailing bytes",
+                payload.len() as u64 - reader.position()
+            ));
+        }
+        Value::try_from(value).map_err(|er| er.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::AvroDecoder;
+    use crate::kafka::PayloadDecoder;
+
+    const ORDER_SCHEMA: &str = r#"{
+        "type": "record",
+        "name": "Order",
+        "fields": [
+            {"name": "id", "type": "string"},
+            {"name": "amount", "type": "int"},
+            {"name": "note", "type": ["null", "string"]}
+        ]
+    }"#;
+
+    #[test]
+    fn test_avro_to_json() {
+        let decoder = AvroDecoder::from_str(ORDER_SCHEMA).unwrap();
+        // id = "o-1", amount = 75, note = null
+        let payload = [0x06, b'o', b'-', b'1', 0x96, 0x01, 0x00];
+        assert_eq!(
+            decoder.to_json(&payload).unwrap(),
+            json!({"id": "o-1", "amount": 75, "note": null})
+        );
+        assert!(decoder.to_json(&payload[..3]).is_err());
+        assert!(decoder
+            .to_json(&[0x06, b'o', b'-', b'1', 0x96, 0x01, 0x00, 0x00])
+            .is_err());
+    }
+
+    #[test]
+    fn test_avro_invalid_schema() {
+        assert!(AvroDecoder::from_str(r#"{"type": "record"}"#).is_err());
+    }
+}
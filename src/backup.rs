@@ -0,0 +1,220 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::admin::AclBinding;
+
+/// Topics and ACLs of a cluster, written by `cluster backup` as one JSON document and recreated
+/// by `cluster restore`. Internal topics are left out.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ClusterBackup {
+    /// Epoch milliseconds
+    pub created: i64,
+    pub topics: Vec<TopicBackup>,
+    #[serde(default)]
+    pub acls: Vec<AclBinding>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TopicBackup {
+    pub name: String,
+    pub partitions: i32,
+    pub replication_factor: i32,
+    /// Configs set on the topic itself; defaults and sensitive values are not backed up
+    #[serde(default)]
+    pub configs: BTreeMap<String, String>,
+}
+
+/// One change `cluster restore` makes to bring a cluster in line with a backup.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RestoreStep<'a> {
+    CreateTopic(&'a TopicBackup),
+    AddPartitions {
+        topic: &'a str,
+        from: i32,
+        to: i32,
+    },
+    /// Configs that are missing on the topic or set to another value
+    SetConfigs {
+        topic: &'a str,
+        configs: Vec<(String, String)>,
+    },
+    CreateAcl(&'a AclBinding),
+    /// A difference restore cannot fix, with the reason
+    Skip {
+        topic: &'a str,
+        reason: String,
+    },
+}
+
+/// Compares `backup` with the `current` state of the target cluster. Topics, configs and ACLs
+/// the target has on top of the backup are left alone.
+pub fn plan_restore<'a>(
+    backup: &'a ClusterBackup,
+    current: &ClusterBackup,
+) -> Vec<RestoreStep<'a>> {
+    let existing: BTreeMap<&str, &TopicBackup> = current
+        .topics
+        .iter()
+        .map(|topic| (topic.name.as_str(), topic))
+        .collect();
+    let mut steps = vec![];
+    for topic in &backup.topics {
+        let Some(target) = existing.get(topic.name.as_str()) else {
+            steps.push(RestoreStep::CreateTopic(topic));
+            continue;
+        };
+        if topic.partitions > target.partitions {
+            steps.push(RestoreStep::AddPartitions {
+                topic: &topic.name,
+                from: target.partitions,
+                to: topic.partitions,
+            });
+        } else if topic.partitions < target.partitions {
+            steps.push(RestoreStep::Skip {
+                topic: &topic.name,
+                reason: format!(
+                    "has {} partitions, more than the {} of the backup",
+                    target.partitions, topic.partitions
+                ),
+            });
+        }
+        if topic.replication_factor != target.replication_factor {
+            steps.push(RestoreStep::Skip {
+                topic: &topic.name,
+                reason: format!(
+                    "replication factor is {} instead of {}, see admin set-replication",
+                    target.replication_factor, topic.replication_factor
+                ),
+            });
+        }
+        let configs: Vec<(String, String)> = topic
+            .configs
+            .iter()
+            .filter(|(name, value)| target.configs.get(*name) != Some(value))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        if !configs.is_empty() {
+            steps.push(RestoreStep::SetConfigs {
+                topic: &topic.name,
+                configs,
+            });
+        }
+    }
+    let acls: BTreeSet<&AclBinding> = current.acls.iter().collect();
+    steps.extend(
+        backup
+            .acls
+            .iter()
+            .filter(|acl| !acls.contains(acl))
+            .map(RestoreStep::CreateAcl),
+    );
+    steps
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::{plan_restore, ClusterBackup, RestoreStep, TopicBackup};
+    use crate::admin::{AclBinding, AclOperation, AclPattern, AclPermission, AclResource};
+
+    fn topic(name: &str, partitions: i32, configs: &[(&str, &str)]) -> TopicBackup {
+        TopicBackup {
+            name: name.to_string(),
+            partitions,
+            replication_factor: 3,
+            configs: configs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<BTreeMap<_, _>>(),
+        }
+    }
+
+    fn acl(principal: &str) -> AclBinding {
+        AclBinding {
+            resource_type: AclResource::Topic,
+            resource_name: "orders".to_string(),
+            pattern_type: AclPattern::Literal,
+            principal: principal.to_string(),
+            host: "*".to_string(),
+            operation: AclOperation::Read,
+            permission: AclPermission::Allow,
+        }
+    }
+
+    #[test]
+    fn test_plan_restore() {
+        let backup = ClusterBackup {
+            created: 0,
+            topics: vec![
+                topic("orders", 6, &[("retention.ms", "86400000")]),
+                topic("payments", 3, &[("cleanup.policy", "compact")]),
+                topic("audit", 2, &[]),
+            ],
+            acls: vec![acl("User:billing"), acl("User:shipping")],
+        };
+        let mut fewer_replicas = topic("audit", 4, &[]);
+        fewer_replicas.replication_factor = 2;
+        let current = ClusterBackup {
+            created: 0,
+            topics: vec![
+                topic("orders", 3, &[("retention.ms", "3600000")]),
+                fewer_replicas,
+                topic("legacy", 1, &[]),
+            ],
+            acls: vec![acl("User:billing"), acl("User:legacy")],
+        };
+
+        let steps = plan_restore(&backup, &current);
+        assert_eq!(
+            steps,
+            vec![
+                RestoreStep::AddPartitions {
+                    topic: "orders",
+                    from: 3,
+                    to: 6
+                },
+                RestoreStep::SetConfigs {
+                    topic: "orders",
+                    configs: vec![("retention.ms".to_string(), "86400000".to_string())],
+                },
+                RestoreStep::CreateTopic(&backup.topics[1]),
+                RestoreStep::Skip {
+                    topic: "audit",
+                    reason: "has 4 partitions, more than the 2 of the backup".to_string(),
+                },
+                RestoreStep::Skip {
+                    topic: "audit",
+                    reason: "replication factor is 2 instead of 3, see admin set-replication"
+                        .to_string(),
+                },
+                RestoreStep::CreateAcl(&backup.acls[1]),
+            ]
+        );
+        assert!(plan_restore(&backup, &backup).is_empty());
+    }
+
+    #[test]
+    fn test_read_backup_defaults() {
+        let backup: ClusterBackup = serde_json::from_str(
+            r#"{
+                "created": 1714557600000,
+                "topics": [{"name": "orders", "partitions": 6, "replication_factor": 3}],
+                "acls": [{
+                    "resource_type": "topic",
+                    "resource_name": "orders",
+                    "principal": "User:billing",
+                    "operation": "read"
+                }]
+            }"#,
+        )
+        .unwrap();
+        assert!(backup.topics[0].configs.is_empty());
+        assert_eq!(backup.acls, vec![acl("User:billing")]);
+        assert_eq!(
+            backup.acls[0].to_string(),
+            "allow User:billing read on topic orders"
+        );
+    }
+}
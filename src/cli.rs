@@ -1,10 +1,16 @@
 use std::{
+    collections::{BTreeMap, BTreeSet},
     fs::{self, File},
     io,
-    path::Path,
+    path::{Path, PathBuf},
+    time::Duration,
 };
 
-use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap::{
+    builder::FalseyValueParser, ArgGroup, Args, CommandFactory, Parser, Subcommand, ValueEnum,
+};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
 
 #[derive(Parser, Debug)]
 #[command(
@@ -15,6 +21,55 @@ use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
 pub struct Cli {
     #[command(subcommand)]
     pub command: Command,
+    /// Run a read-only command against every configured environment
+    #[arg(long, global = true)]
+    pub all_envs: bool,
+    /// Environment to use instead of the active one, or @group for every environment of a group
+    #[arg(
+        short,
+        long,
+        global = true,
+        env = "KFCLI_ENV",
+        conflicts_with = "all_envs"
+    )]
+    pub env: Option<String>,
+    /// Print long tables directly instead of through $PAGER
+    #[arg(long, global = true, env = "KFCLI_NO_PAGER", value_parser = FalseyValueParser::new())]
+    pub no_pager: bool,
+    /// Only show these table columns, e.g. topic,partitions,rf. Columns are the headers in lower
+    /// case with dashes, one of their words, or the initials of headers of several words
+    #[arg(long, global = true, value_delimiter = ',')]
+    pub columns: Vec<String>,
+    /// Sort table rows by this column, named like in --columns
+    #[arg(long, global = true)]
+    pub sort_by: Option<String>,
+    /// Sort in descending order
+    #[arg(long, global = true, requires = "sort_by")]
+    pub desc: bool,
+    /// Show counts, sizes and durations as 1.2M, 3.4 GiB or 7d. On by default on a terminal
+    #[arg(long, global = true, conflicts_with = "no_human")]
+    pub human: bool,
+    /// Show counts, sizes and durations as raw numbers
+    #[arg(long, global = true, env = "KFCLI_NO_HUMAN", value_parser = FalseyValueParser::new())]
+    pub no_human: bool,
+    /// How to show timestamps. Tail prints record timestamps only when this is given
+    #[arg(long, global = true, value_enum, env = "KFCLI_TIME_FORMAT")]
+    pub time_format: Option<TimeFormat>,
+    /// Only print results and errors, leaving out confirmations and progress
+    #[arg(
+        short,
+        long,
+        global = true,
+        env = "KFCLI_QUIET",
+        value_parser = FalseyValueParser::new()
+    )]
+    pub quiet: bool,
+    /// Log what kfcli does at this level, to stderr unless --log-file is given
+    #[arg(long, global = true, value_enum, env = "KFCLI_LOG_LEVEL")]
+    pub log_level: Option<LogLevel>,
+    /// Append the log to this file, at debug level unless --log-level is given
+    #[arg(long, global = true, env = "KFCLI_LOG_FILE")]
+    pub log_file: Option<PathBuf>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -30,14 +85,240 @@ pub enum Command {
     Brokers(BrokerCommandArgs),
     #[command(name = "consumer", about = "Query consumers")]
     Consumer(ConsumerCommandArgs),
+    #[command(name = "health", about = "Check the cluster for availability risks")]
+    Health(HealthArgs),
+    #[command(name = "probe", about = "Measure how fast the brokers respond")]
+    Probe(ProbeArgs),
+    #[command(name = "dlq", about = "Triage dead-letter topics")]
+    Dlq(DlqArgs),
+    #[command(name = "admin", about = "Change cluster settings")]
+    Admin(AdminArgs),
+    #[command(
+        name = "cluster",
+        about = "Back up and restore topics, configs and ACLs"
+    )]
+    Cluster(ClusterArgs),
+    #[command(name = "acl", about = "Manage ACLs")]
+    Acl(AclArgs),
+    #[command(name = "simulate", about = "Generate client load for testing")]
+    Simulate(SimulateArgs),
+    #[command(
+        name = "serve",
+        about = "Serve read-only cluster state as JSON over HTTP until Ctrl-C"
+    )]
+    Serve(ServeArgs),
     #[command(name = "completion", about = "Generate shell completions")]
     Completion(CompletionArgs),
+    #[command(name = "audit", about = "Show the commands that changed a cluster")]
+    Audit(AuditArgs),
+}
+
+impl Command {
+    /// Whether the command only reads from the cluster and finishes on its own, which makes it
+    /// safe to run against every environment in one go.
+    pub fn is_read_only(&self) -> bool {
+        match self {
+            Command::Topics(args) => match &args.command {
+                TopicCommand::Tail(tail) => tail.no_follow,
+                TopicCommand::Replay(_)
+                | TopicCommand::Dump(_)
+                | TopicCommand::Restore(_)
+                | TopicCommand::Seed(_)
+                | TopicCommand::Produce(_)
+                | TopicCommand::WatchMeta(_) => false,
+                _ => true,
+            },
+            Command::Brokers(_) | Command::Health(_) => true,
+            Command::Consumer(args) => match &args.command {
+                Some(ConsumerCommand::Members(members)) => !members.watch,
+                Some(ConsumerCommand::Record(_)) => false,
+                Some(ConsumerCommand::Orphans(orphans)) => !orphans.delete,
+                Some(ConsumerCommand::SetOffset(_))
+                | Some(ConsumerCommand::ResetOffsets(_))
+                | Some(ConsumerCommand::RemoveMember(_))
+                | Some(ConsumerCommand::Create(_)) => false,
+                _ => true,
+            },
+            Command::Dlq(args) => matches!(args.command, DlqCommand::Inspect(_)),
+            Command::Admin(args) => match &args.command {
+                AdminCommand::Loggers(loggers) => loggers.set.is_empty(),
+                AdminCommand::Plan(_) | AdminCommand::Quotas(_) => true,
+                AdminCommand::CreateTopic(_)
+                | AdminCommand::DeleteTopic(_)
+                | AdminCommand::SetReplication(_)
+                | AdminCommand::DeleteTopics(_) => false,
+            },
+            Command::Acl(args) => matches!(args.command, AclCommand::Whoami(_)),
+            Command::Cluster(_)
+            | Command::Serve(_)
+            | Command::Probe(_)
+            | Command::Simulate(_)
+            | Command::Config(_)
+            | Command::Completion(_)
+            | Command::Audit(_) => false,
+        }
+    }
+
+    /// Whether the command changes topics, configs or offsets and is recorded in the audit log.
+    pub fn is_audited(&self) -> bool {
+        match self {
+            Command::Topics(args) => match &args.command {
+                TopicCommand::Tail(tail) => tail.commit,
+                TopicCommand::Replay(_)
+                | TopicCommand::Restore(_)
+                | TopicCommand::Seed(_)
+                | TopicCommand::Produce(_) => true,
+                _ => false,
+            },
+            Command::Consumer(args) => match &args.command {
+                Some(ConsumerCommand::Orphans(orphans)) => orphans.delete,
+                Some(ConsumerCommand::SetOffset(_))
+                | Some(ConsumerCommand::ResetOffsets(_))
+                | Some(ConsumerCommand::RemoveMember(_))
+                | Some(ConsumerCommand::Create(_)) => true,
+                _ => false,
+            },
+            Command::Dlq(args) => matches!(args.command, DlqCommand::Replay(_)),
+            Command::Admin(args) => match &args.command {
+                AdminCommand::Loggers(loggers) => !loggers.set.is_empty(),
+                AdminCommand::Plan(_) | AdminCommand::Quotas(_) => false,
+                AdminCommand::CreateTopic(_)
+                | AdminCommand::DeleteTopic(_)
+                | AdminCommand::SetReplication(_)
+                | AdminCommand::DeleteTopics(_) => true,
+            },
+            Command::Cluster(args) => match &args.command {
+                ClusterCommand::Backup(_) => false,
+                ClusterCommand::Restore(restore) => !restore.dry_run,
+            },
+            Command::Acl(args) => match &args.command {
+                AclCommand::Apply(apply) => !apply.dry_run,
+                AclCommand::Whoami(_) => false,
+            },
+            Command::Probe(_) | Command::Simulate(_) => true,
+            _ => false,
+        }
+    }
+
+    /// The topic of a command that takes one but was run without it, to be picked.
+    pub fn missing_topic(&mut self) -> Option<&mut Option<String>> {
+        let topic = match self {
+            Command::Topics(args) => match &mut args.command {
+                TopicCommand::Details(details) => &mut details.topic,
+                TopicCommand::Tail(tail) => &mut tail.topic,
+                _ => return None,
+            },
+            Command::Admin(args) => match &mut args.command {
+                AdminCommand::DeleteTopic(delete) => &mut delete.topic,
+                AdminCommand::SetReplication(replication) => &mut replication.topic,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        topic.is_none().then_some(topic)
+    }
+
+    /// The arguments of `consumer` run without a subcommand, `--list` or `--consumer`, which
+    /// picks the group to show.
+    pub fn missing_group(&mut self) -> Option<&mut ConsumerCommandArgs> {
+        match self {
+            Command::Consumer(args)
+                if args.command.is_none() && !args.list && args.consumer.is_none() =>
+            {
+                Some(args)
+            }
+            _ => None,
+        }
+    }
 }
 
 #[derive(Args, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct ConfigArgs {
+    #[command(subcommand)]
+    pub command: Option<ConfigCommand>,
     #[arg(short, long)]
     pub activate: Option<String>,
+    /// List the configured environments, the active one marked with *
+    #[arg(short, long, conflicts_with = "activate")]
+    pub list: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    #[command(name = "add", about = "Add an environment without prompting")]
+    Add(AddEnvironmentArgs),
+    #[command(name = "current", about = "Show the active environment")]
+    Current(CurrentEnvironmentArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct CurrentEnvironmentArgs {
+    /// Print only the name, in the environment's color, for shell prompts
+    #[arg(short, long)]
+    pub short: bool,
+    /// Leave out the color of --short
+    #[arg(long, requires = "short")]
+    pub no_color: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct AddEnvironmentArgs {
+    /// Name of the environment, replacing an environment of the same name
+    #[arg(short, long)]
+    pub name: String,
+    #[arg(short, long)]
+    pub brokers: String,
+    /// Group to select the environment with -e @group
+    #[arg(short, long)]
+    pub group: Option<String>,
+    /// Fill in the connection settings of a managed Kafka
+    #[arg(long, value_enum)]
+    pub preset: Option<EnvironmentPreset>,
+    /// API key, the SASL username of the preset
+    #[arg(long, required_if_eq("preset", "confluent-cloud"))]
+    pub api_key: Option<String>,
+    /// API secret, the SASL password of the preset
+    #[arg(long, required_if_eq("preset", "confluent-cloud"))]
+    pub api_secret: Option<String>,
+    /// Color of the name printed by `config current --short`
+    #[arg(long, value_enum)]
+    pub color: Option<EnvironmentColor>,
+    /// group.id of kfcli's own consumers, such as the one tail uses. A new
+    /// kfcli-eph-<user>-<pid> for every run by default
+    #[arg(long)]
+    pub group_id: Option<String>,
+}
+
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EnvironmentColor {
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+}
+
+impl EnvironmentColor {
+    /// ANSI escape code of the foreground color.
+    pub fn ansi_code(&self) -> u8 {
+        match self {
+            EnvironmentColor::Red => 31,
+            EnvironmentColor::Green => 32,
+            EnvironmentColor::Yellow => 33,
+            EnvironmentColor::Blue => 34,
+            EnvironmentColor::Magenta => 35,
+            EnvironmentColor::Cyan => 36,
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum EnvironmentPreset {
+    /// SASL_SSL with PLAIN, authenticating with an API key
+    ConfluentCloud,
 }
 
 #[derive(Args, Debug)]
@@ -58,85 +339,1779 @@ pub enum TopicCommand {
     // Delete(TopicCommandArgs),
     #[command(name = "tail", about = "Tail a topic")]
     Tail(TailArgs),
+    #[command(
+        name = "skew",
+        about = "Compare message counts across the partitions of a topic"
+    )]
+    Skew(SkewArgs),
+    #[command(
+        name = "keys",
+        about = "Sample recent records and report the key distribution"
+    )]
+    Keys(KeysArgs),
+    #[command(name = "sizes", about = "Sample recent records and report their sizes")]
+    Sizes(SizesArgs),
+    #[command(
+        name = "size",
+        about = "Report the storage a topic takes on disk per partition"
+    )]
+    Size(SizeArgs),
+    #[command(
+        name = "retention-report",
+        about = "Compare configured retention with the oldest record and size of each topic"
+    )]
+    RetentionReport,
+    #[command(
+        name = "state",
+        about = "Show the latest value per key of a compacted topic"
+    )]
+    State(StateArgs),
+    #[command(name = "replay", about = "Copy records from one topic to another")]
+    Replay(ReplayArgs),
+    #[command(
+        name = "dump",
+        about = "Export the records of a topic to a snapshot file"
+    )]
+    Dump(DumpArgs),
+    #[command(
+        name = "restore",
+        about = "Produce the records of a snapshot file to a topic"
+    )]
+    Restore(RestoreArgs),
+    #[command(
+        name = "seed",
+        about = "Produce generated records from a JSON template with placeholders"
+    )]
+    Seed(SeedArgs),
+    #[command(
+        name = "window",
+        about = "Read the records produced between two times and stop"
+    )]
+    Window(WindowArgs),
+    #[command(
+        name = "produce",
+        about = "Produce a record, typed in the editor with --edit"
+    )]
+    Produce(ProduceArgs),
+    #[command(
+        name = "large-messages",
+        about = "Find records close to or over the largest message the topic accepts"
+    )]
+    LargeMessages(LargeMessagesArgs),
+    #[command(
+        name = "idle",
+        about = "List empty topics and topics without recent records"
+    )]
+    Idle(IdleArgs),
+    #[command(
+        name = "watch-meta",
+        about = "Print partition count, leader, ISR and config changes of a topic until Ctrl-C"
+    )]
+    WatchMeta(WatchMetaArgs),
+    #[command(
+        name = "watermarks",
+        about = "Show the low and high watermark of each partition"
+    )]
+    Watermarks(WatermarksArgs),
+    #[command(
+        name = "diff-configs",
+        about = "Show non-default configs that differ between topics"
+    )]
+    DiffConfigs(TopicDiffConfigsArgs),
+    #[command(
+        name = "validate",
+        about = "Count the records of a topic that do not decode with a schema"
+    )]
+    Validate(ValidateArgs),
+    #[command(
+        name = "transactions",
+        about = "Decode __transaction_state to show the state of each transactional id"
+    )]
+    Transactions(TransactionsArgs),
 }
 
 #[derive(Args, Debug)]
-pub struct TopicCommandArgs {
+#[command(group(ArgGroup::new("schemas").required(true).args(["schema", "schema_registry"])))]
+pub struct ValidateArgs {
+    /// Name of the topic to validate
     #[arg(short, long)]
     pub topic: String,
+    /// Avro schema (.avsc) of payloads written without the Confluent wire format
+    #[arg(long)]
+    pub schema: Option<PathBuf>,
+    /// Schema Registry URL to validate payloads against their registered schema
+    #[arg(long)]
+    pub schema_registry: Option<String>,
+    /// Number of recent records to validate
+    #[arg(short, long, default_value_t = 10000)]
+    pub sample: usize,
+    /// Validate the records produced at or after this time instead of a sample
+    #[arg(long, value_parser = parse_timestamp, conflicts_with = "sample")]
+    pub since: Option<i64>,
+    /// Stop at records produced at this time
+    #[arg(long, value_parser = parse_timestamp, requires = "since")]
+    pub until: Option<i64>,
+}
+
+#[derive(Args, Debug)]
+pub struct TransactionsArgs {
+    /// Show every logged state of this transactional id instead of the latest of each
+    #[arg(short, long)]
+    pub id: Option<String>,
+    /// Only show transactions that are open or not yet committed or aborted
+    #[arg(long)]
+    pub ongoing: bool,
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+    pub output: OutputFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct TopicCommandArgs {
+    /// Name of the topic, picked from a list when left out on a terminal
+    #[arg(short, long)]
+    pub topic: Option<String>,
+    /// Also show the configs set on the topic rather than inherited from the broker
+    #[arg(long)]
+    pub configs: bool,
+    /// Also show the partition with the most lag for every consuming group
+    #[arg(long)]
+    pub lag: bool,
+    /// Also show the principals that ACLs let read or write the topic
+    #[arg(long)]
+    pub acls: bool,
+    /// Also show the timestamps of the earliest and latest record of every partition, which
+    /// needs READ on the topic
+    #[arg(long)]
+    pub time_range: bool,
 }
 
 #[derive(Args, Debug)]
 pub struct TailArgs {
-    /// Name of the topic to tail
+    /// Name of the topic to tail, picked from a list when left out on a terminal
     #[arg(short, long)]
-    pub topic: String,
+    pub topic: Option<String>,
     #[arg(short, long)]
     /// Start the tail before the current offset
     pub before: Option<usize>,
     /// Apply the given filter to the tail
     #[arg(short, long)]
     pub filter: Option<String>,
+    /// Print at most this many records, e.g. 100/s or 600/m
+    #[arg(long, value_parser = parse_rate)]
+    pub max_rate: Option<f64>,
+    /// Print only this fraction of the records, e.g. 0.01
+    #[arg(long, value_parser = parse_fraction)]
+    pub sample: Option<f64>,
+    /// Start at records produced at or after this time
+    #[arg(long, value_parser = parse_timestamp)]
+    pub since: Option<i64>,
+    /// Start at the earliest offset of every partition
+    #[arg(long, conflicts_with_all = ["since", "before"])]
+    pub from_beginning: bool,
+    /// Compiled protobuf descriptor set (protoc --include_imports --descriptor_set_out)
+    #[arg(long, requires = "proto_message")]
+    pub proto_descriptor: Option<PathBuf>,
+    /// Fully qualified protobuf message of the payloads, e.g. com.acme.Order
+    #[arg(long, requires = "proto_descriptor")]
+    pub proto_message: Option<String>,
+    /// Avro schema (.avsc) of payloads written without the Confluent wire format
+    #[arg(long, conflicts_with = "proto_descriptor")]
+    pub avro_schema: Option<PathBuf>,
+    /// Schema Registry URL to decode Avro, Protobuf and JSON Schema payloads by their schema id
+    #[arg(long, conflicts_with_all = ["proto_descriptor", "avro_schema"])]
+    pub schema_registry: Option<String>,
+    /// Stop at the end offsets seen at startup. Starts at the beginning unless --since is given
+    #[arg(long)]
+    pub no_follow: bool,
+    /// Payload format. Auto reads payloads starting with `<` as XML and the rest as JSON
+    #[arg(long, value_enum, default_value_t = PayloadFormat::Auto)]
+    pub format: PayloadFormat,
+    /// Print only the latest record per key within each window of records
+    #[arg(long)]
+    pub dedupe_by_key: bool,
+    /// Number of records per deduplication window
+    #[arg(long, default_value_t = 1000, requires = "dedupe_by_key")]
+    pub window: usize,
+    /// Warn when offsets are skipped within a partition and list the gaps on exit
+    #[arg(long)]
+    pub detect_gaps: bool,
+    /// Mask these JSON fields before printing, e.g. data.customer.email,data.card.*
+    #[arg(long, value_delimiter = ',')]
+    pub redact: Vec<String>,
+    /// Print each JSON record on one line as dotted key=value pairs
+    #[arg(long)]
+    pub flatten: bool,
+    /// Jsonl prints each record as one uncolored JSON object with its topic, partition,
+    /// offset, timestamp, key, headers and value, for piping into jq
+    #[arg(short, long, value_enum, default_value_t = TailOutput::Pretty, conflicts_with_all = ["flatten", "highlight"])]
+    pub output: TailOutput,
+    /// Save the offsets read under this name and continue from them on the next run. The start
+    /// options only apply to a new session
+    #[arg(long)]
+    pub session: Option<String>,
+    /// Highlight the matches of this regex in the printed records, on top of the filtered field
+    #[arg(long, value_parser = parse_regex)]
+    pub highlight: Option<Regex>,
+    /// Keep a line with the records per second, shown, filtered out and read per partition at
+    /// the bottom of the terminal, refreshed every second
+    #[arg(long)]
+    pub stats: bool,
+    /// Read as the group_id of the environment: start at its committed offsets unless a start
+    /// option is given, and commit the offsets of the records read
+    #[arg(long, conflicts_with_all = ["no_follow", "session"])]
+    pub commit: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct SkewArgs {
+    /// Name of the topic to analyse
+    #[arg(short, long)]
+    pub topic: String,
+    /// Deviation from the mean, in percent, above which a partition is highlighted
+    #[arg(long, default_value_t = 20.0)]
+    pub threshold: f64,
+}
+
+#[derive(Args, Debug)]
+pub struct KeysArgs {
+    /// Name of the topic to sample
+    #[arg(short, long)]
+    pub topic: String,
+    /// Number of recent records to read
+    #[arg(short, long, default_value_t = 10000)]
+    pub sample: usize,
+    /// Number of hottest keys to show
+    #[arg(long, default_value_t = 10)]
+    pub top: usize,
+}
+
+#[derive(Args, Debug)]
+pub struct SizesArgs {
+    /// Name of the topic to sample
+    #[arg(short, long)]
+    pub topic: String,
+    /// Number of recent records to read
+    #[arg(short, long, default_value_t = 5000)]
+    pub sample: usize,
+}
+
+#[derive(Args, Debug)]
+pub struct SizeArgs {
+    /// Name of the topic to measure
+    #[arg(short, long, required_unless_present = "all")]
+    pub topic: Option<String>,
+    /// Rank all topics by their size on disk instead
+    #[arg(long, conflicts_with = "topic")]
+    pub all: bool,
+    /// Number of largest topics to show with --all
+    #[arg(long, default_value_t = 20)]
+    pub top: usize,
+}
+
+#[derive(Args, Debug)]
+pub struct StateArgs {
+    /// Name of the compacted topic
+    #[arg(short, long)]
+    pub topic: String,
+    /// Only show the value of this key
+    #[arg(short, long)]
+    pub key: Option<String>,
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+    pub output: OutputFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct ReplayArgs {
+    /// Topic to read the records from
+    #[arg(long)]
+    pub from: String,
+    /// Topic to produce the records to
+    #[arg(long)]
+    pub to: String,
+    /// Only replay records produced at or after this time (RFC 3339, epoch millis or a duration ago such as 1h)
+    #[arg(long, value_parser = parse_timestamp, conflicts_with = "from_offset")]
+    pub since: Option<i64>,
+    /// Start replaying every partition from this offset
+    #[arg(long)]
+    pub from_offset: Option<i64>,
+    /// Only replay JSON records matching this filter (same syntax as tail)
+    #[arg(long)]
+    pub transform_filter: Option<String>,
+    #[command(flatten)]
+    pub headers: ReplayHeaderArgs,
+    #[command(flatten)]
+    pub producer: ProducerArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct WindowArgs {
+    /// Name of the topic
+    #[arg(short, long)]
+    pub topic: String,
+    /// Start of the window, included (RFC 3339, epoch millis or a duration ago such as 2h)
+    #[arg(long, value_parser = parse_timestamp)]
+    pub from: i64,
+    /// End of the window, excluded
+    #[arg(long, value_parser = parse_timestamp)]
+    pub to: i64,
+    /// Write the records to this snapshot file instead of printing them
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct LargeMessagesArgs {
+    /// Name of the topic to scan
+    #[arg(short, long)]
+    pub topic: String,
+    /// Report records of at least this size (e.g. 900KB, 1MiB), 90% of the topic's
+    /// max.message.bytes by default
+    #[arg(long, value_parser = parse_byte_size)]
+    pub threshold: Option<u64>,
+    /// Number of recent records to scan
+    #[arg(short, long, default_value_t = 50000)]
+    pub sample: usize,
+    /// Scan every record produced since this time instead (RFC 3339, epoch millis or a
+    /// duration ago such as 2h)
+    #[arg(long, value_parser = parse_timestamp, conflicts_with = "sample")]
+    pub from: Option<i64>,
+    /// Number of records to list, largest first
+    #[arg(short, long, default_value_t = 20)]
+    pub limit: usize,
+}
+
+#[derive(Args, Debug)]
+pub struct IdleArgs {
+    /// Report topics whose newest record is older than this (e.g. 12h, 30d)
+    #[arg(long, default_value = "30d", value_parser = parse_duration)]
+    pub since: Duration,
+}
+
+#[derive(Args, Debug)]
+pub struct WatchMetaArgs {
+    /// Name of the topic to watch
+    #[arg(short, long)]
+    pub topic: String,
+    /// Polling interval (e.g. 500ms, 5s, 1m)
+    #[arg(short, long, default_value = "5s", value_parser = parse_duration)]
+    pub interval: Duration,
+}
+
+#[derive(Args, Debug)]
+pub struct ProduceArgs {
+    /// Name of the topic to produce the record to
+    #[arg(short, long)]
+    pub topic: String,
+    /// Key of the record
+    #[arg(short, long)]
+    pub key: Option<String>,
+    /// Value of the record, sent as it is
+    #[arg(short, long, required_unless_present = "edit")]
+    pub value: Option<String>,
+    /// Header as name=value, can be repeated
+    #[arg(long = "header", value_parser = parse_header)]
+    pub headers: Vec<(String, String)>,
+    /// Partition to produce to, instead of the one the partitioner picks
+    #[arg(short, long)]
+    pub partition: Option<i32>,
+    /// Write the key, headers and value as JSON in $EDITOR, starting from --key and --header
+    #[arg(long, conflicts_with = "value")]
+    pub edit: bool,
+    /// Number of records to produce. The placeholders of seed templates, such as {{seq}} and
+    /// {{now}}, in the key, headers and value are filled in for each record
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    pub repeat: u64,
+    /// Time to wait between records, such as 10ms
+    #[arg(long, value_parser = parse_duration)]
+    pub interval: Option<Duration>,
+    #[command(flatten)]
+    pub producer: ProducerArgs,
+}
+
+/// Producer settings shared by the commands that produce records. Unset ones keep the
+/// librdkafka defaults.
+#[derive(Args, Debug)]
+pub struct ProducerArgs {
+    /// Compression codec of the produced batches
+    #[arg(long, value_enum)]
+    pub compression: Option<Compression>,
+    /// Acknowledgements to wait for before a record counts as delivered
+    #[arg(long, value_enum)]
+    pub acks: Option<Acks>,
+}
+
+/// Headers of the records a replay produces. The headers of the source records are kept
+/// unless dropped, and `--header` adds to them or replaces the ones of the same name.
+#[derive(Args, Debug)]
+pub struct ReplayHeaderArgs {
+    /// Header as name=value to set on every replayed record, can be repeated
+    #[arg(long = "header", value_parser = parse_header)]
+    pub headers: Vec<(String, String)>,
+    /// Leave out the headers of the source records
+    #[arg(long)]
+    pub drop_source_headers: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct DumpArgs {
+    /// Name of the topic to export
+    #[arg(short, long)]
+    pub topic: String,
+    /// Snapshot file to write, one JSON record per line
+    #[arg(short, long)]
+    pub file: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct RestoreArgs {
+    /// Snapshot file created by `topics dump`
+    #[arg(short, long)]
+    pub file: PathBuf,
+    /// Name of the topic to produce the records to
+    #[arg(short, long)]
+    pub topic: String,
+    /// Produce each record to the partition it was dumped from
+    #[arg(long)]
+    pub keep_partitions: bool,
+    #[command(flatten)]
+    pub producer: ProducerArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct SeedArgs {
+    /// Name of the topic to produce the records to
+    #[arg(short, long)]
+    pub topic: String,
+    /// JSON file for the record values. Strings may use {{uuid}}, {{int <min> <max>}},
+    /// {{timestamp}} and {{choice <a> <b> ...}}
+    #[arg(long)]
+    pub template: PathBuf,
+    /// Template for the record keys, such as {{uuid}}. Records have no key by default
+    #[arg(short, long)]
+    pub key: Option<String>,
+    /// Number of records to produce
+    #[arg(short, long, default_value_t = 100)]
+    pub count: usize,
+    /// Header as name=value to set on every record, can be repeated
+    #[arg(long = "header", value_parser = parse_header)]
+    pub headers: Vec<(String, String)>,
+    #[command(flatten)]
+    pub producer: ProducerArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct WatermarksArgs {
+    /// Name of the topic
+    #[arg(short, long)]
+    pub topic: String,
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+    pub output: OutputFormat,
 }
 
 #[derive(Args, Debug)]
+pub struct TopicDiffConfigsArgs {
+    /// Topic to compare, given at least twice
+    #[arg(short, long = "topic", required = true)]
+    pub topics: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct ConsumerCommandArgs {
+    #[command(subcommand)]
+    pub command: Option<ConsumerCommand>,
     /// List all consumer groups
     #[arg(short, long)]
     pub list: bool,
+    /// Only list groups in this state
+    #[arg(long, value_enum, requires = "list")]
+    pub state: Option<GroupState>,
+    /// Only list groups consuming from or with offsets committed on this topic
+    #[arg(short, long, requires = "list")]
+    pub topic: Option<String>,
+    /// Show the total lag of each group in the list
+    #[arg(long, requires = "list")]
+    pub with_lag: bool,
+    /// Also list the groups of kfcli's own consumers, which are left out
+    #[arg(long, requires = "list")]
+    pub show_kfcli_groups: bool,
     /// Get details of a consumer group
     #[arg(short, long)]
     pub consumer: Option<String>,
     /// Include the lag to the consumer details
     #[arg(short, long)]
     pub pending: bool,
+    /// Number of lag measurements to take with --pending
+    #[arg(long, default_value_t = 1, requires = "pending")]
+    pub samples: usize,
+    /// Time between lag measurements (e.g. 10s, 1m)
+    #[arg(long, default_value = "10s", value_parser = parse_duration)]
+    pub interval: Duration,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConsumerCommand {
+    #[command(name = "members", about = "List the members of a consumer group")]
+    Members(MembersArgs),
+    #[command(name = "top", about = "List the consumer groups with the highest lag")]
+    Top(TopArgs),
+    #[command(
+        name = "set-offset",
+        about = "Commit an offset for a stopped consumer group"
+    )]
+    SetOffset(SetOffsetArgs),
+    #[command(
+        name = "reset-offsets",
+        about = "Move the offsets of a stopped consumer group to the earliest, latest or a time"
+    )]
+    ResetOffsets(ResetOffsetsArgs),
+    #[command(
+        name = "remove-member",
+        about = "Evict static members from a consumer group by group.instance.id"
+    )]
+    RemoveMember(RemoveMemberArgs),
+    #[command(
+        name = "lag",
+        about = "Export the lag of a consumer group per partition"
+    )]
+    Lag(LagArgs),
+    #[command(
+        name = "translate-offsets",
+        about = "Map the committed offsets of a group to another cluster by timestamp"
+    )]
+    TranslateOffsets(TranslateOffsetsArgs),
+    #[command(
+        name = "create",
+        about = "Create a consumer group by committing its initial offsets on a topic"
+    )]
+    Create(CreateGroupArgs),
+    #[command(
+        name = "orphans",
+        about = "List groups without members whose topics are gone or that stopped consuming"
+    )]
+    Orphans(OrphansArgs),
+    #[command(
+        name = "record",
+        about = "Append the lag of a consumer group to a CSV file at an interval"
+    )]
+    Record(RecordLagArgs),
+    #[command(
+        name = "chart",
+        about = "Chart the lag over time recorded in a CSV file"
+    )]
+    Chart(ChartLagArgs),
 }
 
 #[derive(Args, Debug)]
-pub struct BrokerCommandArgs {
+pub struct RecordLagArgs {
+    /// Consumer group to record the lag of
     #[arg(short, long)]
-    pub list: bool,
+    pub group: String,
+    /// Time between samples (e.g. 30s, 5m)
+    #[arg(short, long, default_value = "30s", value_parser = parse_duration)]
+    pub interval: Duration,
+    /// CSV file to append the lag per partition to, in the format of `consumer lag --output csv`
+    #[arg(short, long)]
+    pub file: PathBuf,
 }
 
-#[derive(ValueEnum, Debug, Clone)]
-pub enum Shell {
-    Bash,
-    Zsh,
+#[derive(Args, Debug)]
+pub struct ChartLagArgs {
+    /// CSV file written by `consumer record` or `consumer lag --output csv --file`
+    #[arg(short, long)]
+    pub file: PathBuf,
+    /// Only chart this consumer group
+    #[arg(short, long)]
+    pub group: Option<String>,
+    /// Width of the chart in characters
+    #[arg(short, long, default_value_t = 60)]
+    pub width: usize,
 }
 
 #[derive(Args, Debug)]
-pub struct CompletionArgs {
-    #[arg(value_enum)]
-    pub shell: Shell,
+pub struct OrphansArgs {
+    /// Report groups whose last consumed record is older than this (e.g. 12h, 30d)
+    #[arg(long, default_value = "30d", value_parser = parse_duration)]
+    pub older_than: Duration,
+    /// Delete the reported groups
+    #[arg(long)]
+    pub delete: bool,
+    /// Delete without asking for confirmation first
+    #[arg(short, long, requires = "delete")]
+    pub yes: bool,
 }
 
-pub fn generate_completion(shell: Shell) -> Result<(), io::Error> {
-    let mut cmd = Cli::command();
-    let dir = match shell {
-        Shell::Bash => ".bash_completion.d",
-        Shell::Zsh => ".zfunc",
-    };
+#[derive(Args, Debug)]
+pub struct CreateGroupArgs {
+    /// Consumer group to create, which must not exist yet
+    #[arg(short, long)]
+    pub group: String,
+    /// Topic whose partitions to commit offsets on
+    #[arg(short, long)]
+    pub topic: String,
+    /// Where the group starts: earliest, latest, or a time (RFC 3339, epoch millis or a
+    /// duration ago such as 1h)
+    #[arg(short, long, value_parser = parse_group_start)]
+    pub start: GroupStart,
+    /// Commit without showing the offsets for confirmation first
+    #[arg(short, long)]
+    pub yes: bool,
+}
 
-    // Create the directory if it doesn't exist
-    if !Path::new(dir).exists() {
-        fs::create_dir_all(dir)?;
-    }
+/// Initial position of a new consumer group.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GroupStart {
+    Earliest,
+    Latest,
+    /// Epoch milliseconds
+    Timestamp(i64),
+}
 
-    let file_path = match shell {
-        Shell::Bash => format!("{}/kfcli.bash", dir),
-        Shell::Zsh => format!("{}/_kfcli", dir),
-    };
+#[derive(Args, Debug)]
+pub struct TranslateOffsetsArgs {
+    /// Consumer group whose committed offsets to translate
+    #[arg(short, long)]
+    pub group: String,
+    /// Environment of the mirrored cluster the group fails over to
+    #[arg(long)]
+    pub target_env: String,
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+    pub output: OutputFormat,
+}
 
-    let mut file = File::create(file_path)?;
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("groups").required(true).args(["group", "all_groups"])))]
+pub struct LagArgs {
+    /// Consumer group to export the lag of
+    #[arg(short, long)]
+    pub group: Option<String>,
+    /// Export the lag of every consumer group
+    #[arg(long)]
+    pub all_groups: bool,
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+    pub output: OutputFormat,
+    /// Append the rows to this file instead of printing them, writing the header only to a new
+    /// file. Only for csv output
+    #[arg(short, long)]
+    pub file: Option<PathBuf>,
+}
 
-    match shell {
-        Shell::Bash => {
-            clap_complete::generate(clap_complete::shells::Bash, &mut cmd, "kfcli", &mut file);
-        }
-        Shell::Zsh => {
-            clap_complete::generate(clap_complete::shells::Zsh, &mut cmd, "kfcli", &mut file);
-        }
-    }
+#[derive(Args, Debug)]
+pub struct RemoveMemberArgs {
+    /// Consumer group to remove the members from
+    #[arg(short, long)]
+    pub group: String,
+    /// group.instance.id of the member to remove. Can be repeated
+    #[arg(short, long, required = true)]
+    pub instance_id: Vec<String>,
+}
 
-    Ok(())
+#[derive(Args, Debug)]
+pub struct SetOffsetArgs {
+    /// Consumer group to commit for
+    #[arg(short, long)]
+    pub group: String,
+    /// Topic of the partitions
+    #[arg(short, long)]
+    pub topic: String,
+    /// Partition to commit the offset on
+    #[arg(short, long, required_unless_present = "all_partitions")]
+    pub partition: Option<i32>,
+    /// Commit the offset on every partition of the topic
+    #[arg(long, conflicts_with = "partition")]
+    pub all_partitions: bool,
+    /// Offset to commit, between the low and high watermark
+    #[arg(short, long)]
+    pub offset: i64,
+    /// Commit without showing the changes for confirmation first
+    #[arg(short, long)]
+    pub yes: bool,
+}
+
+#[derive(Args, Debug)]
+#[command(group(
+    ArgGroup::new("target")
+        .required(true)
+        .args(["to_earliest", "to_latest", "to_datetime"])
+))]
+pub struct ResetOffsetsArgs {
+    /// Consumer group to reset
+    #[arg(short, long)]
+    pub group: String,
+    /// Topic whose partitions to reset
+    #[arg(short, long)]
+    pub topic: String,
+    /// Reset to the low watermark, reprocessing everything retained
+    #[arg(long)]
+    pub to_earliest: bool,
+    /// Reset to the high watermark, skipping everything not yet consumed
+    #[arg(long)]
+    pub to_latest: bool,
+    /// Reset to the first records produced at or after this time
+    #[arg(long, value_parser = parse_timestamp)]
+    pub to_datetime: Option<i64>,
+    /// Commit without showing the changes for confirmation first
+    #[arg(short, long)]
+    pub yes: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct MembersArgs {
+    /// Consumer group to inspect
+    #[arg(short, long)]
+    pub group: String,
+    /// Keep polling the group and log joins, leaves and assignment changes
+    #[arg(short, long)]
+    pub watch: bool,
+    /// Polling interval used with --watch (e.g. 500ms, 5s, 1m)
+    #[arg(short, long, default_value = "5s", value_parser = parse_duration)]
+    pub interval: Duration,
+}
+
+#[derive(Args, Debug)]
+pub struct TopArgs {
+    /// Number of groups to show
+    #[arg(short, long, default_value_t = 20)]
+    pub limit: usize,
+}
+
+#[derive(Args, Debug)]
+pub struct HealthArgs {
+    #[command(subcommand)]
+    pub command: HealthCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum HealthCommand {
+    #[command(
+        name = "min-isr",
+        about = "List partitions whose ISR is at or below min.insync.replicas"
+    )]
+    MinIsr,
+}
+
+#[derive(Args, Debug)]
+pub struct ProbeArgs {
+    #[command(subcommand)]
+    pub command: ProbeCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ProbeCommand {
+    #[command(
+        name = "brokers",
+        about = "Produce to a partition led by each broker and show the ack latency per broker"
+    )]
+    Brokers(ProbeBrokersArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct ProbeBrokersArgs {
+    /// Topic to produce the probe records to, with a partition led by each broker
+    #[arg(short, long, default_value = "kfcli-probe")]
+    pub topic: String,
+    /// Records timed per broker
+    #[arg(short, long, default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+    pub count: u64,
+    /// Acknowledgements to wait for, the producer default when not set
+    #[arg(long, value_enum)]
+    pub acks: Option<Acks>,
+    /// How long to wait for an acknowledgement before the broker counts as failed
+    #[arg(long, value_parser = parse_duration, default_value = "10s")]
+    pub timeout: Duration,
+}
+
+#[derive(Args, Debug)]
+pub struct DlqArgs {
+    #[command(subcommand)]
+    pub command: DlqCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DlqCommand {
+    #[command(
+        name = "inspect",
+        about = "Group dead-letter records by error and source partition"
+    )]
+    Inspect(DlqInspectArgs),
+    #[command(name = "replay", about = "Re-drive selected dead-letter records")]
+    Replay(DlqReplayArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct DlqInspectArgs {
+    /// Dead-letter topic to inspect
+    #[arg(short, long)]
+    pub topic: String,
+    /// Only inspect records produced at or after this time
+    #[arg(long, value_parser = parse_timestamp)]
+    pub since: Option<i64>,
+    /// Header holding the error, instead of the Kafka Connect and Spring defaults
+    #[arg(long)]
+    pub error_header: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct DlqReplayArgs {
+    /// Dead-letter topic to read from
+    #[arg(short, long)]
+    pub topic: String,
+    /// Topic to re-drive the records to
+    #[arg(long)]
+    pub to: String,
+    /// Only re-drive records whose error contains this text
+    #[arg(long)]
+    pub error: Option<String>,
+    /// Header holding the error, instead of the Kafka Connect and Spring defaults
+    #[arg(long)]
+    pub error_header: Option<String>,
+    /// Only re-drive records that failed on this source partition
+    #[arg(long)]
+    pub source_partition: Option<i32>,
+    /// Only re-drive JSON records matching this filter (same syntax as tail)
+    #[arg(short, long)]
+    pub filter: Option<String>,
+    /// Only re-drive records produced at or after this time
+    #[arg(long, value_parser = parse_timestamp)]
+    pub since: Option<i64>,
+    #[command(flatten)]
+    pub headers: ReplayHeaderArgs,
+    #[command(flatten)]
+    pub producer: ProducerArgs,
+}
+
+#[derive(Args, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct BrokerCommandArgs {
+    #[command(subcommand)]
+    pub command: Option<BrokerCommand>,
+    #[arg(short, long)]
+    pub list: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum BrokerCommand {
+    #[command(
+        name = "balance",
+        about = "Count partition leaders and replicas per broker"
+    )]
+    Balance(BalanceArgs),
+    #[command(
+        name = "diff-configs",
+        about = "Show broker configs whose values differ between brokers"
+    )]
+    DiffConfigs(BrokerDiffConfigsArgs),
+    #[command(
+        name = "details",
+        about = "Show the listeners, partitions, log dirs and dynamic configs of a broker"
+    )]
+    Details(BrokerDetailsArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct BrokerDetailsArgs {
+    /// Id of the broker to describe
+    #[arg(long)]
+    pub id: i32,
+}
+
+#[derive(Args, Debug)]
+pub struct BalanceArgs {
+    /// Deviation from an even share, in percent, above which a broker is flagged
+    #[arg(long, default_value_t = 20.0)]
+    pub threshold: f64,
+}
+
+#[derive(Args, Debug)]
+pub struct BrokerDiffConfigsArgs {
+    /// Comma separated ids of the brokers to compare, all brokers when omitted
+    #[arg(short, long, value_delimiter = ',')]
+    pub brokers: Vec<i32>,
+}
+
+#[derive(Args, Debug)]
+pub struct AdminArgs {
+    #[command(subcommand)]
+    pub command: AdminCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AdminCommand {
+    #[command(name = "loggers", about = "Show or change the log levels of a broker")]
+    Loggers(LoggersArgs),
+    #[command(name = "create-topic", about = "Create a topic")]
+    CreateTopic(CreateTopicArgs),
+    #[command(name = "delete-topic", about = "Delete a topic")]
+    DeleteTopic(DeleteTopicArgs),
+    #[command(
+        name = "set-replication",
+        about = "Change the replication factor of a topic"
+    )]
+    SetReplication(SetReplicationArgs),
+    #[command(
+        name = "delete-topics",
+        about = "Delete every topic matching a pattern, after a preview"
+    )]
+    DeleteTopics(DeleteTopicsArgs),
+    #[command(
+        name = "plan",
+        about = "Report how the topics differ from a desired-state file, failing on drift"
+    )]
+    Plan(PlanArgs),
+    #[command(name = "quotas", about = "Inspect client quotas")]
+    Quotas(QuotasArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct QuotasArgs {
+    #[command(subcommand)]
+    pub command: QuotasCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum QuotasCommand {
+    #[command(
+        name = "usage",
+        about = "List the client quotas and whether the brokers are throttling each client id now"
+    )]
+    Usage(QuotaUsageArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct QuotaUsageArgs {
+    /// Probe whether each quota'd client id is throttled by fetching under that client id. The
+    /// probe's own requests count against the quotas of the clients using it
+    #[arg(long)]
+    pub impersonate: bool,
+    /// Topic to fetch from while probing, the first topic of the cluster by default. Probes
+    /// fetch from the end, so no records are read
+    #[arg(short, long, requires = "impersonate")]
+    pub topic: Option<String>,
+    /// How long to probe each client id (e.g. 3s, 10s)
+    #[arg(short, long, default_value = "3s", value_parser = parse_duration)]
+    pub duration: Duration,
+}
+
+#[derive(Args, Debug)]
+pub struct LoggersArgs {
+    /// Id of the broker whose loggers to show or change
+    #[arg(short, long)]
+    pub broker: i32,
+    /// Set a logger level, e.g. kafka.request.logger=DEBUG. Can be repeated
+    #[arg(long, value_parser = parse_logger_level)]
+    pub set: Vec<(String, String)>,
+}
+
+#[derive(Args, Debug)]
+pub struct CreateTopicArgs {
+    /// Name of the topic to create
+    #[arg(short, long)]
+    pub topic: String,
+    /// Number of partitions
+    #[arg(short, long, default_value_t = 1, conflicts_with = "assignment")]
+    pub partitions: i32,
+    /// Number of replicas of each partition
+    #[arg(short, long, default_value_t = 1, conflicts_with = "assignment")]
+    pub replication_factor: i32,
+    /// Brokers holding the replicas of each partition, e.g. "0:1,2;1:2,3". The first broker of a
+    /// partition is its preferred leader
+    #[arg(long, value_parser = parse_replica_assignment)]
+    pub assignment: Option<ReplicaAssignment>,
+    /// Set a topic config, e.g. retention.ms=86400000. Can be repeated
+    #[arg(short, long, value_parser = parse_config_entry)]
+    pub config: Vec<(String, String)>,
+    /// Succeed without changes when the topic already exists
+    #[arg(long)]
+    pub if_not_exists: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct DeleteTopicArgs {
+    /// Name of the topic to delete, picked from a list when left out on a terminal
+    #[arg(short, long)]
+    pub topic: Option<String>,
+    /// Succeed without changes when the topic does not exist
+    #[arg(long)]
+    pub if_exists: bool,
+    /// Delete without asking for confirmation
+    #[arg(short, long)]
+    pub yes: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct SetReplicationArgs {
+    /// Name of the topic to change, picked from a list when left out on a terminal
+    #[arg(short, long)]
+    pub topic: Option<String>,
+    /// Number of replicas each partition should have
+    #[arg(short, long)]
+    pub factor: usize,
+    /// Reassign without asking for confirmation
+    #[arg(short, long)]
+    pub yes: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct DeleteTopicsArgs {
+    /// Regular expression the whole topic name has to match, e.g. 'loadtest-.*'
+    #[arg(long)]
+    pub regex: String,
+    /// Number of topics deleted per request
+    #[arg(long, default_value_t = 50)]
+    pub batch_size: usize,
+    /// Delete without asking for confirmation
+    #[arg(short, long)]
+    pub yes: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct PlanArgs {
+    /// YAML file listing the topics with their partitions, replication factor and configs
+    #[arg(short, long)]
+    pub file: PathBuf,
+}
+
+/// Replica brokers per partition, indexed by partition id.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplicaAssignment(pub Vec<Vec<i32>>);
+
+#[derive(Args, Debug)]
+pub struct ClusterArgs {
+    #[command(subcommand)]
+    pub command: ClusterCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ClusterCommand {
+    #[command(
+        name = "backup",
+        about = "Save the topics, their configs and the ACLs of the cluster to a file"
+    )]
+    Backup(ClusterBackupArgs),
+    #[command(
+        name = "restore",
+        about = "Recreate the topics, configs and ACLs of a backup, after showing the changes"
+    )]
+    Restore(ClusterRestoreArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct ClusterBackupArgs {
+    /// Backup file to write
+    #[arg(short, long)]
+    pub file: PathBuf,
+    /// Back up the topics only, such as on a cluster without an authorizer
+    #[arg(long)]
+    pub skip_acls: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ClusterRestoreArgs {
+    /// Backup file written by cluster backup
+    #[arg(short, long)]
+    pub file: PathBuf,
+    /// Only show the changes
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Apply without asking for confirmation
+    #[arg(short, long)]
+    pub yes: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct AclArgs {
+    #[command(subcommand)]
+    pub command: AclCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AclCommand {
+    #[command(
+        name = "apply",
+        about = "Create the ACLs of a file that the cluster is missing, after showing the changes"
+    )]
+    Apply(AclApplyArgs),
+    #[command(
+        name = "whoami",
+        about = "Show the ACLs of a principal and what they allow on each topic and group"
+    )]
+    Whoami(AclWhoamiArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct AclApplyArgs {
+    /// YAML file listing the ACLs
+    #[arg(short, long)]
+    pub file: PathBuf,
+    /// Also delete ACLs of the principals in the file that the file does not list
+    #[arg(long)]
+    pub prune: bool,
+    /// Only show the changes
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Apply without asking for confirmation
+    #[arg(short, long)]
+    pub yes: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct AclWhoamiArgs {
+    /// Principal to check, such as User:svc-orders
+    #[arg(short, long)]
+    pub principal: String,
+}
+
+#[derive(Args, Debug)]
+pub struct ServeArgs {
+    /// Address to listen on. Use 0.0.0.0:<port> to accept connections from other hosts
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    pub address: String,
+}
+
+#[derive(Args, Debug)]
+pub struct SimulateArgs {
+    #[command(subcommand)]
+    pub command: SimulateCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SimulateCommand {
+    #[command(
+        name = "consume",
+        about = "Consume and commit at a fixed rate to produce realistic lag"
+    )]
+    Consume(SimulateConsumeArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct SimulateConsumeArgs {
+    /// Topic to consume
+    #[arg(short, long)]
+    pub topic: String,
+    /// Consumer group to consume and commit as
+    #[arg(short, long)]
+    pub group: String,
+    /// Records consumed at most, e.g. 500 (per second) or 600/m
+    #[arg(long, value_parser = parse_rate)]
+    pub rate: f64,
+    /// Time between offset commits
+    #[arg(long, value_parser = parse_duration, default_value = "5s")]
+    pub commit_interval: Duration,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum GroupState {
+    Stable,
+    Empty,
+    /// Preparing or completing a rebalance
+    Rebalancing,
+    Dead,
+}
+
+impl GroupState {
+    /// Whether `state`, as reported by the group list, is this state.
+    pub fn matches(&self, state: &str) -> bool {
+        match self {
+            GroupState::Stable => state == "Stable",
+            GroupState::Empty => state == "Empty",
+            GroupState::Rebalancing => {
+                matches!(state, "PreparingRebalance" | "CompletingRebalance")
+            }
+            GroupState::Dead => state == "Dead",
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Snappy,
+    Lz4,
+    Zstd,
+}
+
+impl Compression {
+    /// Value of the `compression.type` producer setting.
+    pub fn config_value(&self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Gzip => "gzip",
+            Compression::Snappy => "snappy",
+            Compression::Lz4 => "lz4",
+            Compression::Zstd => "zstd",
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum Acks {
+    /// Do not wait for the broker
+    #[value(name = "0")]
+    None,
+    /// Wait for the partition leader
+    #[value(name = "1")]
+    Leader,
+    /// Wait for every in-sync replica
+    All,
+}
+
+impl Acks {
+    /// Value of the `acks` producer setting.
+    pub fn config_value(&self) -> &'static str {
+        match self {
+            Acks::None => "0",
+            Acks::Leader => "1",
+            Acks::All => "all",
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum TailOutput {
+    Pretty,
+    Jsonl,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum PayloadFormat {
+    Auto,
+    Json,
+    Xml,
+}
+
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeFormat {
+    Utc,
+    #[default]
+    Local,
+    Relative,
+    Epoch,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    /// Debug plus the protocol and connection logs of librdkafka
+    Trace,
+}
+
+impl LogLevel {
+    pub fn level(&self) -> tracing::Level {
+        match self {
+            LogLevel::Error => tracing::Level::ERROR,
+            LogLevel::Warn => tracing::Level::WARN,
+            LogLevel::Info => tracing::Level::INFO,
+            LogLevel::Debug => tracing::Level::DEBUG,
+            LogLevel::Trace => tracing::Level::TRACE,
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone)]
+pub enum Shell {
+    Bash,
+    Zsh,
+}
+
+#[derive(Args, Debug)]
+pub struct AuditArgs {
+    /// Number of most recent entries to show
+    #[arg(short, long, default_value_t = 50)]
+    pub limit: usize,
+}
+
+#[derive(Args, Debug)]
+pub struct CompletionArgs {
+    #[arg(value_enum)]
+    pub shell: Shell,
+}
+
+/// Parses durations such as `500ms`, `10s`, `5m`, `1h` or `7d`. A bare number is read as seconds.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("Invalid duration: {}", value))?;
+    match unit {
+        "ms" => Ok(Duration::from_millis(amount)),
+        "" | "s" => Ok(Duration::from_secs(amount)),
+        "m" => Ok(Duration::from_secs(amount * 60)),
+        "h" => Ok(Duration::from_secs(amount * 60 * 60)),
+        "d" => Ok(Duration::from_secs(amount * 60 * 60 * 24)),
+        _ => Err(format!(
+            "Invalid duration unit in {}, use ms, s, m, h or d",
+            value
+        )),
+    }
+}
+
+/// Parses a size such as `900KB`, `1MiB` or `1048588`: KB, MB and GB are powers of 1000, KiB,
+/// MiB and GiB powers of 1024, and a bare number is in bytes.
+pub fn parse_byte_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("Invalid size: {}", value))?;
+    let multiplier = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" => 1_000,
+        "mb" => 1_000_000,
+        "gb" => 1_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        _ => {
+            return Err(format!(
+                "Invalid size unit in {}, use B, KB, MB, GB, KiB, MiB or GiB",
+                value
+            ))
+        }
+    };
+    amount
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("Size {} is too large", value))
+}
+
+pub fn parse_group_start(value: &str) -> Result<GroupStart, String> {
+    match value.trim() {
+        "earliest" => Ok(GroupStart::Earliest),
+        "latest" => Ok(GroupStart::Latest),
+        value => parse_timestamp(value)
+            .map(GroupStart::Timestamp)
+            .map_err(|_| format!("Invalid start: {}, use earliest, latest or a time", value)),
+    }
+}
+
+/// Parses a point in time into epoch milliseconds. Accepts RFC 3339 (`2024-05-01T10:00:00Z`),
+/// epoch milliseconds, or a duration such as `30m` meaning that long ago.
+pub fn parse_timestamp(value: &str) -> Result<i64, String> {
+    let value = value.trim();
+    if let Ok(time) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Ok(time.timestamp_millis());
+    }
+    if !value.is_empty() && value.chars().all(|c| c.is_ascii_digit()) {
+        return value
+            .parse()
+            .map_err(|_| format!("Invalid timestamp: {}", value));
+    }
+    let ago = parse_duration(value).map_err(|_| {
+        format!(
+            "Invalid timestamp: {}, use RFC 3339, epoch millis or a duration such as 1h",
+            value
+        )
+    })?;
+    Ok(chrono::Utc::now().timestamp_millis() - ago.as_millis() as i64)
+}
+
+/// Parses a rate such as `100/s` or `600/m` into records per second. A bare number is per second.
+pub fn parse_rate(value: &str) -> Result<f64, String> {
+    let (amount, unit) = value.trim().split_once('/').unwrap_or((value.trim(), "s"));
+    let amount: f64 = amount
+        .parse()
+        .map_err(|_| format!("Invalid rate: {}", value))?;
+    let per_second = match unit {
+        "s" => amount,
+        "m" => amount / 60.0,
+        "h" => amount / 3600.0,
+        _ => return Err(format!("Invalid rate unit in {}, use /s, /m or /h", value)),
+    };
+    if per_second > 0.0 {
+        Ok(per_second)
+    } else {
+        Err(format!("Rate must be positive: {}", value))
+    }
+}
+
+/// Parses a fraction greater than 0 and at most 1.
+pub fn parse_fraction(value: &str) -> Result<f64, String> {
+    match value.trim().parse::<f64>() {
+        Ok(fraction) if fraction > 0.0 && fraction <= 1.0 => Ok(fraction),
+        _ => Err(format!(
+            "Invalid fraction: {}, use a number in (0, 1]",
+            value
+        )),
+    }
+}
+
+/// Parses a `logger=LEVEL` pair. Levels are the log4j ones and are not case sensitive.
+pub fn parse_logger_level(value: &str) -> Result<(String, String), String> {
+    let (logger, level) = value
+        .split_once('=')
+        .ok_or_else(|| format!("Invalid logger setting: {}, use logger=LEVEL", value))?;
+    let logger = logger.trim();
+    let level = level.trim().to_uppercase();
+    if logger.is_empty() {
+        return Err(format!("Missing logger name in {}", value));
+    }
+    match level.as_str() {
+        "TRACE" | "DEBUG" | "INFO" | "WARN" | "ERROR" | "FATAL" | "OFF" => {
+            Ok((logger.to_string(), level))
+        }
+        _ => Err(format!(
+            "Invalid log level in {}, use TRACE, DEBUG, INFO, WARN, ERROR, FATAL or OFF",
+            value
+        )),
+    }
+}
+
+pub fn parse_config_entry(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((name, value)) if !name.trim().is_empty() => {
+            Ok((name.trim().to_string(), value.trim().to_string()))
+        }
+        _ => Err(format!("Invalid config: {}, use name=value", value)),
+    }
+}
+
+pub fn parse_regex(value: &str) -> Result<Regex, String> {
+    Regex::new(value).map_err(|er| format!("Invalid regex {}: {}", value, er))
+}
+
+/// Parses a `name=value` record header. The value is kept as it is, spaces included.
+pub fn parse_header(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((name, value)) if !name.trim().is_empty() => {
+            Ok((name.trim().to_string(), value.to_string()))
+        }
+        _ => Err(format!("Invalid header: {}, use name=value", value)),
+    }
+}
+
+/// Parses `partition:broker,broker;...`. Every partition from 0 up must be listed once, with
+/// the same number of distinct brokers.
+pub fn parse_replica_assignment(value: &str) -> Result<ReplicaAssignment, String> {
+    let mut partitions = BTreeMap::new();
+    for entry in value.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+        let (partition, brokers) = entry
+            .split_once(':')
+            .ok_or_else(|| format!("Invalid assignment: {}, use partition:broker,broker", entry))?;
+        let partition: i32 = partition
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid partition in {}", entry))?;
+        let brokers = brokers
+            .split(',')
+            .map(|broker| broker.trim().parse::<i32>())
+            .collect::<Result<Vec<i32>, _>>()
+            .map_err(|_| format!("Invalid broker id in {}", entry))?;
+        if brokers.iter().collect::<BTreeSet<_>>().len() != brokers.len() {
+            return Err(format!("Partition {} lists a broker twice", partition));
+        }
+        if partitions.insert(partition, brokers).is_some() {
+            return Err(format!("Partition {} is assigned twice", partition));
+        }
+    }
+    if partitions.is_empty() {
+        return Err("The assignment has no partitions".to_string());
+    }
+    if partitions.keys().copied().ne(0..partitions.len() as i32) {
+        return Err("Partitions must be numbered from 0 without gaps".to_string());
+    }
+    let replicas: Vec<Vec<i32>> = partitions.into_values().collect();
+    if replicas.iter().any(|r| r.len() != replicas[0].len()) {
+        return Err("Every partition needs the same number of replicas".to_string());
+    }
+    Ok(ReplicaAssignment(replicas))
+}
+
+pub fn generate_completion(shell: Shell) -> Result<(), io::Error> {
+    let mut cmd = Cli::command();
+    let dir = match shell {
+        Shell::Bash => ".bash_completion.d",
+        Shell::Zsh => ".zfunc",
+    };
+
+    // Create the directory if it doesn't exist
+    if !Path::new(dir).exists() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let file_path = match shell {
+        Shell::Bash => format!("{}/kfcli.bash", dir),
+        Shell::Zsh => format!("{}/_kfcli", dir),
+    };
+
+    let mut file = File::create(file_path)?;
+
+    match shell {
+        Shell::Bash => {
+            clap_complete::generate(clap_complete::shells::Bash, &mut cmd, "kfcli", &mut file);
+        }
+        Shell::Zsh => {
+            clap_complete::generate(clap_complete::shells::Zsh, &mut cmd, "kfcli", &mut file);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{parse_byte_size, parse_duration};
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("10s").unwrap(), Duration::from_secs(10));
+        assert_eq!(parse_duration("10").unwrap(), Duration::from_secs(10));
+        assert_eq!(parse_duration("5m").unwrap(), Duration::from_secs(300));
+        assert_eq!(parse_duration("1h").unwrap(), Duration::from_secs(3600));
+        assert_eq!(parse_duration("7d").unwrap(), Duration::from_secs(604800));
+    }
+
+    #[test]
+    fn test_parse_byte_size() {
+        assert_eq!(parse_byte_size("1048588"), Ok(1_048_588));
+        assert_eq!(parse_byte_size("900KB"), Ok(900_000));
+        assert_eq!(parse_byte_size("900 kb"), Ok(900_000));
+        assert_eq!(parse_byte_size("1MiB"), Ok(1_048_576));
+        assert_eq!(parse_byte_size("2GB"), Ok(2_000_000_000));
+        assert!(parse_byte_size("1.5MB").is_err());
+        assert!(parse_byte_size("10TB").is_err());
+        assert!(parse_byte_size("18446744073709551615 KB").is_err());
+    }
+
+    #[test]
+    fn test_missing_topic_and_group() {
+        use clap::Parser;
+
+        use super::Cli;
+
+        let mut details = Cli::parse_from(["kfcli", "topics", "details", "--lag"]).command;
+        assert_eq!(details.missing_topic(), Some(&mut None));
+        let mut tail = Cli::parse_from(["kfcli", "topics", "tail", "-t", "orders"]).command;
+        assert_eq!(tail.missing_topic(), None);
+        let mut list = Cli::parse_from(["kfcli", "topics", "list"]).command;
+        assert_eq!(list.missing_topic(), None);
+
+        let mut consumer = Cli::parse_from(["kfcli", "consumer"]).command;
+        assert!(consumer.missing_group().is_some());
+        let mut listed = Cli::parse_from(["kfcli", "consumer", "--list"]).command;
+        assert!(listed.missing_group().is_none());
+        let mut given = Cli::parse_from(["kfcli", "consumer", "-c", "billing"]).command;
+        assert!(given.missing_group().is_none());
+    }
+
+    #[test]
+    fn test_group_state_matches() {
+        use super::GroupState;
+
+        assert!(GroupState::Stable.matches("Stable"));
+        assert!(!GroupState::Stable.matches("Empty"));
+        assert!(GroupState::Rebalancing.matches("PreparingRebalance"));
+        assert!(GroupState::Rebalancing.matches("CompletingRebalance"));
+        assert!(!GroupState::Rebalancing.matches("Stable"));
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(
+            super::parse_timestamp("2024-05-01T10:00:00Z").unwrap(),
+            1714557600000
+        );
+        assert_eq!(
+            super::parse_timestamp("1714557600000").unwrap(),
+            1714557600000
+        );
+
+        let an_hour_ago = chrono::Utc::now().timestamp_millis() - 3_600_000;
+        let parsed = super::parse_timestamp("1h").unwrap();
+        assert!((parsed - an_hour_ago).abs() < 1000);
+
+        assert!(super::parse_timestamp("yesterday").is_err());
+    }
+
+    #[test]
+    fn test_parse_group_start() {
+        use super::{parse_group_start, GroupStart};
+
+        assert_eq!(parse_group_start("earliest"), Ok(GroupStart::Earliest));
+        assert_eq!(parse_group_start("latest"), Ok(GroupStart::Latest));
+        assert_eq!(
+            parse_group_start("2024-05-01T10:00:00Z"),
+            Ok(GroupStart::Timestamp(1714557600000))
+        );
+        assert!(parse_group_start("newest").is_err());
+    }
+
+    #[test]
+    fn test_parse_invalid_duration() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("s").is_err());
+        assert!(parse_duration("10w").is_err());
+    }
+
+    #[test]
+    fn test_parse_logger_level() {
+        assert_eq!(
+            super::parse_logger_level("kafka.request.logger=debug").unwrap(),
+            ("kafka.request.logger".to_string(), "DEBUG".to_string())
+        );
+        assert!(super::parse_logger_level("kafka.request.logger").is_err());
+        assert!(super::parse_logger_level("=INFO").is_err());
+        assert!(super::parse_logger_level("root=VERBOSE").is_err());
+    }
+
+    #[test]
+    fn test_parse_replica_assignment() {
+        use super::{parse_replica_assignment, ReplicaAssignment};
+
+        assert_eq!(
+            parse_replica_assignment("1:2,3; 0:1,2").unwrap(),
+            ReplicaAssignment(vec![vec![1, 2], vec![2, 3]])
+        );
+        assert!(parse_replica_assignment("0:1,2;2:2,3").is_err());
+        assert!(parse_replica_assignment("0:1,2;1:2").is_err());
+        assert!(parse_replica_assignment("0:1,1").is_err());
+        assert!(parse_replica_assignment("0:1;0:2").is_err());
+        assert!(parse_replica_assignment("0").is_err());
+        assert!(parse_replica_assignment("").is_err());
+    }
+
+    #[test]
+    fn test_is_read_only() {
+        use clap::Parser;
+
+        let read_only = |args: &[&str]| super::Cli::parse_from(args).command.is_read_only();
+        assert!(read_only(&["kfcli", "topics", "list", "--all-envs"]));
+        assert!(read_only(&["kfcli", "--all-envs", "health", "min-isr"]));
+        assert!(read_only(&["kfcli", "consumer", "top"]));
+        assert!(!read_only(&["kfcli", "topics", "tail", "-t", "orders"]));
+        assert!(read_only(&[
+            "kfcli",
+            "topics",
+            "tail",
+            "-t",
+            "orders",
+            "--no-follow"
+        ]));
+        assert!(!read_only(&[
+            "kfcli", "consumer", "members", "-g", "g", "--watch"
+        ]));
+        assert!(!read_only(&[
+            "kfcli",
+            "admin",
+            "loggers",
+            "-b",
+            "1",
+            "--set",
+            "root=INFO"
+        ]));
+    }
+
+    #[test]
+    fn test_is_audited() {
+        use clap::Parser;
+
+        let audited = |args: &[&str]| super::Cli::parse_from(args).command.is_audited();
+        assert!(audited(&["kfcli", "admin", "create-topic", "-t", "orders"]));
+        assert!(audited(&[
+            "kfcli",
+            "consumer",
+            "reset-offsets",
+            "-g",
+            "g",
+            "-t",
+            "orders",
+            "--to-earliest"
+        ]));
+        assert!(!audited(&["kfcli", "admin", "loggers", "-b", "1"]));
+        assert!(!audited(&["kfcli", "topics", "list"]));
+        assert!(!audited(&["kfcli", "topics", "tail", "-t", "orders"]));
+        assert!(audited(&[
+            "kfcli", "topics", "tail", "-t", "orders", "--commit"
+        ]));
+        assert!(audited(&["kfcli", "probe", "brokers", "-t", "probe"]));
+        assert!(audited(&[
+            "kfcli", "simulate", "consume", "-t", "orders", "-g", "billing", "--rate", "10"
+        ]));
+    }
+
+    #[test]
+    fn test_parse_rate_and_fraction() {
+        assert_eq!(super::parse_rate("100/s").unwrap(), 100.0);
+        assert_eq!(super::parse_rate("600/m").unwrap(), 10.0);
+        assert_eq!(super::parse_rate("5").unwrap(), 5.0);
+        assert!(super::parse_rate("0/s").is_err());
+        assert!(super::parse_rate("10/d").is_err());
+        assert_eq!(super::parse_fraction("0.01").unwrap(), 0.01);
+        assert!(super::parse_fraction("0").is_err());
+        assert!(super::parse_fraction("1.5").is_err());
+    }
+
+    #[test]
+    fn test_cli_definition() {
+        use clap::CommandFactory;
+
+        super::Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_parse_producer_args() {
+        use clap::Parser;
+
+        let cli = super::Cli::parse_from([
+            "kfcli",
+            "topics",
+            "restore",
+            "-f",
+            "orders.snapshot",
+            "-t",
+            "orders",
+            "--compression",
+            "zstd",
+            "--acks",
+            "0",
+        ]);
+        let super::Command::Topics(args) = cli.command else {
+            panic!("expected a topics command");
+        };
+        let super::TopicCommand::Restore(restore) = args.command else {
+            panic!("expected topics restore");
+        };
+        assert_eq!(
+            restore.producer.compression.map(|c| c.config_value()),
+            Some("zstd")
+        );
+        assert_eq!(restore.producer.acks.map(|a| a.config_value()), Some("0"));
+        assert!(super::Cli::try_parse_from([
+            "kfcli",
+            "topics",
+            "seed",
+            "-t",
+            "t",
+            "--template",
+            "t.json",
+            "--acks",
+            "2"
+        ])
+        .is_err());
+    }
 }
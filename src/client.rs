@@ -0,0 +1,413 @@
+use std::{
+    env,
+    error::Error,
+    ffi::{c_char, CStr, CString},
+    path::Path,
+    process, ptr,
+    sync::{Mutex, RwLock},
+};
+
+use chrono::Utc;
+use rdkafka::{
+    admin::AdminClient,
+    bindings::rd_kafka_oauthbearer_set_token,
+    client::{Client, OAuthToken},
+    consumer::{BaseConsumer, Consumer, ConsumerContext},
+    error::KafkaError as RdKafkaError,
+    types::RDKafkaRespErr,
+    ClientConfig, ClientContext,
+};
+use serde::Deserialize;
+
+use crate::{
+    config::{EnvironmentConfig, OAuthConfig, SaslConfig, TlsConfig},
+    kafka::KafkaError,
+    logging,
+};
+
+/// Tokens are used as long as they have this much of their lifetime left
+const TOKEN_MARGIN_MS: i64 = 60_000;
+/// Lifetime of tokens whose response leaves out `expires_in`
+const DEFAULT_TOKEN_LIFETIME_SECS: i64 = 3600;
+
+/// Security settings of the environment the command runs against, set before any client is
+/// created and replaced for each environment of a multi-environment run.
+static SECURITY: RwLock<Security> = RwLock::new(Security {
+    oauth: None,
+    tls: None,
+    sasl: None,
+});
+static TOKEN: Mutex<Option<CachedToken>> = Mutex::new(None);
+/// `group.id` the environment sets for kfcli's own consumers
+static GROUP_ID: RwLock<Option<String>> = RwLock::new(None);
+
+#[derive(Debug, Clone)]
+struct Security {
+    oauth: Option<OAuthConfig>,
+    tls: Option<TlsConfig>,
+    sasl: Option<SaslConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct CachedToken {
+    token: String,
+    principal: String,
+    /// Epoch milliseconds
+    expires_at: i64,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<i64>,
+}
+
+/// Context of every client kfcli creates, refreshing SASL OAUTHBEARER tokens when the
+/// environment has OAuth settings.
+#[derive(Default)]
+pub struct ClientAuth;
+
+impl ClientContext for ClientAuth {
+    const ENABLE_REFRESH_OAUTH_TOKEN: bool = true;
+
+    fn generate_oauth_token(&self, _: Option<&str>) -> Result<OAuthToken, Box<dyn Error>> {
+        refresh_token()
+    }
+}
+
+impl ConsumerContext for ClientAuth {}
+
+/// Switches the clients created from now on to `environment`. Its TLS files are checked and its
+/// first OAuth token fetched, so that a misconfigured environment fails the command up front
+/// instead of every client timing out.
+pub fn use_environment(environment: &EnvironmentConfig) -> Result<(), KafkaError> {
+    tracing::debug!(
+        tls = environment.tls.is_some(),
+        sasl = environment.sasl.is_some(),
+        oauth = environment.oauth.is_some(),
+        "Using environment security settings"
+    );
+    if let Some(tls) = &environment.tls {
+        check_tls_files(tls)?;
+    }
+    *SECURITY.write().expect("Security settings poisoned") = Security {
+        oauth: environment.oauth.clone(),
+        tls: environment.tls.clone(),
+        sasl: environment.sasl.clone(),
+    };
+    *TOKEN.lock().expect("OAuth token poisoned") = None;
+    *GROUP_ID.write().expect("Group id poisoned") = environment.group_id.clone();
+    match &environment.oauth {
+        Some(oauth) => cached_token(oauth).map(|_| ()),
+        None => Ok(()),
+    }
+}
+
+fn security() -> Security {
+    SECURITY.read().expect("Security settings poisoned").clone()
+}
+
+fn oauth() -> Option<OAuthConfig> {
+    security().oauth
+}
+
+/// Prefix of the `group.id` kfcli makes up for its own consumers, distinct enough that no
+/// application group is taken for one.
+const EPHEMERAL_GROUP_PREFIX: &str = "kfcli-eph-";
+
+/// `group.id` of kfcli's own consumers. Unless the environment sets one, it is unique to the
+/// user and process, so operators tailing at the same time do not share a group.
+pub fn group_id() -> String {
+    configured_group_id().unwrap_or_else(|| {
+        let user = env::var("USER")
+            .or_else(|_| env::var("USERNAME"))
+            .unwrap_or_else(|_| "user".to_string());
+        format!("{}{}-{}", EPHEMERAL_GROUP_PREFIX, user, process::id())
+    })
+}
+
+/// `group.id` the environment sets for kfcli's own consumers, if any.
+pub fn configured_group_id() -> Option<String> {
+    GROUP_ID.read().expect("Group id poisoned").clone()
+}
+
+/// Whether `group` is one kfcli made up for its own consumers, or the fixed `kfcli` group of
+/// earlier versions, so listings can leave it out.
+pub fn is_kfcli_group(group: &str) -> bool {
+    group == "kfcli"
+        || group
+            .strip_prefix(EPHEMERAL_GROUP_PREFIX)
+            .is_some_and(|rest| {
+                rest.rsplit_once('-').is_some_and(|(user, pid)| {
+                    !user.is_empty() && !pid.is_empty() && pid.bytes().all(|b| b.is_ascii_digit())
+                })
+            })
+}
+
+/// Whether clients authenticate with SASL or TLS, which the raw protocol requests do not speak.
+pub fn uses_secure_connection() -> bool {
+    let security = security();
+    security.oauth.is_some() || security.tls.is_some() || security.sasl.is_some()
+}
+
+/// The principal the brokers most likely see kfcli as, for hints about ACLs.
+pub fn principal() -> String {
+    let security = security();
+    if let Some(sasl) = &security.sasl {
+        return format!("User:{}", sasl.username);
+    }
+    if let Some(oauth) = &security.oauth {
+        return format!("the principal of OAuth client {}", oauth.client_id);
+    }
+    if security.tls.is_some() {
+        return "the principal of the client certificate".to_string();
+    }
+    "User:ANONYMOUS".to_string()
+}
+
+/// Errors for each of the TLS files that is not there, naming its setting.
+fn check_tls_files(tls: &TlsConfig) -> Result<(), KafkaError> {
+    let missing: Vec<String> = [
+        ("certificate_location", Some(&tls.certificate_location)),
+        ("key_location", Some(&tls.key_location)),
+        ("ca_location", tls.ca_location.as_ref()),
+    ]
+    .into_iter()
+    .filter_map(|(setting, path)| {
+        path.filter(|path| !Path::new(path).exists())
+            .map(|path| format!("{} {}", setting, path))
+    })
+    .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    Err(KafkaError::Generic(format!(
+        "TLS files of the environment not found: {}",
+        missing.join(", ")
+    )))
+}
+
+/// Client config for `bootstrap_servers` with the security settings of the environment.
+pub fn config(bootstrap_servers: &str) -> ClientConfig {
+    let security = security();
+    let mut config = ClientConfig::new();
+    config.set("bootstrap.servers", bootstrap_servers);
+    if let Some(tls) = &security.tls {
+        config
+            .set("security.protocol", "ssl")
+            .set("ssl.certificate.location", &tls.certificate_location)
+            .set("ssl.key.location", &tls.key_location);
+        if let Some(password) = &tls.key_password {
+            config.set("ssl.key.password", password);
+        }
+        if let Some(ca) = &tls.ca_location {
+            config.set("ssl.ca.location", ca);
+        }
+    }
+    if let Some(sasl) = &security.sasl {
+        config
+            .set(
+                "security.protocol",
+                sasl.security_protocol.as_deref().unwrap_or("sasl_ssl"),
+            )
+            .set("sasl.mechanism", &sasl.mechanism)
+            .set("sasl.username", &sasl.username)
+            .set("sasl.password", &sasl.password);
+    }
+    if let Some(oauth) = &security.oauth {
+        config
+            .set(
+                "security.protocol",
+                oauth.security_protocol.as_deref().unwrap_or("sasl_ssl"),
+            )
+            .set("sasl.mechanism", "OAUTHBEARER");
+    }
+    if let Some(contexts) = logging::librdkafka_debug() {
+        config.set("debug", contexts);
+    }
+    tracing::debug!(
+        bootstrap_servers,
+        security_protocol = config.get("security.protocol").unwrap_or("plaintext"),
+        "Configuring client"
+    );
+    config
+}
+
+pub fn consumer(config: &ClientConfig) -> Result<BaseConsumer<ClientAuth>, KafkaError> {
+    let consumer: BaseConsumer<ClientAuth> = config
+        .create_with_context(ClientAuth)
+        .map_err(creation_error)?;
+    authenticate(consumer.client())?;
+    Ok(consumer)
+}
+
+pub fn admin(config: &ClientConfig) -> Result<AdminClient<ClientAuth>, KafkaError> {
+    let admin: AdminClient<ClientAuth> = config
+        .create_with_context(ClientAuth)
+        .map_err(creation_error)?;
+    authenticate(admin.inner())?;
+    Ok(admin)
+}
+
+/// The error of a client librdkafka could not create, such as for an invalid setting.
+pub fn creation_error(er: RdKafkaError) -> KafkaError {
+    KafkaError::Generic(format!("Failed to create the Kafka client: {}", er))
+}
+
+/// Hands the cached token to a new client. librdkafka only asks for a token through an event
+/// that is served by polling, and most commands never poll before their first request.
+pub fn authenticate<C: ClientContext>(client: &Client<C>) -> Result<(), KafkaError> {
+    let Some(oauth) = oauth() else {
+        return Ok(());
+    };
+    let token = cached_token(&oauth)?;
+    let value = CString::new(token.token)
+        .map_err(|_| KafkaError::Generic("OAuth token contains a NUL".to_string()))?;
+    let principal = CString::new(token.principal)
+        .map_err(|_| KafkaError::Generic("OAuth client id contains a NUL".to_string()))?;
+    let mut errstr = [0 as c_char; 512];
+    let code = unsafe {
+        rd_kafka_oauthbearer_set_token(
+            client.native_ptr(),
+            value.as_ptr(),
+            token.expires_at,
+            principal.as_ptr(),
+            ptr::null_mut(),
+            0,
+            errstr.as_mut_ptr(),
+            errstr.len(),
+        )
+    };
+    if code != RDKafkaRespErr::RD_KAFKA_RESP_ERR_NO_ERROR {
+        let reason = unsafe { CStr::from_ptr(errstr.as_ptr()) };
+        return Err(KafkaError::Generic(format!(
+            "Failed to set the OAuth token: {}",
+            reason.to_string_lossy()
+        )));
+    }
+    Ok(())
+}
+
+/// The cached token, or a new one when it is about to expire.
+fn cached_token(oauth: &OAuthConfig) -> Result<CachedToken, KafkaError> {
+    let mut cached = TOKEN.lock().expect("OAuth token poisoned");
+    match &*cached {
+        Some(token) if token.expires_at - Utc::now().timestamp_millis() > TOKEN_MARGIN_MS => {
+            Ok(token.clone())
+        }
+        _ => {
+            let token = fetch_token(oauth)?;
+            *cached = Some(token.clone());
+            Ok(token)
+        }
+    }
+}
+
+/// Called by librdkafka when a client's token is due for a refresh.
+fn refresh_token() -> Result<OAuthToken, Box<dyn Error>> {
+    let oauth = oauth().ok_or("The environment has no OAuth settings")?;
+    let token = fetch_token(&oauth)?;
+    *TOKEN.lock().expect("OAuth token poisoned") = Some(token.clone());
+    Ok(OAuthToken {
+        token: token.token,
+        principal_name: token.principal,
+        lifetime_ms: token.expires_at,
+    })
+}
+
+/// Exchanges the client credentials for an access token at the token endpoint.
+fn fetch_token(oauth: &OAuthConfig) -> Result<CachedToken, KafkaError> {
+    let mut form = vec![
+        ("grant_type", "client_credentials"),
+        ("client_id", oauth.client_id.as_str()),
+        ("client_secret", oauth.client_secret.as_str()),
+    ];
+    if let Some(scope) = &oauth.scope {
+        form.push(("scope", scope.as_str()));
+    }
+    tracing::debug!(
+        token_endpoint = oauth.token_endpoint,
+        client_id = oauth.client_id,
+        "Fetching OAuth token"
+    );
+    let body = ureq::post(&oauth.token_endpoint)
+        .send_form(form)
+        .and_then(|response| response.into_body().read_to_string())
+        .map_err(|er| {
+            KafkaError::Generic(format!(
+                "Failed to fetch an OAuth token from {}: {}",
+                oauth.token_endpoint, er
+            ))
+        })?;
+    parse_token(&body, &oauth.client_id, Utc::now().timestamp_millis()).map_err(|er| {
+        KafkaError::Generic(format!(
+            "Invalid token response from {}: {}",
+            oauth.token_endpoint, er
+        ))
+    })
+}
+
+fn parse_token(body: &str, principal: &str, now: i64) -> Result<CachedToken, String> {
+    let response: TokenResponse = serde_json::from_str(body).map_err(|er| er.to_string())?;
+    let lifetime = response.expires_in.unwrap_or(DEFAULT_TOKEN_LIFETIME_SECS);
+    Ok(CachedToken {
+        token: response.access_token,
+        principal: principal.to_string(),
+        expires_at: now + lifetime * 1000,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check_tls_files, is_kfcli_group, parse_token, CachedToken};
+    use crate::config::TlsConfig;
+
+    #[test]
+    fn test_parse_token() {
+        let body = r#"{"access_token": "eyJ.a.b", "token_type": "Bearer", "expires_in": 300}"#;
+        assert_eq!(
+            parse_token(body, "kfcli", 1_000).unwrap(),
+            CachedToken {
+                token: "eyJ.a.b".to_string(),
+                principal: "kfcli".to_string(),
+                expires_at: 301_000,
+            }
+        );
+        let token = parse_token(r#"{"access_token": "t"}"#, "kfcli", 0).unwrap();
+        assert_eq!(token.expires_at, 3_600_000);
+        assert!(parse_token(r#"{"error": "invalid_client"}"#, "kfcli", 0).is_err());
+    }
+
+    #[test]
+    fn test_check_tls_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let certificate = dir.path().join("client.pem");
+        std::fs::write(&certificate, "").unwrap();
+        let mut tls = TlsConfig {
+            certificate_location: certificate.display().to_string(),
+            key_location: certificate.display().to_string(),
+            key_password: None,
+            ca_location: None,
+        };
+        assert!(check_tls_files(&tls).is_ok());
+
+        tls.key_location = "/missing/client.key".to_string();
+        tls.ca_location = Some("/missing/ca.pem".to_string());
+        assert_eq!(
+            check_tls_files(&tls).unwrap_err().to_string(),
+            "TLS files of the environment not found: key_location /missing/client.key, \
+             ca_location /missing/ca.pem"
+        );
+    }
+
+    #[test]
+    fn test_is_kfcli_group() {
+        assert!(is_kfcli_group("kfcli"));
+        assert!(is_kfcli_group("kfcli-eph-alice-4242"));
+        assert!(is_kfcli_group("kfcli-eph-first.last-7"));
+        assert!(!is_kfcli_group("kfcli-billing"));
+        assert!(!is_kfcli_group("kfcli-orders-2"));
+        assert!(!is_kfcli_group("kfcli-eph--12"));
+        assert!(!is_kfcli_group("billing-kfcli-eph-alice-1"));
+    }
+}
@@ -1,21 +1,111 @@
 use std::{
     collections::HashMap,
     env,
-    fs::File,
+    fs::{File, OpenOptions},
     io::{self, Read, Seek, Write},
-    path::Path,
+    path::{Path, PathBuf},
 };
 
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::{
+    cli::{AddEnvironmentArgs, EnvironmentColor, EnvironmentPreset, TimeFormat},
+    output,
+};
+
 const CONFIG_FOLDER: &str = ".config/kcfli";
 const CONFIG_FILE: &str = "config.toml";
+const PROJECT_CONFIG_FILE: &str = ".kfcli.toml";
+/// Name of the environment `KFCLI_BROKERS` sets up when there is no config file
+const VARIABLES_ENVIRONMENT: &str = "env";
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct EnvironmentConfig {
     pub brokers: String,
     pub is_default: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// Color of the name in shell prompts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<EnvironmentColor>,
+    /// `group.id` of kfcli's own consumers, a new `kfcli-eph-<user>-<pid>` for every run unless set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oauth: Option<OAuthConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sasl: Option<SaslConfig>,
+}
+
+/// Username and password authentication, such as the API keys of Confluent Cloud.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SaslConfig {
+    /// `PLAIN`, `SCRAM-SHA-256` or `SCRAM-SHA-512`
+    pub mechanism: String,
+    pub username: String,
+    pub password: String,
+    /// `sasl_ssl` unless set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub security_protocol: Option<String>,
+}
+
+/// Client credentials for SASL OAUTHBEARER, exchanged for tokens at an OIDC token endpoint.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OAuthConfig {
+    pub token_endpoint: String,
+    pub client_id: String,
+    pub client_secret: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    /// `sasl_ssl` unless set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub security_protocol: Option<String>,
+}
+
+/// Client certificate for clusters that require mutual TLS.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TlsConfig {
+    /// PEM certificate, `ssl.certificate.location`
+    pub certificate_location: String,
+    /// PEM private key, `ssl.key.location`
+    pub key_location: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_password: Option<String>,
+    /// CA bundle file or directory, `ssl.ca.location`. The system CAs are used when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_location: Option<String>,
+}
+
+/// Settings of a `.kfcli.toml` in a project folder, applying whenever kfcli runs in that folder
+/// or below it:
+///
+/// ```toml
+/// environment = "billing-dev"
+///
+/// [defaults]
+/// time_format = "utc"
+/// no_pager = true
+/// ```
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectConfig {
+    /// Environment, or `@group`, to use instead of the active one unless `-e` is given
+    pub environment: Option<String>,
+    #[serde(default)]
+    pub defaults: ProjectDefaults,
+}
+
+/// Values of global flags that are not given on the command line.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectDefaults {
+    pub no_pager: Option<bool>,
+    pub human: Option<bool>,
+    pub time_format: Option<TimeFormat>,
+    pub quiet: Option<bool>,
 }
 
 #[derive(Debug, Error)]
@@ -53,13 +143,18 @@ pub fn configure() -> Result<(), ConfigError> {
     let mut is_ok = false;
     let mut environment = String::new();
     let mut brokers = String::new();
+    let mut group = String::new();
     while !is_ok {
         environment = get_environment();
         brokers = get_kafka_brokers();
+        group = get_environment_group();
 
         println!("Are these values correct? (y/n)");
         println!("Environment: {}", environment);
         println!("Brokers: {}", brokers);
+        if !group.is_empty() {
+            println!("Group: {}", group);
+        }
 
         io::stdout().flush().unwrap(); // Ensure the prompt is displayed before reading input
         let mut input = String::new();
@@ -80,8 +175,65 @@ pub fn configure() -> Result<(), ConfigError> {
     let config = EnvironmentConfig {
         brokers,
         is_default: false,
+        group: (!group.is_empty()).then_some(group),
+        color: None,
+        group_id: None,
+        oauth: None,
+        tls: None,
+        sasl: None,
     };
 
+    // Security settings, colors and group ids are edited in the file, keep them when the
+    // environment is reconfigured
+    save_environment(environment, |existing| match existing {
+        Some(existing) => EnvironmentConfig {
+            color: existing.color,
+            group_id: existing.group_id,
+            oauth: existing.oauth,
+            tls: existing.tls,
+            sasl: existing.sasl,
+            ..config
+        },
+        None => config,
+    })
+}
+
+/// Adds an environment from the command line, filling in the settings of its preset.
+pub fn add_environment(args: &AddEnvironmentArgs) -> Result<(), ConfigError> {
+    let config = environment_from_args(args);
+    save_environment(args.name.clone(), |existing| EnvironmentConfig {
+        is_default: existing.is_some_and(|e| e.is_default),
+        ..config
+    })
+}
+
+fn environment_from_args(args: &AddEnvironmentArgs) -> EnvironmentConfig {
+    let sasl = args.preset.map(|preset| match preset {
+        EnvironmentPreset::ConfluentCloud => SaslConfig {
+            mechanism: "PLAIN".to_string(),
+            username: args.api_key.clone().unwrap_or_default(),
+            password: args.api_secret.clone().unwrap_or_default(),
+            security_protocol: None,
+        },
+    });
+    EnvironmentConfig {
+        brokers: args.brokers.clone(),
+        is_default: false,
+        group: args.group.clone(),
+        color: args.color,
+        group_id: args.group_id.clone(),
+        oauth: None,
+        tls: None,
+        sasl,
+    }
+}
+
+/// Writes environment `name` to the config file, creating the file on first use. `update`
+/// gets the environment it replaces, if any, and returns the one to write.
+fn save_environment(
+    name: String,
+    update: impl FnOnce(Option<EnvironmentConfig>) -> EnvironmentConfig,
+) -> Result<(), ConfigError> {
     // Get config folder path
     let home_dir = env::var("HOME").expect("Could not get home directory");
     let config_folder = Path::new(&home_dir).join(CONFIG_FOLDER);
@@ -91,26 +243,28 @@ pub fn configure() -> Result<(), ConfigError> {
             ConfigError::ConfigCreate(format!("Failed to create {:?}", config_folder.to_str()), er)
         })?;
         let config_path = Path::new(&home_dir).join(CONFIG_FOLDER).join(CONFIG_FILE);
-        let _ = File::create(&config_path).map_err(|er| {
+        let _ = create_private_file(&config_path).map_err(|er| {
             ConfigError::ConfigCreate(format!("Failed to create {:?}", config_path.to_str()), er)
         })?;
     }
 
     let file = get_config_file()?;
-    // Read the existing config and remove the environment if it already exists
+    // Read the existing config and replace the environment if it already exists
     let mut environments = read_config(&file)?;
-    if environments.contains_key(&environment) {
-        environments.remove(&environment);
-    }
-
-    environments.insert(environment, config);
+    let config = update(environments.remove(&name));
+    tracing::info!(
+        environment = name,
+        brokers = config.brokers,
+        "Saving environment"
+    );
+    environments.insert(name, config);
     let toml_string = toml::to_string(&environments).map_err(|err| {
         ConfigError::ConfigSerialize("Failed to serialize config".to_string(), err)
     })?;
 
     // Write the config to a file
     let config_path = config_folder.join(CONFIG_FILE);
-    let mut file = File::create(&config_path).map_err(|er| {
+    let mut file = create_private_file(&config_path).map_err(|er| {
         ConfigError::ConfigCreate(
             format!("Failed to create config file: {:?}", config_path),
             er,
@@ -124,10 +278,27 @@ pub fn configure() -> Result<(), ConfigError> {
         )
     })?;
 
-    println!("Configuration saved to {:?}", config_path);
+    output::info(format!("Configuration saved to {:?}", config_path));
     Ok(())
 }
 
+/// Creates or empties the file at `path`, readable and writable by its owner only since the
+/// config holds SASL passwords, OAuth client secrets and TLS key passwords.
+fn create_private_file(path: &Path) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        let file = options.mode(0o600).open(path)?;
+        // The mode only applies to a new file, a config written by an older version keeps its own
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        Ok(file)
+    }
+    #[cfg(not(unix))]
+    options.open(path)
+}
+
 fn get_environment() -> String {
     println!("Enter environment name");
     read_user_inout()
@@ -138,6 +309,11 @@ fn get_kafka_brokers() -> String {
     read_user_inout()
 }
 
+fn get_environment_group() -> String {
+    println!("Enter environment group (leave empty for none)");
+    read_user_inout()
+}
+
 fn read_user_inout() -> String {
     let mut input = String::new();
     io::stdin()
@@ -157,6 +333,7 @@ pub fn read_config(
     // Deserialize the string into a HashMap
     let environments: HashMap<String, EnvironmentConfig> = toml::from_str(&toml_string)
         .map_err(|er| ConfigError::ConfigParse("Failed to parse config".to_string(), er))?;
+    tracing::debug!(environments = environments.len(), "Read config");
 
     Ok(environments)
 }
@@ -205,16 +382,26 @@ pub fn activate_environment(
             )
         })?;
 
-    println!("Environment {} activated", environment);
+    tracing::info!(environment, "Activated environment");
+    output::info(format!("Environment {} activated", environment));
     Ok(())
 }
 
+/// Path of a file in the config folder, such as the audit log.
+pub fn config_path(file: &str) -> Result<PathBuf, ConfigError> {
+    let home_dir = env::var("HOME").map_err(|_| {
+        ConfigError::HomeDirNotFound("HOME environment variable not found".to_string())
+    })?;
+    Ok(Path::new(&home_dir).join(CONFIG_FOLDER).join(file))
+}
+
 pub fn get_config_file() -> Result<File, ConfigError> {
     // Get the home directory
     let home_dir = env::var("HOME").map_err(|_| {
         ConfigError::HomeDirNotFound("HOME environment variable not found".to_string())
     })?;
     let config_path = Path::new(&home_dir).join(CONFIG_FOLDER).join(CONFIG_FILE);
+    tracing::debug!(path = ?config_path, "Opening config file");
 
     // Read the TOML file into a string
     let file = File::open(&config_path).map_err(|er| {
@@ -227,22 +414,217 @@ pub fn get_config_file() -> Result<File, ConfigError> {
     Ok(file)
 }
 
-pub fn get_active_environment(config_file: File) -> Result<EnvironmentConfig, ConfigError> {
-    let environments = read_config(&config_file)?;
+/// The nearest `.kfcli.toml`, in `dir` or the folders above it, the way git finds its repository.
+fn find_project_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|folder| folder.join(PROJECT_CONFIG_FILE))
+        .find(|path| path.is_file())
+}
+
+/// The `.kfcli.toml` that applies in the current folder, with its path.
+pub fn read_project_config() -> Result<Option<(PathBuf, ProjectConfig)>, ConfigError> {
+    let Some(path) = env::current_dir()
+        .ok()
+        .and_then(|dir| find_project_config(&dir))
+    else {
+        return Ok(None);
+    };
+    let content = std::fs::read_to_string(&path)
+        .map_err(|er| ConfigError::ConfigRead(format!("Failed to read {:?}", path), er))?;
+    let project = toml::from_str(&content)
+        .map_err(|er| ConfigError::ConfigParse(format!("Failed to parse {:?}", path), er))?;
+    tracing::debug!(path = ?path, ?project, "Read project config");
+    Ok(Some((path, project)))
+}
+
+/// The environments of the config file. Without a config file, `KFCLI_BROKERS` stands for an
+/// active environment named `env`, so containers can run kfcli from variables alone.
+pub fn read_environments() -> Result<HashMap<String, EnvironmentConfig>, ConfigError> {
+    match get_config_file() {
+        Ok(file) => read_config(&file),
+        Err(ConfigError::ConfigFileNotFound(..) | ConfigError::HomeDirNotFound(_))
+            if variable("KFCLI_BROKERS").is_some() =>
+        {
+            tracing::debug!("No config file, using the environment from KFCLI_BROKERS");
+            Ok(HashMap::from([(
+                VARIABLES_ENVIRONMENT.to_string(),
+                EnvironmentConfig {
+                    brokers: String::new(),
+                    is_default: true,
+                    group: None,
+                    color: None,
+                    group_id: None,
+                    oauth: None,
+                    tls: None,
+                    sasl: None,
+                },
+            )]))
+        }
+        Err(er) => Err(er),
+    }
+}
+
+/// Settings that `KFCLI_<PATH>` variables override, such as `KFCLI_SASL_PASSWORD` for
+/// `sasl.password`.
+const VARIABLE_SETTINGS: [&[&str]; 15] = [
+    &["brokers"],
+    &["group_id"],
+    &["sasl", "mechanism"],
+    &["sasl", "username"],
+    &["sasl", "password"],
+    &["sasl", "security_protocol"],
+    &["oauth", "token_endpoint"],
+    &["oauth", "client_id"],
+    &["oauth", "client_secret"],
+    &["oauth", "scope"],
+    &["oauth", "security_protocol"],
+    &["tls", "certificate_location"],
+    &["tls", "key_location"],
+    &["tls", "key_password"],
+    &["tls", "ca_location"],
+];
+
+fn variable(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// `environment` with the settings given by `KFCLI_*` variables, which win over the config file.
+pub fn with_variables(environment: EnvironmentConfig) -> Result<EnvironmentConfig, ConfigError> {
+    apply_variables(environment, variable)
+}
+
+/// Sets each setting of `VARIABLE_SETTINGS` that `variable` has a value for. A section the
+/// environment does not have, such as `sasl`, is created, and needs its required settings.
+fn apply_variables(
+    environment: EnvironmentConfig,
+    variable: impl Fn(&str) -> Option<String>,
+) -> Result<EnvironmentConfig, ConfigError> {
+    let mut settings = toml::Table::try_from(&environment).map_err(|er| {
+        ConfigError::ConfigSerialize("Failed to serialize environment".to_string(), er)
+    })?;
+    let mut overridden = vec![];
+    for path in VARIABLE_SETTINGS {
+        let name = format!("KFCLI_{}", path.join("_").to_uppercase());
+        let Some(value) = variable(&name) else {
+            continue;
+        };
+        let (field, sections) = path.split_last().expect("Setting paths are not empty");
+        let mut table = &mut settings;
+        for section in sections {
+            table = table
+                .entry(section.to_string())
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+                .as_table_mut()
+                .expect("Environment sections are tables");
+        }
+        table.insert(field.to_string(), toml::Value::String(value));
+        overridden.push(name);
+    }
+    if overridden.is_empty() {
+        return Ok(environment);
+    }
+    tracing::debug!(variables = ?overridden, "Applied environment variables");
+    settings.try_into().map_err(|er| {
+        ConfigError::ConfigParse(
+            format!(
+                "Invalid settings from {}: {}",
+                overridden.join(", "),
+                er.message()
+            ),
+            er,
+        )
+    })
+}
+
+/// The active environment with its name.
+pub fn get_active_environment(
+    environments: HashMap<String, EnvironmentConfig>,
+) -> Result<(String, EnvironmentConfig), ConfigError> {
     let active_env = environments
-        .iter()
-        .find(|(_, config)| config.is_default)
-        .map(|(_, env)| env.clone());
+        .into_iter()
+        .find(|(_, config)| config.is_default);
 
     if active_env.is_none() {
         return Err(ConfigError::NoActiveEnvironment(
             "No active environment found".to_string(),
         ));
     }
-    Ok(active_env.unwrap())
+    let active_env = active_env.unwrap();
+    tracing::debug!(environment = active_env.0, "Using the active environment");
+    Ok(active_env)
+}
+
+/// Resolves `-e` to environments in name order: `@group` selects every environment of that
+/// group, anything else a single environment by name.
+pub fn select_environments(
+    environments: HashMap<String, EnvironmentConfig>,
+    selector: &str,
+) -> Result<Vec<(String, EnvironmentConfig)>, ConfigError> {
+    let mut selected: Vec<(String, EnvironmentConfig)> = match selector.strip_prefix('@') {
+        Some(group) => environments
+            .into_iter()
+            .filter(|(_, env)| env.group.as_deref() == Some(group))
+            .collect(),
+        None => environments
+            .into_iter()
+            .filter(|(name, _)| name == selector)
+            .collect(),
+    };
+    if selected.is_empty() {
+        return Err(ConfigError::EnvironmentNotFound(
+            match selector.strip_prefix('@') {
+                Some(group) => format!("No environments in group {}", group),
+                None => format!("Environment {} not found", selector),
+            },
+        ));
+    }
+    selected.sort_by(|a, b| a.0.cmp(&b.0));
+    tracing::debug!(
+        selector,
+        environments = ?selected.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+        "Selected environments"
+    );
+    Ok(selected)
+}
+
+/// `name` for a shell prompt, wrapped in the ANSI codes of `color` when there is one.
+fn prompt_name(name: &str, color: Option<EnvironmentColor>) -> String {
+    match color {
+        Some(color) => format!("\x1b[{}m{}\x1b[0m", color.ansi_code(), name),
+        None => name.to_string(),
+    }
+}
+
+/// Prints the active environment, with `short` only its name for embedding in a shell prompt.
+pub fn print_current_environment(
+    (name, env): &(String, EnvironmentConfig),
+    short: bool,
+    color: bool,
+) {
+    if short {
+        println!("{}", prompt_name(name, env.color.filter(|_| color)));
+        return;
+    }
+    match &env.group {
+        Some(group) => println!("{} [{}] {}", name, group, env.brokers),
+        None => println!("{} {}", name, env.brokers),
+    }
+}
+
+/// Prints the environments, marking the active one.
+pub fn list_environments(environments: &[(String, EnvironmentConfig)]) {
+    for (name, env) in environments {
+        let marker = if env.is_default { "*" } else { " " };
+        match &env.group {
+            Some(group) => println!("{} {} [{}] {}", marker, name, group, env.brokers),
+            None => println!("{} {} {}", marker, name, env.brokers),
+        }
+    }
 }
 
 #[cfg(test)]
+// These tests import more than they use and compare bools with assert_eq
+#[allow(unused_imports, clippy::bool_assert_comparison)]
 mod test {
     use std::{
         env, fs,
@@ -251,7 +633,11 @@ mod test {
 
     use tempfile::NamedTempFile;
 
-    use super::read_config;
+    use super::{
+        apply_variables, create_private_file, find_project_config, prompt_name, read_config,
+        EnvironmentConfig, ProjectConfig, ProjectDefaults,
+    };
+    use crate::cli::{EnvironmentColor, TimeFormat};
 
     #[test]
     fn test_empty_read_config() -> io::Result<()> {
@@ -313,6 +699,64 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_read_oauth_config() -> io::Result<()> {
+        let mut file = NamedTempFile::new()?;
+        let config = r#"
+            [prod]
+            brokers = "prodhost:9093"
+            is_default = true
+
+            [prod.oauth]
+            token_endpoint = "https://login.example.com/oauth2/token"
+            client_id = "kfcli"
+            client_secret = "s3cret"
+            scope = "kafka"
+        "#;
+        writeln!(file, "{}", config)?;
+        file.flush()?;
+
+        let config = read_config(&file.reopen()?).unwrap();
+        let oauth = config.get("prod").unwrap().oauth.as_ref().unwrap();
+        assert_eq!(
+            oauth.token_endpoint,
+            "https://login.example.com/oauth2/token"
+        );
+        assert_eq!(oauth.client_id, "kfcli");
+        assert_eq!(oauth.scope.as_deref(), Some("kafka"));
+        assert_eq!(oauth.security_protocol, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_environment_from_confluent_cloud_preset() {
+        use crate::cli::{AddEnvironmentArgs, EnvironmentPreset};
+
+        let args = AddEnvironmentArgs {
+            name: "cloud".to_string(),
+            brokers: "pkc-123.eu-west-1.aws.confluent.cloud:9092".to_string(),
+            group: None,
+            preset: Some(EnvironmentPreset::ConfluentCloud),
+            api_key: Some("KEY".to_string()),
+            api_secret: Some("SECRET".to_string()),
+            color: Some(EnvironmentColor::Red),
+            group_id: None,
+        };
+        let config = super::environment_from_args(&args);
+        assert_eq!(config.brokers, args.brokers);
+        assert_eq!(config.color, Some(EnvironmentColor::Red));
+        assert_eq!(
+            config.sasl,
+            Some(super::SaslConfig {
+                mechanism: "PLAIN".to_string(),
+                username: "KEY".to_string(),
+                password: "SECRET".to_string(),
+                security_protocol: None,
+            })
+        );
+    }
+
     #[test]
     fn test_activate_not_found_environment() {
         let mut file = NamedTempFile::new().unwrap();
@@ -380,7 +824,150 @@ mod test {
         writeln!(tmp_file, "{}", config).unwrap();
         let file = tmp_file.reopen().unwrap();
 
-        let active_env = super::get_active_environment(file).unwrap();
+        let environments = super::read_config(&file).unwrap();
+        let (name, active_env) = super::get_active_environment(environments).unwrap();
+        assert_eq!(name, "dev");
         assert_eq!(active_env.brokers, "localhost:9092");
     }
+
+    #[test]
+    fn test_select_environments() {
+        let mut tmp_file = NamedTempFile::new().unwrap();
+        let config = r#"
+            [dev]
+            brokers = "localhost:9092"
+            is_default = true
+
+            [prod-eu-2]
+            brokers = "prod-eu-2:9092"
+            is_default = false
+            group = "eu"
+
+            [prod-eu-1]
+            brokers = "prod-eu-1:9092"
+            is_default = false
+            group = "eu"
+        "#;
+        writeln!(tmp_file, "{}", config).unwrap();
+        let file = tmp_file.reopen().unwrap();
+        let environments = super::read_config(&file).unwrap();
+
+        let eu = super::select_environments(environments.clone(), "@eu").unwrap();
+        let names: Vec<&str> = eu.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["prod-eu-1", "prod-eu-2"]);
+
+        let dev = super::select_environments(environments.clone(), "dev").unwrap();
+        assert_eq!(dev.len(), 1);
+        assert_eq!(dev[0].1.group, None);
+
+        let error = super::select_environments(environments, "@us").unwrap_err();
+        assert_eq!(error.to_string(), "No environments in group us");
+    }
+
+    #[test]
+    fn test_prompt_name() {
+        assert_eq!(prompt_name("dev", None), "dev");
+        assert_eq!(
+            prompt_name("prod", Some(EnvironmentColor::Red)),
+            "\x1b[31mprod\x1b[0m"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_create_private_file() -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "old")?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644))?;
+
+        create_private_file(&path)?;
+        assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o600);
+        assert_eq!(fs::read_to_string(&path)?, "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_project_config() -> io::Result<()> {
+        let root = tempfile::tempdir()?;
+        let service = root.path().join("service");
+        let nested = service.join("src").join("handlers");
+        fs::create_dir_all(&nested)?;
+        assert_eq!(find_project_config(&nested), None);
+
+        fs::write(
+            service.join(".kfcli.toml"),
+            "environment = \"billing-dev\"\n",
+        )?;
+        assert_eq!(
+            find_project_config(&nested),
+            Some(service.join(".kfcli.toml"))
+        );
+        assert_eq!(find_project_config(root.path()), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_project_config() {
+        let project: ProjectConfig = toml::from_str(
+            r#"
+            environment = "billing-dev"
+
+            [defaults]
+            time_format = "utc"
+            no_pager = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            project,
+            ProjectConfig {
+                environment: Some("billing-dev".to_string()),
+                defaults: ProjectDefaults {
+                    no_pager: Some(true),
+                    human: None,
+                    time_format: Some(TimeFormat::Utc),
+                    quiet: None,
+                },
+            }
+        );
+        assert!(toml::from_str::<ProjectConfig>("enviroment = \"dev\"").is_err());
+    }
+
+    #[test]
+    fn test_apply_variables() {
+        let environment: EnvironmentConfig = toml::from_str(
+            r#"
+            brokers = "localhost:9092"
+            is_default = true
+
+            [sasl]
+            mechanism = "PLAIN"
+            username = "billing"
+            password = "from-file"
+            "#,
+        )
+        .unwrap();
+        let variables = |name: &str| match name {
+            "KFCLI_BROKERS" => Some("kafka:9093".to_string()),
+            "KFCLI_SASL_PASSWORD" => Some("from-variable".to_string()),
+            _ => None,
+        };
+        let applied = apply_variables(environment.clone(), variables).unwrap();
+        assert_eq!(applied.brokers, "kafka:9093");
+        assert!(applied.is_default);
+        let sasl = applied.sasl.unwrap();
+        assert_eq!(sasl.username, "billing");
+        assert_eq!(sasl.password, "from-variable");
+
+        let unchanged = apply_variables(environment.clone(), |_| None).unwrap();
+        assert_eq!(unchanged.brokers, "localhost:9092");
+
+        // A section the config file does not have needs its required settings
+        let tls =
+            |name: &str| (name == "KFCLI_TLS_CA_LOCATION").then(|| "/etc/ssl/ca.pem".to_string());
+        assert!(apply_variables(environment, tls).is_err());
+    }
 }
@@ -0,0 +1,235 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use serde::{Deserialize, Deserializer};
+
+use crate::backup::TopicBackup;
+
+/// Topics a cluster is expected to have, read from a YAML file such as:
+///
+/// ```yaml
+/// topics:
+///   - name: orders
+///     partitions: 6
+///     replication_factor: 3
+///     configs:
+///       retention.ms: 604800000
+/// ```
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct DesiredState {
+    #[serde(default)]
+    pub topics: Vec<DesiredTopic>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct DesiredTopic {
+    pub name: String,
+    pub partitions: i32,
+    pub replication_factor: Option<i32>,
+    /// Every config set on the topic itself; configs it has on top of these are drift too
+    #[serde(default, deserialize_with = "config_values")]
+    pub configs: BTreeMap<String, String>,
+}
+
+/// Accepts numbers and booleans as config values, so `retention.ms: 604800000` needs no quotes.
+fn config_values<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<String, String>, D::Error> {
+    let values: BTreeMap<String, serde_yaml::Value> = BTreeMap::deserialize(deserializer)?;
+    values
+        .into_iter()
+        .map(|(name, value)| {
+            let value = match value {
+                serde_yaml::Value::String(value) => value,
+                serde_yaml::Value::Number(value) => value.to_string(),
+                serde_yaml::Value::Bool(value) => value.to_string(),
+                _ => {
+                    return Err(serde::de::Error::custom(format!(
+                        "config {} must be a string, number or boolean",
+                        name
+                    )))
+                }
+            };
+            Ok((name, value))
+        })
+        .collect()
+}
+
+/// A way the cluster differs from the desired state.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Drift<'a> {
+    MissingTopic(&'a str),
+    Partitions {
+        topic: &'a str,
+        desired: i32,
+        actual: i32,
+    },
+    ReplicationFactor {
+        topic: &'a str,
+        desired: i32,
+        actual: i32,
+    },
+    /// `None` on the desired side is a config the file does not set, on the actual side a
+    /// config the topic leaves at its default
+    Config {
+        topic: &'a str,
+        name: String,
+        desired: Option<String>,
+        actual: Option<String>,
+    },
+}
+
+impl Drift<'_> {
+    pub fn topic(&self) -> &str {
+        match self {
+            Drift::MissingTopic(topic)
+            | Drift::Partitions { topic, .. }
+            | Drift::ReplicationFactor { topic, .. }
+            | Drift::Config { topic, .. } => topic,
+        }
+    }
+}
+
+impl fmt::Display for Drift<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Drift::MissingTopic(_) => write!(f, "missing topic"),
+            Drift::Partitions { .. } => write!(f, "partitions"),
+            Drift::ReplicationFactor { .. } => write!(f, "replication factor"),
+            Drift::Config { name, .. } => write!(f, "config {}", name),
+        }
+    }
+}
+
+/// Lists the differences between `desired` and the `actual` topics of the cluster, in the
+/// order of the file. Topics the file does not mention are not checked.
+pub fn detect_drift<'a>(desired: &'a DesiredState, actual: &[TopicBackup]) -> Vec<Drift<'a>> {
+    let actual: HashMap<&str, &TopicBackup> = actual.iter().map(|t| (t.name.as_str(), t)).collect();
+    let mut drift = vec![];
+    for topic in &desired.topics {
+        let name = topic.name.as_str();
+        let Some(current) = actual.get(name) else {
+            drift.push(Drift::MissingTopic(name));
+            continue;
+        };
+        if topic.partitions != current.partitions {
+            drift.push(Drift::Partitions {
+                topic: name,
+                desired: topic.partitions,
+                actual: current.partitions,
+            });
+        }
+        if let Some(factor) = topic
+            .replication_factor
+            .filter(|f| *f != current.replication_factor)
+        {
+            drift.push(Drift::ReplicationFactor {
+                topic: name,
+                desired: factor,
+                actual: current.replication_factor,
+            });
+        }
+        let mut configs: Vec<&String> = topic.configs.keys().collect();
+        configs.extend(
+            current
+                .configs
+                .keys()
+                .filter(|c| !topic.configs.contains_key(*c)),
+        );
+        configs.sort();
+        for config in configs {
+            let desired = topic.configs.get(config);
+            let actual = current.configs.get(config);
+            if desired != actual {
+                drift.push(Drift::Config {
+                    topic: name,
+                    name: config.clone(),
+                    desired: desired.cloned(),
+                    actual: actual.cloned(),
+                });
+            }
+        }
+    }
+    drift
+}
+
+#[cfg(test)]
+mod test {
+    use super::{detect_drift, DesiredState, Drift};
+    use crate::backup::TopicBackup;
+
+    #[test]
+    fn test_detect_drift() {
+        let desired: DesiredState = serde_yaml::from_str(
+            r#"
+            topics:
+              - name: orders
+                partitions: 6
+                replication_factor: 3
+                configs:
+                  retention.ms: 604800000
+                  cleanup.policy: delete
+              - name: payments
+                partitions: 3
+                configs:
+                  min.insync.replicas: 2
+              - name: audit
+                partitions: 1
+            "#,
+        )
+        .unwrap();
+        assert_eq!(desired.topics[0].configs["retention.ms"], "604800000");
+
+        let actual = vec![
+            TopicBackup {
+                name: "orders".to_string(),
+                partitions: 3,
+                replication_factor: 2,
+                configs: [
+                    ("cleanup.policy", "delete"),
+                    ("retention.ms", "86400000"),
+                    ("segment.ms", "3600000"),
+                ]
+                .into_iter()
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect(),
+            },
+            TopicBackup {
+                name: "payments".to_string(),
+                partitions: 3,
+                replication_factor: 1,
+                configs: [("min.insync.replicas".to_string(), "2".to_string())].into(),
+            },
+        ];
+        assert_eq!(
+            detect_drift(&desired, &actual),
+            vec![
+                Drift::Partitions {
+                    topic: "orders",
+                    desired: 6,
+                    actual: 3
+                },
+                Drift::ReplicationFactor {
+                    topic: "orders",
+                    desired: 3,
+                    actual: 2
+                },
+                Drift::Config {
+                    topic: "orders",
+                    name: "retention.ms".to_string(),
+                    desired: Some("604800000".to_string()),
+                    actual: Some("86400000".to_string()),
+                },
+                Drift::Config {
+                    topic: "orders",
+                    name: "segment.ms".to_string(),
+                    desired: None,
+                    actual: Some("3600000".to_string()),
+                },
+                Drift::MissingTopic("audit"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use crate::{kafka::KafkaError, output};
+
+/// Bars of rising height, the lowest standing for no lag.
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Total lag of a group at each sample time, oldest first.
+type LagSamples = Vec<(i64, i64)>;
+
+/// Splits a line written by `output::csv_line` into its fields.
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// The lag of every group in a history written by `consumer record` or by `consumer lag
+/// --output csv --file`, summed over the partitions of each sample.
+fn parse_lag_history(text: &str) -> Result<BTreeMap<String, LagSamples>, String> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+    let (_, header) = lines.next().ok_or("The file is empty")?;
+    let header = csv_fields(header);
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|column| column == name)
+            .ok_or_else(|| format!("The header has no {} column", name))
+    };
+    let (time, group, lag) = (column("time")?, column("group")?, column("lag")?);
+
+    let mut totals: BTreeMap<String, BTreeMap<i64, i64>> = BTreeMap::new();
+    for (index, line) in lines {
+        let fields = csv_fields(line);
+        let field = |column: usize| {
+            fields
+                .get(column)
+                .ok_or_else(|| format!("Line {} has too few fields", index + 1))
+        };
+        let number = |column: usize| {
+            field(column)?
+                .parse::<i64>()
+                .map_err(|_| format!("Invalid number {:?} on line {}", fields[column], index + 1))
+        };
+        *totals
+            .entry(field(group)?.clone())
+            .or_default()
+            .entry(number(time)?)
+            .or_default() += number(lag)?;
+    }
+    Ok(totals
+        .into_iter()
+        .map(|(group, samples)| (group, samples.into_iter().collect()))
+        .collect())
+}
+
+/// One bar per value, scaled from no lag to the highest value. More values than `width` are
+/// put together, each bar showing the highest of its values so that spikes stay visible.
+fn sparkline(values: &[i64], width: usize) -> String {
+    let width = width.max(1);
+    let bars: Vec<i64> = if values.len() > width {
+        (0..width)
+            .map(|bar| {
+                let values = &values[bar * values.len() / width..(bar + 1) * values.len() / width];
+                values.iter().copied().max().unwrap_or_default()
+            })
+            .collect()
+    } else {
+        values.to_vec()
+    };
+    let highest = bars.iter().copied().max().unwrap_or_default();
+    bars.iter()
+        .map(|&value| {
+            if highest <= 0 {
+                return BARS[0];
+            }
+            let height = value.max(0) as f64 / highest as f64 * (BARS.len() - 1) as f64;
+            BARS[height.round() as usize]
+        })
+        .collect()
+}
+
+/// Prints the lag over time of each group in the history `file`, or only of `group`, as a
+/// sparkline `width` characters wide with the range it covers.
+pub fn chart_lag_history(file: &Path, group: Option<&str>, width: usize) -> Result<(), KafkaError> {
+    let text = fs::read_to_string(file)
+        .map_err(|er| KafkaError::Io(format!("Failed to read {:?}", file), er))?;
+    let history = parse_lag_history(&text)
+        .map_err(|er| KafkaError::Generic(format!("Invalid lag history {:?}: {}", file, er)))?;
+    let history: Vec<(String, LagSamples)> = history
+        .into_iter()
+        .filter(|(name, _)| group.is_none_or(|group| group == name))
+        .collect();
+    if history.is_empty() {
+        return Err(KafkaError::Generic(match group {
+            Some(group) => format!("{:?} has no lag samples of group {}", file, group),
+            None => format!("{:?} has no lag samples", file),
+        }));
+    }
+
+    for (name, samples) in history {
+        let lags: Vec<i64> = samples.iter().map(|(_, lag)| *lag).collect();
+        let (first, last) = (samples[0], samples[samples.len() - 1]);
+        println!("{}", name);
+        println!("  {}", sparkline(&lags, width));
+        println!(
+            "  {} to {}, {} samples",
+            output::timestamp(first.0),
+            output::timestamp(last.0),
+            samples.len()
+        );
+        println!(
+            "  min {}  max {}  last {}  change {:+}",
+            output::count(lags.iter().copied().min().unwrap_or_default()),
+            output::count(lags.iter().copied().max().unwrap_or_default()),
+            output::count(last.1),
+            last.1 - first.1
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_lag_history, sparkline};
+
+    #[test]
+    fn test_parse_lag_history() {
+        let history = parse_lag_history(
+            "time,group,topic,partition,committed,end,lag\n\
+             1000,billing,orders,0,5,15,10\n\
+             1000,billing,orders,1,5,7,2\n\
+             1000,\"ops,eu\",orders,0,15,15,0\n\
+             \n\
+             2000,billing,orders,0,15,18,3\n",
+        )
+        .unwrap();
+        assert_eq!(history["billing"], vec![(1000, 12), (2000, 3)]);
+        assert_eq!(history["ops,eu"], vec![(1000, 0)]);
+
+        assert!(parse_lag_history("").is_err());
+        assert!(parse_lag_history("time,group\n1000,billing\n").is_err());
+        assert!(parse_lag_history("time,group,lag\n1000,billing,many\n").is_err());
+        assert!(parse_lag_history("time,group,lag\n1000,billing\n").is_err());
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(sparkline(&[0, 2, 14, 2, 0], 10), "▁▂█▂▁");
+        assert_eq!(sparkline(&[0, 0, 0], 10), "▁▁▁");
+        assert_eq!(sparkline(&[0, 2, 0, 0, 1, 1], 3), "█▁▅");
+        assert_eq!(sparkline(&[], 10), "");
+    }
+}
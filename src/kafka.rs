@@ -1,25 +1,57 @@
 use std::{
-    collections::HashMap,
-    f32::consts::E,
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
     fmt::Debug,
-    io::{Cursor, Read},
-    time::Duration,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Cursor, IsTerminal, Read, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicI64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
 };
 
 use byteorder::{BigEndian, ReadBytesExt};
 use colored_json::to_colored_json_auto;
 use prettytable::{row, Table};
 use rdkafka::{
-    consumer::{BaseConsumer, Consumer},
-    error::KafkaResult,
-    metadata::{Metadata, MetadataPartition, MetadataTopic},
-    ClientConfig, Message, Offset, TopicPartitionList,
+    admin::{
+        AdminClient, ConfigEntry, ConfigSource, NewPartitions, NewTopic, ResourceSpecifier,
+        TopicReplication, TopicResult,
+    },
+    client::OAuthToken,
+    consumer::{BaseConsumer, CommitMode, Consumer, ConsumerContext},
+    error::RDKafkaErrorCode,
+    groups::{GroupInfo, GroupMemberInfo},
+    message::{BorrowedMessage, Headers},
+    metadata::{Metadata, MetadataPartition},
+    producer::{BaseProducer, BaseRecord, DeliveryResult, Producer, ProducerContext},
+    ClientContext, Message, Offset, Statistics, TopicPartitionList,
 };
-use serde::{Deserialize, Serialize};
+use regex::Regex;
+use serde::Serialize;
 use thiserror::Error;
 use toml::Value;
 
-const GROUP_ID: &str = "kfcli";
+use crate::{
+    acl::{applies_to, effective_operations, plan_acls, topic_access, AclFile},
+    admin::{self, AclBinding, AclPermission, AclResource},
+    backup::{plan_restore, ClusterBackup, RestoreStep, TopicBackup},
+    cli::{
+        Acks, GroupState, OutputFormat, PayloadFormat, ProducerArgs, ReplayHeaderArgs, TailOutput,
+    },
+    client::{self, ClientAuth},
+    config::EnvironmentConfig,
+    desired::{detect_drift, DesiredState, Drift},
+    keys::{KeyAction, Keyboard},
+    output,
+    produce::{self, DraftRecord, DraftTemplate},
+    session::{self, TailSession},
+    snapshot::SnapshotRecord,
+    template::{RecordTemplate, TextTemplate},
+    transaction::{self, TransactionMetadata, TRANSACTION_STATE_TOPIC},
+    xml,
+};
 
 #[derive(Debug, Error)]
 pub enum KafkaError {
@@ -40,59 +72,114 @@ pub enum KafkaError {
 
     #[error("{0}")]
     TopicNotExists(String),
-}
 
-fn get_consumer(bootstrap_servers: &str) -> BaseConsumer {
-    let consumer: BaseConsumer = ClientConfig::new()
-        .set("bootstrap.servers", bootstrap_servers)
-        .set("group.id", GROUP_ID)
-        .set("auto.offset.reset", "latest")
-        .create()
-        .expect("Consumer creation failed");
+    #[error("{0}")]
+    Produce(String, #[source] rdkafka::error::KafkaError),
 
-    consumer
+    #[error("{0}")]
+    Io(String, #[source] std::io::Error),
+
+    #[error("{0}")]
+    Admin(String),
 }
 
-fn get_given_consumer(bootstrap_servers: &str, group_id: &str) -> BaseConsumer {
-    let consumer: BaseConsumer = ClientConfig::new()
-        .set("bootstrap.servers", bootstrap_servers)
-        .set("group.id", group_id)
-        .set("auto.offset.reset", "latest")
-        .create()
-        .expect("Consumer creation failed");
+/// Consumer for inspecting the cluster, which never commits offsets.
+fn get_consumer(bootstrap_servers: &str) -> Result<BaseConsumer<ClientAuth>, KafkaError> {
+    get_given_consumer(bootstrap_servers, &client::group_id())
+}
 
-    consumer
+/// Consumer acting for `group_id`, such as to read or set its offsets. Offsets are only
+/// committed when asked for, so reading never moves the group.
+fn get_given_consumer(
+    bootstrap_servers: &str,
+    group_id: &str,
+) -> Result<BaseConsumer<ClientAuth>, KafkaError> {
+    tracing::debug!(group = group_id, "Creating consumer");
+    client::consumer(
+        client::config(bootstrap_servers)
+            .set("group.id", group_id)
+            .set("enable.auto.commit", "false")
+            .set("auto.offset.reset", "latest"),
+    )
 }
 
-pub fn get_topics(bootstrap_servers: &str) -> Result<(), KafkaError> {
-    let metadata = get_topics_inner(bootstrap_servers, None).map_err(|er| {
-        if let rdkafka::error::KafkaError::MetadataFetch(_) = er {
-            KafkaError::MetadataFetch("Error while fetching topic metadata".to_string(), er)
-        } else {
-            KafkaError::Generic("Error while fetching topics".to_string())
-        }
+/// Consumer for `tail --commit`, committing the offsets of the records read as the group of
+/// the environment.
+fn get_committing_consumer(
+    bootstrap_servers: &str,
+) -> Result<BaseConsumer<ClientAuth>, KafkaError> {
+    let group_id = client::configured_group_id().ok_or_else(|| {
+        KafkaError::Generic(
+            "--commit needs the group_id of the environment, or KFCLI_GROUP_ID, to commit as"
+                .to_string(),
+        )
     })?;
+    tracing::debug!(group = group_id, "Creating committing consumer");
+    client::consumer(
+        client::config(bootstrap_servers)
+            .set("group.id", &group_id)
+            .set("enable.auto.commit", "true")
+            .set("auto.offset.reset", "latest"),
+    )
+}
+
+pub fn get_topics(bootstrap_servers: &str) -> Result<(), KafkaError> {
+    let metadata = get_topics_inner(bootstrap_servers, None)?;
     let mut table = Table::new();
-    table.add_row(row!["Topic", "Partitions"]);
+    table.add_row(row!["Topic", "Partitions", "Replication Factor"]);
     metadata.topics().iter().for_each(|t| {
-        table.add_row(row![t.name(), t.partitions().len(),]);
+        let replication_factor = t.partitions().first().map_or(0, |p| p.replicas().len());
+        table.add_row(row![t.name(), t.partitions().len(), replication_factor]);
     });
-    table.printstd();
+    output::print_table(&table);
     Ok(())
 }
 
-fn get_topics_inner(
-    bootstrap_servers: &str,
-    topic: Option<&str>,
-) -> Result<Metadata, rdkafka::error::KafkaError> {
-    let consumer = get_consumer(bootstrap_servers);
-    consumer.fetch_metadata(topic, Duration::from_secs(10))
+/// Names of the topics of the cluster in order, without internal ones such as
+/// `__consumer_offsets`.
+pub fn topic_names(bootstrap_servers: &str) -> Result<Vec<String>, KafkaError> {
+    let metadata = get_topics_inner(bootstrap_servers, None)?;
+    let mut names: Vec<String> = metadata
+        .topics()
+        .iter()
+        .map(|t| t.name().to_string())
+        .filter(|name| !name.starts_with("__"))
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+fn get_topics_inner(bootstrap_servers: &str, topic: Option<&str>) -> Result<Metadata, KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    tracing::debug!(?topic, "Fetching metadata");
+    consumer
+        .fetch_metadata(topic, Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::MetadataFetch("Error while fetching topic metadata".to_string(), er)
+        })
+}
+
+/// What `topics details` shows besides the partitions and consumer groups.
+pub struct DetailOptions {
+    /// The non-default topic configs
+    pub configs: bool,
+    /// The partition with the most lag of every consumer group
+    pub lag: bool,
+    /// The principals that ACLs let read or write the topic
+    pub acls: bool,
+    /// The timestamps of the earliest and latest record of every partition
+    pub time_range: bool,
 }
 
-pub fn get_topic_detail(bootstrap_servers: &str, topic: &str) -> Result<(), KafkaError> {
-    let consumer = get_consumer(bootstrap_servers);
+pub fn get_topic_detail(
+    bootstrap_servers: &str,
+    topic: &str,
+    options: DetailOptions,
+) -> Result<(), KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let racks = broker_racks(&consumer);
 
-    get_topic_detail_inner(&consumer, topic).map(
+    get_topic_detail_inner(&consumer, topic, &racks).map(
         |(overall_header, overall_detail, partition_detail_header, partition_detail)| {
             let mut overall_table = Table::new();
             overall_table.add_row(row![
@@ -105,30 +192,203 @@ pub fn get_topic_detail(bootstrap_servers: &str, topic: &str) -> Result<(), Kafk
                 overall_detail[1],
                 overall_detail[2]
             ]);
-            overall_table.printstd();
+            output::print_table(&overall_table);
 
             let mut partition_table = Table::new();
             partition_table.add_row(row![
                 partition_detail_header[0],
                 partition_detail_header[1],
-                partition_detail_header[2]
+                partition_detail_header[2],
+                partition_detail_header[3],
+                partition_detail_header[4],
+                partition_detail_header[5],
+                partition_detail_header[6]
             ]);
             for row in partition_detail {
-                partition_table.add_row(row![row[0], row[1], row[2]]);
+                if row[5] == "in-sync" {
+                    partition_table
+                        .add_row(row![row[0], row[1], row[2], row[3], row[4], row[5], row[6]]);
+                } else {
+                    partition_table
+                        .add_row(row![row[0], row[1], row[2], row[3], row[4], Fr->row[5], row[6]]);
+                }
             }
-            partition_table.printstd();
+            output::print_table(&partition_table);
         },
     )?;
 
-    list_consumers_for_topic(&consumer, topic)?;
+    if options.time_range {
+        print_record_time_range(bootstrap_servers, &consumer, topic)?;
+    }
+    if options.configs {
+        print_topic_configs(bootstrap_servers, topic)?;
+    }
+    if options.acls {
+        print_topic_access(&consumer, topic);
+    }
+    list_consumers_for_topic(bootstrap_servers, &consumer, topic, options.lag)?;
+
+    Ok(())
+}
+
+/// Records read back from the end of a partition to find its latest record, since the last
+/// offsets can be transaction markers rather than records.
+const LATEST_RECORD_LOOKBACK: i64 = 10;
+
+/// Reads `topic` from the given offset of each partition and returns the timestamp of the
+/// first record, or with `last` of the last record before the end of the partition.
+fn read_edge_timestamps(
+    bootstrap_servers: &str,
+    topic: &str,
+    starts: &[(i32, i64)],
+    last: bool,
+) -> Result<HashMap<i32, i64>, KafkaError> {
+    let mut timestamps = HashMap::new();
+    if starts.is_empty() {
+        return Ok(timestamps);
+    }
+    let consumer = client::consumer(
+        client::config(bootstrap_servers)
+            .set("group.id", client::group_id())
+            .set("enable.auto.commit", "false")
+            .set("enable.partition.eof", "true"),
+    )?;
+    let mut assignment = TopicPartitionList::new();
+    for &(partition, offset) in starts {
+        assignment
+            .add_partition_offset(topic, partition, Offset::Offset(offset))
+            .map_err(|er| {
+                KafkaError::OffsetFetch("Error while preparing offsets".to_string(), er)
+            })?;
+    }
+    consumer
+        .assign(&assignment)
+        .map_err(|er| KafkaError::Generic(format!("Error while assigning partitions: {:?}", er)))?;
+
+    let mut remaining: BTreeSet<i32> = starts.iter().map(|&(partition, _)| partition).collect();
+    let mut idle_polls = 0;
+    while !remaining.is_empty() && idle_polls < 100 {
+        match consumer.poll(Duration::from_millis(100)) {
+            Some(Ok(message)) => {
+                idle_polls = 0;
+                if !remaining.contains(&message.partition()) {
+                    continue;
+                }
+                if let Some(timestamp) = message.timestamp().to_millis() {
+                    timestamps.insert(message.partition(), timestamp);
+                }
+                if !last {
+                    remaining.remove(&message.partition());
+                }
+            }
+            Some(Err(rdkafka::error::KafkaError::PartitionEOF(partition))) => {
+                remaining.remove(&partition);
+            }
+            Some(Err(e)) => {
+                Err(KafkaError::Generic(format!("Error while polling: {:?}", e)))?;
+            }
+            None => idle_polls += 1,
+        }
+    }
+    if !remaining.is_empty() {
+        return Err(unread_partitions_error(
+            remaining.iter().map(|&partition| (topic, partition)),
+        ));
+    }
+    Ok(timestamps)
+}
+
+/// Shows the timestamps of the earliest and latest record of every partition, which tell how
+/// far back the data really goes.
+fn print_record_time_range(
+    bootstrap_servers: &str,
+    consumer: &BaseConsumer<ClientAuth>,
+    topic: &str,
+) -> Result<(), KafkaError> {
+    let watermarks = topic_watermarks(consumer, topic)?;
+    let non_empty = || watermarks.iter().filter(|(_, low, high)| low < high);
+    let earliest = read_edge_timestamps(
+        bootstrap_servers,
+        topic,
+        &non_empty().map(|&(p, low, _)| (p, low)).collect::<Vec<_>>(),
+        false,
+    )?;
+    let latest = read_edge_timestamps(
+        bootstrap_servers,
+        topic,
+        &non_empty()
+            .map(|&(p, low, high)| (p, (high - LATEST_RECORD_LOOKBACK).max(low)))
+            .collect::<Vec<_>>(),
+        true,
+    )?;
+
+    let time = |timestamps: &HashMap<i32, i64>, partition: i32| {
+        timestamps
+            .get(&partition)
+            .map_or("-".to_string(), |millis| output::timestamp(*millis))
+    };
+    let mut table = Table::new();
+    table.add_row(row!["Partition ID", "Earliest Record", "Latest Record"]);
+    for &(partition, _, _) in &watermarks {
+        table.add_row(row![
+            partition,
+            time(&earliest, partition),
+            time(&latest, partition)
+        ]);
+    }
+    output::print_table(&table);
+    Ok(())
+}
 
+fn print_topic_configs(bootstrap_servers: &str, topic: &str) -> Result<(), KafkaError> {
+    let admin_client = admin::get_admin_client(bootstrap_servers)?;
+    let configs = admin::describe_topic_configs(&admin_client, &[topic])?;
+    let entries = configs.get(topic).map(Vec::as_slice).unwrap_or(&[]);
+    let configs: BTreeMap<String, String> = non_default_configs(entries, &[]).into_iter().collect();
+    if configs.is_empty() {
+        println!("Topic {} uses the broker defaults for every config", topic);
+        return Ok(());
+    }
+    let mut table = Table::new();
+    table.add_row(row!["Config", "Value"]);
+    for (name, value) in &configs {
+        table.add_row(row![name, output::config_value(name, value)]);
+    }
+    output::print_table(&table);
     Ok(())
 }
 
+/// Lists the principals that can read or write `topic`. Clusters without an authorizer reject
+/// the request, which is reported without failing the rest of the details.
+fn print_topic_access(consumer: &BaseConsumer<ClientAuth>, topic: &str) {
+    let acls = match admin::describe_acls(consumer) {
+        Ok(acls) => acls,
+        Err(er) => {
+            output::status(format!("Could not describe ACLs: {}", er));
+            return;
+        }
+    };
+    let access = topic_access(&acls, topic);
+    if access.is_empty() {
+        println!("No ACL lets a principal read or write {}", topic);
+        return;
+    }
+    let mark = |allowed: bool| if allowed { "yes" } else { "-" };
+    let mut table = Table::new();
+    table.add_row(row!["Principal", "Read", "Write"]);
+    for (principal, read, write) in &access {
+        table.add_row(row![principal, mark(*read), mark(*write)]);
+    }
+    output::print_table(&table);
+}
+
+type TopicDetail<'a> = ([&'a str; 3], [String; 3], [&'a str; 7], Vec<[String; 7]>);
+
 fn get_topic_detail_inner<'a>(
-    consumer: &'a BaseConsumer,
+    consumer: &'a BaseConsumer<ClientAuth>,
     topic: &'a str,
-) -> Result<([&'a str; 3], [String; 3], [&'a str; 3], Vec<[String; 3]>), KafkaError> {
+    racks: &HashMap<i32, String>,
+) -> Result<TopicDetail<'a>, KafkaError> {
     let topic_detail = consumer
         .fetch_metadata(Option::Some(topic), std::time::Duration::from_secs(10))
         .map_err(|er| {
@@ -140,10 +400,18 @@ fn get_topic_detail_inner<'a>(
         })?;
 
     let overall_header = ["Partitions", "Partition IDs", "Total Messages"];
-    let partition_detail_header = ["Partition ID", "Leader", "Offset"];
+    let partition_detail_header = [
+        "Partition ID",
+        "Leader",
+        "Offset",
+        "Replicas",
+        "ISR",
+        "Health",
+        "Rack Spread",
+    ];
 
     let topci_metadata = &topic_detail.topics()[0];
-    if topci_metadata.partitions().len() == 0 {
+    if topci_metadata.partitions().is_empty() {
         return Err(KafkaError::TopicNotExists(format!(
             "Topic {} does not exist",
             topic
@@ -156,7 +424,7 @@ fn get_topic_detail_inner<'a>(
         topci_metadata.partitions().iter().fold(
             (String::new(), vec![], 0),
             |(mut partition_ids, mut partition_detail, mut total_messages), p| {
-                let partition_result = partition_detail_inner(p, topic, consumer);
+                let partition_result = partition_detail_inner(p, topic, consumer, racks);
                 if let Ok((ids, detail, messages)) = partition_result {
                     partition_ids.push_str(&ids);
                     partition_ids.push_str(", ");
@@ -187,8 +455,9 @@ fn get_topic_detail_inner<'a>(
 fn partition_detail_inner(
     p: &MetadataPartition,
     topic: &str,
-    consumer: &BaseConsumer,
-) -> Result<(String, Vec<[String; 3]>, i64), KafkaError> {
+    consumer: &BaseConsumer<ClientAuth>,
+    racks: &HashMap<i32, String>,
+) -> Result<(String, Vec<[String; 7]>, i64), KafkaError> {
     let mut partition_ids = String::new();
     let mut partition_detail = vec![];
     let mut total_messages = 0;
@@ -219,15 +488,87 @@ fn partition_detail_inner(
         p.id().to_string(),
         p.leader().to_string(),
         partion_offset.to_string(),
+        join_ids(p.replicas()),
+        join_ids(p.isr()),
+        partition_health(p.leader(), p.replicas(), p.isr()).to_string(),
+        describe_rack_spread(p.replicas(), racks),
     ]);
 
     Ok((partition_ids, partition_detail, total_messages))
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-struct Assignment {
-    topic: String,
-    partitions: Vec<i32>,
+fn join_ids(ids: &[i32]) -> String {
+    ids.iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Broker configs that are expected to differ from broker to broker.
+const BROKER_IDENTITY_CONFIGS: [&str; 5] = [
+    "broker.id",
+    "node.id",
+    "broker.rack",
+    "listeners",
+    "advertised.listeners",
+];
+
+/// Keeps the explicitly set configs of a resource, leaving out sensitive values and `skip`.
+fn non_default_configs(entries: &[ConfigEntry], skip: &[&str]) -> HashMap<String, String> {
+    entries
+        .iter()
+        .filter(|e| !e.is_default && !e.is_sensitive && !skip.contains(&e.name.as_str()))
+        .map(|e| (e.name.clone(), e.value.clone().unwrap_or_default()))
+        .collect()
+}
+
+/// Lists, by config name, the configs whose value is not the same in every column. `None`
+/// marks a column where the config is left at its default.
+fn diff_configs(columns: &[HashMap<String, String>]) -> Vec<(String, Vec<Option<String>>)> {
+    let names: BTreeSet<&String> = columns.iter().flat_map(|c| c.keys()).collect();
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let values: Vec<Option<String>> =
+                columns.iter().map(|c| c.get(name).cloned()).collect();
+            if values.iter().all(|v| *v == values[0]) {
+                None
+            } else {
+                Some((name.clone(), values))
+            }
+        })
+        .collect()
+}
+
+fn partition_health(leader: i32, replicas: &[i32], isr: &[i32]) -> &'static str {
+    if leader < 0 {
+        "offline"
+    } else if isr.len() < replicas.len() {
+        "under-replicated"
+    } else {
+        "in-sync"
+    }
+}
+
+/// Tells whether the replicas of a partition live in more than one rack.
+fn describe_rack_spread(replicas: &[i32], racks: &HashMap<i32, String>) -> String {
+    let replica_racks: BTreeSet<&str> = replicas
+        .iter()
+        .filter_map(|r| racks.get(r).map(|rack| rack.as_str()))
+        .collect();
+    if replica_racks.is_empty() {
+        return "-".to_string();
+    }
+    let names = replica_racks
+        .iter()
+        .copied()
+        .collect::<Vec<&str>>()
+        .join(", ");
+    if replica_racks.len() > 1 {
+        format!("yes ({})", names)
+    } else {
+        format!("no ({})", names)
+    }
 }
 
 fn deserialize_assignment(data: &[u8]) -> Result<HashMap<String, Vec<i32>>, KafkaError> {
@@ -236,23 +577,23 @@ fn deserialize_assignment(data: &[u8]) -> Result<HashMap<String, Vec<i32>>, Kafk
 
     // Read the version
     let _version = cursor.read_i16::<BigEndian>().map_err(|er| {
-        KafkaError::Deserialize(format!("Error while reading assignment version:"), er)
+        KafkaError::Deserialize("Error while reading assignment version:".to_string(), er)
     })?;
 
     // Read the number of topics
-    let topic_count = cursor
-        .read_i32::<BigEndian>()
-        .map_err(|er| KafkaError::Deserialize(format!("Error while reading topic count:"), er))?;
+    let topic_count = cursor.read_i32::<BigEndian>().map_err(|er| {
+        KafkaError::Deserialize("Error while reading topic count:".to_string(), er)
+    })?;
 
     for _ in 0..topic_count {
         // Read the topic name
         let topic_len = cursor.read_i16::<BigEndian>().map_err(|er| {
-            KafkaError::Deserialize(format!("Error while reading topic length:"), er)
+            KafkaError::Deserialize("Error while reading topic length:".to_string(), er)
         })? as usize;
 
         let mut topic_bytes = vec![0; topic_len];
         cursor.read_exact(&mut topic_bytes).map_err(|er| {
-            KafkaError::Deserialize(format!("Error while reading topic name:"), er)
+            KafkaError::Deserialize("Error while reading topic name:".to_string(), er)
         })?;
 
         let topic = String::from_utf8(topic_bytes).map_err(|er| {
@@ -261,12 +602,12 @@ fn deserialize_assignment(data: &[u8]) -> Result<HashMap<String, Vec<i32>>, Kafk
 
         // Read the number of partitions
         let partition_count = cursor.read_i32::<BigEndian>().map_err(|er| {
-            KafkaError::Deserialize(format!("Error while reading partition count:"), er)
+            KafkaError::Deserialize("Error while reading partition count:".to_string(), er)
         })?;
         let mut partitions = Vec::new();
         for _ in 0..partition_count {
             let partition = cursor.read_i32::<BigEndian>().map_err(|er| {
-                KafkaError::Deserialize(format!("Error while reading partition:"), er)
+                KafkaError::Deserialize("Error while reading partition:".to_string(), er)
             })?;
             partitions.push(partition);
         }
@@ -277,240 +618,1390 @@ fn deserialize_assignment(data: &[u8]) -> Result<HashMap<String, Vec<i32>>, Kafk
     Ok(assignments)
 }
 
-pub fn list_consumers_for_topic(consumer: &BaseConsumer, topic: &str) -> Result<(), KafkaError> {
+/// Lists the groups consuming `topic`: the ones with members assigned to it, and stopped ones
+/// that still have offsets committed on it, marked as inactive. With `max_lag` the partition
+/// each group lags most on is shown too.
+pub fn list_consumers_for_topic(
+    bootstrap_servers: &str,
+    consumer: &BaseConsumer<ClientAuth>,
+    topic: &str,
+    max_lag: bool,
+) -> Result<(), KafkaError> {
     let groups = consumer
-        .fetch_group_list(None, std::time::Duration::from_secs(10))
+        .fetch_group_list(None, Duration::from_secs(10))
         .map_err(|er| {
-            if let rdkafka::error::KafkaError::GroupListFetch(_) = er {
-                KafkaError::Generic("Error while fetching consumer groups".to_string())
-            } else {
-                KafkaError::Generic("Error while fetching consumer groups".to_string())
-            }
+            KafkaError::GroupListFetch("Error while fetching consumer groups".to_string(), er)
+        })?;
+    let metadata = consumer
+        .fetch_metadata(Some(topic), Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::MetadataFetch("Error while fetching topic metadata".to_string(), er)
         })?;
+    let tpl = all_topic_partitions(&metadata);
+    let end_offsets = fetch_end_offsets(consumer, &tpl)?;
 
+    let mut table = Table::new();
+    let mut header = vec![
+        "Group ID",
+        "State",
+        "Protocol Type",
+        "Protocol",
+        "Status",
+        "Lag",
+    ];
+    if max_lag {
+        header.extend(["Max Lag", "Max Lag Partition"]);
+    }
+    table.add_row(header.into());
+    let progress =
+        output::Progress::new("Fetching group offsets", Some(groups.groups().len() as u64));
     for group in groups.groups() {
-        let mut is_consuming = false;
-        if group.state() == "Stable" {
-            for member in group.members() {
-                let assignment = member.assignment();
-                if assignment.is_none() {
-                    continue;
-                }
-                println!("Assignment: {:?}", assignment);
-                let assignment = deserialize_assignment(assignment.unwrap())?;
-                if assignment.contains_key(topic) {
-                    is_consuming = true;
-                    break;
-                }
+        if !is_consumer_group(group) {
+            progress.inc(1);
+            continue;
+        }
+        let assigned = group_assigns_topic(group.members(), topic);
+        let lags = fetch_group_lag(bootstrap_servers, group.name(), &tpl, &end_offsets);
+        progress.inc(1);
+        let lags = match lags {
+            Ok(lags) => lags,
+            Err(er) => {
+                output::status(format!("Skipped group {}: {}", group.name(), er));
+                continue;
             }
-
-            if is_consuming {
-                let mut table = Table::new();
-                table.add_row(row!["Group ID", "State", "Protocol Type", "Protocol"]);
-
-                table.add_row(row![
-                    group.name(),
-                    group.state(),
-                    group.protocol_type(),
-                    group.protocol()
-                ]);
-                table.printstd();
-
-                for member in group.members() {
-                    let assignment = member.assignment();
-                    if assignment.is_none() {
-                        continue;
-                    }
-                    let assignment = deserialize_assignment(assignment.unwrap())?;
-                    if assignment.contains_key(topic) {}
-                }
+        };
+        let Some(status) = topic_consumer_status(assigned, &lags) else {
+            continue;
+        };
+        let mut row = vec![
+            group.name().to_string(),
+            group.state().to_string(),
+            group.protocol_type().to_string(),
+            group.protocol().to_string(),
+            status.to_string(),
+            output::count(lags.iter().map(|l| l.lag).sum()),
+        ];
+        if max_lag {
+            match most_lagging_partition(&lags) {
+                Some(lag) => row.extend([output::count(lag.lag), lag.partition.to_string()]),
+                None => row.extend(["-".to_string(), "-".to_string()]),
             }
         }
+        table.add_row(row.into());
+    }
+    progress.finish();
+    if table.len() > 1 {
+        output::print_table(&table);
     }
     Ok(())
 }
 
-pub fn tail_topic(
-    bootstrap_servers: &str,
-    topic: &str,
-    filter: Option<String>,
-) -> Result<(), KafkaError> {
-    let consumer = get_consumer(bootstrap_servers);
+/// The partition with the most lag, the lowest one on a tie.
+fn most_lagging_partition(lags: &[PartitionLag]) -> Option<&PartitionLag> {
+    lags.iter().max_by(|a, b| {
+        a.lag
+            .cmp(&b.lag)
+            .then_with(|| b.partition.cmp(&a.partition))
+    })
+}
 
-    consumer
-        .subscribe(&[topic])
-        .map_err(|er| KafkaError::Generic(format!("Error while subscribing to topic: {:?}", er)))?;
+/// Whether a group consumes a topic it has `lags` for, if at all.
+fn topic_consumer_status(assigned: bool, lags: &[PartitionLag]) -> Option<&'static str> {
+    if assigned {
+        Some("Active")
+    } else if !lags.is_empty() {
+        Some("Inactive")
+    } else {
+        None
+    }
+}
 
-    loop {
-        match consumer.poll(Duration::from_millis(100)) {
-            Some(Ok(message)) => {
-                let payload = message
-                    .payload_view::<str>()
-                    .unwrap_or(Ok(""))
-                    .unwrap_or("");
-                let _ = message.key_view::<str>().unwrap_or(Ok("")).unwrap_or("");
-
-                if let Ok(json) = serde_json::from_str::<Value>(payload) {
-                    if let Some(filter) = &filter {
-                        if apply_filter(&json, filter) {
-                            let colored_json = colorize_json(&json);
-                            println!("{}", colored_json);
-                        }
-                    } else {
-                        let colored_json = colorize_json(&json);
-                        println!("{}", colored_json);
-                    }
-                }
-            }
-            Some(Err(e)) => {
-                Err(KafkaError::Generic(format!("Error while polling: {:?}", e)))?;
-            }
-            None => {
-                // No message received, continue polling
-            }
+/// Turns binary payloads into JSON for printing and filtering.
+pub trait PayloadDecoder {
+    fn to_json(&self, payload: &[u8]) -> Result<serde_json::Value, String>;
+}
+
+/// How `tail_topic` picks the records to print.
+pub struct TailOptions<'a> {
+    pub filter: Option<&'a str>,
+    /// Decodes binary payloads, which are read as JSON or XML otherwise
+    pub decoder: Option<&'a dyn PayloadDecoder>,
+    /// Where to start, the end of the topic, or the group's offsets with `commit`, when `None`
+    pub start: Option<StartPosition>,
+    /// Keep waiting for new records instead of stopping at the end offsets seen at startup
+    pub follow: bool,
+    /// Records printed per second at most
+    pub max_rate: Option<f64>,
+    /// Fraction of the records to print, between 0 and 1
+    pub sample: Option<f64>,
+    /// How payloads are read when there is no decoder
+    pub format: PayloadFormat,
+    /// Print only the latest record per key out of every this many records
+    pub dedupe_window: Option<usize>,
+    /// Warn about skipped offsets and list them when the tail ends
+    pub detect_gaps: bool,
+    /// Dotted paths of the JSON fields to mask, `*` matching any field
+    pub redact: &'a [String],
+    /// Print JSON records on one line as `path=value` pairs
+    pub flatten: bool,
+    /// Print records as they are or as JSON Lines with their coordinates
+    pub output: TailOutput,
+    /// Name to save the offsets read under, and resume them from
+    pub session: Option<&'a str>,
+    /// Matches to show in reverse video, on top of the filtered field
+    pub highlight: Option<&'a Regex>,
+    /// Keep a line of live counts at the bottom of the terminal
+    pub stats: bool,
+    /// Commit the offsets read as the environment's group, starting from its committed ones
+    pub commit: bool,
+}
+
+/// Keeps an even share of the records: with a fraction of 0.25 every fourth record is kept.
+struct Sampler {
+    fraction: f64,
+    credit: f64,
+}
+
+impl Sampler {
+    fn new(fraction: f64) -> Self {
+        Sampler {
+            fraction,
+            credit: 0.0,
+        }
+    }
+
+    fn keep(&mut self) -> bool {
+        self.credit += self.fraction;
+        if self.credit >= 1.0 {
+            self.credit -= 1.0;
+            true
+        } else {
+            false
         }
     }
 }
 
-fn apply_filter(json: &Value, filter: &str) -> bool {
-    let parts: Vec<&str> = filter.split('=').collect();
-    let path = parts[0];
-    let path_parts: Vec<&str> = path.split('.').collect();
-    let mut current = json;
+/// Token bucket allowing `rate` records per second with bursts of up to one second's worth.
+struct RateLimiter {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
 
-    for part in path_parts {
-        match current.get(part) {
-            Some(value) => current = value,
-            None => return false,
+impl RateLimiter {
+    fn new(rate: f64, now: Instant) -> Self {
+        RateLimiter {
+            rate,
+            tokens: rate.max(1.0),
+            last: now,
         }
     }
 
-    if parts.len() == 2 {
-        let expected_value = parts[1];
-        let current_value = current.to_string().replace("\"", "");
-        return current_value == expected_value;
+    fn allow(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate.max(1.0));
+        self.last = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
     }
-
-    true
 }
 
-fn colorize_json(json: &Value) -> String {
-    to_colored_json_auto(json).unwrap_or_else(|_| "Invalid JSON".to_string())
+/// Holds back records so that out of every `window` only the latest one per key is released.
+/// Records without a key are never merged.
+struct Deduper<T> {
+    window: usize,
+    seen: usize,
+    next: usize,
+    latest: HashMap<Vec<u8>, usize>,
+    pending: BTreeMap<usize, T>,
 }
 
-pub fn get_broker_detail(bootstrap_servers: &str) -> Result<(), KafkaError> {
-    let consumer = get_consumer(bootstrap_servers);
-    let metadata: KafkaResult<Metadata> =
-        consumer.fetch_metadata(None, std::time::Duration::from_secs(10));
+impl<T> Deduper<T> {
+    fn new(window: usize) -> Self {
+        Deduper {
+            window,
+            seen: 0,
+            next: 0,
+            latest: HashMap::new(),
+            pending: BTreeMap::new(),
+        }
+    }
 
-    match metadata {
-        Ok(metadata) => {
-            let mut table = Table::new();
-            table.add_row(row!["Broker ID", "Host", "Port"]);
-            metadata.brokers().iter().for_each(|b| {
-                table.add_row(row![b.id(), b.host(), b.port()]);
-            });
-            table.printstd();
+    /// Adds a record, returning true once the window is full and should be flushed.
+    fn push(&mut self, key: Option<&[u8]>, record: T) -> bool {
+        let seq = self.next;
+        self.next += 1;
+        if let Some(key) = key {
+            if let Some(previous) = self.latest.insert(key.to_vec(), seq) {
+                self.pending.remove(&previous);
+            }
         }
-        Err(e) => {
-            println!("Error while getting brokers: {:?}", e);
+        self.pending.insert(seq, record);
+        self.seen += 1;
+        self.seen >= self.window
+    }
+
+    /// Releases the held records in arrival order of their latest version.
+    fn flush(&mut self) -> Vec<T> {
+        self.seen = 0;
+        self.latest.clear();
+        std::mem::take(&mut self.pending).into_values().collect()
+    }
+}
+
+/// Offsets skipped between consecutive records of a partition. Aborted transactions, their
+/// commit markers and compaction leave gaps too, not only lost records.
+#[derive(Default)]
+struct GapDetector {
+    last: HashMap<i32, i64>,
+    gaps: Vec<(i32, i64, i64)>,
+}
+
+impl GapDetector {
+    /// Records a consumed offset, returning the first and last offset skipped before it.
+    fn observe(&mut self, partition: i32, offset: i64) -> Option<(i64, i64)> {
+        let previous = self.last.insert(partition, offset)?;
+        if offset <= previous + 1 {
+            return None;
         }
+        self.gaps.push((partition, previous + 1, offset - 1));
+        Some((previous + 1, offset - 1))
     }
+}
 
-    get_broker_detail_inner(bootstrap_servers)
-        .map(|(headers, rows)| print_broker_table(&headers, &rows))?;
+const TAIL_SUMMARY_INTERVAL: Duration = Duration::from_secs(5);
 
-    Ok(())
+/// Prints the tailed records that pass the filter, sampling and rate limit, and keeps count of
+/// the ones it leaves out.
+const TAIL_STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Counts behind the `tail --stats` footer.
+struct TailStats {
+    shown: u64,
+    /// Records read but not printed because of the filter or a decoding error
+    filtered: u64,
+    /// Records read per partition
+    partitions: BTreeMap<i32, u64>,
+    /// Records shown since the footer was last refreshed
+    recent: u64,
+    last_refresh: Instant,
 }
 
-fn get_broker_detail_inner(
-    bootstrap_servers: &str,
-) -> Result<([&str; 3], Vec<[String; 3]>), KafkaError> {
-    let consumer = get_consumer(bootstrap_servers);
-    let metadata = consumer
-        .fetch_metadata(None, Duration::from_secs(10))
-        .map_err(|er| {
-            if let rdkafka::error::KafkaError::MetadataFetch(_) = er {
-                KafkaError::MetadataFetch("Error while fetching broker metadata".to_string(), er)
-            } else {
-                KafkaError::Generic("Error while fetching brokers".to_string())
-            }
-        })?;
+impl TailStats {
+    fn new() -> Self {
+        TailStats {
+            shown: 0,
+            filtered: 0,
+            partitions: BTreeMap::new(),
+            recent: 0,
+            last_refresh: Instant::now(),
+        }
+    }
 
-    let headers = ["Broker ID", "Host", "Port"];
-    let rows: Vec<[String; 3]> = metadata
-        .brokers()
+    /// The footer line, with the rate of records shown since the last refresh.
+    fn render(&mut self) -> String {
+        let elapsed = self.last_refresh.elapsed().as_secs_f64();
+        let rate = if elapsed > 0.0 {
+            self.recent as f64 / elapsed
+        } else {
+            0.0
+        };
+        self.recent = 0;
+        self.last_refresh = Instant::now();
+        format_tail_stats(rate, self.shown, self.filtered, &self.partitions)
+    }
+}
+
+fn format_tail_stats(
+    rate: f64,
+    shown: u64,
+    filtered: u64,
+    partitions: &BTreeMap<i32, u64>,
+) -> String {
+    let partitions: Vec<String> = partitions
         .iter()
-        .map(|b| {
-            [
-                b.id().to_string(),
-                b.host().to_string(),
-                b.port().to_string(),
-            ]
-        })
+        .map(|(partition, count)| format!("p{}={}", partition, output::count(*count as i64)))
         .collect();
-    Ok((headers, rows))
+    format!(
+        "{:.1} msg/s | shown {} | filtered out {} | {}",
+        rate,
+        output::count(shown as i64),
+        output::count(filtered as i64),
+        partitions.join(" ")
+    )
 }
 
-fn print_broker_table(headers: &[&str; 3], rows: &[[String; 3]]) {
-    let mut table = Table::new();
-    table.add_row(row![headers[0], headers[1], headers[2]]);
-    for row in rows {
-        table.add_row(row![row[0], row[1], row[2]]);
+/// Records held while a tail is paused from the keyboard
+const PAUSE_BUFFER: usize = 10_000;
+
+/// The records rendered while paused, the oldest dropped once the buffer is full.
+#[derive(Default)]
+struct PausedRecords {
+    records: VecDeque<(Option<i64>, String)>,
+    dropped: usize,
+}
+
+impl PausedRecords {
+    fn push(&mut self, record: (Option<i64>, String)) {
+        if self.records.len() == PAUSE_BUFFER {
+            self.records.pop_front();
+            self.dropped += 1;
+        }
+        self.records.push_back(record);
     }
-    table.printstd();
 }
 
-pub fn get_consumer_groups(bootstrap_servers: &str) -> Result<(), KafkaError> {
-    get_consumer_groups_inner(bootstrap_servers)
-        .map(|(headers, rows)| print_consumer_groups_table(&headers, &rows))?;
-    Ok(())
+struct TailPrinter<'a> {
+    filter: Option<String>,
+    highlight: Option<&'a Regex>,
+    decoder: Option<&'a dyn PayloadDecoder>,
+    format: PayloadFormat,
+    sampler: Option<Sampler>,
+    limiter: Option<RateLimiter>,
+    deduper: Option<Deduper<(Option<i64>, String)>>,
+    gaps: Option<GapDetector>,
+    redact: Vec<Vec<&'a str>>,
+    flatten: bool,
+    jsonl: bool,
+    highlights: Vec<Regex>,
+    stats: TailStats,
+    /// Keep the counts in the footer
+    live_stats: bool,
+    /// Print the counts when the tail ends
+    report_stats: bool,
+    stats_line: String,
+    paused: Option<PausedRecords>,
+    footer: output::Footer,
+    sampled_out: usize,
+    rate_limited: usize,
+    last_summary: Instant,
 }
 
-fn get_consumer_groups_inner(
-    bootstrap_servers: &str,
-) -> Result<([&str; 4], Vec<[String; 4]>), KafkaError> {
-    let consumer = get_consumer(bootstrap_servers);
-    let groups = consumer
-        .fetch_group_list(None, Duration::from_secs(10))
-        .map_err(|er| {
-            if let rdkafka::error::KafkaError::GroupListFetch(_) = er {
-                KafkaError::GroupListFetch("Error while fetching consumer groups".to_string(), er)
+impl<'a> TailPrinter<'a> {
+    fn new(options: &TailOptions<'a>) -> Self {
+        TailPrinter {
+            filter: options.filter.map(String::from),
+            highlight: options.highlight,
+            decoder: options.decoder,
+            format: options.format,
+            sampler: options.sample.map(Sampler::new),
+            limiter: options
+                .max_rate
+                .map(|rate| RateLimiter::new(rate, Instant::now())),
+            deduper: options.dedupe_window.map(Deduper::new),
+            gaps: options.detect_gaps.then(GapDetector::default),
+            redact: options
+                .redact
+                .iter()
+                .map(|path| path.split('.').collect())
+                .collect(),
+            flatten: options.flatten,
+            jsonl: options.output == TailOutput::Jsonl,
+            // Escape codes would end up in files and pipes
+            highlights: if std::io::stdout().is_terminal() && options.output == TailOutput::Pretty {
+                highlight_patterns(options.filter, options.highlight)
             } else {
-                KafkaError::Generic("Error while fetching consumer groups".to_string())
+                vec![]
+            },
+            stats: TailStats::new(),
+            live_stats: options.stats,
+            report_stats: options.stats,
+            stats_line: String::new(),
+            paused: None,
+            footer: output::Footer::new(),
+            sampled_out: 0,
+            rate_limited: 0,
+            last_summary: Instant::now(),
+        }
+    }
+
+    fn print(&mut self, message: &BorrowedMessage) {
+        self.footer.clear();
+        self.print_record(message);
+        self.footer.redraw();
+    }
+
+    fn print_record(&mut self, message: &BorrowedMessage) {
+        *self
+            .stats
+            .partitions
+            .entry(message.partition())
+            .or_default() += 1;
+        if let Some((first, last)) = self
+            .gaps
+            .as_mut()
+            .and_then(|gaps| gaps.observe(message.partition(), message.offset()))
+        {
+            output::status(format!(
+                "Gap on partition {}: offsets {} to {} were skipped",
+                message.partition(),
+                first,
+                last
+            ));
+        }
+        let Some(rendered) = self.render(message) else {
+            self.stats.filtered += 1;
+            return;
+        };
+        let timestamp = message.timestamp().to_millis();
+        match self.deduper.as_mut() {
+            Some(deduper) => {
+                if deduper.push(message.key(), (timestamp, rendered)) {
+                    self.flush();
+                }
+            }
+            None => self.emit(timestamp, rendered),
+        }
+    }
+
+    /// Prints the records held back for deduplication.
+    fn flush(&mut self) {
+        let records = self
+            .deduper
+            .as_mut()
+            .map(Deduper::flush)
+            .unwrap_or_default();
+        for (timestamp, rendered) in records {
+            self.emit(timestamp, rendered);
+        }
+    }
+
+    fn emit(&mut self, timestamp: Option<i64>, rendered: String) {
+        if self.sampler.as_mut().is_some_and(|s| !s.keep()) {
+            self.sampled_out += 1;
+            return;
+        }
+        if self
+            .limiter
+            .as_mut()
+            .is_some_and(|l| !l.allow(Instant::now()))
+        {
+            self.rate_limited += 1;
+            return;
+        }
+        match self.paused.as_mut() {
+            Some(paused) => paused.push((timestamp, rendered)),
+            None => self.write(timestamp, rendered),
+        }
+    }
+
+    fn write(&mut self, timestamp: Option<i64>, rendered: String) {
+        if self.jsonl {
+            println!("{}", rendered);
+            // Stream processors act on each record as it arrives
+            let _ = std::io::stdout().flush();
+        } else {
+            if output::show_record_time() {
+                if let Some(millis) = timestamp {
+                    println!("[{}]", output::timestamp(millis));
+                }
+            }
+            println!("{}", rendered);
+        }
+        self.stats.shown += 1;
+        self.stats.recent += 1;
+    }
+
+    /// Holds the records instead of printing them, or prints the ones held when already paused.
+    fn toggle_pause(&mut self) {
+        match self.paused.take() {
+            None => self.paused = Some(PausedRecords::default()),
+            Some(paused) => {
+                self.footer.clear();
+                if paused.dropped > 0 {
+                    output::status(format!(
+                        "Dropped {} records while paused, the oldest ones",
+                        paused.dropped
+                    ));
+                }
+                for (timestamp, rendered) in paused.records {
+                    self.write(timestamp, rendered);
+                }
+            }
+        }
+        self.update_footer();
+    }
+
+    /// Filters the records read from now on with `filter`, or shows them all with `None`.
+    fn set_filter(&mut self, filter: Option<String>) {
+        if std::io::stdout().is_terminal() && !self.jsonl {
+            self.highlights = highlight_patterns(filter.as_deref(), self.highlight);
+        }
+        self.filter = filter;
+    }
+
+    /// The clause for the footer while paused, `None` when running.
+    fn pause_note(&self) -> Option<String> {
+        self.paused.as_ref().map(|paused| {
+            format!(
+                "paused, {} held, space to resume",
+                output::count(paused.records.len() as i64)
+            )
+        })
+    }
+
+    fn update_footer(&mut self) {
+        let parts: Vec<String> = [
+            Some(self.stats_line.clone()).filter(|line| !line.is_empty()),
+            self.pause_note(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        self.footer.update(parts.join(" | "));
+    }
+
+    /// Decodes, filters and formats a record, `None` when it is not printed.
+    fn render(&self, message: &BorrowedMessage) -> Option<String> {
+        let json = match self.decoder {
+            Some(decoder) => match decoder
+                .to_json(message.payload().unwrap_or_default())
+                .and_then(|json| serde_json::from_value::<Value>(json).map_err(|er| er.to_string()))
+            {
+                Ok(json) => json,
+                Err(er) => {
+                    output::status(format!(
+                        "Skipped {}:{}: {}",
+                        message.partition(),
+                        message.offset(),
+                        er
+                    ));
+                    return None;
+                }
+            },
+            None => {
+                let payload = String::from_utf8_lossy(message.payload().unwrap_or_default());
+                let payload = payload.as_ref();
+                let is_xml = match self.format {
+                    PayloadFormat::Xml => true,
+                    PayloadFormat::Json => false,
+                    PayloadFormat::Auto => payload.trim_start().starts_with('<'),
+                };
+                if is_xml {
+                    // Filters address JSON fields, so they never match an XML record
+                    if self.filter.is_some() {
+                        return None;
+                    }
+                    // Nor can fields be masked, so the record is held back rather than leaked
+                    if !self.redact.is_empty() {
+                        output::status(format!(
+                            "Skipped {}:{}: --redact only applies to JSON records",
+                            message.partition(),
+                            message.offset()
+                        ));
+                        return None;
+                    }
+                    if self.jsonl {
+                        return Some(jsonl_record(message, payload));
+                    }
+                    let colored = std::io::stdout().is_terminal();
+                    let rendered =
+                        xml::pretty_print(payload, colored).unwrap_or_else(|_| payload.to_string());
+                    return Some(highlight_matches(&rendered, &self.highlights));
+                }
+                match serde_json::from_str::<Value>(payload) {
+                    Ok(json) => json,
+                    // JSON Lines keep every record, with other payloads as strings
+                    Err(_) if self.jsonl && self.filter.is_none() && self.redact.is_empty() => {
+                        let value = message.payload().map(|_| payload);
+                        return Some(jsonl_record(message, value));
+                    }
+                    Err(_) => return None,
+                }
+            }
+        };
+        if !self
+            .filter
+            .as_deref()
+            .is_none_or(|f| apply_filter(&json, f))
+        {
+            return None;
+        }
+        let mut json = json;
+        for path in &self.redact {
+            redact_field(&mut json, path);
+        }
+        if self.jsonl {
+            return Some(jsonl_record(message, &json));
+        }
+        let rendered = if self.flatten {
+            flatten_json(&json)
+        } else {
+            colorize_json(&json)
+        };
+        Some(highlight_matches(&rendered, &self.highlights))
+    }
+
+    /// Prints what is still held back and the final summaries once the tail ends.
+    fn finish(&mut self) {
+        self.footer.clear();
+        self.flush();
+        self.summarize(true);
+        self.footer.clear();
+        if let Some(paused) = self.paused.take() {
+            output::status(format!(
+                "Left {} records unprinted while paused",
+                paused.records.len() + paused.dropped
+            ));
+        }
+        if self.report_stats {
+            output::status(self.stats.render());
+        }
+        let Some(detector) = &self.gaps else {
+            return;
+        };
+        if detector.gaps.is_empty() {
+            output::info("No offset gaps detected");
+            return;
+        }
+        let mut table = Table::new();
+        table.add_row(row![
+            "Partition",
+            "First Missing",
+            "Last Missing",
+            "Missing"
+        ]);
+        for (partition, first, last) in &detector.gaps {
+            table.add_row(row![partition, first, last, last - first + 1]);
+        }
+        output::print_table(&table);
+    }
+
+    /// Reports the records left out since the last summary, once per interval unless `force`.
+    fn summarize(&mut self, force: bool) {
+        if self.live_stats && self.stats.last_refresh.elapsed() >= TAIL_STATS_INTERVAL {
+            self.stats_line = self.stats.render();
+            self.update_footer();
+        } else if self.paused.is_some() {
+            self.update_footer();
+        }
+        if !force && self.last_summary.elapsed() < TAIL_SUMMARY_INTERVAL {
+            return;
+        }
+        if self.sampled_out > 0 || self.rate_limited > 0 {
+            self.footer.clear();
+            output::status(format!(
+                "Skipped {} records by sampling and dropped {} over the rate limit in the last {}s",
+                self.sampled_out,
+                self.rate_limited,
+                self.last_summary.elapsed().as_secs()
+            ));
+            self.sampled_out = 0;
+            self.rate_limited = 0;
+            self.footer.redraw();
+        }
+        self.last_summary = Instant::now();
+    }
+}
+
+/// A record as one line of `tail --output jsonl`. Headers are `[key, value]` pairs in the
+/// order of the record, since a key may repeat.
+#[derive(Serialize)]
+struct JsonlRecord<'a, V: Serialize> {
+    topic: &'a str,
+    partition: i32,
+    offset: i64,
+    /// Epoch milliseconds
+    timestamp: Option<i64>,
+    key: Option<String>,
+    headers: Vec<(&'a str, Option<String>)>,
+    value: V,
+}
+
+/// `message` with its decoded `value` as a line of JSON. Keys and header values that are not
+/// UTF-8 are printed lossily.
+fn jsonl_record<M: Message>(message: &M, value: impl Serialize) -> String {
+    let headers = message
+        .headers()
+        .map(|headers| {
+            headers
+                .iter()
+                .map(|h| {
+                    (
+                        h.key,
+                        h.value.map(|v| String::from_utf8_lossy(v).into_owned()),
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let record = JsonlRecord {
+        topic: message.topic(),
+        partition: message.partition(),
+        offset: message.offset(),
+        timestamp: message.timestamp().to_millis(),
+        key: message
+            .key()
+            .map(|key| String::from_utf8_lossy(key).into_owned()),
+        headers,
+        value,
+    };
+    serde_json::to_string(&record).expect("Records serialize to JSON")
+}
+
+const SESSION_SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Keeps track of the offsets a `tail --session` has read and saves them every few seconds.
+struct SessionTracker<'a> {
+    name: &'a str,
+    session: TailSession,
+    /// Whether the session was saved by an earlier run
+    resumed: bool,
+    last_save: Instant,
+}
+
+impl<'a> SessionTracker<'a> {
+    fn open(name: &'a str, topic: &str) -> Result<Self, KafkaError> {
+        session::check_name(name).map_err(KafkaError::Generic)?;
+        let saved = session::load(name).map_err(|er| {
+            KafkaError::Generic(format!("Failed to load tail session {}: {}", name, er))
+        })?;
+        if let Some(saved) = saved.as_ref().filter(|saved| saved.topic != topic) {
+            return Err(KafkaError::Generic(format!(
+                "Session {} tails topic {}, not {}",
+                name, saved.topic, topic
+            )));
+        }
+        Ok(SessionTracker {
+            name,
+            resumed: saved.is_some(),
+            session: saved.unwrap_or_else(|| TailSession {
+                topic: topic.to_string(),
+                offsets: BTreeMap::new(),
+            }),
+            last_save: Instant::now(),
+        })
+    }
+
+    /// Starts every partition of `watermarks` after its last record read in the session, and
+    /// partitions the session has not seen at their beginning.
+    fn resume(&self, watermarks: Vec<(i32, i64, i64)>) -> Vec<(i32, i64, i64)> {
+        watermarks
+            .into_iter()
+            .map(|(partition, low, high)| {
+                let start = self
+                    .session
+                    .offsets
+                    .get(&partition)
+                    .map_or(low, |&offset| offset.clamp(low, high));
+                (partition, start, high)
+            })
+            .collect()
+    }
+
+    fn observe(&mut self, message: &BorrowedMessage) {
+        self.session
+            .offsets
+            .insert(message.partition(), message.offset() + 1);
+        if self.last_save.elapsed() >= SESSION_SAVE_INTERVAL {
+            self.save();
+        }
+    }
+
+    fn save(&mut self) {
+        if let Err(er) = session::save(self.name, &self.session) {
+            output::status(format!("Failed to save tail session {}: {}", self.name, er));
+        }
+        self.last_save = Instant::now();
+    }
+}
+
+pub fn tail_topic(
+    bootstrap_servers: &str,
+    topic: &str,
+    options: TailOptions,
+) -> Result<(), KafkaError> {
+    let consumer = if options.commit {
+        get_committing_consumer(bootstrap_servers)?
+    } else {
+        get_consumer(bootstrap_servers)?
+    };
+    let mut printer = TailPrinter::new(&options);
+    let mut tracker = options
+        .session
+        .map(|name| SessionTracker::open(name, topic))
+        .transpose()?;
+    let resumed = tracker.as_ref().filter(|tracker| tracker.resumed);
+    if let Some(tracker) = resumed {
+        output::status(format!("Resuming session {}", tracker.name));
+    }
+
+    if !options.follow {
+        let ranges = match resumed {
+            Some(tracker) => {
+                tracker.resume(ranges_from(&consumer, topic, &StartPosition::Beginning)?)
+            }
+            None => {
+                let start = options.start.as_ref().unwrap_or(&StartPosition::Beginning);
+                ranges_from(&consumer, topic, start)?
+            }
+        };
+        read_ranges(&consumer, topic, &ranges, usize::MAX, |message| {
+            printer.print(message);
+            printer.summarize(false);
+            if let Some(tracker) = tracker.as_mut() {
+                tracker.observe(message);
             }
         })?;
+        printer.finish();
+        if let Some(tracker) = tracker.as_mut() {
+            tracker.save();
+        }
+        return Ok(());
+    }
 
-    let headers = ["Group ID", "State", "Protocol Type", "Protocol"];
+    let ranges = match (resumed, &options.start) {
+        (Some(tracker), _) => {
+            Some(tracker.resume(ranges_from(&consumer, topic, &StartPosition::Beginning)?))
+        }
+        (None, Some(start)) => Some(ranges_from(&consumer, topic, start)?),
+        // Only a committing tail joins its group, to start at the group's offsets
+        (None, None) if options.commit => None,
+        (None, None) => Some(ranges_from(&consumer, topic, &StartPosition::End)?),
+    };
+    match ranges {
+        Some(ranges) => {
+            let mut assignment = TopicPartitionList::new();
+            for (partition, offset, _) in ranges {
+                assignment
+                    .add_partition_offset(topic, partition, Offset::Offset(offset))
+                    .map_err(|er| {
+                        KafkaError::OffsetFetch("Error while preparing offsets".to_string(), er)
+                    })?;
+            }
+            consumer.assign(&assignment).map_err(|er| {
+                KafkaError::Generic(format!("Error while assigning partitions: {:?}", er))
+            })?;
+        }
+        None => {
+            consumer.subscribe(&[topic]).map_err(|er| {
+                KafkaError::Generic(format!("Error while subscribing to topic: {:?}", er))
+            })?;
+        }
+    }
 
-    let rows: Vec<[String; 4]> = groups
-        .groups()
+    let stopped = stop_on_ctrl_c()?;
+    let mut keyboard = Keyboard::open();
+    if keyboard.is_some() {
+        printer.report_stats = true;
+        output::status("Press space to pause or resume, f to change the filter and q to quit");
+    }
+    while !stopped.load(Ordering::SeqCst) {
+        match keyboard.as_mut().and_then(Keyboard::poll) {
+            Some(KeyAction::TogglePause) => printer.toggle_pause(),
+            Some(KeyAction::EditFilter) => {
+                printer.footer.clear();
+                let prompt = format!(
+                    "Filter [{}], empty for none: ",
+                    printer.filter.as_deref().unwrap_or("none")
+                );
+                if let Some(filter) = keyboard.as_mut().and_then(|k| k.prompt(&prompt)) {
+                    printer.set_filter(Some(filter).filter(|f| !f.is_empty()));
+                }
+                printer.footer.redraw();
+            }
+            Some(KeyAction::Quit) => break,
+            None => {}
+        }
+        printer.summarize(false);
+        match consumer.poll(Duration::from_millis(100)) {
+            Some(Ok(message)) => {
+                printer.print(&message);
+                if let Some(tracker) = tracker.as_mut() {
+                    tracker.observe(&message);
+                }
+            }
+            Some(Err(e)) => {
+                Err(KafkaError::Generic(format!("Error while polling: {:?}", e)))?;
+            }
+            None => {
+                // Caught up, so show what the deduplication window holds so far
+                printer.flush();
+            }
+        }
+    }
+    printer.finish();
+    if let Some(tracker) = tracker.as_mut() {
+        tracker.save();
+    }
+    Ok(())
+}
+
+/// Returns a flag that is raised on Ctrl-C, so long running commands can wrap up before exiting.
+pub fn stop_on_ctrl_c() -> Result<Arc<AtomicBool>, KafkaError> {
+    let stopped = Arc::new(AtomicBool::new(false));
+    let handler_stopped = stopped.clone();
+    ctrlc::set_handler(move || {
+        tracing::info!("Stopping on Ctrl-C");
+        handler_stopped.store(true, Ordering::SeqCst)
+    })
+    .map_err(|er| {
+        KafkaError::Generic(format!("Error while installing the Ctrl-C handler: {}", er))
+    })?;
+    Ok(stopped)
+}
+
+/// Consumes `topic` as `group` at no more than `rate` records per second, committing every
+/// `commit_interval`, until Ctrl-C.
+pub fn simulate_consume(
+    bootstrap_servers: &str,
+    topic: &str,
+    group: &str,
+    rate: f64,
+    commit_interval: Duration,
+) -> Result<(), KafkaError> {
+    let consumer = client::consumer(
+        client::config(bootstrap_servers)
+            .set("group.id", group)
+            .set("enable.auto.commit", "false")
+            .set("auto.offset.reset", "latest"),
+    )
+    .map_err(|er| KafkaError::Generic(format!("Error while creating consumer: {:?}", er)))?;
+    consumer
+        .subscribe(&[topic])
+        .map_err(|er| KafkaError::Generic(format!("Error while subscribing to topic: {:?}", er)))?;
+    output::info(format!(
+        "Consuming {} as {} at up to {}/s, committing every {}s. Press Ctrl-C to stop",
+        topic,
+        group,
+        rate,
+        commit_interval.as_secs_f64()
+    ));
+
+    let stopped = stop_on_ctrl_c()?;
+    let mut limiter = RateLimiter::new(rate, Instant::now());
+    let mut last_commit = Instant::now();
+    let mut consumed = 0;
+    while !stopped.load(Ordering::SeqCst) {
+        if last_commit.elapsed() >= commit_interval {
+            commit_simulation(&consumer, consumed, last_commit.elapsed())?;
+            consumed = 0;
+            last_commit = Instant::now();
+        }
+        if !limiter.allow(Instant::now()) {
+            std::thread::sleep(Duration::from_millis(10));
+            continue;
+        }
+        match consumer.poll(Duration::from_millis(100)) {
+            Some(Ok(_)) => consumed += 1,
+            Some(Err(e)) => {
+                Err(KafkaError::Generic(format!("Error while polling: {:?}", e)))?;
+            }
+            None => {
+                // Caught up with the topic, keep polling
+            }
+        }
+    }
+    commit_simulation(&consumer, consumed, last_commit.elapsed())
+}
+
+fn commit_simulation(
+    consumer: &BaseConsumer<ClientAuth>,
+    consumed: usize,
+    elapsed: Duration,
+) -> Result<(), KafkaError> {
+    // Nothing was consumed since the last commit, or no partitions were assigned yet
+    if consumed == 0 {
+        return Ok(());
+    }
+    consumer
+        .commit_consumer_state(CommitMode::Sync)
+        .map_err(|er| KafkaError::Generic(format!("Error while committing offsets: {:?}", er)))?;
+    output::status(format!(
+        "[{}] Committed {} records ({:.1}/s)",
+        output::now(),
+        consumed,
+        consumed as f64 / elapsed.as_secs_f64().max(0.001)
+    ));
+    Ok(())
+}
+
+fn apply_filter(json: &Value, filter: &str) -> bool {
+    let parts: Vec<&str> = filter.split('=').collect();
+    let path = parts[0];
+    let path_parts: Vec<&str> = path.split('.').collect();
+    let mut current = json;
+
+    for part in path_parts {
+        match current.get(part) {
+            Some(value) => current = value,
+            None => return false,
+        }
+    }
+
+    if parts.len() == 2 {
+        let expected_value = parts[1];
+        let current_value = current.to_string().replace("\"", "");
+        return current_value == expected_value;
+    }
+
+    true
+}
+
+/// What tail highlights: the `--highlight` matches and, with a filter, the filtered field as
+/// printed in JSON (`"status"`) or flattened (`data.status`), and the value it has to have.
+fn highlight_patterns(filter: Option<&str>, highlight: Option<&Regex>) -> Vec<Regex> {
+    let mut patterns: Vec<Regex> = highlight.into_iter().cloned().collect();
+    if let Some(filter) = filter {
+        let (path, value) = match filter.split_once('=') {
+            Some((path, value)) => (path, Some(value)),
+            None => (filter, None),
+        };
+        let field = path.rsplit('.').next().unwrap_or(path);
+        let mut pattern = format!(r#""{}"|\b{}\b"#, regex::escape(field), regex::escape(path));
+        if let Some(value) = value.filter(|v| !v.is_empty()) {
+            pattern.push_str(&format!(r#"|"{0}"|\b{0}\b"#, regex::escape(value)));
+        }
+        patterns.extend(Regex::new(&pattern).ok());
+    }
+    patterns
+}
+
+const HIGHLIGHT_ON: &str = "\x1b[7m";
+const HIGHLIGHT_OFF: &str = "\x1b[27m";
+
+/// Shows the matches of `patterns` in reverse video, which keeps the colors of the JSON. Color
+/// escape codes already in `text` are skipped, so matches never break them up.
+fn highlight_matches(text: &str, patterns: &[Regex]) -> String {
+    if patterns.is_empty() {
+        return text.to_string();
+    }
+    let mut highlighted = String::with_capacity(text.len());
+    let mut rest = text;
+    while !rest.is_empty() {
+        let (plain, escape) = match rest.find("\x1b[") {
+            Some(start) => {
+                let end = rest[start..]
+                    .find('m')
+                    .map_or(rest.len(), |end| start + end + 1);
+                (&rest[..start], &rest[start..end])
+            }
+            None => (rest, ""),
+        };
+        let mut matches: Vec<(usize, usize)> = patterns
+            .iter()
+            .flat_map(|pattern| pattern.find_iter(plain))
+            .filter(|found| !found.is_empty())
+            .map(|found| (found.start(), found.end()))
+            .collect();
+        matches.sort();
+        let mut position = 0;
+        for (start, end) in matches {
+            if end <= position {
+                continue;
+            }
+            let start = start.max(position);
+            highlighted.push_str(&plain[position..start]);
+            highlighted.push_str(HIGHLIGHT_ON);
+            highlighted.push_str(&plain[start..end]);
+            highlighted.push_str(HIGHLIGHT_OFF);
+            position = end;
+        }
+        highlighted.push_str(&plain[position..]);
+        highlighted.push_str(escape);
+        rest = &rest[plain.len() + escape.len()..];
+    }
+    highlighted
+}
+
+const REDACTED: &str = "***";
+
+/// Masks the values at `path`, where `*` matches every field of an object. Arrays are looked
+/// through, so `items.email` masks the email of every item.
+fn redact_field(json: &mut Value, path: &[&str]) {
+    let Some((first, rest)) = path.split_first() else {
+        *json = Value::String(REDACTED.to_string());
+        return;
+    };
+    match json {
+        Value::Table(fields) => {
+            for (name, value) in fields.iter_mut() {
+                if *first == "*" || name == first {
+                    redact_field(value, rest);
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                redact_field(item, path);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Renders a record as `path=value` pairs on one line, e.g. `data.items.0.sku=x`, so that the
+/// output can be grepped and counted. Strings with spaces, quotes or `=` are quoted.
+fn flatten_json(json: &Value) -> String {
+    let mut pairs = vec![];
+    flatten_into(json, String::new(), &mut pairs);
+    pairs.join(" ")
+}
+
+fn flatten_into(json: &Value, path: String, pairs: &mut Vec<String>) {
+    let child = |key: &str| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", path, key)
+        }
+    };
+    match json {
+        Value::Table(fields) if !fields.is_empty() => {
+            for (key, value) in fields {
+                flatten_into(value, child(key), pairs);
+            }
+        }
+        Value::Array(items) if !items.is_empty() => {
+            for (index, item) in items.iter().enumerate() {
+                flatten_into(item, child(&index.to_string()), pairs);
+            }
+        }
+        Value::Table(_) => pairs.push(format!("{}={{}}", path)),
+        Value::Array(_) => pairs.push(format!("{}=[]", path)),
+        Value::String(value) => {
+            if value.is_empty()
+                || value.contains(|c: char| c.is_whitespace() || c == '"' || c == '=')
+            {
+                pairs.push(format!("{}={:?}", path, value));
+            } else {
+                pairs.push(format!("{}={}", path, value));
+            }
+        }
+        scalar => pairs.push(format!("{}={}", path, scalar)),
+    }
+}
+
+fn colorize_json(json: &Value) -> String {
+    to_colored_json_auto(json).unwrap_or_else(|_| "Invalid JSON".to_string())
+}
+
+pub fn get_broker_detail(bootstrap_servers: &str) -> Result<(), KafkaError> {
+    get_broker_detail_inner(bootstrap_servers)
+        .map(|(headers, rows)| print_broker_table(&headers, &rows))?;
+
+    Ok(())
+}
+
+/// Rack of every broker, empty when the cluster does not support DescribeCluster.
+fn broker_racks(consumer: &BaseConsumer<ClientAuth>) -> HashMap<i32, String> {
+    admin::describe_cluster_nodes(consumer)
+        .map(|nodes| {
+            nodes
+                .into_iter()
+                .filter_map(|node| node.rack.map(|rack| (node.id, rack)))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn get_broker_detail_inner(
+    bootstrap_servers: &str,
+) -> Result<([&str; 4], Vec<[String; 4]>), KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let metadata = consumer
+        .fetch_metadata(None, Duration::from_secs(10))
+        .map_err(|er| {
+            if let rdkafka::error::KafkaError::MetadataFetch(_) = er {
+                KafkaError::MetadataFetch("Error while fetching broker metadata".to_string(), er)
+            } else {
+                KafkaError::Generic("Error while fetching brokers".to_string())
+            }
+        })?;
+    let racks = broker_racks(&consumer);
+
+    let headers = ["Broker ID", "Host", "Port", "Rack"];
+    let rows: Vec<[String; 4]> = metadata
+        .brokers()
         .iter()
-        .map(|g| {
+        .map(|b| {
             [
-                g.name().to_string(),
-                g.state().to_string(),
-                g.protocol_type().to_string(),
-                g.protocol().to_string(),
+                b.id().to_string(),
+                b.host().to_string(),
+                b.port().to_string(),
+                racks
+                    .get(&b.id())
+                    .cloned()
+                    .unwrap_or_else(|| "-".to_string()),
             ]
         })
         .collect();
     Ok((headers, rows))
 }
 
-fn print_consumer_groups_table(headers: &[&str; 4], rows: &[[String; 4]]) {
+fn print_broker_table(headers: &[&str; 4], rows: &[[String; 4]]) {
     let mut table = Table::new();
     table.add_row(row![headers[0], headers[1], headers[2], headers[3]]);
     for row in rows {
         table.add_row(row![row[0], row[1], row[2], row[3]]);
     }
-    table.printstd();
+    output::print_table(&table);
 }
 
-pub fn get_consumers_group_details(
-    bootstrap_servers: &str,
+/// Which groups `consumer --list` shows.
+pub struct GroupFilter<'a> {
+    pub state: Option<GroupState>,
+    /// Only groups with members assigned to, or offsets committed on, this topic
+    pub topic: Option<&'a str>,
+    /// Show the total lag of each group
+    pub with_lag: bool,
+    /// List the groups of kfcli's own consumers too
+    pub show_kfcli_groups: bool,
+}
+
+/// Names of the consumer groups of the cluster in order, without kfcli's own.
+pub fn group_names(bootstrap_servers: &str) -> Result<Vec<String>, KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let groups = consumer
+        .fetch_group_list(None, Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::GroupListFetch("Error while fetching consumer groups".to_string(), er)
+        })?;
+    let mut names: Vec<String> = groups
+        .groups()
+        .iter()
+        .map(|g| g.name().to_string())
+        .filter(|name| !client::is_kfcli_group(name))
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+pub fn get_consumer_groups(bootstrap_servers: &str, filter: GroupFilter) -> Result<(), KafkaError> {
+    get_consumer_groups_inner(bootstrap_servers, &filter)
+        .map(|(headers, rows)| print_consumer_groups_table(&headers, &rows, filter.with_lag))?;
+    Ok(())
+}
+
+fn get_consumer_groups_inner(
+    bootstrap_servers: &str,
+    filter: &GroupFilter,
+) -> Result<([&'static str; 5], Vec<[String; 5]>), KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let groups = consumer
+        .fetch_group_list(None, Duration::from_secs(10))
+        .map_err(|er| {
+            if let rdkafka::error::KafkaError::GroupListFetch(_) = er {
+                KafkaError::GroupListFetch("Error while fetching consumer groups".to_string(), er)
+            } else {
+                KafkaError::Generic("Error while fetching consumer groups".to_string())
+            }
+        })?;
+
+    let lags: HashMap<String, GroupLag> = if filter.with_lag {
+        collect_group_lags(bootstrap_servers)?
+            .into_iter()
+            .map(|l| (l.group.clone(), l))
+            .collect()
+    } else {
+        HashMap::new()
+    };
+    // Without the lag, only the offsets on the filtered topic are fetched, and only for groups
+    // with no member assigned to it
+    let topic_partitions = match filter.topic {
+        Some(topic) if !filter.with_lag => {
+            let metadata = consumer
+                .fetch_metadata(Some(topic), Duration::from_secs(10))
+                .map_err(|er| {
+                    KafkaError::MetadataFetch("Error while fetching topic metadata".to_string(), er)
+                })?;
+            all_topic_partitions(&metadata)
+        }
+        _ => TopicPartitionList::new(),
+    };
+
+    let headers = ["Group ID", "State", "Protocol Type", "Protocol", "Lag"];
+
+    let mut rows = Vec::new();
+    for g in groups.groups() {
+        let lag = lags.get(g.name());
+        if !filter.show_kfcli_groups && client::is_kfcli_group(g.name()) {
+            continue;
+        }
+        if filter.state.is_some_and(|state| !state.matches(g.state())) {
+            continue;
+        }
+        if let Some(topic) = filter.topic {
+            let matches = if !is_consumer_group(g) {
+                false
+            } else if group_assigns_topic(g.members(), topic) {
+                true
+            } else if filter.with_lag {
+                lag.is_some_and(|l| l.topics.contains(topic))
+            } else if topic_partitions.count() > 0 {
+                let committed =
+                    fetch_committed_offsets(bootstrap_servers, g.name(), &topic_partitions)?;
+                !committed_topics(&committed).is_empty()
+            } else {
+                false
+            };
+            if !matches {
+                continue;
+            }
+        }
+        rows.push([
+            g.name().to_string(),
+            g.state().to_string(),
+            g.protocol_type().to_string(),
+            g.protocol().to_string(),
+            output::count(lag.map_or(0, |l| l.lag)),
+        ]);
+    }
+    Ok((headers, rows))
+}
+
+/// Whether a group may consume topics: it uses the consumer protocol, or has no members left
+/// to tell, as a group that only has offsets committed. Groups of Kafka Connect workers and
+/// other protocols assign something else than partitions.
+fn is_consumer_group(group: &GroupInfo) -> bool {
+    matches!(group.protocol_type(), "consumer" | "")
+}
+
+/// Whether any member of a group has partitions of `topic` assigned. An assignment that does
+/// not decode as the consumer protocol's counts as not assigned.
+fn group_assigns_topic(members: &[GroupMemberInfo], topic: &str) -> bool {
+    members.iter().any(|member| {
+        member
+            .assignment()
+            .filter(|data| !data.is_empty())
+            .is_some_and(|data| match deserialize_assignment(data) {
+                Ok(assignment) => assignment.contains_key(topic),
+                Err(er) => {
+                    tracing::debug!(member = member.id(), error = %er, "Undecodable assignment");
+                    false
+                }
+            })
+    })
+}
+
+fn print_consumer_groups_table(headers: &[&str; 5], rows: &[[String; 5]], with_lag: bool) {
+    let mut table = Table::new();
+    if with_lag {
+        table.add_row(row![
+            headers[0], headers[1], headers[2], headers[3], headers[4]
+        ]);
+        for row in rows {
+            table.add_row(row![row[0], row[1], row[2], row[3], row[4]]);
+        }
+    } else {
+        table.add_row(row![headers[0], headers[1], headers[2], headers[3]]);
+        for row in rows {
+            table.add_row(row![row[0], row[1], row[2], row[3]]);
+        }
+    }
+    output::print_table(&table);
+}
+
+pub fn get_consumers_group_details(
+    bootstrap_servers: &str,
     group: String,
     lag: bool,
+    samples: usize,
+    interval: Duration,
 ) -> Result<(), KafkaError> {
     get_consumers_group_details_inner(bootstrap_servers, &group).map(
         |(group_header, group_detail, member_header, member_detail)| {
@@ -519,15 +2010,17 @@ pub fn get_consumers_group_details(
                 group_header[0],
                 group_header[1],
                 group_header[2],
-                group_header[3]
+                group_header[3],
+                group_header[4]
             ]);
             group_table.add_row(row![
                 group_detail[0],
                 group_detail[1],
                 group_detail[2],
-                group_detail[3]
+                group_detail[3],
+                group_detail[4]
             ]);
-            group_table.printstd();
+            output::print_table(&group_table);
 
             let mut member_table = Table::new();
             member_table.add_row(row![
@@ -535,268 +2028,7085 @@ pub fn get_consumers_group_details(
                 member_header[1],
                 member_header[2],
                 member_header[3],
-                member_header[4]
+                member_header[4],
+                member_header[5]
             ]);
             member_table.add_row(row![
                 member_detail[0],
                 member_detail[1],
                 member_detail[2],
                 member_detail[3],
-                member_detail[4]
+                member_detail[4],
+                member_detail[5]
             ]);
-            member_table.printstd();
+            output::print_table(&member_table);
         },
     )?;
 
-    if lag {
-        calculate_consumer_lag(bootstrap_servers, &group)?;
+    if lag && samples > 1 {
+        sample_consumer_lag(bootstrap_servers, &group, samples, interval)?;
+    } else if lag {
+        calculate_consumer_lag(bootstrap_servers, &group)?;
+    }
+
+    Ok(())
+}
+
+type GroupDetail<'a> = ([&'a str; 5], [String; 5], [&'a str; 6], [String; 6]);
+
+fn get_consumers_group_details_inner<'a>(
+    bootstrap_servers: &str,
+    group: &'a str,
+) -> Result<GroupDetail<'a>, KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let groups = consumer
+        .fetch_group_list(Some(group), std::time::Duration::from_secs(10))
+        .map_err(|er| {
+            if let rdkafka::error::KafkaError::GroupListFetch(_) = er {
+                KafkaError::GroupListFetch("Error while fetching consumer groups".to_string(), er)
+            } else {
+                KafkaError::Generic("Error while fetching consumer groups".to_string())
+            }
+        })?;
+
+    let group_header = [
+        "Group ID",
+        "State",
+        "Protocol Type",
+        "Protocol",
+        "Coordinator",
+    ];
+    // A group can be listed while its coordinator is unreachable, so a failed lookup is reported
+    // without failing the whole command
+    let description = admin::describe_consumer_group(&consumer, group)
+        .map_err(|er| output::status(format!("Could not describe the group: {}", er)))
+        .ok();
+    let coordinator = description
+        .as_ref()
+        .and_then(|d| d.coordinator.as_ref())
+        .map_or("-".to_string(), |node| node.to_string());
+    let instance_id = |member: &str| {
+        description
+            .as_ref()
+            .and_then(|d| d.instance_ids.get(member).cloned())
+            .unwrap_or_else(|| "-".to_string())
+    };
+    let mut group_detail = [
+        String::from(""),
+        String::from(""),
+        String::from(""),
+        String::from(""),
+        coordinator.clone(),
+    ];
+
+    let member_header = [
+        "Member ID",
+        "Instance ID",
+        "Client ID",
+        "Host",
+        "Topoc",
+        "Partitions",
+    ];
+    let mut member_detail = [
+        String::from(""),
+        String::from(""),
+        String::from(""),
+        String::from(""),
+        String::from(""),
+        String::from(""),
+    ];
+
+    for group in groups.groups() {
+        group_detail = [
+            group.name().to_string(),
+            group.state().to_string(),
+            group.protocol_type().to_string(),
+            group.protocol().to_string(),
+            coordinator.clone(),
+        ];
+
+        if group.state() == "Stable" {
+            for member in group.members() {
+                let assignment = member.assignment();
+                if assignment.is_none() {
+                    continue;
+                }
+                let assignment = deserialize_assignment(member.assignment().unwrap())?;
+
+                for (topic, partitions) in assignment {
+                    let partitions = partitions
+                        .iter()
+                        .map(|p| p.to_string())
+                        .collect::<Vec<String>>();
+                    member_detail = [
+                        member.id().to_string(),
+                        instance_id(member.id()),
+                        member.client_id().to_string(),
+                        member.client_host().to_string(),
+                        topic,
+                        partitions.join(", "),
+                    ];
+
+                    // get_topic_detail_inner(&consumer, &topic);
+                }
+            }
+        }
+    }
+    Ok((group_header, group_detail, member_header, member_detail))
+}
+
+fn calculate_consumer_lag(bootstrap_servers: &str, group_id: &str) -> Result<(), KafkaError> {
+    let consumer = get_given_consumer(bootstrap_servers, group_id)?;
+
+    let _subscription = consumer.subscription().map_err(|er| {
+        KafkaError::Generic(format!("Error while fetching subscription: {:?}", er))
+    })?;
+
+    // #TODO: This should be implemented
+    // let subscription = subscription.unwrap();
+    // let topics: Vec<String> = subscription
+    //     .elements()
+    //     .iter()
+    //     .map(|tp| tp.topic().to_string())
+    //     .collect();
+
+    // Get metadata for topic partitions
+    let metadata = consumer
+        .fetch_metadata(None, Duration::from_secs(10))
+        .map_err(|er| {
+            if let rdkafka::error::KafkaError::MetadataFetch(_) = er {
+                KafkaError::MetadataFetch("Error while fetching topic metadata".to_string(), er)
+            } else {
+                KafkaError::Generic("Error while fetching topics".to_string())
+            }
+        })?;
+
+    for topic in metadata.topics() {
+        let topic_metadata = topic;
+        println!("Topic: {}", topic_metadata.name());
+
+        // Create partition list for committed offsets
+        let mut tpl = TopicPartitionList::new();
+        for partition in topic_metadata.partitions() {
+            tpl.add_partition(topic_metadata.name(), partition.id());
+        }
+
+        let mut table = Table::new();
+        table.add_row(row!["Partition", "Current Offset", "Latest Offset", "Lag"]);
+
+        let mut partition_details: Vec<[String; 7]> = vec![];
+
+        for partition in topic_metadata.partitions() {
+            let partition_id = partition.id();
+
+            let (_, partition_detail, _) = partition_detail_inner(
+                partition,
+                topic_metadata.name(),
+                &consumer,
+                &HashMap::new(),
+            )?;
+
+            partition_details.extend(partition_detail);
+
+            // Get latest offset
+            let (_, high_watermark) = consumer
+                .fetch_watermarks(topic_metadata.name(), partition_id, Duration::from_secs(5))
+                .map_err(|er| {
+                    KafkaError::Generic(format!("Error while fetching watermarks: {:?}", er))
+                })?;
+
+            let mut topic_partition_list = TopicPartitionList::new();
+            topic_partition_list.add_partition(topic_metadata.name(), partition_id);
+            let committed_offsets = consumer
+                .committed_offsets(topic_partition_list, std::time::Duration::from_secs(5))
+                .expect("Failed to fetch committed offsets");
+
+            // Get committed offset
+            let committed_offset = committed_offsets
+                .find_partition(topic_metadata.name(), partition_id)
+                .map(|p| p.offset().to_raw())
+                .unwrap_or(Some(0))
+                .unwrap_or(0);
+
+            // Calculate lag
+            let lag = high_watermark - committed_offset;
+
+            table.add_row(row![
+                partition_id,
+                committed_offset,
+                high_watermark,
+                output::count(lag)
+            ]);
+        }
+        output::print_table(&table);
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct GroupMember {
+    client_id: String,
+    host: String,
+    assignment: BTreeMap<String, Vec<i32>>,
+}
+
+#[derive(Debug, PartialEq)]
+enum MemberEvent {
+    StateChanged(String, String),
+    Joined(String, GroupMember),
+    Left(String, GroupMember),
+    AssignmentChanged(String, GroupMember, GroupMember),
+}
+
+fn fetch_group_members(
+    consumer: &BaseConsumer<ClientAuth>,
+    group: &str,
+) -> Result<(String, BTreeMap<String, GroupMember>), KafkaError> {
+    let groups = consumer
+        .fetch_group_list(Some(group), Duration::from_secs(10))
+        .map_err(|er| {
+            if let rdkafka::error::KafkaError::GroupListFetch(_) = er {
+                KafkaError::GroupListFetch("Error while fetching consumer groups".to_string(), er)
+            } else {
+                KafkaError::Generic("Error while fetching consumer groups".to_string())
+            }
+        })?;
+
+    let mut state = String::new();
+    let mut members = BTreeMap::new();
+    for group in groups.groups() {
+        state = group.state().to_string();
+        for member in group.members() {
+            // Members that are still joining a rebalance have no assignment yet
+            let assignment = match member.assignment() {
+                Some(data) if !data.is_empty() => {
+                    deserialize_assignment(data)?.into_iter().collect()
+                }
+                _ => BTreeMap::new(),
+            };
+            members.insert(
+                member.id().to_string(),
+                GroupMember {
+                    client_id: member.client_id().to_string(),
+                    host: member.client_host().to_string(),
+                    assignment,
+                },
+            );
+        }
+    }
+    Ok((state, members))
+}
+
+fn diff_group_members(
+    old_state: &str,
+    new_state: &str,
+    old: &BTreeMap<String, GroupMember>,
+    new: &BTreeMap<String, GroupMember>,
+) -> Vec<MemberEvent> {
+    let mut events = vec![];
+    if old_state != new_state {
+        events.push(MemberEvent::StateChanged(
+            old_state.to_string(),
+            new_state.to_string(),
+        ));
+    }
+    for (id, member) in new {
+        match old.get(id) {
+            None => events.push(MemberEvent::Joined(id.clone(), member.clone())),
+            Some(previous) if previous.assignment != member.assignment => events.push(
+                MemberEvent::AssignmentChanged(id.clone(), previous.clone(), member.clone()),
+            ),
+            _ => {}
+        }
+    }
+    for (id, member) in old {
+        if !new.contains_key(id) {
+            events.push(MemberEvent::Left(id.clone(), member.clone()));
+        }
+    }
+    events
+}
+
+fn format_assignment(assignment: &BTreeMap<String, Vec<i32>>) -> String {
+    if assignment.is_empty() {
+        return "-".to_string();
+    }
+    assignment
+        .iter()
+        .map(|(topic, partitions)| {
+            let partitions = partitions
+                .iter()
+                .map(|p| p.to_string())
+                .collect::<Vec<String>>();
+            format!("{}[{}]", topic, partitions.join(", "))
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn format_member_event(event: &MemberEvent) -> String {
+    match event {
+        MemberEvent::StateChanged(old, new) => format!("group state changed: {} -> {}", old, new),
+        MemberEvent::Joined(id, member) => format!(
+            "member joined: {} ({}@{}) assigned {}",
+            id,
+            member.client_id,
+            member.host,
+            format_assignment(&member.assignment)
+        ),
+        MemberEvent::Left(id, member) => format!(
+            "member left: {} ({}@{}) was assigned {}",
+            id,
+            member.client_id,
+            member.host,
+            format_assignment(&member.assignment)
+        ),
+        MemberEvent::AssignmentChanged(id, old, new) => format!(
+            "assignment changed: {} ({}@{}) {} -> {}",
+            id,
+            new.client_id,
+            new.host,
+            format_assignment(&old.assignment),
+            format_assignment(&new.assignment)
+        ),
+    }
+}
+
+pub fn get_group_members(bootstrap_servers: &str, group: &str) -> Result<(), KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let (state, members) = fetch_group_members(&consumer, group)?;
+
+    let instance_ids = admin::describe_consumer_group(&consumer, group)
+        .map(|description| description.instance_ids)
+        .unwrap_or_default();
+
+    println!("Group {} is {}", group, state);
+    let mut table = Table::new();
+    table.add_row(row![
+        "Member ID",
+        "Instance ID",
+        "Client ID",
+        "Host",
+        "Assignment"
+    ]);
+    for (id, member) in &members {
+        table.add_row(row![
+            id,
+            instance_ids.get(id).map_or("-", String::as_str),
+            member.client_id,
+            member.host,
+            format_assignment(&member.assignment)
+        ]);
+    }
+    output::print_table(&table);
+    Ok(())
+}
+
+pub fn watch_group_members(
+    bootstrap_servers: &str,
+    group: &str,
+    interval: Duration,
+) -> Result<(), KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let (mut state, mut members) = fetch_group_members(&consumer, group)?;
+
+    let now = output::now();
+    println!(
+        "[{}] watching group {} ({}, {} members)",
+        now,
+        group,
+        state,
+        members.len()
+    );
+    for (id, member) in &members {
+        println!(
+            "[{}] {}",
+            now,
+            format_member_event(&MemberEvent::Joined(id.clone(), member.clone()))
+        );
+    }
+
+    loop {
+        std::thread::sleep(interval);
+        let (new_state, new_members) = fetch_group_members(&consumer, group)?;
+        let now = output::now();
+        for event in diff_group_members(&state, &new_state, &members, &new_members) {
+            println!("[{}] {}", now, format_member_event(&event));
+        }
+        state = new_state;
+        members = new_members;
+    }
+}
+
+/// Evicts static members of `group` by `group.instance.id`, so their partitions are reassigned
+/// without waiting for `session.timeout.ms`.
+pub fn remove_group_members(
+    bootstrap_servers: &str,
+    group: &str,
+    instance_ids: &[String],
+) -> Result<(), KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    admin::check_feature(&consumer, admin::Feature::STATIC_MEMBER_REMOVAL)?;
+    let coordinator = admin::describe_consumer_group(&consumer, group)?
+        .coordinator
+        .ok_or_else(|| KafkaError::Admin(format!("Group {} has no coordinator", group)))?;
+    let instance_ids: Vec<&str> = instance_ids.iter().map(String::as_str).collect();
+    let failed = admin::remove_static_members(&coordinator, group, &instance_ids)?;
+    for instance_id in &instance_ids {
+        match failed.iter().find(|(id, _)| id == instance_id) {
+            Some((_, reason)) => eprintln!("Could not remove {}: {}", instance_id, reason),
+            None => output::info(format!("Removed {} from group {}", instance_id, group)),
+        }
+    }
+    if !failed.is_empty() {
+        return Err(KafkaError::Admin(format!(
+            "{} of {} members were not removed",
+            failed.len(),
+            instance_ids.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Why `consumer orphans` reports a group.
+#[derive(Debug, PartialEq)]
+enum OrphanReason {
+    NoOffsets,
+    /// Every topic the group committed offsets on was deleted
+    TopicsDeleted,
+    /// The last record the group consumed is older than the threshold
+    Idle,
+    /// The records the group consumed were already removed by retention
+    Expired,
+}
+
+impl OrphanReason {
+    fn describe(&self) -> &'static str {
+        match self {
+            OrphanReason::NoOffsets => "no committed offsets",
+            OrphanReason::TopicsDeleted => "topics deleted",
+            OrphanReason::Idle => "idle",
+            OrphanReason::Expired => "consumed records expired",
+        }
+    }
+}
+
+/// Whether a group without members is orphaned, given the topics it committed offsets on that
+/// still exist, whether retention removed every record it consumed from them, and the timestamp
+/// of the last record it consumed. An unknown timestamp, as when the record before the committed
+/// offset is a transaction marker or was compacted away, does not make a group orphaned.
+fn orphan_reason(
+    committed_topics: usize,
+    live_topics: usize,
+    expired: bool,
+    last_consumed: Option<i64>,
+    now: i64,
+    older_than_ms: i64,
+) -> Option<OrphanReason> {
+    if committed_topics == 0 {
+        return Some(OrphanReason::NoOffsets);
+    }
+    if live_topics == 0 {
+        return Some(OrphanReason::TopicsDeleted);
+    }
+    if expired {
+        return Some(OrphanReason::Expired);
+    }
+    last_consumed
+        .filter(|timestamp| now - timestamp > older_than_ms)
+        .map(|_| OrphanReason::Idle)
+}
+
+/// Reports the groups without members whose committed topics were all deleted, or whose last
+/// consumed record is older than `older_than`. Commit times are not exposed by the brokers, so
+/// the timestamp of the record before each committed offset stands in for them. With `delete`
+/// the reported groups are deleted after confirmation.
+pub fn find_orphan_groups(
+    bootstrap_servers: &str,
+    older_than: Duration,
+    delete: bool,
+    assume_yes: bool,
+) -> Result<(), KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let metadata = consumer
+        .fetch_metadata(None, Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::MetadataFetch("Error while fetching topic metadata".to_string(), er)
+        })?;
+    let existing: BTreeSet<&str> = metadata.topics().iter().map(|t| t.name()).collect();
+    let groups = consumer
+        .fetch_group_list(None, Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::GroupListFetch("Error while fetching consumer groups".to_string(), er)
+        })?;
+    let idle: Vec<(String, String)> = groups
+        .groups()
+        .iter()
+        .filter(|g| g.members().is_empty())
+        .map(|g| (g.name().to_string(), g.state().to_string()))
+        .collect();
+
+    let now = chrono::Utc::now().timestamp_millis();
+    let mut table = Table::new();
+    table.add_row(row![
+        "Group",
+        "State",
+        "Last Consumed",
+        "Deleted Topics",
+        "Reason"
+    ]);
+    let mut orphans = vec![];
+    for (group, state) in &idle {
+        let committed = admin::list_group_offsets(&consumer, group)?;
+        let topics: BTreeSet<&str> = committed.iter().map(|(t, _, _)| t.as_str()).collect();
+        let deleted: Vec<&str> = topics
+            .iter()
+            .copied()
+            .filter(|topic| !existing.contains(topic))
+            .collect();
+        let consumed: Vec<(String, i32, i64)> = committed
+            .iter()
+            .filter(|(topic, _, offset)| existing.contains(topic.as_str()) && *offset > 0)
+            .map(|(topic, partition, offset)| (topic.clone(), *partition, offset - 1))
+            .collect();
+        let last_consumed = committed_timestamps(&consumer, &consumed)?
+            .into_values()
+            .max();
+        let mut partitions = TopicPartitionList::new();
+        for (topic, partition, _) in &consumed {
+            partitions.add_partition(topic, *partition);
+        }
+        let low = if consumed.is_empty() {
+            HashMap::new()
+        } else {
+            fetch_offsets_at(&consumer, &partitions, Offset::Beginning)?
+        };
+        // Only when the committed offset is at or below the low watermark everywhere
+        let expired = !consumed.is_empty()
+            && consumed.iter().all(|(topic, partition, last)| {
+                low.get(&(topic.clone(), *partition))
+                    .is_some_and(|low| last < low)
+            });
+        let Some(reason) = orphan_reason(
+            topics.len(),
+            topics.len() - deleted.len(),
+            expired,
+            last_consumed,
+            now,
+            older_than.as_millis() as i64,
+        ) else {
+            continue;
+        };
+        table.add_row(row![
+            group,
+            state,
+            last_consumed.map_or("-".to_string(), output::timestamp),
+            if deleted.is_empty() {
+                "-".to_string()
+            } else {
+                deleted.join(", ")
+            },
+            reason.describe()
+        ]);
+        orphans.push(group.as_str());
+    }
+
+    if orphans.is_empty() {
+        output::info(format!(
+            "None of the {} groups without members is orphaned",
+            idle.len()
+        ));
+        return Ok(());
+    }
+    output::print_table(&table);
+    if !delete {
+        return Ok(());
+    }
+    confirm_change(
+        format!("Delete these {} groups?", orphans.len()),
+        assume_yes,
+        "No groups were deleted",
+    )?;
+
+    let admin_client = admin::get_admin_client(bootstrap_servers)?;
+    let mut results = Table::new();
+    results.add_row(row!["Group", "Result"]);
+    let mut failed = 0;
+    for result in admin::delete_groups(&admin_client, &orphans)? {
+        match result {
+            Ok(group) => results.add_row(row![group, Fg->"deleted"]),
+            Err((group, code)) => {
+                failed += 1;
+                results.add_row(row![group, Fr->code.to_string()])
+            }
+        };
+    }
+    output::print_table(&results);
+    if failed > 0 {
+        return Err(KafkaError::Admin(format!(
+            "{} of {} groups could not be deleted",
+            failed,
+            orphans.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Asks before changing the cluster unless `assume_yes`. A declined prompt, or a stdin that is
+/// not a terminal to ask on, fails the command with `unchanged`, so that scripts and the audit
+/// log do not take it as done.
+fn confirm_change(question: String, assume_yes: bool, unchanged: &str) -> Result<(), KafkaError> {
+    if assume_yes {
+        return Ok(());
+    }
+    if !std::io::stdin().is_terminal() {
+        return Err(KafkaError::Generic(format!(
+            "{}, confirm with --yes when stdin is not a terminal",
+            unchanged
+        )));
+    }
+    if !output::confirm(question) {
+        return Err(KafkaError::Generic(format!(
+            "{}, the change was declined",
+            unchanged
+        )));
+    }
+    Ok(())
+}
+
+/// Pairs `offset` with `partition`, or every partition when `None`, after checking it lies
+/// within the watermarks. The high watermark is allowed, it means nothing is left to consume.
+/// Returns the partition, the offset and the high watermark.
+fn plan_offset_commit(
+    watermarks: &[(i32, i64, i64)],
+    partition: Option<i32>,
+    offset: i64,
+) -> Result<Vec<(i32, i64, i64)>, KafkaError> {
+    if let Some(partition) = partition {
+        if !watermarks.iter().any(|(p, _, _)| *p == partition) {
+            return Err(KafkaError::Generic(format!(
+                "Partition {} does not exist",
+                partition
+            )));
+        }
+    }
+    watermarks
+        .iter()
+        .filter(|(p, _, _)| partition.is_none_or(|partition| *p == partition))
+        .map(|&(p, low, high)| {
+            if offset < low || offset > high {
+                return Err(KafkaError::Generic(format!(
+                    "Offset {} is outside the watermarks [{}, {}] of partition {}",
+                    offset, low, high, p
+                )));
+            }
+            Ok((p, offset, high))
+        })
+        .collect()
+}
+
+/// Commits `offset` for `group` on one partition of `topic`, or all of them when `partition` is
+/// `None`.
+pub fn set_group_offset(
+    bootstrap_servers: &str,
+    group: &str,
+    topic: &str,
+    partition: Option<i32>,
+    offset: i64,
+    assume_yes: bool,
+) -> Result<(), KafkaError> {
+    let consumer = get_given_consumer(bootstrap_servers, group)?;
+    let targets = plan_offset_commit(&topic_watermarks(&consumer, topic)?, partition, offset)?;
+    commit_group_offsets(&consumer, group, topic, &targets, assume_yes)
+}
+
+/// Moves the committed offsets of `group` on every partition of `topic` to `target`.
+pub fn reset_group_offsets(
+    bootstrap_servers: &str,
+    group: &str,
+    topic: &str,
+    target: StartPosition,
+    assume_yes: bool,
+) -> Result<(), KafkaError> {
+    let consumer = get_given_consumer(bootstrap_servers, group)?;
+    let targets = ranges_from(&consumer, topic, &target)?;
+    commit_group_offsets(&consumer, group, topic, &targets, assume_yes)
+}
+
+/// Creates `group` by committing offsets at `start` on every partition of `topic`, so a new
+/// service starts where intended instead of where `auto.offset.reset` puts it. Fails when the
+/// group already exists.
+pub fn create_group(
+    bootstrap_servers: &str,
+    group: &str,
+    topic: &str,
+    start: StartPosition,
+    assume_yes: bool,
+) -> Result<(), KafkaError> {
+    let consumer = get_given_consumer(bootstrap_servers, group)?;
+    let (state, _) = fetch_group_members(&consumer, group)?;
+    if !state.is_empty() && state != "Dead" {
+        return Err(KafkaError::Generic(format!(
+            "Group {} already exists ({}), use consumer reset-offsets to move it",
+            group, state
+        )));
+    }
+    let targets = ranges_from(&consumer, topic, &start)?;
+    commit_group_offsets(&consumer, group, topic, &targets, assume_yes)
+}
+
+/// What committing a new offset does to one partition.
+#[derive(Debug, PartialEq)]
+struct OffsetChange {
+    partition: i32,
+    committed: Option<i64>,
+    target: i64,
+    /// Records left to consume from the target
+    lag: i64,
+}
+
+impl OffsetChange {
+    /// Records skipped when positive, consumed again when negative.
+    fn shift(&self) -> Option<i64> {
+        self.committed.map(|committed| self.target - committed)
+    }
+}
+
+fn describe_offset_changes(
+    targets: &[(i32, i64, i64)],
+    committed: &HashMap<i32, i64>,
+) -> Vec<OffsetChange> {
+    targets
+        .iter()
+        .map(|&(partition, target, high)| OffsetChange {
+            partition,
+            committed: committed.get(&partition).copied(),
+            target,
+            lag: (high - target).max(0),
+        })
+        .collect()
+}
+
+/// Shows the committed offset, target and resulting lag of every partition in `targets`, given
+/// as partition, offset and high watermark, and commits the targets once confirmed. The group
+/// must have no active members, as the coordinator rejects commits from outside the group.
+fn commit_group_offsets(
+    consumer: &BaseConsumer<ClientAuth>,
+    group: &str,
+    topic: &str,
+    targets: &[(i32, i64, i64)],
+    assume_yes: bool,
+) -> Result<(), KafkaError> {
+    let (_, members) = fetch_group_members(consumer, group)?;
+    if !members.is_empty() {
+        return Err(KafkaError::Generic(format!(
+            "Group {} has {} active members, stop them before changing offsets",
+            group,
+            members.len()
+        )));
+    }
+
+    let mut tpl = TopicPartitionList::new();
+    for &(partition, offset, _) in targets {
+        tpl.add_partition_offset(topic, partition, Offset::Offset(offset))
+            .map_err(|er| {
+                KafkaError::OffsetFetch("Error while preparing offsets".to_string(), er)
+            })?;
+    }
+    let committed: HashMap<i32, i64> = consumer
+        .committed_offsets(tpl.clone(), Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::OffsetFetch("Error while fetching committed offsets".to_string(), er)
+        })?
+        .elements()
+        .iter()
+        .filter_map(|e| match e.offset() {
+            Offset::Offset(offset) => Some((e.partition(), offset)),
+            _ => None,
+        })
+        .collect();
+
+    let mut table = Table::new();
+    table.add_row(row!["Partition", "Committed", "Target", "Shift", "Lag"]);
+    for change in describe_offset_changes(targets, &committed) {
+        table.add_row(row![
+            change.partition,
+            change.committed.map_or("-".to_string(), |c| c.to_string()),
+            change.target,
+            change
+                .shift()
+                .map_or("-".to_string(), |s| format!("{:+}", s)),
+            output::count(change.lag)
+        ]);
+    }
+    output::print_table(&table);
+
+    confirm_change(
+        format!("Commit these offsets for group {} on {}?", group, topic),
+        assume_yes,
+        "No offsets were committed",
+    )?;
+    consumer
+        .commit(&tpl, CommitMode::Sync)
+        .map_err(|er| KafkaError::Generic(format!("Error while committing offsets: {:?}", er)))?;
+    output::info(format!(
+        "Committed offsets of {} partitions for group {}",
+        targets.len(),
+        group
+    ));
+    Ok(())
+}
+
+const LAG_WORKERS: usize = 8;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct PartitionLag {
+    topic: String,
+    partition: i32,
+    committed: i64,
+    end: i64,
+    lag: i64,
+}
+
+/// All partitions of the non-internal topics in the cluster.
+fn all_topic_partitions(metadata: &Metadata) -> TopicPartitionList {
+    let mut tpl = TopicPartitionList::new();
+    for topic in metadata.topics() {
+        if topic.name().starts_with("__") {
+            continue;
+        }
+        for partition in topic.partitions() {
+            tpl.add_partition(topic.name(), partition.id());
+        }
+    }
+    tpl
+}
+
+/// Resolves the offset at `position` (`Offset::Beginning` or `Offset::End`) of every partition in
+/// `tpl` with a single ListOffsets round trip.
+fn fetch_offsets_at(
+    consumer: &BaseConsumer<ClientAuth>,
+    tpl: &TopicPartitionList,
+    position: Offset,
+) -> Result<HashMap<(String, i32), i64>, KafkaError> {
+    let mut request = TopicPartitionList::new();
+    for element in tpl.elements() {
+        request
+            .add_partition_offset(element.topic(), element.partition(), position)
+            .map_err(|er| {
+                KafkaError::OffsetFetch("Error while preparing offsets".to_string(), er)
+            })?;
+    }
+    tracing::debug!(partitions = request.count(), ?position, "Listing offsets");
+    let offsets = consumer
+        .offsets_for_times(request, Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::OffsetFetch("Error while fetching partition offsets".to_string(), er)
+        })?;
+
+    Ok(offsets
+        .elements()
+        .iter()
+        .filter_map(|e| match e.offset() {
+            Offset::Offset(offset) => Some(((e.topic().to_string(), e.partition()), offset)),
+            _ => None,
+        })
+        .collect())
+}
+
+fn fetch_end_offsets(
+    consumer: &BaseConsumer<ClientAuth>,
+    tpl: &TopicPartitionList,
+) -> Result<HashMap<(String, i32), i64>, KafkaError> {
+    fetch_offsets_at(consumer, tpl, Offset::End)
+}
+
+fn compute_partition_lag(
+    committed: &TopicPartitionList,
+    end_offsets: &HashMap<(String, i32), i64>,
+) -> Vec<PartitionLag> {
+    committed
+        .elements()
+        .iter()
+        .filter_map(|e| {
+            let Offset::Offset(committed) = e.offset() else {
+                return None;
+            };
+            let end = *end_offsets.get(&(e.topic().to_string(), e.partition()))?;
+            Some(PartitionLag {
+                topic: e.topic().to_string(),
+                partition: e.partition(),
+                committed,
+                end,
+                lag: (end - committed).max(0),
+            })
+        })
+        .collect()
+}
+
+fn fetch_committed_offsets(
+    bootstrap_servers: &str,
+    group: &str,
+    tpl: &TopicPartitionList,
+) -> Result<TopicPartitionList, KafkaError> {
+    let consumer = get_given_consumer(bootstrap_servers, group)?;
+    consumer
+        .committed_offsets(tpl.clone(), Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::OffsetFetch(format!("Error while fetching offsets of {}", group), er)
+        })
+}
+
+fn fetch_group_lag(
+    bootstrap_servers: &str,
+    group: &str,
+    tpl: &TopicPartitionList,
+    end_offsets: &HashMap<(String, i32), i64>,
+) -> Result<Vec<PartitionLag>, KafkaError> {
+    let committed = fetch_committed_offsets(bootstrap_servers, group, tpl)?;
+    Ok(compute_partition_lag(&committed, end_offsets))
+}
+
+/// Topics with an offset committed in `committed`.
+fn committed_topics(committed: &TopicPartitionList) -> BTreeSet<String> {
+    committed
+        .elements()
+        .iter()
+        .filter(|e| matches!(e.offset(), Offset::Offset(_)))
+        .map(|e| e.topic().to_string())
+        .collect()
+}
+
+/// End offset by partition of one topic, empty until fetched.
+type TopicEndOffsets = Arc<Mutex<Option<HashMap<i32, i64>>>>;
+
+/// End offsets shared by the lag workers. The watermarks of a topic are fetched the first time
+/// a group with offsets on it asks for them, so topics nobody consumes are never queried and
+/// topics many groups consume are queried once.
+struct WatermarkCache<'a> {
+    consumer: &'a BaseConsumer<ClientAuth>,
+    tpl: &'a TopicPartitionList,
+    /// End offsets by topic. The map is only locked to find the slot of a topic, a fetch holds
+    /// the slots of its topics so other workers only wait for the topics they share.
+    fetched: Mutex<HashMap<String, TopicEndOffsets>>,
+}
+
+impl<'a> WatermarkCache<'a> {
+    fn new(consumer: &'a BaseConsumer<ClientAuth>, tpl: &'a TopicPartitionList) -> Self {
+        WatermarkCache {
+            consumer,
+            tpl,
+            fetched: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// End offsets of every partition of `topics`.
+    fn end_offsets(
+        &self,
+        topics: &BTreeSet<String>,
+    ) -> Result<HashMap<(String, i32), i64>, KafkaError> {
+        let slots: Vec<(&String, TopicEndOffsets)> = {
+            let mut fetched = self.fetched.lock().expect("Watermark cache poisoned");
+            topics
+                .iter()
+                .map(|topic| (topic, fetched.entry(topic.clone()).or_default().clone()))
+                .collect()
+        };
+        // Slots are locked in topic order, so two workers never wait on each other
+        let mut slots: Vec<_> = slots
+            .iter()
+            .map(|(topic, slot)| (*topic, slot.lock().expect("Watermark cache poisoned")))
+            .collect();
+        let mut missing = TopicPartitionList::new();
+        for element in self.tpl.elements() {
+            if slots
+                .iter()
+                .any(|(topic, offsets)| *topic == element.topic() && offsets.is_none())
+            {
+                missing.add_partition(element.topic(), element.partition());
+            }
+        }
+        if missing.count() > 0 {
+            let mut fetched: HashMap<String, HashMap<i32, i64>> = HashMap::new();
+            for ((topic, partition), offset) in fetch_end_offsets(self.consumer, &missing)? {
+                fetched.entry(topic).or_default().insert(partition, offset);
+            }
+            for (topic, offsets) in slots.iter_mut() {
+                if offsets.is_none() {
+                    **offsets = Some(fetched.remove(*topic).unwrap_or_default());
+                }
+            }
+        }
+        Ok(slots
+            .iter()
+            .filter_map(|(topic, offsets)| Some((*topic, offsets.as_ref()?)))
+            .flat_map(|(topic, offsets)| {
+                offsets
+                    .iter()
+                    .map(|(partition, offset)| ((topic.clone(), *partition), *offset))
+            })
+            .collect())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct GroupLag {
+    group: String,
+    state: String,
+    /// Topics the group has committed offsets on
+    topics: BTreeSet<String>,
+    lag: i64,
+}
+
+fn rank_group_lags(mut lags: Vec<GroupLag>, limit: usize) -> Vec<GroupLag> {
+    lags.sort_by(|a, b| b.lag.cmp(&a.lag).then_with(|| a.group.cmp(&b.group)));
+    lags.truncate(limit);
+    lags
+}
+
+pub fn get_top_lagging_groups(bootstrap_servers: &str, limit: usize) -> Result<(), KafkaError> {
+    let lags = collect_group_lags(bootstrap_servers)?;
+
+    let mut table = Table::new();
+    table.add_row(row!["Group ID", "State", "Topics", "Total Lag"]);
+    for lag in rank_group_lags(lags, limit) {
+        table.add_row(row![
+            lag.group,
+            lag.state,
+            lag.topics.len(),
+            output::count(lag.lag)
+        ]);
+    }
+    output::print_table(&table);
+    Ok(())
+}
+
+/// One partition of a lag export, stamped with the time of the export so that appended
+/// snapshots can be told apart.
+#[derive(Serialize, Debug, PartialEq)]
+struct LagRow {
+    time: i64,
+    group: String,
+    topic: String,
+    partition: i32,
+    committed: i64,
+    end: i64,
+    lag: i64,
+    /// How long ago the next record to consume was produced, 0 without lag. Left out of CSV so
+    /// that appended files keep their columns.
+    time_behind_ms: Option<i64>,
+}
+
+/// How far behind a group is in time: the age of the record at its committed offset, which is
+/// the next one it will read. `None` when that record is not known, as after it expired.
+fn time_behind(lag: i64, committed_timestamp: Option<i64>, now: i64) -> Option<i64> {
+    if lag <= 0 {
+        return Some(0);
+    }
+    committed_timestamp.map(|timestamp| (now - timestamp).max(0))
+}
+
+/// Reads the timestamp of the record at each committed offset, one record per partition.
+/// Partitions read to the end, or whose committed record has expired, are left out.
+fn committed_timestamps(
+    consumer: &BaseConsumer<ClientAuth>,
+    committed: &[(String, i32, i64)],
+) -> Result<HashMap<(String, i32), i64>, KafkaError> {
+    if committed.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let progress = output::Progress::new("Reading timestamps", None);
+    let mut timestamps = HashMap::new();
+    let topics: BTreeSet<&str> = committed.iter().map(|(t, _, _)| t.as_str()).collect();
+    for topic in topics {
+        let offsets: HashMap<i32, i64> = committed
+            .iter()
+            .filter(|(t, _, _)| t == topic)
+            .map(|(_, partition, offset)| (*partition, *offset))
+            .collect();
+        let ranges: Vec<(i32, i64, i64)> = topic_watermarks(consumer, topic)?
+            .into_iter()
+            .filter_map(|(partition, low, high)| {
+                let start = (*offsets.get(&partition)?).max(low);
+                (start < high).then_some((partition, start, start + 1))
+            })
+            .collect();
+        read_ranges(consumer, topic, &ranges, usize::MAX, |message| {
+            if let Some(timestamp) = message.timestamp().to_millis() {
+                timestamps.insert((topic.to_string(), message.partition()), timestamp);
+            }
+            progress.inc(1);
+        })?;
+    }
+    progress.finish();
+    Ok(timestamps)
+}
+
+/// Reads the timestamp of the first record at or after each committed offset, past transaction
+/// markers and offsets removed by compaction. Partitions read to the end are left out.
+fn next_record_timestamps(
+    consumer: &BaseConsumer<ClientAuth>,
+    committed: &[(String, i32, i64)],
+) -> Result<HashMap<(String, i32), i64>, KafkaError> {
+    let mut timestamps = HashMap::new();
+    let topics: BTreeSet<&str> = committed.iter().map(|(t, _, _)| t.as_str()).collect();
+    for topic in topics {
+        let offsets: HashMap<i32, i64> = committed
+            .iter()
+            .filter(|(t, _, _)| t == topic)
+            .map(|(_, partition, offset)| (*partition, *offset))
+            .collect();
+        for (partition, low, high) in topic_watermarks(consumer, topic)? {
+            let Some(&offset) = offsets.get(&partition) else {
+                continue;
+            };
+            read_ranges(
+                consumer,
+                topic,
+                &[(partition, offset.max(low), high)],
+                1,
+                |message| {
+                    if let Some(timestamp) = message.timestamp().to_millis() {
+                        timestamps.insert((topic.to_string(), partition), timestamp);
+                    }
+                },
+            )?;
+        }
+    }
+    Ok(timestamps)
+}
+
+const LAG_CSV_HEADER: [&str; 7] = [
+    "time",
+    "group",
+    "topic",
+    "partition",
+    "committed",
+    "end",
+    "lag",
+];
+
+impl LagRow {
+    fn new(time: i64, group: &str, lag: PartitionLag) -> LagRow {
+        LagRow {
+            time,
+            group: group.to_string(),
+            topic: lag.topic,
+            partition: lag.partition,
+            committed: lag.committed,
+            end: lag.end,
+            lag: lag.lag,
+            time_behind_ms: None,
+        }
+    }
+
+    fn csv_line(&self) -> String {
+        output::csv_line(&[
+            self.time.to_string(),
+            self.group.clone(),
+            self.topic.clone(),
+            self.partition.to_string(),
+            self.committed.to_string(),
+            self.end.to_string(),
+            self.lag.to_string(),
+        ])
+    }
+}
+
+/// Exports the lag per partition of `group`, or of every group, as a table, JSON or CSV. CSV
+/// can be appended to `file` so that a cron job builds up a history.
+pub fn export_group_lag(
+    bootstrap_servers: &str,
+    group: Option<&str>,
+    format: OutputFormat,
+    file: Option<&Path>,
+) -> Result<(), KafkaError> {
+    if file.is_some() && format != OutputFormat::Csv {
+        return Err(KafkaError::Generic(
+            "Only csv output can be written to a file".to_string(),
+        ));
+    }
+    let consumer = get_consumer(bootstrap_servers)?;
+    let metadata = consumer
+        .fetch_metadata(None, Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::MetadataFetch("Error while fetching topic metadata".to_string(), er)
+        })?;
+    let groups: Vec<String> = match group {
+        Some(group) => vec![group.to_string()],
+        None => consumer
+            .fetch_group_list(None, Duration::from_secs(10))
+            .map_err(|er| {
+                KafkaError::GroupListFetch("Error while fetching consumer groups".to_string(), er)
+            })?
+            .groups()
+            .iter()
+            .map(|g| g.name().to_string())
+            .collect(),
+    };
+    let tpl = all_topic_partitions(&metadata);
+    let end_offsets = fetch_end_offsets(&consumer, &tpl)?;
+
+    let time = chrono::Utc::now().timestamp_millis();
+    let progress = output::Progress::new("Fetching group lag", Some(groups.len() as u64));
+    let mut rows = vec![];
+    for group in &groups {
+        let lags = fetch_group_lag(bootstrap_servers, group, &tpl, &end_offsets);
+        progress.inc(1);
+        rows.extend(lags?.into_iter().map(|lag| LagRow::new(time, group, lag)));
+    }
+    progress.finish();
+
+    if format != OutputFormat::Csv {
+        // Groups are read one at a time, as they can be at different offsets of a partition
+        for group in &groups {
+            let lagging: Vec<(String, i32, i64)> = rows
+                .iter()
+                .filter(|row| &row.group == group && row.lag > 0)
+                .map(|row| (row.topic.clone(), row.partition, row.committed))
+                .collect();
+            let timestamps = committed_timestamps(&consumer, &lagging)?;
+            let now = chrono::Utc::now().timestamp_millis();
+            for row in rows.iter_mut().filter(|row| &row.group == group) {
+                let timestamp = timestamps.get(&(row.topic.clone(), row.partition));
+                row.time_behind_ms = time_behind(row.lag, timestamp.copied(), now);
+            }
+        }
+    }
+
+    match (format, file) {
+        (OutputFormat::Csv, Some(file)) => {
+            append_lag_rows(file, &rows)?;
+            output::info(format!("Appended {} rows to {:?}", rows.len(), file));
+        }
+        (OutputFormat::Csv, None) => {
+            println!("{}", output::csv_line(&LAG_CSV_HEADER));
+            for row in &rows {
+                println!("{}", row.csv_line());
+            }
+        }
+        (OutputFormat::Json, _) => {
+            println!(
+                "{}",
+                serde_json::to_string(&rows).expect("Lag rows serialize to JSON")
+            );
+        }
+        (OutputFormat::Table, _) => {
+            let mut table = Table::new();
+            table.add_row(row![
+                "Group ID",
+                "Topic",
+                "Partition",
+                "Committed",
+                "End",
+                "Lag",
+                "Time Behind"
+            ]);
+            for row in &rows {
+                table.add_row(row![
+                    row.group,
+                    row.topic,
+                    row.partition,
+                    row.committed,
+                    row.end,
+                    output::count(row.lag),
+                    row.time_behind_ms.map_or("-".to_string(), output::elapsed)
+                ]);
+            }
+            output::print_table(&table);
+        }
+    }
+    Ok(())
+}
+
+/// Appends `rows` to the CSV `file`, writing the header first when the file is new.
+fn append_lag_rows(file: &Path, rows: &[LagRow]) -> Result<(), KafkaError> {
+    let io_error = |er| KafkaError::Io(format!("Failed to write {:?}", file), er);
+    let mut writer = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file)
+        .map_err(io_error)?;
+    if writer.metadata().map_err(io_error)?.len() == 0 {
+        writeln!(writer, "{}", output::csv_line(&LAG_CSV_HEADER)).map_err(io_error)?;
+    }
+    for row in rows {
+        writeln!(writer, "{}", row.csv_line()).map_err(io_error)?;
+    }
+    Ok(())
+}
+
+/// Appends the lag per partition of `group` to `file` every `interval`, in the CSV format of
+/// `export_group_lag`, until stopped. Topics created in the meantime are picked up, and a sample
+/// that fails, such as while a broker restarts, is reported and taken again at the next interval.
+pub fn record_group_lag(
+    bootstrap_servers: &str,
+    group: &str,
+    interval: Duration,
+    file: &Path,
+) -> Result<(), KafkaError> {
+    if interval.is_zero() {
+        return Err(KafkaError::Generic(
+            "The interval between samples has to be longer than 0s".to_string(),
+        ));
+    }
+    let consumer = get_consumer(bootstrap_servers)?;
+    output::info(format!(
+        "Recording the lag of {} every {}s to {:?}. Press Ctrl-C to stop",
+        group,
+        interval.as_secs_f64(),
+        file
+    ));
+    loop {
+        match sample_lag_rows(&consumer, bootstrap_servers, group) {
+            Ok(rows) => {
+                append_lag_rows(file, &rows)?;
+                output::status(format!(
+                    "[{}] Lag {} on {} partitions",
+                    output::now(),
+                    output::count(rows.iter().map(|row| row.lag).sum()),
+                    rows.len()
+                ));
+            }
+            Err(er) => output::status(format!(
+                "[{}] Sample failed, retrying in {}s: {}",
+                output::now(),
+                interval.as_secs_f64(),
+                er
+            )),
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+/// The lag per partition of `group` now, across the topics the cluster has now.
+fn sample_lag_rows(
+    consumer: &BaseConsumer<ClientAuth>,
+    bootstrap_servers: &str,
+    group: &str,
+) -> Result<Vec<LagRow>, KafkaError> {
+    let metadata = consumer
+        .fetch_metadata(None, Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::MetadataFetch("Error while fetching topic metadata".to_string(), er)
+        })?;
+    let tpl = all_topic_partitions(&metadata);
+    let end_offsets = fetch_end_offsets(consumer, &tpl)?;
+    let time = chrono::Utc::now().timestamp_millis();
+    Ok(
+        fetch_group_lag(bootstrap_servers, group, &tpl, &end_offsets)?
+            .into_iter()
+            .map(|lag| LagRow::new(time, group, lag))
+            .collect(),
+    )
+}
+
+/// Where a group stands on a partition of the source cluster and the offset that holds the same
+/// position on the target cluster.
+#[derive(Serialize, Debug, PartialEq)]
+struct OffsetTranslation {
+    topic: String,
+    partition: i32,
+    committed: i64,
+    /// Timestamp of the record at the committed offset, `None` when the group has read the
+    /// whole partition
+    timestamp: Option<i64>,
+    /// `None` when the partition does not exist on the target
+    target_offset: Option<i64>,
+}
+
+const TRANSLATION_CSV_HEADER: [&str; 5] = [
+    "topic",
+    "partition",
+    "committed",
+    "timestamp",
+    "target_offset",
+];
+
+impl OffsetTranslation {
+    fn csv_line(&self) -> String {
+        output::csv_line(&[
+            self.topic.clone(),
+            self.partition.to_string(),
+            self.committed.to_string(),
+            self.timestamp.map(|t| t.to_string()).unwrap_or_default(),
+            self.target_offset
+                .map(|o| o.to_string())
+                .unwrap_or_default(),
+        ])
+    }
+}
+
+/// Maps each committed offset to the target: the first offset at or after the timestamp of the
+/// record the group reads next, or the end of the partition when the group has read up to the
+/// end of the source or the target has no record that recent. Fails for partitions with unread
+/// records whose timestamp is unknown, rather than skipping those records on the target.
+fn translate_offsets(
+    committed: Vec<(String, i32, i64)>,
+    source_ends: &HashMap<(String, i32), i64>,
+    timestamps: &HashMap<(String, i32), i64>,
+    target_offsets: &HashMap<(String, i32), i64>,
+    target_ends: &HashMap<(String, i32), i64>,
+) -> Result<Vec<OffsetTranslation>, KafkaError> {
+    let mut unknown = vec![];
+    let translations = committed
+        .into_iter()
+        .map(|(topic, partition, committed)| {
+            let key = (topic, partition);
+            let timestamp = timestamps.get(&key).copied();
+            let end = target_ends.get(&key).copied();
+            let caught_up = source_ends.get(&key).is_some_and(|&high| committed >= high);
+            let target_offset = match timestamp {
+                _ if end.is_none() || caught_up => end,
+                Some(_) => target_offsets.get(&key).copied().or(end),
+                None => {
+                    unknown.push(format!("{}/{} at {}", key.0, partition, committed));
+                    None
+                }
+            };
+            OffsetTranslation {
+                topic: key.0,
+                partition,
+                committed,
+                timestamp,
+                target_offset,
+            }
+        })
+        .collect();
+    if !unknown.is_empty() {
+        return Err(KafkaError::Generic(format!(
+            "No record could be read after the committed offset of {}, their offsets cannot be translated by timestamp",
+            unknown.join(", ")
+        )));
+    }
+    Ok(translations)
+}
+
+/// Translates the committed offsets of `group` to the cluster of `target` by timestamp, so the
+/// group can fail over to a mirrored cluster. Prints the offsets to commit there; nothing is
+/// committed.
+pub fn translate_group_offsets(
+    bootstrap_servers: &str,
+    group: &str,
+    target: &EnvironmentConfig,
+    format: OutputFormat,
+) -> Result<(), KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let metadata = consumer
+        .fetch_metadata(None, Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::MetadataFetch("Error while fetching topic metadata".to_string(), er)
+        })?;
+    let committed =
+        fetch_committed_offsets(bootstrap_servers, group, &all_topic_partitions(&metadata))?;
+    let committed: Vec<(String, i32, i64)> = committed
+        .elements()
+        .iter()
+        .filter_map(|e| match e.offset() {
+            Offset::Offset(offset) => Some((e.topic().to_string(), e.partition(), offset)),
+            _ => None,
+        })
+        .collect();
+    if committed.is_empty() {
+        return Err(KafkaError::Generic(format!(
+            "Group {} has no committed offsets",
+            group
+        )));
+    }
+
+    let mut partitions = TopicPartitionList::new();
+    for (topic, partition, _) in &committed {
+        partitions.add_partition(topic, *partition);
+    }
+    let source_ends = fetch_end_offsets(&consumer, &partitions)?;
+    let timestamps = next_record_timestamps(&consumer, &committed)?;
+
+    // Every client from here on connects to the target cluster
+    client::use_environment(target)?;
+    let target_consumer = get_consumer(&target.brokers)?;
+    let target_metadata = target_consumer
+        .fetch_metadata(None, Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::MetadataFetch("Error while fetching target metadata".to_string(), er)
+        })?;
+    let existing: BTreeSet<(&str, i32)> = target_metadata
+        .topics()
+        .iter()
+        .flat_map(|t| t.partitions().iter().map(move |p| (t.name(), p.id())))
+        .collect();
+    let mut ends = TopicPartitionList::new();
+    let mut request = TopicPartitionList::new();
+    for (topic, partition, _) in &committed {
+        if !existing.contains(&(topic.as_str(), *partition)) {
+            continue;
+        }
+        ends.add_partition(topic, *partition);
+        if let Some(timestamp) = timestamps.get(&(topic.clone(), *partition)) {
+            request
+                .add_partition_offset(topic, *partition, Offset::Offset(*timestamp))
+                .map_err(|er| {
+                    KafkaError::OffsetFetch("Error while preparing offsets".to_string(), er)
+                })?;
+        }
+    }
+    let target_ends = fetch_end_offsets(&target_consumer, &ends)?;
+    let target_offsets: HashMap<(String, i32), i64> = target_consumer
+        .offsets_for_times(request, Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::OffsetFetch("Error while fetching target offsets".to_string(), er)
+        })?
+        .elements()
+        .iter()
+        .filter_map(|e| match e.offset() {
+            Offset::Offset(offset) => Some(((e.topic().to_string(), e.partition()), offset)),
+            _ => None,
+        })
+        .collect();
+
+    let translations = translate_offsets(
+        committed,
+        &source_ends,
+        &timestamps,
+        &target_offsets,
+        &target_ends,
+    )?;
+    match format {
+        OutputFormat::Csv => {
+            println!("{}", output::csv_line(&TRANSLATION_CSV_HEADER));
+            for translation in &translations {
+                println!("{}", translation.csv_line());
+            }
+        }
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string(&translations).expect("Offset translations serialize")
+            );
+        }
+        OutputFormat::Table => {
+            let mut table = Table::new();
+            table.add_row(row![
+                "Topic",
+                "Partition",
+                "Committed",
+                "Timestamp",
+                "Target Offset"
+            ]);
+            for translation in &translations {
+                let timestamp = translation
+                    .timestamp
+                    .map(output::timestamp)
+                    .unwrap_or_else(|| "(caught up)".to_string());
+                match translation.target_offset {
+                    Some(offset) => table.add_row(row![
+                        translation.topic,
+                        translation.partition,
+                        translation.committed,
+                        timestamp,
+                        offset
+                    ]),
+                    None => table.add_row(row![
+                        translation.topic,
+                        translation.partition,
+                        translation.committed,
+                        timestamp,
+                        Fr->"missing on target"
+                    ]),
+                };
+            }
+            output::print_table(&table);
+        }
+    }
+    let missing = translations
+        .iter()
+        .filter(|t| t.target_offset.is_none())
+        .count();
+    if missing > 0 {
+        output::status(format!(
+            "{} partitions of {} do not exist on the target",
+            missing, group
+        ));
+    }
+    Ok(())
+}
+
+/// Total lag of every consumer group, fetched by a pool of workers.
+fn collect_group_lags(bootstrap_servers: &str) -> Result<Vec<GroupLag>, KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let metadata = consumer
+        .fetch_metadata(None, Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::MetadataFetch("Error while fetching topic metadata".to_string(), er)
+        })?;
+    let groups = consumer
+        .fetch_group_list(None, Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::GroupListFetch("Error while fetching consumer groups".to_string(), er)
+        })?;
+
+    let tpl = all_topic_partitions(&metadata);
+    let watermarks = WatermarkCache::new(&consumer, &tpl);
+
+    let groups: Vec<(String, String)> = groups
+        .groups()
+        .iter()
+        .map(|g| (g.name().to_string(), g.state().to_string()))
+        .collect();
+    let chunk_size = groups.len().div_ceil(LAG_WORKERS).max(1);
+    let progress = output::Progress::new("Fetching group lag", Some(groups.len() as u64));
+
+    let results: Vec<Result<GroupLag, KafkaError>> = std::thread::scope(|scope| {
+        let handles: Vec<_> = groups
+            .chunks(chunk_size)
+            .map(|chunk| {
+                let tpl = &tpl;
+                let watermarks = &watermarks;
+                let progress = &progress;
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|(group, state)| {
+                            let committed = fetch_committed_offsets(bootstrap_servers, group, tpl);
+                            progress.inc(1);
+                            let committed = committed?;
+                            let topics = committed_topics(&committed);
+                            let end_offsets = watermarks.end_offsets(&topics)?;
+                            let lags = compute_partition_lag(&committed, &end_offsets);
+                            Ok(GroupLag {
+                                group: group.clone(),
+                                state: state.clone(),
+                                topics,
+                                lag: lags.iter().map(|l| l.lag).sum(),
+                            })
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("Lag worker panicked"))
+            .collect()
+    });
+    progress.finish();
+    results.into_iter().collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct LagTrend {
+    topic: String,
+    partition: i32,
+    lag: i64,
+    delta: i64,
+    rate: f64,
+    eta: Option<Duration>,
+}
+
+/// Compares the first and last lag snapshots of a partition to estimate how fast the group
+/// is catching up. `eta` is `None` when the lag is not shrinking.
+fn compute_lag_trends(
+    first: &[PartitionLag],
+    last: &[PartitionLag],
+    elapsed: Duration,
+) -> Vec<LagTrend> {
+    let seconds = elapsed.as_secs_f64();
+    last.iter()
+        .filter_map(|current| {
+            let previous = first
+                .iter()
+                .find(|p| p.topic == current.topic && p.partition == current.partition)?;
+            let delta = current.lag - previous.lag;
+            let rate = if seconds > 0.0 {
+                (current.committed - previous.committed) as f64 / seconds
+            } else {
+                0.0
+            };
+            let eta = if current.lag == 0 {
+                Some(Duration::ZERO)
+            } else if delta < 0 && seconds > 0.0 {
+                let drain_rate = -delta as f64 / seconds;
+                Some(Duration::from_secs_f64(current.lag as f64 / drain_rate))
+            } else {
+                None
+            };
+            Some(LagTrend {
+                topic: current.topic.clone(),
+                partition: current.partition,
+                lag: current.lag,
+                delta,
+                rate,
+                eta,
+            })
+        })
+        .collect()
+}
+
+fn format_eta(eta: Option<Duration>) -> String {
+    let Some(eta) = eta else {
+        return "never".to_string();
+    };
+    let seconds = eta.as_secs();
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {}m", seconds / 3600, (seconds % 3600) / 60),
+    }
+}
+
+fn sample_consumer_lag(
+    bootstrap_servers: &str,
+    group_id: &str,
+    samples: usize,
+    interval: Duration,
+) -> Result<(), KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let metadata = consumer
+        .fetch_metadata(None, Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::MetadataFetch("Error while fetching topic metadata".to_string(), er)
+        })?;
+    let tpl = all_topic_partitions(&metadata);
+
+    let mut first: Option<(Instant, Vec<PartitionLag>)> = None;
+    let mut last: Option<(Instant, Vec<PartitionLag>)> = None;
+    for sample in 0..samples {
+        if sample > 0 {
+            std::thread::sleep(interval);
+        }
+        let end_offsets = fetch_end_offsets(&consumer, &tpl)?;
+        let lags = fetch_group_lag(bootstrap_servers, group_id, &tpl, &end_offsets)?;
+        output::status(format!("Sample {}/{} taken", sample + 1, samples));
+        if first.is_none() {
+            first = Some((Instant::now(), lags));
+        } else {
+            last = Some((Instant::now(), lags));
+        }
+    }
+
+    let (Some((started, first)), Some((finished, last))) = (first, last) else {
+        return Err(KafkaError::Generic(
+            "At least two samples are required".to_string(),
+        ));
+    };
+
+    let mut table = Table::new();
+    table.add_row(row![
+        "Topic",
+        "Partition",
+        "Lag",
+        "Lag Delta",
+        "Consumed/s",
+        "ETA"
+    ]);
+    for trend in compute_lag_trends(&first, &last, finished - started) {
+        table.add_row(row![
+            trend.topic,
+            trend.partition,
+            output::count(trend.lag),
+            format!("{:+}", trend.delta),
+            format!("{:.1}", trend.rate),
+            format_eta(trend.eta)
+        ]);
+    }
+    output::print_table(&table);
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct PartitionSkew {
+    partition: i32,
+    messages: i64,
+    share: f64,
+    deviation: f64,
+}
+
+/// Computes each partition's share of the topic and its deviation from the mean, in percent.
+fn compute_skew(counts: &[(i32, i64)]) -> Vec<PartitionSkew> {
+    let total: i64 = counts.iter().map(|(_, messages)| messages).sum();
+    let mean = total as f64 / counts.len().max(1) as f64;
+    counts
+        .iter()
+        .map(|&(partition, messages)| PartitionSkew {
+            partition,
+            messages,
+            share: if total > 0 {
+                messages as f64 * 100.0 / total as f64
+            } else {
+                0.0
+            },
+            deviation: if mean > 0.0 {
+                (messages as f64 - mean) * 100.0 / mean
+            } else {
+                0.0
+            },
+        })
+        .collect()
+}
+
+pub fn get_topic_skew(
+    bootstrap_servers: &str,
+    topic: &str,
+    threshold: f64,
+) -> Result<(), KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let counts: Vec<(i32, i64)> = topic_watermarks(&consumer, topic)?
+        .into_iter()
+        .map(|(partition, low, high)| (partition, high - low))
+        .collect();
+
+    let mut table = Table::new();
+    table.add_row(row!["Partition", "Messages", "Share", "Deviation"]);
+    let mut skewed = 0;
+    for skew in compute_skew(&counts) {
+        let share = format!("{:.1}%", skew.share);
+        let deviation = format!("{:+.1}%", skew.deviation);
+        if skew.deviation.abs() > threshold {
+            skewed += 1;
+            table.add_row(row![Fr->skew.partition, Fr->output::count(skew.messages), Fr->share, Fr->deviation]);
+        } else {
+            table.add_row(row![
+                skew.partition,
+                output::count(skew.messages),
+                share,
+                deviation
+            ]);
+        }
+    }
+    output::print_table(&table);
+    println!(
+        "{} of {} partitions deviate more than {}% from the mean",
+        skewed,
+        counts.len(),
+        threshold
+    );
+    Ok(())
+}
+
+/// Low and high watermark of every partition of `topic`, ordered by partition id.
+fn topic_watermarks(
+    consumer: &BaseConsumer<ClientAuth>,
+    topic: &str,
+) -> Result<Vec<(i32, i64, i64)>, KafkaError> {
+    let metadata = consumer
+        .fetch_metadata(Some(topic), Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::MetadataFetch("Error while fetching topic metadata".to_string(), er)
+        })?;
+    let topic_metadata = &metadata.topics()[0];
+    if topic_metadata.partitions().is_empty() {
+        return Err(KafkaError::TopicNotExists(format!(
+            "Topic {} does not exist",
+            topic
+        )));
+    }
+
+    let mut tpl = TopicPartitionList::new();
+    for partition in topic_metadata.partitions() {
+        tpl.add_partition(topic, partition.id());
+    }
+    let low = fetch_offsets_at(consumer, &tpl, Offset::Beginning)?;
+    let high = fetch_offsets_at(consumer, &tpl, Offset::End)?;
+
+    let mut watermarks: Vec<(i32, i64, i64)> = topic_metadata
+        .partitions()
+        .iter()
+        .map(|p| {
+            let key = (topic.to_string(), p.id());
+            (
+                p.id(),
+                *low.get(&key).unwrap_or(&0),
+                *high.get(&key).unwrap_or(&0),
+            )
+        })
+        .collect();
+    watermarks.sort();
+    tracing::debug!(topic, ?watermarks, "Fetched watermarks");
+    Ok(watermarks)
+}
+
+/// Reads the `[start, end)` offset range of each `(partition, start, end)` entry, stopping after
+/// `limit` records, and hands each record to `visit`. Returns the number of records visited.
+fn read_ranges<F>(
+    consumer: &BaseConsumer<ClientAuth>,
+    topic: &str,
+    ranges: &[(i32, i64, i64)],
+    limit: usize,
+    mut visit: F,
+) -> Result<usize, KafkaError>
+where
+    F: FnMut(&BorrowedMessage),
+{
+    let mut assignment = TopicPartitionList::new();
+    let mut remaining: HashMap<i32, i64> = HashMap::new();
+    for &(partition, start, end) in ranges {
+        if start < end {
+            assignment
+                .add_partition_offset(topic, partition, Offset::Offset(start))
+                .map_err(|er| {
+                    KafkaError::OffsetFetch("Error while preparing offsets".to_string(), er)
+                })?;
+            remaining.insert(partition, end);
+        }
+    }
+    if remaining.is_empty() {
+        return Ok(0);
+    }
+    consumer
+        .assign(&assignment)
+        .map_err(|er| KafkaError::Generic(format!("Error while assigning partitions: {:?}", er)))?;
+
+    let mut visited = 0;
+    let mut idle_polls = 0;
+    while !remaining.is_empty() && visited < limit && idle_polls < 100 {
+        match consumer.poll(Duration::from_millis(100)) {
+            Some(Ok(message)) => {
+                idle_polls = 0;
+                let Some(&end) = remaining.get(&message.partition()) else {
+                    continue;
+                };
+                if message.offset() >= end - 1 {
+                    remaining.remove(&message.partition());
+                }
+                if message.offset() < end {
+                    visit(&message);
+                    visited += 1;
+                }
+            }
+            Some(Err(e)) => {
+                Err(KafkaError::Generic(format!("Error while polling: {:?}", e)))?;
+            }
+            None => {
+                idle_polls += 1;
+                let positions = fetch_positions(consumer)?;
+                remaining.retain(|&partition, end| {
+                    positions
+                        .get(&(topic.to_string(), partition))
+                        .is_none_or(|position| position < end)
+                });
+            }
+        }
+    }
+    if !remaining.is_empty() && visited < limit {
+        return Err(unread_partitions_error(
+            remaining.keys().map(|&partition| (topic, partition)),
+        ));
+    }
+    Ok(visited)
+}
+
+/// The offset the consumer fetches next from each assigned partition. It moves past
+/// transaction markers and compacted offsets, which never come back as records.
+fn fetch_positions(
+    consumer: &BaseConsumer<ClientAuth>,
+) -> Result<HashMap<(String, i32), i64>, KafkaError> {
+    let positions = consumer
+        .position()
+        .map_err(|er| KafkaError::OffsetFetch("Error while reading positions".to_string(), er))?;
+    Ok(positions
+        .elements()
+        .iter()
+        .filter_map(|e| match e.offset() {
+            Offset::Offset(offset) => Some(((e.topic().to_string(), e.partition()), offset)),
+            _ => None,
+        })
+        .collect())
+}
+
+/// The error of a read that gave up on partitions after 10s without records, rather than
+/// returning a result that silently misses them.
+fn unread_partitions_error<'a>(partitions: impl Iterator<Item = (&'a str, i32)>) -> KafkaError {
+    let partitions: Vec<String> = partitions
+        .map(|(topic, partition)| format!("{}/{}", topic, partition))
+        .collect();
+    KafkaError::Generic(format!(
+        "Gave up reading {} after 10s without records, the results would be incomplete",
+        partitions.join(", ")
+    ))
+}
+
+/// Reads up to `sample` of the most recent records of `topic`, spread evenly over its
+/// partitions, and hands each of them to `visit`. Returns the number of records visited.
+fn sample_recent_records<F>(
+    bootstrap_servers: &str,
+    topic: &str,
+    sample: usize,
+    visit: F,
+) -> Result<usize, KafkaError>
+where
+    F: FnMut(&BorrowedMessage),
+{
+    let consumer = get_consumer(bootstrap_servers)?;
+    let watermarks = topic_watermarks(&consumer, topic)?;
+    let per_partition = sample.div_ceil(watermarks.len()) as i64;
+    let ranges: Vec<(i32, i64, i64)> = watermarks
+        .iter()
+        .map(|&(partition, low, high)| (partition, (high - per_partition).max(low), high))
+        .collect();
+    read_ranges(&consumer, topic, &ranges, sample, visit)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct KeyStats {
+    key: Option<String>,
+    records: usize,
+    partitions: BTreeSet<i32>,
+}
+
+/// Groups sampled `(key, partition)` pairs by key, hottest keys first.
+fn summarize_keys(records: &[(Option<String>, i32)]) -> Vec<KeyStats> {
+    let mut stats: HashMap<&Option<String>, KeyStats> = HashMap::new();
+    for (key, partition) in records {
+        let entry = stats.entry(key).or_insert_with(|| KeyStats {
+            key: key.clone(),
+            records: 0,
+            partitions: BTreeSet::new(),
+        });
+        entry.records += 1;
+        entry.partitions.insert(*partition);
+    }
+    let mut stats: Vec<KeyStats> = stats.into_values().collect();
+    stats.sort_by(|a, b| b.records.cmp(&a.records).then_with(|| a.key.cmp(&b.key)));
+    stats
+}
+
+pub fn get_key_distribution(
+    bootstrap_servers: &str,
+    topic: &str,
+    sample: usize,
+    top: usize,
+) -> Result<(), KafkaError> {
+    let mut records = vec![];
+    sample_recent_records(bootstrap_servers, topic, sample, |message| {
+        let key = message
+            .key()
+            .map(|key| String::from_utf8_lossy(key).to_string());
+        records.push((key, message.partition()));
+    })?;
+
+    let stats = summarize_keys(&records);
+    let null_keys = stats
+        .iter()
+        .find(|s| s.key.is_none())
+        .map(|s| s.records)
+        .unwrap_or(0);
+
+    let mut summary = Table::new();
+    summary.add_row(row!["Records Sampled", "Distinct Keys", "Null Keys"]);
+    summary.add_row(row![records.len(), stats.len(), null_keys]);
+    output::print_table(&summary);
+
+    let mut table = Table::new();
+    table.add_row(row!["Key", "Records", "Share", "Partitions"]);
+    for stat in stats.iter().take(top) {
+        let partitions = stat
+            .partitions
+            .iter()
+            .map(|p| p.to_string())
+            .collect::<Vec<String>>();
+        table.add_row(row![
+            stat.key.as_deref().unwrap_or("<null>"),
+            stat.records,
+            format!("{:.1}%", stat.records as f64 * 100.0 / records.len() as f64),
+            partitions.join(", ")
+        ]);
+    }
+    output::print_table(&table);
+    Ok(())
+}
+
+fn record_size(message: &BorrowedMessage) -> usize {
+    let headers = message
+        .headers()
+        .map(|headers| {
+            headers
+                .iter()
+                .map(|h| h.key.len() + h.value.map(|v| v.len()).unwrap_or(0))
+                .sum()
+        })
+        .unwrap_or(0);
+    message.key_len() + message.payload_len() + headers
+}
+
+#[derive(Debug, PartialEq)]
+struct SizeStats {
+    min: usize,
+    avg: usize,
+    p95: usize,
+    max: usize,
+}
+
+fn compute_size_stats(sizes: &mut [usize]) -> Option<SizeStats> {
+    if sizes.is_empty() {
+        return None;
+    }
+    sizes.sort_unstable();
+    // Nearest-rank percentile
+    let p95_rank = (sizes.len() * 95).div_ceil(100).max(1);
+    Some(SizeStats {
+        min: sizes[0],
+        avg: sizes.iter().sum::<usize>() / sizes.len(),
+        p95: sizes[p95_rank - 1],
+        max: sizes[sizes.len() - 1],
+    })
+}
+
+pub fn get_message_sizes(
+    bootstrap_servers: &str,
+    topic: &str,
+    sample: usize,
+) -> Result<(), KafkaError> {
+    let mut sizes = vec![];
+    sample_recent_records(bootstrap_servers, topic, sample, |message| {
+        sizes.push(record_size(message));
+    })?;
+
+    let Some(stats) = compute_size_stats(&mut sizes) else {
+        println!("Topic {} has no records to sample", topic);
+        return Ok(());
+    };
+
+    let mut table = Table::new();
+    table.add_row(row!["Records", "Min", "Avg", "P95", "Max"]);
+    table.add_row(row![
+        sizes.len(),
+        output::bytes(stats.min as u64),
+        output::bytes(stats.avg as u64),
+        output::bytes(stats.p95 as u64),
+        output::bytes(stats.max as u64)
+    ]);
+    output::print_table(&table);
+    output::info("Sizes include the key, value and headers");
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct LargeRecord {
+    partition: i32,
+    offset: i64,
+    timestamp: Option<i64>,
+    size: usize,
+}
+
+/// Keeps the `limit` largest records, largest first.
+fn rank_large_records(mut records: Vec<LargeRecord>, limit: usize) -> Vec<LargeRecord> {
+    records.sort_by(|a, b| {
+        b.size
+            .cmp(&a.size)
+            .then_with(|| (a.partition, a.offset).cmp(&(b.partition, b.offset)))
+    });
+    records.truncate(limit);
+    records
+}
+
+/// Scans the latest `sample` records of `topic`, or all records since `from`, for records of at
+/// least `threshold` bytes, which defaults to 90% of the topic's `max.message.bytes`. Sizes are
+/// before compression, while the broker checks the compressed batch, so compressed topics
+/// accept records somewhat larger than the limit.
+pub fn find_large_messages(
+    bootstrap_servers: &str,
+    topic: &str,
+    threshold: Option<u64>,
+    sample: usize,
+    from: Option<i64>,
+    limit: usize,
+) -> Result<(), KafkaError> {
+    let admin_client = admin::get_admin_client(bootstrap_servers)?;
+    let configs = admin::describe_topic_configs(&admin_client, &[topic])?;
+    let max_bytes = configs
+        .get(topic)
+        .and_then(|entries| entries.iter().find(|e| e.name == "max.message.bytes"))
+        .and_then(|entry| entry.value.as_deref()?.parse::<u64>().ok());
+    let threshold = match (threshold, max_bytes) {
+        (Some(threshold), _) => threshold,
+        (None, Some(max_bytes)) => max_bytes * 9 / 10,
+        (None, None) => {
+            return Err(KafkaError::Generic(format!(
+                "Could not read max.message.bytes of {}, pass --threshold",
+                topic
+            )))
+        }
+    };
+
+    let mut scanned = 0;
+    let mut records = vec![];
+    let mut visit = |message: &BorrowedMessage| {
+        scanned += 1;
+        let size = record_size(message);
+        if size as u64 >= threshold {
+            records.push(LargeRecord {
+                partition: message.partition(),
+                offset: message.offset(),
+                timestamp: message.timestamp().to_millis(),
+                size,
+            });
+        }
+    };
+    match from {
+        Some(from) => {
+            let consumer = get_consumer(bootstrap_servers)?;
+            let ranges = ranges_from(&consumer, topic, &StartPosition::Timestamp(from))?;
+            read_ranges(&consumer, topic, &ranges, usize::MAX, visit)?;
+        }
+        None => {
+            sample_recent_records(bootstrap_servers, topic, sample, &mut visit)?;
+        }
+    }
+
+    let found = records.len();
+    if found > 0 {
+        let mut table = Table::new();
+        table.add_row(row!["Partition", "Offset", "Timestamp", "Size", "Of Limit"]);
+        for record in rank_large_records(records, limit) {
+            table.add_row(row![
+                record.partition,
+                record.offset,
+                record.timestamp.map_or("-".to_string(), output::timestamp),
+                output::bytes(record.size as u64),
+                max_bytes.map_or("-".to_string(), |max| format!(
+                    "{:.0}%",
+                    record.size as f64 * 100.0 / max as f64
+                ))
+            ]);
+        }
+        output::print_table(&table);
+    }
+    output::info(format!(
+        "{} of {} records scanned are {} or larger{}",
+        found,
+        scanned,
+        output::bytes(threshold),
+        max_bytes.map_or(String::new(), |max| format!(
+            ", max.message.bytes is {}",
+            output::bytes(max)
+        ))
+    ));
+    Ok(())
+}
+
+/// Records that failed to decode with the same error.
+#[derive(Debug, Clone, PartialEq)]
+struct ValidationFailure {
+    error: String,
+    records: usize,
+    /// `(partition, offset)` of the first few records
+    examples: Vec<(i32, i64)>,
+}
+
+const VALIDATION_EXAMPLES: usize = 3;
+
+/// Groups failed records by error, most frequent error first.
+fn summarize_validation(failures: Vec<(String, i32, i64)>) -> Vec<ValidationFailure> {
+    let mut by_error: BTreeMap<String, ValidationFailure> = BTreeMap::new();
+    for (error, partition, offset) in failures {
+        let failure = by_error
+            .entry(error.clone())
+            .or_insert_with(|| ValidationFailure {
+                error,
+                records: 0,
+                examples: vec![],
+            });
+        failure.records += 1;
+        if failure.examples.len() < VALIDATION_EXAMPLES {
+            failure.examples.push((partition, offset));
+        }
+    }
+    let mut failures: Vec<ValidationFailure> = by_error.into_values().collect();
+    failures.sort_by_key(|failure| std::cmp::Reverse(failure.records));
+    failures
+}
+
+/// Decodes a sample of recent records, or the records produced in `range` (epoch millis), with
+/// `decoder` and reports the ones that fail. Fails when any record does, so it can gate a
+/// pipeline.
+pub fn validate_topic(
+    bootstrap_servers: &str,
+    topic: &str,
+    decoder: &dyn PayloadDecoder,
+    sample: usize,
+    range: Option<(i64, i64)>,
+) -> Result<(), KafkaError> {
+    let mut validated = 0;
+    let mut tombstones = 0;
+    let mut failures = vec![];
+    let mut visit = |message: &BorrowedMessage| match message.payload() {
+        Some(payload) => {
+            validated += 1;
+            if let Err(er) = decoder.to_json(payload) {
+                failures.push((er, message.partition(), message.offset()));
+            }
+        }
+        None => tombstones += 1,
+    };
+    match range {
+        Some((since, until)) => {
+            let consumer = get_consumer(bootstrap_servers)?;
+            let mut ranges = ranges_from(&consumer, topic, &StartPosition::Timestamp(since))?;
+            if until < i64::MAX {
+                let mut tpl = TopicPartitionList::new();
+                for &(partition, _, _) in &ranges {
+                    tpl.add_partition(topic, partition);
+                }
+                let ends = fetch_offsets_at(&consumer, &tpl, Offset::Offset(until))?;
+                for (partition, _, end) in ranges.iter_mut() {
+                    if let Some(&until_offset) = ends.get(&(topic.to_string(), *partition)) {
+                        *end = until_offset.min(*end);
+                    }
+                }
+            }
+            read_ranges(&consumer, topic, &ranges, usize::MAX, &mut visit)?;
+        }
+        None => {
+            sample_recent_records(bootstrap_servers, topic, sample, &mut visit)?;
+        }
+    }
+
+    if validated == 0 {
+        println!("Topic {} has no records to validate", topic);
+        return Ok(());
+    }
+    let failed = failures.len();
+    if failed > 0 {
+        let mut table = Table::new();
+        table.add_row(row!["Error", "Records", "Examples"]);
+        for failure in summarize_validation(failures) {
+            let examples: Vec<String> = failure
+                .examples
+                .iter()
+                .map(|(partition, offset)| format!("{}:{}", partition, offset))
+                .collect();
+            table.add_row(row![failure.error, failure.records, examples.join(" ")]);
+        }
+        output::print_table(&table);
+    }
+    if tombstones > 0 {
+        output::info(format!("Skipped {} tombstones", tombstones));
+    }
+    println!(
+        "{} of {} records failed to decode ({:.2}%)",
+        failed,
+        validated,
+        failed as f64 * 100.0 / validated as f64
+    );
+    if failed > 0 {
+        return Err(KafkaError::Generic(format!(
+            "{} records of {} do not match the schema",
+            failed, topic
+        )));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct PartitionSize {
+    partition: i32,
+    replicas: usize,
+    /// Size of the largest replica, which is what the partition holds once followers catch up
+    size: i64,
+    /// Sum over all replicas
+    on_disk: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct TopicSize {
+    topic: String,
+    partitions: Vec<PartitionSize>,
+    size: i64,
+    on_disk: i64,
+}
+
+/// Groups replica sizes by topic and partition, largest topic on disk first.
+fn summarize_topic_sizes(replicas: &[admin::ReplicaSize]) -> Vec<TopicSize> {
+    let mut by_partition: BTreeMap<(&str, i32), Vec<i64>> = BTreeMap::new();
+    for replica in replicas {
+        by_partition
+            .entry((replica.topic.as_str(), replica.partition))
+            .or_default()
+            .push(replica.size);
+    }
+    let mut topics: Vec<TopicSize> = vec![];
+    for ((topic, partition), sizes) in by_partition {
+        let partition = PartitionSize {
+            partition,
+            replicas: sizes.len(),
+            size: sizes.iter().copied().max().unwrap_or(0),
+            on_disk: sizes.iter().sum(),
+        };
+        match topics.last_mut() {
+            Some(last) if last.topic == topic => {
+                last.size += partition.size;
+                last.on_disk += partition.on_disk;
+                last.partitions.push(partition);
+            }
+            _ => topics.push(TopicSize {
+                topic: topic.to_string(),
+                size: partition.size,
+                on_disk: partition.on_disk,
+                partitions: vec![partition],
+            }),
+        }
+    }
+    topics.sort_by(|a, b| {
+        b.on_disk
+            .cmp(&a.on_disk)
+            .then_with(|| a.topic.cmp(&b.topic))
+    });
+    topics
+}
+
+/// Reports how much storage a topic takes per partition, or with `topic` left out the `top`
+/// largest topics of the cluster, from the log dirs of every broker.
+pub fn get_topic_disk_usage(
+    bootstrap_servers: &str,
+    topic: Option<&str>,
+    top: usize,
+) -> Result<(), KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let partitions = match topic {
+        Some(topic) => {
+            let metadata = consumer
+                .fetch_metadata(Some(topic), Duration::from_secs(10))
+                .map_err(|er| {
+                    KafkaError::MetadataFetch("Error while fetching topic metadata".to_string(), er)
+                })?;
+            let partitions: Vec<i32> = metadata
+                .topics()
+                .iter()
+                .flat_map(|t| t.partitions().iter().map(|p| p.id()))
+                .collect();
+            if partitions.is_empty() {
+                return Err(KafkaError::TopicNotExists(format!(
+                    "Topic {} does not exist",
+                    topic
+                )));
+            }
+            Some(vec![(topic, partitions)])
+        }
+        None => None,
+    };
+
+    admin::check_feature(&consumer, admin::Feature::LOG_DIRS)?;
+    let brokers = admin::describe_cluster_nodes(&consumer)?;
+    let progress = output::Progress::new("Describing log dirs", Some(brokers.len() as u64));
+    let mut replicas = vec![];
+    for broker in &brokers {
+        let sizes = admin::describe_log_dirs(broker, partitions.as_deref());
+        progress.inc(1);
+        replicas.extend(sizes?);
+    }
+    progress.finish();
+    let sizes = summarize_topic_sizes(&replicas);
+
+    let mut table = Table::new();
+    match sizes.first().filter(|_| topic.is_some()) {
+        Some(topic) => {
+            table.add_row(row!["Partition", "Replicas", "Size", "On Disk"]);
+            for partition in &topic.partitions {
+                table.add_row(row![
+                    partition.partition,
+                    partition.replicas,
+                    output::bytes(partition.size as u64),
+                    output::bytes(partition.on_disk as u64)
+                ]);
+            }
+            table.add_row(row![
+                "Total",
+                "",
+                output::bytes(topic.size as u64),
+                output::bytes(topic.on_disk as u64)
+            ]);
+        }
+        None => {
+            table.add_row(row!["Topic", "Partitions", "Size", "On Disk"]);
+            for topic in sizes.iter().take(top) {
+                table.add_row(row![
+                    topic.topic,
+                    topic.partitions.len(),
+                    output::bytes(topic.size as u64),
+                    output::bytes(topic.on_disk as u64)
+                ]);
+            }
+        }
+    }
+    output::print_table(&table);
+    Ok(())
+}
+
+/// Why a topic's retention deserves a second look, if it does. `nothing_deleted` means no
+/// partition has moved its low watermark past 0.
+fn retention_flag(
+    retention_ms: i64,
+    retention_bytes: i64,
+    compacted: bool,
+    nothing_deleted: bool,
+) -> Option<&'static str> {
+    if compacted {
+        None
+    } else if retention_ms < 0 && retention_bytes < 0 {
+        Some("retention is unlimited")
+    } else if nothing_deleted {
+        Some("retention never reached")
+    } else {
+        None
+    }
+}
+
+/// How much of a retention limit is used, or `-` when there is no limit.
+fn retention_used(used: i64, limit: i64) -> String {
+    if limit <= 0 {
+        "-".to_string()
+    } else {
+        format!("{:.0}%", used as f64 * 100.0 / limit as f64)
+    }
+}
+
+/// The timestamp of the oldest record of each topic, or with `newest` of its newest record, read
+/// from the first or last offset of every partition that is not empty.
+fn fetch_edge_timestamps(
+    consumer: &BaseConsumer<ClientAuth>,
+    low: &HashMap<(String, i32), i64>,
+    high: &HashMap<(String, i32), i64>,
+    newest: bool,
+) -> Result<HashMap<String, i64>, KafkaError> {
+    let mut assignment = TopicPartitionList::new();
+    let mut remaining = BTreeMap::new();
+    for ((topic, partition), &start) in low {
+        let Some(&end) = high.get(&(topic.clone(), *partition)) else {
+            continue;
+        };
+        if start < end {
+            let offset = if newest { end - 1 } else { start };
+            assignment
+                .add_partition_offset(topic, *partition, Offset::Offset(offset))
+                .map_err(|er| {
+                    KafkaError::OffsetFetch("Error while preparing offsets".to_string(), er)
+                })?;
+            remaining.insert((topic.clone(), *partition), end);
+        }
+    }
+    let mut edges: HashMap<String, i64> = HashMap::new();
+    if remaining.is_empty() {
+        return Ok(edges);
+    }
+    consumer
+        .assign(&assignment)
+        .map_err(|er| KafkaError::Generic(format!("Error while assigning partitions: {:?}", er)))?;
+
+    let mut idle_polls = 0;
+    while !remaining.is_empty() && idle_polls < 100 {
+        match consumer.poll(Duration::from_millis(100)) {
+            Some(Ok(message)) => {
+                idle_polls = 0;
+                let key = (message.topic().to_string(), message.partition());
+                if remaining.remove(&key).is_none() {
+                    continue;
+                }
+                if let Some(timestamp) = message.timestamp().to_millis() {
+                    let entry = edges.entry(key.0).or_insert(timestamp);
+                    *entry = if newest {
+                        (*entry).max(timestamp)
+                    } else {
+                        (*entry).min(timestamp)
+                    };
+                }
+            }
+            Some(Err(e)) => {
+                Err(KafkaError::Generic(format!("Error while polling: {:?}", e)))?;
+            }
+            None => {
+                idle_polls += 1;
+                // A last offset that is a transaction marker has no record to read
+                let positions = fetch_positions(consumer)?;
+                remaining
+                    .retain(|key, end| positions.get(key).is_none_or(|position| position < end));
+            }
+        }
+    }
+    if !remaining.is_empty() {
+        return Err(unread_partitions_error(
+            remaining
+                .keys()
+                .map(|(topic, partition)| (topic.as_str(), *partition)),
+        ));
+    }
+    Ok(edges)
+}
+
+/// Compares the configured retention of every topic with the age of its oldest record and the
+/// size of its largest partition, which is what `retention.bytes` applies to.
+pub fn get_retention_report(bootstrap_servers: &str) -> Result<(), KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let metadata = consumer
+        .fetch_metadata(None, Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::MetadataFetch("Error while fetching topic metadata".to_string(), er)
+        })?;
+    let tpl = all_topic_partitions(&metadata);
+    let low = fetch_offsets_at(&consumer, &tpl, Offset::Beginning)?;
+    let high = fetch_offsets_at(&consumer, &tpl, Offset::End)?;
+
+    let topics: Vec<&str> = metadata
+        .topics()
+        .iter()
+        .map(|t| t.name())
+        .filter(|name| !name.starts_with("__"))
+        .collect();
+    let admin_client = admin::get_admin_client(bootstrap_servers)?;
+    let configs = admin::describe_topic_configs(&admin_client, &topics)?;
+
+    admin::check_feature(&consumer, admin::Feature::LOG_DIRS)?;
+    let brokers = admin::describe_cluster_nodes(&consumer)?;
+    let mut replicas = vec![];
+    for broker in &brokers {
+        replicas.extend(admin::describe_log_dirs(broker, None)?);
+    }
+    let largest_partitions: HashMap<String, i64> = summarize_topic_sizes(&replicas)
+        .into_iter()
+        .map(|t| {
+            let largest = t.partitions.iter().map(|p| p.size).max().unwrap_or(0);
+            (t.topic, largest)
+        })
+        .collect();
+    let oldest = fetch_edge_timestamps(&get_consumer(bootstrap_servers)?, &low, &high, false)?;
+    let now = chrono::Utc::now().timestamp_millis();
+
+    let mut table = Table::new();
+    table.add_row(row![
+        "Topic",
+        "retention.ms",
+        "Oldest Record",
+        "Time Used",
+        "retention.bytes",
+        "Largest Partition",
+        "Bytes Used",
+        "Flag"
+    ]);
+    let mut flagged = 0;
+    for topic in topics {
+        let config = |name: &str| {
+            configs
+                .get(topic)
+                .and_then(|entries| entries.iter().find(|e| e.name == name))
+                .and_then(|entry| entry.value.clone())
+        };
+        let retention_ms = config("retention.ms")
+            .and_then(|value| value.parse::<i64>().ok())
+            .unwrap_or(-1);
+        let retention_bytes = config("retention.bytes")
+            .and_then(|value| value.parse::<i64>().ok())
+            .unwrap_or(-1);
+        let compacted = config("cleanup.policy").is_some_and(|policy| !policy.contains("delete"));
+        let nothing_deleted = low
+            .iter()
+            .filter(|((name, _), _)| name == topic)
+            .all(|(_, &offset)| offset == 0);
+        let oldest = oldest.get(topic).copied();
+        let largest = largest_partitions.get(topic).copied().unwrap_or(0);
+
+        let flag = retention_flag(retention_ms, retention_bytes, compacted, nothing_deleted);
+        if flag.is_some() {
+            flagged += 1;
+        }
+        table.add_row(row![
+            topic,
+            output::config_value("retention.ms", &retention_ms.to_string()),
+            oldest.map_or("-".to_string(), output::timestamp),
+            oldest.map_or("-".to_string(), |oldest| retention_used(now - oldest, retention_ms)),
+            output::config_value("retention.bytes", &retention_bytes.to_string()),
+            output::bytes(largest as u64),
+            retention_used(largest, retention_bytes),
+            Fy->flag.unwrap_or(if compacted { "compacted" } else { "" })
+        ]);
+    }
+    output::print_table(&table);
+    output::info(format!(
+        "{} topics have retention that is never applied",
+        flagged
+    ));
+    Ok(())
+}
+
+/// Why `topics idle` reports a topic, `None` when it has a record newer than `cutoff`. Topics
+/// whose newest record could not be read, such as one ending in a transaction marker, are not
+/// reported.
+fn idle_reason(messages: i64, newest: Option<i64>, cutoff: i64) -> Option<&'static str> {
+    if messages == 0 {
+        return Some("empty");
+    }
+    newest.filter(|&t| t < cutoff).map(|_| "idle")
+}
+
+/// Lists the topics that hold no records, or whose newest record is older than `since`.
+pub fn get_idle_topics(bootstrap_servers: &str, since: Duration) -> Result<(), KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let metadata = consumer
+        .fetch_metadata(None, Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::MetadataFetch("Error while fetching topic metadata".to_string(), er)
+        })?;
+    let tpl = all_topic_partitions(&metadata);
+    let low = fetch_offsets_at(&consumer, &tpl, Offset::Beginning)?;
+    let high = fetch_offsets_at(&consumer, &tpl, Offset::End)?;
+    let newest = fetch_edge_timestamps(&get_consumer(bootstrap_servers)?, &low, &high, true)?;
+    let cutoff = chrono::Utc::now().timestamp_millis() - since.as_millis() as i64;
+
+    let mut topics: Vec<&str> = metadata
+        .topics()
+        .iter()
+        .map(|t| t.name())
+        .filter(|name| !name.starts_with("__"))
+        .collect();
+    topics.sort_unstable();
+    let mut table = Table::new();
+    table.add_row(row![
+        "Topic",
+        "Partitions",
+        "Messages",
+        "Newest Record",
+        "Flag"
+    ]);
+    let mut flagged = 0;
+    for topic in &topics {
+        let partitions: Vec<(i64, i64)> = high
+            .iter()
+            .filter(|((name, _), _)| name == topic)
+            .map(|(key, &end)| (low.get(key).copied().unwrap_or(0), end))
+            .collect();
+        let messages: i64 = partitions.iter().map(|(start, end)| end - start).sum();
+        let newest = newest.get(*topic).copied();
+        let Some(reason) = idle_reason(messages, newest, cutoff) else {
+            continue;
+        };
+        flagged += 1;
+        table.add_row(row![
+            topic,
+            partitions.len(),
+            output::count(messages),
+            newest.map_or("-".to_string(), output::timestamp),
+            Fy->reason
+        ]);
+    }
+    if flagged > 0 {
+        output::print_table(&table);
+    }
+    output::info(format!(
+        "{} of {} topics are empty or have no record newer than {}",
+        flagged,
+        topics.len(),
+        output::timestamp(cutoff)
+    ));
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct LatestValue {
+    partition: i32,
+    offset: i64,
+    value: String,
+}
+
+/// Applies a record to the materialized state the same way log compaction would: the newest
+/// value wins and a tombstone (null value) removes the key.
+fn apply_to_state(
+    state: &mut BTreeMap<String, LatestValue>,
+    key: String,
+    value: Option<String>,
+    partition: i32,
+    offset: i64,
+) {
+    match value {
+        Some(value) => {
+            state.insert(
+                key,
+                LatestValue {
+                    partition,
+                    offset,
+                    value,
+                },
+            );
+        }
+        None => {
+            state.remove(&key);
+        }
+    }
+}
+
+pub fn get_topic_state(
+    bootstrap_servers: &str,
+    topic: &str,
+    key: Option<&str>,
+    output: OutputFormat,
+) -> Result<(), KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let watermarks = topic_watermarks(&consumer, topic)?;
+
+    let mut state = BTreeMap::new();
+    let mut keyless = 0;
+    read_ranges(&consumer, topic, &watermarks, usize::MAX, |message| {
+        let Some(record_key) = message.key() else {
+            keyless += 1;
+            return;
+        };
+        let record_key = String::from_utf8_lossy(record_key).to_string();
+        if key.is_some_and(|key| key != record_key) {
+            return;
+        }
+        let value = message
+            .payload()
+            .map(|value| String::from_utf8_lossy(value).to_string());
+        apply_to_state(
+            &mut state,
+            record_key,
+            value,
+            message.partition(),
+            message.offset(),
+        );
+    })?;
+
+    if keyless > 0 {
+        output::status(format!("Skipped {} records without a key", keyless));
+    }
+
+    match output {
+        OutputFormat::Json => {
+            let json: serde_json::Map<String, serde_json::Value> = state
+                .into_iter()
+                .map(|(key, latest)| {
+                    let value = serde_json::from_str(&latest.value)
+                        .unwrap_or(serde_json::Value::String(latest.value));
+                    (key, value)
+                })
+                .collect();
+            let json = serde_json::Value::Object(json);
+            println!(
+                "{}",
+                to_colored_json_auto(&json).unwrap_or_else(|_| json.to_string())
+            );
+        }
+        OutputFormat::Csv => {
+            println!(
+                "{}",
+                output::csv_line(&["key", "partition", "offset", "value"])
+            );
+            for (key, latest) in &state {
+                println!(
+                    "{}",
+                    output::csv_line(&[
+                        key,
+                        &latest.partition.to_string(),
+                        &latest.offset.to_string(),
+                        &latest.value
+                    ])
+                );
+            }
+        }
+        OutputFormat::Table => {
+            let mut table = Table::new();
+            table.add_row(row!["Key", "Partition", "Offset", "Value"]);
+            for (key, latest) in &state {
+                table.add_row(row![key, latest.partition, latest.offset, latest.value]);
+            }
+            output::print_table(&table);
+            println!("{} live keys", state.len());
+        }
+    }
+    Ok(())
+}
+
+/// One record of `__transaction_state`. A record without metadata is a tombstone, written when
+/// the transactional id expired.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct TransactionRecord {
+    transactional_id: String,
+    partition: i32,
+    offset: i64,
+    #[serde(flatten)]
+    metadata: Option<TransactionMetadata>,
+}
+
+/// Keeps the latest record of every transactional id, or every record of `id` when given, the
+/// same way `apply_to_state` materializes a compacted topic.
+fn collect_transactions(
+    records: Vec<TransactionRecord>,
+    id: Option<&str>,
+    ongoing: bool,
+) -> Vec<TransactionRecord> {
+    let records: Vec<TransactionRecord> = match id {
+        Some(id) => records
+            .into_iter()
+            .filter(|record| record.transactional_id == id)
+            .collect(),
+        None => {
+            let mut latest = BTreeMap::new();
+            for record in records {
+                if record.metadata.is_some() {
+                    latest.insert(record.transactional_id.clone(), record);
+                } else {
+                    latest.remove(&record.transactional_id);
+                }
+            }
+            latest.into_values().collect()
+        }
+    };
+    records
+        .into_iter()
+        .filter(|record| !ongoing || record.metadata.as_ref().is_some_and(|m| m.is_ongoing()))
+        .collect()
+}
+
+pub fn get_transactions(
+    bootstrap_servers: &str,
+    id: Option<&str>,
+    ongoing: bool,
+    output: OutputFormat,
+) -> Result<(), KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let watermarks = topic_watermarks(&consumer, TRANSACTION_STATE_TOPIC)?;
+
+    let mut records = vec![];
+    let mut skipped = 0;
+    read_ranges(
+        &consumer,
+        TRANSACTION_STATE_TOPIC,
+        &watermarks,
+        usize::MAX,
+        |message| {
+            let decoded = message
+                .key()
+                .ok_or_else(|| "Record without a key".to_string())
+                .and_then(transaction::decode_key)
+                .and_then(|transactional_id| {
+                    let metadata = message.payload().map(transaction::decode_value);
+                    Ok((transactional_id, metadata.transpose()?))
+                });
+            match decoded {
+                Ok((transactional_id, metadata)) => records.push(TransactionRecord {
+                    transactional_id,
+                    partition: message.partition(),
+                    offset: message.offset(),
+                    metadata,
+                }),
+                Err(er) => {
+                    skipped += 1;
+                    output::status(format!(
+                        "Skipped {}:{}: {}",
+                        message.partition(),
+                        message.offset(),
+                        er
+                    ));
+                }
+            }
+        },
+    )?;
+    if skipped > 0 {
+        output::status(format!(
+            "Skipped {} records that could not be decoded",
+            skipped
+        ));
+    }
+
+    let records = collect_transactions(records, id, ongoing);
+    let columns = |record: &TransactionRecord| -> [String; 7] {
+        match &record.metadata {
+            Some(m) => [
+                record.transactional_id.clone(),
+                m.producer_id.to_string(),
+                m.producer_epoch.to_string(),
+                m.state.to_string(),
+                transaction::format_topics(&m.topics),
+                if m.start < 0 {
+                    "-".to_string()
+                } else {
+                    output::timestamp(m.start)
+                },
+                output::timestamp(m.last_update),
+            ],
+            None => [
+                record.transactional_id.clone(),
+                "-".to_string(),
+                "-".to_string(),
+                "Expired".to_string(),
+                String::new(),
+                "-".to_string(),
+                "-".to_string(),
+            ],
+        }
+    };
+    match output {
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string(&records).expect("Transaction records serialize to JSON")
+            );
+        }
+        OutputFormat::Csv => {
+            println!(
+                "{}",
+                output::csv_line(&[
+                    "transactional_id",
+                    "producer_id",
+                    "producer_epoch",
+                    "state",
+                    "partitions",
+                    "started",
+                    "last_update"
+                ])
+            );
+            for record in &records {
+                println!("{}", output::csv_line(&columns(record)));
+            }
+        }
+        OutputFormat::Table => {
+            let mut table = Table::new();
+            table.add_row(row![
+                "Transactional ID",
+                "Producer ID",
+                "Epoch",
+                "State",
+                "Partitions",
+                "Started",
+                "Last Update"
+            ]);
+            for record in &records {
+                let [id, producer_id, epoch, state, partitions, started, updated] = columns(record);
+                if record.metadata.as_ref().is_some_and(|m| m.is_ongoing()) {
+                    table.add_row(
+                        row![id, producer_id, epoch, Fy->state, partitions, started, updated],
+                    );
+                } else {
+                    table.add_row(row![
+                        id,
+                        producer_id,
+                        epoch,
+                        state,
+                        partitions,
+                        started,
+                        updated
+                    ]);
+                }
+            }
+            output::print_table(&table);
+        }
+    }
+    Ok(())
+}
+
+/// Counts delivery reports so that commands re-producing records can report what actually
+/// reached the destination topic.
+#[derive(Default)]
+struct DeliveryCounter {
+    delivered: AtomicUsize,
+    failed: AtomicUsize,
+}
+
+impl ClientContext for DeliveryCounter {
+    const ENABLE_REFRESH_OAUTH_TOKEN: bool = ClientAuth::ENABLE_REFRESH_OAUTH_TOKEN;
+
+    fn generate_oauth_token(
+        &self,
+        config: Option<&str>,
+    ) -> Result<OAuthToken, Box<dyn std::error::Error>> {
+        ClientAuth.generate_oauth_token(config)
+    }
+}
+
+impl DeliveryCounter {
+    /// Fails when any record sent to `topic` was not delivered, so that a partial copy does
+    /// not exit as a success.
+    fn check_delivered(&self, topic: &str) -> Result<(), KafkaError> {
+        let failed = self.failed.load(Ordering::Relaxed);
+        if failed == 0 {
+            return Ok(());
+        }
+        Err(KafkaError::Generic(format!(
+            "{} of {} records were not delivered to {}",
+            failed,
+            failed + self.delivered.load(Ordering::Relaxed),
+            topic
+        )))
+    }
+}
+
+impl ProducerContext for DeliveryCounter {
+    type DeliveryOpaque = ();
+
+    fn delivery(&self, result: &DeliveryResult<'_>, _: Self::DeliveryOpaque) {
+        match result {
+            Ok(_) => self.delivered.fetch_add(1, Ordering::Relaxed),
+            Err(_) => self.failed.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+}
+
+fn get_producer(
+    bootstrap_servers: &str,
+    settings: &ProducerArgs,
+) -> Result<BaseProducer<DeliveryCounter>, KafkaError> {
+    let mut config = client::config(bootstrap_servers);
+    if let Some(compression) = settings.compression {
+        config.set("compression.type", compression.config_value());
+    }
+    if let Some(acks) = settings.acks {
+        config.set("acks", acks.config_value());
+    }
+    tracing::debug!(
+        compression = ?settings.compression,
+        acks = ?settings.acks,
+        "Creating producer"
+    );
+    let producer: BaseProducer<DeliveryCounter> = config
+        .create_with_context(DeliveryCounter::default())
+        .map_err(client::creation_error)?;
+    client::authenticate(producer.client())?;
+    Ok(producer)
+}
+
+/// Enqueues a record, serving delivery reports while the local queue is full.
+fn send_record(
+    producer: &BaseProducer<DeliveryCounter>,
+    mut record: BaseRecord<'_, [u8], [u8]>,
+) -> Result<(), KafkaError> {
+    loop {
+        match producer.send(record) {
+            Ok(()) => {
+                producer.poll(Duration::ZERO);
+                return Ok(());
+            }
+            Err((
+                rdkafka::error::KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull),
+                r,
+            )) => {
+                record = r;
+                producer.poll(Duration::from_millis(100));
+            }
+            Err((er, _)) => {
+                tracing::warn!(error = %er, "Failed to produce record");
+                return Err(KafkaError::Produce(
+                    "Error while producing record".to_string(),
+                    er,
+                ));
+            }
+        }
+    }
+}
+
+fn flush_producer(producer: &BaseProducer<DeliveryCounter>) -> Result<(), KafkaError> {
+    let context = producer.context();
+    tracing::debug!(
+        delivered = context.delivered.load(Ordering::Relaxed),
+        failed = context.failed.load(Ordering::Relaxed),
+        "Flushing producer"
+    );
+    producer
+        .flush(Duration::from_secs(30))
+        .map_err(|er| KafkaError::Produce("Error while flushing producer".to_string(), er))
+}
+
+/// Where to start reading a topic.
+pub enum StartPosition {
+    Beginning,
+    End,
+    Offset(i64),
+    Timestamp(i64),
+}
+
+/// Resolves the `[start, high watermark)` range of every partition for `start`.
+fn ranges_from(
+    consumer: &BaseConsumer<ClientAuth>,
+    topic: &str,
+    start: &StartPosition,
+) -> Result<Vec<(i32, i64, i64)>, KafkaError> {
+    let watermarks = topic_watermarks(consumer, topic)?;
+    let starts = match start {
+        StartPosition::Timestamp(timestamp) => {
+            let mut tpl = TopicPartitionList::new();
+            for &(partition, _, _) in &watermarks {
+                tpl.add_partition(topic, partition);
+            }
+            fetch_offsets_at(consumer, &tpl, Offset::Offset(*timestamp))?
+        }
+        _ => HashMap::new(),
+    };
+
+    Ok(watermarks
+        .into_iter()
+        .map(|(partition, low, high)| {
+            let start = match start {
+                StartPosition::Beginning => low,
+                StartPosition::End => high,
+                StartPosition::Offset(offset) => (*offset).clamp(low, high),
+                // Partitions without records after the timestamp resolve to no offset
+                StartPosition::Timestamp(_) => {
+                    *starts.get(&(topic.to_string(), partition)).unwrap_or(&high)
+                }
+            };
+            (partition, start, high)
+        })
+        .collect())
+}
+
+fn matches_filter(payload: Option<&[u8]>, filter: Option<&str>) -> bool {
+    let Some(filter) = filter else {
+        return true;
+    };
+    payload
+        .and_then(|payload| std::str::from_utf8(payload).ok())
+        .and_then(|payload| serde_json::from_str::<Value>(payload).ok())
+        .is_some_and(|json| apply_filter(&json, filter))
+}
+
+pub fn replay_topic(
+    bootstrap_servers: &str,
+    source: &str,
+    destination: &str,
+    start: StartPosition,
+    filter: Option<&str>,
+    headers: &ReplayHeaderArgs,
+    settings: &ProducerArgs,
+) -> Result<(), KafkaError> {
+    replay_matching(
+        bootstrap_servers,
+        source,
+        destination,
+        start,
+        headers,
+        settings,
+        |message| matches_filter(message.payload(), filter),
+    )
+}
+
+/// Re-produces the records of `source` accepted by `select` to `destination`, keeping their
+/// keys and, unless dropped, their headers.
+fn replay_matching<P>(
+    bootstrap_servers: &str,
+    source: &str,
+    destination: &str,
+    start: StartPosition,
+    headers: &ReplayHeaderArgs,
+    settings: &ProducerArgs,
+    select: P,
+) -> Result<(), KafkaError>
+where
+    P: Fn(&BorrowedMessage) -> bool,
+{
+    let consumer = get_consumer(bootstrap_servers)?;
+    let producer = get_producer(bootstrap_servers, settings)?;
+    let ranges = ranges_from(&consumer, source, &start)?;
+    let total: i64 = ranges.iter().map(|(_, start, end)| end - start).sum();
+
+    let mut read = 0;
+    let mut skipped = 0;
+    let mut error = None;
+    let progress = output::Progress::new("Replaying", Some(total as u64));
+    read_ranges(&consumer, source, &ranges, usize::MAX, |message| {
+        read += 1;
+        progress.inc(1);
+        if error.is_some() {
+            return;
+        }
+        if !select(message) {
+            skipped += 1;
+            return;
+        }
+        let mut record: BaseRecord<'_, [u8], [u8]> = BaseRecord::to(destination);
+        if let Some(key) = message.key() {
+            record = record.key(key);
+        }
+        if let Some(payload) = message.payload() {
+            record = record.payload(payload);
+        }
+        let source_headers = message.headers().filter(|_| !headers.drop_source_headers);
+        record = record.headers(produce::replay_headers(source_headers, &headers.headers));
+        if let Err(er) = send_record(&producer, record) {
+            error = Some(er);
+        }
+    })?;
+    flush_producer(&producer)?;
+    progress.finish();
+    if let Some(error) = error {
+        return Err(error);
+    }
+
+    let context = producer.context();
+    println!(
+        "Read {} records from {}, skipped {}, delivered {} to {}, failed {}",
+        read,
+        source,
+        skipped,
+        context.delivered.load(Ordering::Relaxed),
+        destination,
+        context.failed.load(Ordering::Relaxed)
+    );
+    context.check_delivered(destination)
+}
+
+pub fn dump_topic(bootstrap_servers: &str, topic: &str, file: &Path) -> Result<(), KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let ranges = ranges_from(&consumer, topic, &StartPosition::Beginning)?;
+    let total: i64 = ranges.iter().map(|(_, start, end)| end - start).sum();
+
+    let output = File::create(file)
+        .map_err(|er| KafkaError::Io(format!("Failed to create snapshot file: {:?}", file), er))?;
+    let mut writer = BufWriter::new(output);
+    let mut error = None;
+    let mut written = 0;
+    let progress = output::Progress::new("Dumping", Some(total as u64));
+    read_ranges(&consumer, topic, &ranges, usize::MAX, |message| {
+        if error.is_some() {
+            return;
+        }
+        written += 1;
+        let record = SnapshotRecord::from_message(message);
+        let line = serde_json::to_string(&record).expect("Snapshot records serialize to JSON");
+        if let Err(er) = writeln!(writer, "{}", line) {
+            error = Some(er);
+        }
+        progress.inc(1);
+    })?;
+    progress.finish();
+    if let Some(er) = error {
+        return Err(KafkaError::Io(
+            format!("Failed to write snapshot file: {:?}", file),
+            er,
+        ));
+    }
+    writer
+        .flush()
+        .map_err(|er| KafkaError::Io(format!("Failed to write snapshot file: {:?}", file), er))?;
+
+    output::info(format!(
+        "Dumped {} records of {} to {:?}",
+        written, topic, file
+    ));
+    Ok(())
+}
+
+/// Joins the offsets at the start and at the end of a time window into `[start, end)` ranges.
+/// Partitions without records at or after the end of the window end at their high watermark.
+fn window_ranges(starts: &[(i32, i64, i64)], ends: &[(i32, i64, i64)]) -> Vec<(i32, i64, i64)> {
+    let ends: HashMap<i32, i64> = ends.iter().map(|&(p, end, _)| (p, end)).collect();
+    starts
+        .iter()
+        .map(|&(partition, start, high)| {
+            let end = ends.get(&partition).copied().unwrap_or(high).min(high);
+            (partition, start, end.max(start))
+        })
+        .collect()
+}
+
+/// Reads the records of `topic` produced in `[from, to)` on every partition and stops. They are
+/// printed as tail does, or written to `file` as a snapshot that `topics restore` can load.
+pub fn window_topic(
+    bootstrap_servers: &str,
+    topic: &str,
+    from: i64,
+    to: i64,
+    file: Option<&Path>,
+) -> Result<(), KafkaError> {
+    if from >= to {
+        return Err(KafkaError::Generic(
+            "The end of the window has to be after its start".to_string(),
+        ));
+    }
+    let consumer = get_consumer(bootstrap_servers)?;
+    let ranges = window_ranges(
+        &ranges_from(&consumer, topic, &StartPosition::Timestamp(from))?,
+        &ranges_from(&consumer, topic, &StartPosition::Timestamp(to))?,
+    );
+    let total: i64 = ranges.iter().map(|(_, start, end)| end - start).sum();
+    // Timestamps set by producers are not always in offset order, so records are checked too
+    let in_window = |message: &BorrowedMessage| {
+        message
+            .timestamp()
+            .to_millis()
+            .is_some_and(|timestamp| (from..to).contains(&timestamp))
+    };
+
+    let Some(file) = file else {
+        let mut printer = TailPrinter::new(&TailOptions {
+            filter: None,
+            decoder: None,
+            start: None,
+            follow: false,
+            max_rate: None,
+            sample: None,
+            format: PayloadFormat::Auto,
+            dedupe_window: None,
+            detect_gaps: false,
+            redact: &[],
+            flatten: false,
+            output: TailOutput::Pretty,
+            session: None,
+            highlight: None,
+            stats: false,
+            commit: false,
+        });
+        read_ranges(&consumer, topic, &ranges, usize::MAX, |message| {
+            if in_window(message) {
+                printer.print(message);
+            }
+        })?;
+        printer.finish();
+        return Ok(());
+    };
+
+    let output = File::create(file)
+        .map_err(|er| KafkaError::Io(format!("Failed to create snapshot file: {:?}", file), er))?;
+    let mut writer = BufWriter::new(output);
+    let mut error = None;
+    let mut written = 0;
+    let progress = output::Progress::new("Reading window", Some(total as u64));
+    read_ranges(&consumer, topic, &ranges, usize::MAX, |message| {
+        progress.inc(1);
+        if error.is_some() || !in_window(message) {
+            return;
+        }
+        written += 1;
+        let record = SnapshotRecord::from_message(message);
+        let line = serde_json::to_string(&record).expect("Snapshot records serialize to JSON");
+        if let Err(er) = writeln!(writer, "{}", line) {
+            error = Some(er);
+        }
+    })?;
+    progress.finish();
+    if let Some(er) = error {
+        return Err(KafkaError::Io(
+            format!("Failed to write snapshot file: {:?}", file),
+            er,
+        ));
+    }
+    writer
+        .flush()
+        .map_err(|er| KafkaError::Io(format!("Failed to write snapshot file: {:?}", file), er))?;
+
+    output::info(format!(
+        "Wrote {} records of {} between {} and {} to {:?}",
+        written,
+        topic,
+        output::timestamp(from),
+        output::timestamp(to),
+        file
+    ));
+    Ok(())
+}
+
+pub fn restore_topic(
+    bootstrap_servers: &str,
+    file: &Path,
+    topic: &str,
+    keep_partitions: bool,
+    settings: &ProducerArgs,
+) -> Result<(), KafkaError> {
+    let input = File::open(file)
+        .map_err(|er| KafkaError::Io(format!("Failed to open snapshot file: {:?}", file), er))?;
+    let producer = get_producer(bootstrap_servers, settings)?;
+
+    let mut sent = 0;
+    let progress = output::Progress::new("Restoring", None);
+    for (number, line) in BufReader::new(input).lines().enumerate() {
+        let line = line.map_err(|er| {
+            KafkaError::Io(format!("Failed to read snapshot file: {:?}", file), er)
+        })?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let invalid = |reason: String| {
+            KafkaError::Generic(format!(
+                "Invalid snapshot record on line {}: {}",
+                number + 1,
+                reason
+            ))
+        };
+        let record: SnapshotRecord =
+            serde_json::from_str(&line).map_err(|er| invalid(er.to_string()))?;
+        let key = record.key_bytes().map_err(|er| invalid(er.to_string()))?;
+        let value = record.value_bytes().map_err(|er| invalid(er.to_string()))?;
+        let headers = record
+            .owned_headers()
+            .map_err(|er| invalid(er.to_string()))?;
+
+        let mut base_record: BaseRecord<'_, [u8], [u8]> = BaseRecord::to(topic).headers(headers);
+        if let Some(key) = &key {
+            base_record = base_record.key(key.as_slice());
+        }
+        if let Some(value) = &value {
+            base_record = base_record.payload(value.as_slice());
+        }
+        if let Some(timestamp) = record.timestamp {
+            base_record = base_record.timestamp(timestamp);
+        }
+        if keep_partitions {
+            base_record = base_record.partition(record.partition);
+        }
+        send_record(&producer, base_record)?;
+        sent += 1;
+        progress.inc(1);
+    }
+    flush_producer(&producer)?;
+    progress.finish();
+
+    let context = producer.context();
+    println!(
+        "Restored {} records from {:?} to {}, delivered {}, failed {}",
+        sent,
+        file,
+        topic,
+        context.delivered.load(Ordering::Relaxed),
+        context.failed.load(Ordering::Relaxed)
+    );
+    context.check_delivered(topic)
+}
+
+/// Produces `record` `repeat` times, `interval` apart, with its placeholders such as `{{seq}}`
+/// and `{{now}}` filled in, and waits for the records to be delivered. Ctrl-C stops early.
+pub fn produce_record(
+    bootstrap_servers: &str,
+    topic: &str,
+    record: &DraftRecord,
+    partition: Option<i32>,
+    repeat: u64,
+    interval: Option<Duration>,
+    settings: &ProducerArgs,
+) -> Result<(), KafkaError> {
+    let template = DraftTemplate::parse(record)
+        .map_err(|er| KafkaError::Generic(format!("Invalid record: {}", er)))?;
+    let producer = get_producer(bootstrap_servers, settings)?;
+    let stopped = stop_on_ctrl_c()?;
+    let progress = output::Progress::new("Producing", Some(repeat));
+    let mut rng = fastrand::Rng::new();
+    let mut sent = 0;
+    for seq in 1..=repeat {
+        if stopped.load(Ordering::SeqCst) {
+            break;
+        }
+        let record = template.render(&mut rng, chrono::Utc::now().timestamp_millis(), seq);
+        let value = record.value_bytes();
+        let mut base_record: BaseRecord<'_, [u8], [u8]> =
+            BaseRecord::to(topic).headers(record.owned_headers());
+        if let Some(key) = &record.key {
+            base_record = base_record.key(key.as_bytes());
+        }
+        if let Some(value) = &value {
+            base_record = base_record.payload(value.as_slice());
+        }
+        if let Some(partition) = partition {
+            base_record = base_record.partition(partition);
+        }
+        send_record(&producer, base_record)?;
+        sent += 1;
+        progress.inc(1);
+        if let Some(interval) = interval.filter(|_| seq < repeat) {
+            std::thread::sleep(interval);
+        }
+    }
+    flush_producer(&producer)?;
+    progress.finish();
+
+    let context = producer.context();
+    let delivered = context.delivered.load(Ordering::Relaxed);
+    if repeat == 1 {
+        if delivered == 0 {
+            return Err(KafkaError::Generic(format!(
+                "The record was not delivered to {}",
+                topic
+            )));
+        }
+        output::info(format!("Produced 1 record to {}", topic));
+    } else {
+        let failed = context.failed.load(Ordering::Relaxed);
+        println!(
+            "Produced {} records to {}, delivered {}, failed {}",
+            sent, topic, delivered, failed
+        );
+        context.check_delivered(topic)?;
+    }
+    Ok(())
+}
+
+/// Produces `count` records generated from a JSON template, for demo and test topics.
+pub fn seed_topic(
+    bootstrap_servers: &str,
+    topic: &str,
+    template_file: &Path,
+    key: Option<&str>,
+    count: usize,
+    headers: &[(String, String)],
+    settings: &ProducerArgs,
+) -> Result<(), KafkaError> {
+    let content = std::fs::read_to_string(template_file).map_err(|er| {
+        KafkaError::Io(format!("Failed to read template: {:?}", template_file), er)
+    })?;
+    let template = RecordTemplate::parse(&content).map_err(|er| {
+        KafkaError::Generic(format!("Invalid template {:?}: {}", template_file, er))
+    })?;
+    let key = key
+        .map(TextTemplate::parse)
+        .transpose()
+        .map_err(|er| KafkaError::Generic(format!("Invalid key template: {}", er)))?;
+    let producer = get_producer(bootstrap_servers, settings)?;
+
+    let mut rng = fastrand::Rng::new();
+    let progress = output::Progress::new("Seeding", Some(count as u64));
+    for seq in 1..=count as u64 {
+        let now = chrono::Utc::now().timestamp_millis();
+        let value = template.render(&mut rng, now, seq).to_string();
+        let key = key.as_ref().map(|key| key.render(&mut rng, now, seq));
+        let mut record: BaseRecord<'_, [u8], [u8]> = BaseRecord::to(topic)
+            .payload(value.as_bytes())
+            .headers(produce::owned_headers(headers));
+        if let Some(key) = &key {
+            record = record.key(key.as_bytes());
+        }
+        send_record(&producer, record)?;
+        progress.inc(1);
+    }
+    flush_producer(&producer)?;
+    progress.finish();
+
+    let context = producer.context();
+    println!(
+        "Produced {} records to {}, delivered {}, failed {}",
+        count,
+        topic,
+        context.delivered.load(Ordering::Relaxed),
+        context.failed.load(Ordering::Relaxed)
+    );
+    context.check_delivered(topic)
+}
+
+/// Headers that dead-letter producers use to record why a record failed, most specific first.
+/// Kafka Connect and Spring Kafka conventions are supported out of the box.
+const DLQ_ERROR_HEADERS: [&str; 4] = [
+    "__connect.errors.exception.class.name",
+    "kafka_dlt-exception-fqcn",
+    "exception",
+    "error",
+];
+const DLQ_TOPIC_HEADERS: [&str; 2] = ["__connect.errors.topic", "kafka_dlt-original-topic"];
+const DLQ_PARTITION_HEADERS: [&str; 2] =
+    ["__connect.errors.partition", "kafka_dlt-original-partition"];
+
+/// Returns the raw value of the first header of `names` present on the message.
+fn find_header<'a, M: Message>(message: &'a M, names: &[&str]) -> Option<&'a [u8]> {
+    let headers = message.headers()?;
+    names.iter().find_map(|name| {
+        headers
+            .iter()
+            .find(|h| h.key == *name)
+            .and_then(|h| h.value)
+    })
+}
+
+/// Partition headers are strings for Kafka Connect and big-endian integers for Spring Kafka.
+fn decode_partition_header(value: &[u8]) -> Option<i32> {
+    if let Some(partition) = std::str::from_utf8(value)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+    {
+        return Some(partition);
+    }
+    let bytes: [u8; 4] = value.try_into().ok()?;
+    Some(i32::from_be_bytes(bytes))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct DlqOrigin {
+    error: String,
+    topic: String,
+    partition: Option<i32>,
+}
+
+fn dlq_origin<M: Message>(message: &M, error_header: Option<&str>) -> DlqOrigin {
+    let error = match error_header {
+        Some(header) => find_header(message, &[header]),
+        None => find_header(message, &DLQ_ERROR_HEADERS),
+    };
+    DlqOrigin {
+        error: error
+            .map(|e| String::from_utf8_lossy(e).to_string())
+            .unwrap_or_else(|| "<unknown>".to_string()),
+        topic: find_header(message, &DLQ_TOPIC_HEADERS)
+            .map(|t| String::from_utf8_lossy(t).to_string())
+            .unwrap_or_else(|| "<unknown>".to_string()),
+        partition: find_header(message, &DLQ_PARTITION_HEADERS).and_then(decode_partition_header),
+    }
+}
+
+pub fn inspect_dlq(
+    bootstrap_servers: &str,
+    topic: &str,
+    start: StartPosition,
+    error_header: Option<&str>,
+) -> Result<(), KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let ranges = ranges_from(&consumer, topic, &start)?;
+    let total: i64 = ranges.iter().map(|(_, start, end)| end - start).sum();
+    let progress = output::Progress::new("Inspecting", Some(total as u64));
+
+    // Records, first offset and last offset per origin
+    let mut origins: BTreeMap<DlqOrigin, (usize, String, String)> = BTreeMap::new();
+    let read = read_ranges(&consumer, topic, &ranges, usize::MAX, |message| {
+        progress.inc(1);
+        let position = format!("{}:{}", message.partition(), message.offset());
+        let entry = origins
+            .entry(dlq_origin(message, error_header))
+            .or_insert_with(|| (0, position.clone(), position.clone()));
+        entry.0 += 1;
+        entry.2 = position;
+    })?;
+    progress.finish();
+
+    let mut groups: Vec<(DlqOrigin, (usize, String, String))> = origins.into_iter().collect();
+    groups.sort_by(|a, b| b.1 .0.cmp(&a.1 .0).then_with(|| a.0.cmp(&b.0)));
+
+    let mut table = Table::new();
+    table.add_row(row![
+        "Error",
+        "Source Topic",
+        "Source Partition",
+        "Records",
+        "First",
+        "Last"
+    ]);
+    for (origin, (records, first, last)) in groups {
+        let partition = origin
+            .partition
+            .map(|p| p.to_string())
+            .unwrap_or_else(|| "-".to_string());
+        table.add_row(row![
+            origin.error,
+            origin.topic,
+            partition,
+            output::count(records as i64),
+            first,
+            last
+        ]);
+    }
+    output::print_table(&table);
+    output::info(format!(
+        "{} records inspected, positions are partition:offset",
+        read
+    ));
+    Ok(())
+}
+
+pub struct DlqSelection<'a> {
+    pub error: Option<&'a str>,
+    pub error_header: Option<&'a str>,
+    pub source_partition: Option<i32>,
+    pub filter: Option<&'a str>,
+}
+
+pub fn replay_dlq(
+    bootstrap_servers: &str,
+    topic: &str,
+    destination: &str,
+    start: StartPosition,
+    selection: DlqSelection,
+    headers: &ReplayHeaderArgs,
+    settings: &ProducerArgs,
+) -> Result<(), KafkaError> {
+    replay_matching(
+        bootstrap_servers,
+        topic,
+        destination,
+        start,
+        headers,
+        settings,
+        |message| {
+            let origin = dlq_origin(message, selection.error_header);
+            selection
+                .error
+                .is_none_or(|error| origin.error.contains(error))
+                && selection
+                    .source_partition
+                    .is_none_or(|partition| origin.partition == Some(partition))
+                && matches_filter(message.payload(), selection.filter)
+        },
+    )
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct PartitionState {
+    leader: i32,
+    isr: Vec<i32>,
+}
+
+/// What `topics watch-meta` compares between two polls of a topic.
+#[derive(Debug, Clone, PartialEq, Default)]
+struct TopicMeta {
+    partitions: BTreeMap<i32, PartitionState>,
+    /// Configs that are not the broker default
+    configs: BTreeMap<String, String>,
+}
+
+#[derive(Debug, PartialEq)]
+enum MetaEvent {
+    PartitionCount(usize, usize),
+    LeaderChanged(i32, i32, i32),
+    IsrChanged(i32, Vec<i32>, Vec<i32>),
+    ConfigChanged(String, Option<String>, Option<String>),
+}
+
+fn fetch_topic_meta(
+    consumer: &BaseConsumer<ClientAuth>,
+    admin_client: &AdminClient<ClientAuth>,
+    topic: &str,
+) -> Result<TopicMeta, KafkaError> {
+    let metadata = consumer
+        .fetch_metadata(Some(topic), Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::MetadataFetch("Error while fetching topic metadata".to_string(), er)
+        })?;
+    let partitions: BTreeMap<i32, PartitionState> = metadata.topics()[0]
+        .partitions()
+        .iter()
+        .map(|p| {
+            let mut isr = p.isr().to_vec();
+            isr.sort_unstable();
+            (
+                p.id(),
+                PartitionState {
+                    leader: p.leader(),
+                    isr,
+                },
+            )
+        })
+        .collect();
+    if partitions.is_empty() {
+        return Err(KafkaError::TopicNotExists(format!(
+            "Topic {} does not exist",
+            topic
+        )));
+    }
+    let configs = admin::describe_topic_configs(admin_client, &[topic])?;
+    let entries = configs.get(topic).map(Vec::as_slice).unwrap_or(&[]);
+    Ok(TopicMeta {
+        partitions,
+        configs: non_default_configs(entries, &[]).into_iter().collect(),
+    })
+}
+
+fn diff_topic_meta(old: &TopicMeta, new: &TopicMeta) -> Vec<MetaEvent> {
+    let mut events = vec![];
+    if old.partitions.len() != new.partitions.len() {
+        events.push(MetaEvent::PartitionCount(
+            old.partitions.len(),
+            new.partitions.len(),
+        ));
+    }
+    for (id, partition) in &new.partitions {
+        let Some(previous) = old.partitions.get(id) else {
+            continue;
+        };
+        if previous.leader != partition.leader {
+            events.push(MetaEvent::LeaderChanged(
+                *id,
+                previous.leader,
+                partition.leader,
+            ));
+        }
+        if previous.isr != partition.isr {
+            events.push(MetaEvent::IsrChanged(
+                *id,
+                previous.isr.clone(),
+                partition.isr.clone(),
+            ));
+        }
+    }
+    let names: BTreeSet<&String> = old.configs.keys().chain(new.configs.keys()).collect();
+    for name in names {
+        let (before, after) = (old.configs.get(name), new.configs.get(name));
+        if before != after {
+            events.push(MetaEvent::ConfigChanged(
+                name.clone(),
+                before.cloned(),
+                after.cloned(),
+            ));
+        }
+    }
+    events
+}
+
+fn format_meta_event(event: &MetaEvent) -> String {
+    match event {
+        MetaEvent::PartitionCount(old, new) => {
+            format!("partition count changed: {} -> {}", old, new)
+        }
+        MetaEvent::LeaderChanged(partition, old, new) => {
+            format!("partition {} leader changed: {} -> {}", partition, old, new)
+        }
+        MetaEvent::IsrChanged(partition, old, new) => {
+            let shrunk = new.len() < old.len();
+            format!(
+                "partition {} ISR {}: [{}] -> [{}]",
+                partition,
+                if shrunk { "shrank" } else { "changed" },
+                join_ids(old),
+                join_ids(new)
+            )
+        }
+        MetaEvent::ConfigChanged(name, old, new) => format!(
+            "config {} changed: {} -> {}",
+            name,
+            old.as_ref()
+                .map_or("(default)".to_string(), |v| output::config_value(name, v)),
+            new.as_ref()
+                .map_or("(default)".to_string(), |v| output::config_value(name, v))
+        ),
+    }
+}
+
+/// Polls the metadata and configs of `topic` every `interval` and prints a line for each change
+/// of partition count, leader, ISR or config. Failed polls are reported and retried, as brokers
+/// come and go during a rolling restart.
+pub fn watch_topic_meta(
+    bootstrap_servers: &str,
+    topic: &str,
+    interval: Duration,
+) -> Result<(), KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let admin_client = admin::get_admin_client(bootstrap_servers)?;
+    let mut meta = fetch_topic_meta(&consumer, &admin_client, topic)?;
+    let offline = meta
+        .partitions
+        .values()
+        .filter(|partition| partition.leader < 0)
+        .count();
+    println!(
+        "[{}] watching topic {} ({} partitions, {} offline, {} configs set)",
+        output::now(),
+        topic,
+        meta.partitions.len(),
+        offline,
+        meta.configs.len()
+    );
+
+    let stopped = stop_on_ctrl_c()?;
+    let mut failing = false;
+    while !stopped.load(Ordering::SeqCst) {
+        std::thread::sleep(interval);
+        match fetch_topic_meta(&consumer, &admin_client, topic) {
+            Ok(new_meta) => {
+                if failing {
+                    println!("[{}] polling recovered", output::now());
+                    failing = false;
+                }
+                for event in diff_topic_meta(&meta, &new_meta) {
+                    println!("[{}] {}", output::now(), format_meta_event(&event));
+                }
+                meta = new_meta;
+            }
+            Err(er) if !failing => {
+                println!("[{}] polling failed: {}", output::now(), er);
+                failing = true;
+            }
+            Err(_) => {}
+        }
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct PartitionWatermark {
+    partition: i32,
+    low: i64,
+    high: i64,
+    messages: i64,
+}
+
+pub fn get_topic_watermarks(
+    bootstrap_servers: &str,
+    topic: &str,
+    output: OutputFormat,
+) -> Result<(), KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let watermarks: Vec<PartitionWatermark> = topic_watermarks(&consumer, topic)?
+        .into_iter()
+        .map(|(partition, low, high)| PartitionWatermark {
+            partition,
+            low,
+            high,
+            messages: high - low,
+        })
+        .collect();
+
+    match output {
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string(&watermarks).expect("Watermarks serialize to JSON")
+            );
+        }
+        OutputFormat::Csv => {
+            println!(
+                "{}",
+                output::csv_line(&["partition", "low", "high", "messages"])
+            );
+            for watermark in &watermarks {
+                println!(
+                    "{}",
+                    output::csv_line(&[
+                        watermark.partition.to_string(),
+                        watermark.low.to_string(),
+                        watermark.high.to_string(),
+                        watermark.messages.to_string()
+                    ])
+                );
+            }
+        }
+        OutputFormat::Table => {
+            let mut table = Table::new();
+            table.add_row(row!["Partition", "Low", "High", "Messages"]);
+            for watermark in &watermarks {
+                table.add_row(row![
+                    watermark.partition,
+                    watermark.low,
+                    watermark.high,
+                    output::count(watermark.messages)
+                ]);
+            }
+            output::print_table(&table);
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+struct BrokerBalance {
+    id: i32,
+    leaders: usize,
+    replicas: usize,
+    leader_deviation: f64,
+    replica_deviation: f64,
+}
+
+/// Counts leaders and replicas per broker and how far each broker is from an even share,
+/// in percent. Offline partitions, whose leader is -1, count for no broker.
+fn compute_broker_balance(brokers: &[i32], partitions: &[(i32, Vec<i32>)]) -> Vec<BrokerBalance> {
+    let mut leaders: BTreeMap<i32, usize> = brokers.iter().map(|b| (*b, 0)).collect();
+    let mut replicas = leaders.clone();
+    for (leader, partition_replicas) in partitions {
+        if *leader >= 0 {
+            *leaders.entry(*leader).or_default() += 1;
+        }
+        for replica in partition_replicas {
+            *replicas.entry(*replica).or_default() += 1;
+        }
+    }
+
+    let deviation = |count: usize, total: usize| {
+        let expected = total as f64 / brokers.len().max(1) as f64;
+        if expected > 0.0 {
+            (count as f64 - expected) * 100.0 / expected
+        } else {
+            0.0
+        }
+    };
+    let total_leaders: usize = leaders.values().sum();
+    let total_replicas: usize = replicas.values().sum();
+    leaders
+        .iter()
+        .map(|(id, leader_count)| {
+            let replica_count = *replicas.get(id).unwrap_or(&0);
+            BrokerBalance {
+                id: *id,
+                leaders: *leader_count,
+                replicas: replica_count,
+                leader_deviation: deviation(*leader_count, total_leaders),
+                replica_deviation: deviation(replica_count, total_replicas),
+            }
+        })
+        .collect()
+}
+
+pub fn get_broker_balance(bootstrap_servers: &str, threshold: f64) -> Result<(), KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let metadata = consumer
+        .fetch_metadata(None, Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::MetadataFetch("Error while fetching broker metadata".to_string(), er)
+        })?;
+
+    let brokers: Vec<i32> = metadata.brokers().iter().map(|b| b.id()).collect();
+    let partitions: Vec<(i32, Vec<i32>)> = metadata
+        .topics()
+        .iter()
+        .flat_map(|t| t.partitions())
+        .map(|p| (p.leader(), p.replicas().to_vec()))
+        .collect();
+
+    let mut table = Table::new();
+    table.add_row(row![
+        "Broker ID",
+        "Leaders",
+        "Leader Deviation",
+        "Replicas",
+        "Replica Deviation",
+        "Status"
+    ]);
+    for balance in compute_broker_balance(&brokers, &partitions) {
+        let leader_deviation = format!("{:+.1}%", balance.leader_deviation);
+        let replica_deviation = format!("{:+.1}%", balance.replica_deviation);
+        if balance.leader_deviation > threshold || balance.replica_deviation > threshold {
+            table.add_row(row![
+                Fr->balance.id,
+                Fr->balance.leaders,
+                Fr->leader_deviation,
+                Fr->balance.replicas,
+                Fr->replica_deviation,
+                Fr->"IMBALANCED"
+            ]);
+        } else {
+            table.add_row(row![
+                balance.id,
+                balance.leaders,
+                leader_deviation,
+                balance.replicas,
+                replica_deviation,
+                "OK"
+            ]);
+        }
+    }
+    output::print_table(&table);
+    let offline = partitions.iter().filter(|(leader, _)| *leader < 0).count();
+    if offline > 0 {
+        output::status(format!(
+            "{} offline partitions have no leader and are left out of the leader counts",
+            offline
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+struct BrokerPartitions {
+    leaders: usize,
+    replicas: usize,
+    /// Replicas on the broker that are not in the ISR
+    out_of_sync: usize,
+}
+
+/// Counts the partitions `broker` leads or holds a replica of, from `(leader, replicas, isr)`.
+fn count_broker_partitions(broker: i32, partitions: &[(i32, &[i32], &[i32])]) -> BrokerPartitions {
+    let mut counts = BrokerPartitions::default();
+    for (leader, replicas, isr) in partitions {
+        if *leader == broker {
+            counts.leaders += 1;
+        }
+        if replicas.contains(&broker) {
+            counts.replicas += 1;
+            if !isr.contains(&broker) {
+                counts.out_of_sync += 1;
+            }
+        }
+    }
+    counts
+}
+
+/// Replica count and bytes per log dir, in path order.
+fn summarize_log_dirs(replicas: &[admin::ReplicaSize]) -> Vec<(String, usize, i64)> {
+    let mut dirs: BTreeMap<&str, (usize, i64)> = BTreeMap::new();
+    for replica in replicas {
+        let dir = dirs.entry(replica.log_dir.as_str()).or_default();
+        dir.0 += 1;
+        dir.1 += replica.size;
+    }
+    dirs.into_iter()
+        .map(|(dir, (count, size))| (dir.to_string(), count, size))
+        .collect()
+}
+
+/// Shows one broker: its endpoints, the partitions it leads and holds, how its log dirs are
+/// used and the configs changed on it at runtime.
+pub fn get_single_broker_detail(bootstrap_servers: &str, id: i32) -> Result<(), KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let metadata = consumer
+        .fetch_metadata(None, Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::MetadataFetch("Error while fetching broker metadata".to_string(), er)
+        })?;
+    let node = admin::describe_cluster_nodes(&consumer)?
+        .into_iter()
+        .find(|node| node.id == id)
+        .ok_or_else(|| KafkaError::Generic(format!("Broker {} not found", id)))?;
+    let partitions: Vec<(i32, &[i32], &[i32])> = metadata
+        .topics()
+        .iter()
+        .flat_map(|t| t.partitions())
+        .map(|p| (p.leader(), p.replicas(), p.isr()))
+        .collect();
+    let counts = count_broker_partitions(id, &partitions);
+
+    let mut table = Table::new();
+    table.add_row(row![
+        "Broker ID",
+        "Host",
+        "Port",
+        "Rack",
+        "Leaders",
+        "Replicas",
+        "Out of Sync"
+    ]);
+    let rack = node.rack.as_deref().unwrap_or("-");
+    if counts.out_of_sync > 0 {
+        table.add_row(row![
+            node.id,
+            node.host,
+            node.port,
+            rack,
+            counts.leaders,
+            counts.replicas,
+            Fr->counts.out_of_sync
+        ]);
+    } else {
+        table.add_row(row![
+            node.id,
+            node.host,
+            node.port,
+            rack,
+            counts.leaders,
+            counts.replicas,
+            counts.out_of_sync
+        ]);
+    }
+    output::print_table(&table);
+
+    let admin_client = admin::get_admin_client(bootstrap_servers)?;
+    let configs = admin::describe_configs(&admin_client, &[ResourceSpecifier::Broker(id)])?;
+    let entries = configs
+        .get(&id.to_string())
+        .map(Vec::as_slice)
+        .unwrap_or(&[]);
+    let config = |name: &str| {
+        entries
+            .iter()
+            .find(|e| e.name == name)
+            .and_then(|e| e.value.clone())
+            .unwrap_or_default()
+    };
+    let mut listeners = Table::new();
+    listeners.add_row(row!["Listener", "Advertised As"]);
+    let advertised = config("advertised.listeners");
+    let listener_names = config("listeners");
+    for listener in listener_names.split(',').filter(|l| !l.is_empty()) {
+        let name = listener
+            .split_once("://")
+            .map_or(listener, |(name, _)| name);
+        let advertised = advertised
+            .split(',')
+            .find(|a| a.starts_with(&format!("{}://", name)))
+            .unwrap_or("-");
+        listeners.add_row(row![listener, advertised]);
+    }
+    output::print_table(&listeners);
+
+    let mut log_dirs = Table::new();
+    log_dirs.add_row(row!["Log Dir", "Replicas", "Size"]);
+    for (dir, count, size) in summarize_log_dirs(&admin::describe_log_dirs(&node, None)?) {
+        log_dirs.add_row(row![dir, count, output::bytes(size as u64)]);
+    }
+    output::print_table(&log_dirs);
+
+    let mut dynamic: Vec<&ConfigEntry> = entries
+        .iter()
+        .filter(|e| {
+            matches!(
+                e.source,
+                ConfigSource::DynamicBroker | ConfigSource::DynamicDefaultBroker
+            ) && !e.is_sensitive
+        })
+        .collect();
+    dynamic.sort_by(|a, b| a.name.cmp(&b.name));
+    if dynamic.is_empty() {
+        println!("Broker {} has no dynamic config overrides", id);
+        return Ok(());
+    }
+    let mut overrides = Table::new();
+    overrides.add_row(row!["Dynamic Config", "Value", "Set For"]);
+    for entry in dynamic {
+        let value = entry.value.as_deref().unwrap_or_default();
+        let scope = match entry.source {
+            ConfigSource::DynamicBroker => "this broker",
+            _ => "all brokers",
+        };
+        overrides.add_row(row![
+            entry.name,
+            output::config_value(&entry.name, value),
+            scope
+        ]);
+    }
+    output::print_table(&overrides);
+    Ok(())
+}
+
+/// Why a partition is a risk for producers using `acks=all`, if it is.
+fn min_isr_risk(replicas: usize, isr: usize, min_isr: usize) -> Option<&'static str> {
+    if min_isr > replicas {
+        Some("min.insync.replicas exceeds the replication factor")
+    } else if isr < min_isr {
+        Some("unavailable for acks=all")
+    } else if isr == min_isr && replicas > min_isr {
+        Some("one more replica failure blocks acks=all")
+    } else {
+        None
+    }
+}
+
+pub fn get_min_isr_report(bootstrap_servers: &str) -> Result<(), KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let metadata = consumer
+        .fetch_metadata(None, Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::MetadataFetch("Error while fetching topic metadata".to_string(), er)
+        })?;
+    let topics: Vec<&str> = metadata.topics().iter().map(|t| t.name()).collect();
+    let admin_client = admin::get_admin_client(bootstrap_servers)?;
+    let configs = admin::describe_topic_configs(&admin_client, &topics)?;
+
+    let mut table = Table::new();
+    table.add_row(row![
+        "Topic",
+        "Partition",
+        "Replicas",
+        "ISR",
+        "min.insync.replicas",
+        "Risk"
+    ]);
+    let mut at_risk = 0;
+    for topic in metadata.topics() {
+        let min_isr = configs
+            .get(topic.name())
+            .and_then(|entries| entries.iter().find(|e| e.name == "min.insync.replicas"))
+            .and_then(|entry| entry.value.as_ref())
+            .and_then(|value| value.parse::<usize>().ok())
+            .unwrap_or(1);
+        for partition in topic.partitions() {
+            let replicas = partition.replicas().len();
+            let isr = partition.isr().len();
+            if let Some(risk) = min_isr_risk(replicas, isr, min_isr) {
+                at_risk += 1;
+                table.add_row(row![
+                    topic.name(),
+                    partition.id(),
+                    join_ids(partition.replicas()),
+                    join_ids(partition.isr()),
+                    min_isr,
+                    Fr->risk
+                ]);
+            }
+        }
+    }
+
+    if at_risk == 0 {
+        println!("No partitions are at risk for acks=all producers");
+    } else {
+        output::print_table(&table);
+        println!("{} partitions are at risk for acks=all producers", at_risk);
+    }
+    Ok(())
+}
+
+/// Records when the last probe record was acknowledged, so that each one is timed on its own.
+#[derive(Default)]
+struct AckTimer {
+    acked: Mutex<Option<(Instant, Result<(), String>)>>,
+}
+
+impl ClientContext for AckTimer {
+    const ENABLE_REFRESH_OAUTH_TOKEN: bool = ClientAuth::ENABLE_REFRESH_OAUTH_TOKEN;
+
+    fn generate_oauth_token(
+        &self,
+        config: Option<&str>,
+    ) -> Result<OAuthToken, Box<dyn std::error::Error>> {
+        ClientAuth.generate_oauth_token(config)
+    }
+}
+
+impl ProducerContext for AckTimer {
+    type DeliveryOpaque = ();
+
+    fn delivery(&self, result: &DeliveryResult<'_>, _: Self::DeliveryOpaque) {
+        let outcome = result
+            .as_ref()
+            .map(|_| ())
+            .map_err(|(er, _)| er.to_string());
+        *self.acked.lock().unwrap() = Some((Instant::now(), outcome));
+    }
+}
+
+/// Produces `count` records one at a time to `partition` and returns how long each took to be
+/// acknowledged. One more record is sent first and not timed, as it also opens the connection.
+fn probe_partition(
+    producer: &BaseProducer<AckTimer>,
+    topic: &str,
+    partition: i32,
+    count: u64,
+) -> Result<Vec<Duration>, String> {
+    let mut latencies = Vec::with_capacity(count as usize);
+    for seq in 0..=count {
+        let payload = format!("kfcli probe {}", seq);
+        let sent = Instant::now();
+        producer
+            .send(
+                BaseRecord::<str, str>::to(topic)
+                    .partition(partition)
+                    .key("kfcli-probe")
+                    .payload(&payload),
+            )
+            .map_err(|(er, _)| er.to_string())?;
+        let acked = loop {
+            producer.poll(Duration::from_millis(100));
+            if let Some(acked) = producer.context().acked.lock().unwrap().take() {
+                break acked;
+            }
+        };
+        let (at, outcome) = acked;
+        outcome?;
+        if seq > 0 {
+            latencies.push(at - sent);
+        }
+    }
+    Ok(latencies)
+}
+
+fn median(latencies: &[Duration]) -> Duration {
+    let mut sorted = latencies.to_vec();
+    sorted.sort();
+    sorted.get(sorted.len() / 2).copied().unwrap_or_default()
+}
+
+/// Brokers whose median ack latency is over twice the median of all brokers, and at least
+/// 10 ms over it so that jitter on a fast cluster is not reported.
+fn slow_brokers(medians: &BTreeMap<i32, Duration>) -> BTreeSet<i32> {
+    let overall = median(&medians.values().copied().collect::<Vec<Duration>>());
+    medians
+        .iter()
+        .filter(|(_, latency)| {
+            **latency > overall * 2 && **latency > overall + Duration::from_millis(10)
+        })
+        .map(|(id, _)| *id)
+        .collect()
+}
+
+fn format_latency(latency: Duration) -> String {
+    format!("{:.1} ms", latency.as_secs_f64() * 1000.0)
+}
+
+/// Produces small records to a partition of `topic` led by each broker and shows the ack
+/// latency per broker, flagging brokers much slower than the rest.
+pub fn probe_brokers(
+    bootstrap_servers: &str,
+    topic: &str,
+    count: u64,
+    acks: Option<Acks>,
+    timeout: Duration,
+) -> Result<(), KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let metadata = consumer
+        .fetch_metadata(Some(topic), Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::MetadataFetch("Error while fetching topic metadata".to_string(), er)
+        })?;
+    let topic_metadata = &metadata.topics()[0];
+    if topic_metadata.partitions().is_empty() {
+        return Err(KafkaError::TopicNotExists(format!(
+            "Topic {} does not exist, create it with at least one partition per broker",
+            topic
+        )));
+    }
+    let mut led: BTreeMap<i32, i32> = BTreeMap::new();
+    for partition in topic_metadata.partitions() {
+        let id = led.entry(partition.leader()).or_insert(partition.id());
+        *id = (*id).min(partition.id());
+    }
+
+    let mut config = client::config(bootstrap_servers);
+    config
+        .set("linger.ms", "0")
+        .set("message.timeout.ms", timeout.as_millis().to_string());
+    if let Some(acks) = acks {
+        config.set("acks", acks.config_value());
+    }
+    let producer: BaseProducer<AckTimer> = config
+        .create_with_context(AckTimer::default())
+        .map_err(client::creation_error)?;
+    client::authenticate(producer.client())?;
+
+    let mut brokers: Vec<(i32, String)> = metadata
+        .brokers()
+        .iter()
+        .map(|b| (b.id(), format!("{}:{}", b.host(), b.port())))
+        .collect();
+    brokers.sort();
+    let progress = output::Progress::new("Probing brokers", Some(brokers.len() as u64));
+    let mut results = BTreeMap::new();
+    for (id, _) in &brokers {
+        if let Some(partition) = led.get(id) {
+            results.insert(*id, probe_partition(&producer, topic, *partition, count));
+        }
+        progress.inc(1);
+    }
+    progress.finish();
+
+    let medians: BTreeMap<i32, Duration> = results
+        .iter()
+        .filter_map(|(id, result)| result.as_ref().ok().map(|l| (*id, median(l))))
+        .collect();
+    let slow = slow_brokers(&medians);
+    let mut table = Table::new();
+    table.add_row(row![
+        "Broker",
+        "Host",
+        "Partition",
+        "Min",
+        "Median",
+        "Max",
+        "Status"
+    ]);
+    for (id, host) in &brokers {
+        match (led.get(id), results.get(id)) {
+            (Some(partition), Some(Ok(latencies))) => {
+                let min = latencies.iter().min().copied().unwrap_or_default();
+                let max = latencies.iter().max().copied().unwrap_or_default();
+                let (min, median, max) = (
+                    format_latency(min),
+                    format_latency(medians[id]),
+                    format_latency(max),
+                );
+                if slow.contains(id) {
+                    table.add_row(row![id, host, partition, min, median, max, Fr->"slow"]);
+                } else {
+                    table.add_row(row![id, host, partition, min, median, max, Fg->"ok"]);
+                }
+            }
+            (Some(partition), Some(Err(er))) => {
+                table.add_row(row![id, host, partition, "-", "-", "-", Fr->er]);
+            }
+            _ => {
+                table.add_row(row![id, host, "-", "-", "-", "-", "leads no partition"]);
+            }
+        }
+    }
+    output::print_table(&table);
+    let unled: Vec<String> = brokers
+        .iter()
+        .filter(|(id, _)| !led.contains_key(id))
+        .map(|(id, _)| id.to_string())
+        .collect();
+    if !unled.is_empty() {
+        output::status(format!(
+            "Brokers {} lead no partition of {} and were not probed",
+            unled.join(", "),
+            topic
+        ));
+    }
+    if results.is_empty() {
+        return Err(KafkaError::Generic(format!(
+            "No broker leads a partition of {}, so none was probed",
+            topic
+        )));
+    }
+    let failed = results.values().filter(|result| result.is_err()).count();
+    if failed > 0 {
+        return Err(KafkaError::Generic(format!(
+            "The probe failed on {} of {} brokers",
+            failed,
+            results.len()
+        )));
+    }
+    if slow.is_empty() {
+        output::info(format!(
+            "Probed {} brokers with {} records each, none is markedly slower",
+            medians.len(),
+            count
+        ));
+    } else {
+        output::info(format!(
+            "{} of {} brokers are markedly slower to acknowledge",
+            slow.len(),
+            medians.len()
+        ));
+    }
+    Ok(())
+}
+
+pub fn get_broker_config_diff(
+    bootstrap_servers: &str,
+    broker_ids: &[i32],
+) -> Result<(), KafkaError> {
+    let broker_ids = if broker_ids.is_empty() {
+        let consumer = get_consumer(bootstrap_servers)?;
+        let metadata = consumer
+            .fetch_metadata(None, Duration::from_secs(10))
+            .map_err(|er| {
+                KafkaError::MetadataFetch("Error while fetching broker metadata".to_string(), er)
+            })?;
+        let mut ids: Vec<i32> = metadata.brokers().iter().map(|b| b.id()).collect();
+        ids.sort();
+        ids
+    } else {
+        broker_ids.to_vec()
+    };
+    if broker_ids.len() < 2 {
+        return Err(KafkaError::Generic(
+            "At least two brokers are needed to compare configs".to_string(),
+        ));
+    }
+
+    // librdkafka accepts a single broker resource per DescribeConfigs request
+    let admin_client = admin::get_admin_client(bootstrap_servers)?;
+    let mut columns = Vec::with_capacity(broker_ids.len());
+    for id in &broker_ids {
+        let configs = admin::describe_configs(&admin_client, &[ResourceSpecifier::Broker(*id)])?;
+        let entries = configs
+            .get(&id.to_string())
+            .map(Vec::as_slice)
+            .unwrap_or(&[]);
+        columns.push(non_default_configs(entries, &BROKER_IDENTITY_CONFIGS));
+    }
+
+    let headers: Vec<String> = broker_ids
+        .iter()
+        .map(|id| format!("Broker {}", id))
+        .collect();
+    print_config_diff(&headers, diff_configs(&columns));
+    Ok(())
+}
+
+pub fn get_topic_config_diff(bootstrap_servers: &str, topics: &[String]) -> Result<(), KafkaError> {
+    if topics.len() < 2 {
+        return Err(KafkaError::Generic(
+            "At least two topics are needed to compare configs".to_string(),
+        ));
+    }
+    let names: Vec<&str> = topics.iter().map(String::as_str).collect();
+    let admin_client = admin::get_admin_client(bootstrap_servers)?;
+    let configs = admin::describe_topic_configs(&admin_client, &names)?;
+    let columns: Vec<HashMap<String, String>> = names
+        .iter()
+        .map(|topic| {
+            let entries = configs.get(*topic).map(Vec::as_slice).unwrap_or(&[]);
+            non_default_configs(entries, &[])
+        })
+        .collect();
+
+    print_config_diff(topics, diff_configs(&columns));
+    Ok(())
+}
+
+fn print_config_diff(headers: &[String], differences: Vec<(String, Vec<Option<String>>)>) {
+    if differences.is_empty() {
+        println!("{} have the same configuration", headers.join(", "));
+        return;
+    }
+
+    let mut table = Table::new();
+    let mut header = vec!["Config".to_string()];
+    header.extend(headers.iter().cloned());
+    table.add_row(header.into());
+    for (name, values) in differences {
+        let cells: Vec<String> = std::iter::once(name.clone())
+            .chain(values.into_iter().map(|v| match v {
+                Some(value) => output::config_value(&name, &value),
+                None => "(default)".to_string(),
+            }))
+            .collect();
+        table.add_row(cells.into());
+    }
+    output::print_table(&table);
+}
+
+/// Turns the outcome of a topic admin operation into an error naming the topic. Failing with
+/// `ignored`, e.g. because the topic to create already exists, is not an error and gives `None`.
+fn handle_topic_result(
+    result: TopicResult,
+    action: &str,
+    ignored: Option<RDKafkaErrorCode>,
+) -> Result<Option<String>, KafkaError> {
+    match result {
+        Ok(topic) => Ok(Some(topic)),
+        Err((_, code)) if Some(code) == ignored => Ok(None),
+        Err((topic, code)) => Err(KafkaError::Admin(format!(
+            "Could not {} topic {}: {}",
+            action, topic, code
+        ))),
+    }
+}
+
+/// Creates a topic with `partitions` and `replication_factor`, or with the replicas of each
+/// partition placed on the brokers in `assignment`. With `if_not_exists` an existing topic is
+/// left as it is.
+pub fn create_topic(
+    bootstrap_servers: &str,
+    topic: &str,
+    partitions: i32,
+    replication_factor: i32,
+    assignment: Option<&[Vec<i32>]>,
+    configs: &[(String, String)],
+    if_not_exists: bool,
+) -> Result<(), KafkaError> {
+    let assignment: Option<Vec<&[i32]>> =
+        assignment.map(|replicas| replicas.iter().map(Vec::as_slice).collect());
+    let new_topic = match &assignment {
+        Some(replicas) => NewTopic::new(
+            topic,
+            replicas.len() as i32,
+            TopicReplication::Variable(replicas),
+        ),
+        None => NewTopic::new(
+            topic,
+            partitions,
+            TopicReplication::Fixed(replication_factor),
+        ),
+    };
+    let new_topic = configs.iter().fold(new_topic, |new_topic, (name, value)| {
+        new_topic.set(name, value)
+    });
+
+    let admin_client = admin::get_admin_client(bootstrap_servers)?;
+    let ignored = if_not_exists.then_some(RDKafkaErrorCode::TopicAlreadyExists);
+    for result in admin::create_topics(&admin_client, &[new_topic])? {
+        match handle_topic_result(result, "create", ignored)? {
+            Some(topic) => output::info(format!("Created topic {}", topic)),
+            None => output::info(format!("Topic {} already exists", topic)),
+        }
+    }
+    Ok(())
+}
+
+/// Deletes a topic after asking for confirmation. With `if_exists` a missing topic is not an
+/// error.
+pub fn delete_topic(
+    bootstrap_servers: &str,
+    topic: &str,
+    if_exists: bool,
+    assume_yes: bool,
+) -> Result<(), KafkaError> {
+    confirm_change(
+        format!("Delete topic {}?", topic),
+        assume_yes,
+        "No topics were deleted",
+    )?;
+    let admin_client = admin::get_admin_client(bootstrap_servers)?;
+    let ignored = if_exists.then_some(RDKafkaErrorCode::UnknownTopicOrPartition);
+    for result in admin::delete_topics(&admin_client, &[topic])? {
+        match handle_topic_result(result, "delete", ignored)? {
+            Some(topic) => output::info(format!("Deleted topic {}", topic)),
+            None => output::info(format!("Topic {} does not exist", topic)),
+        }
+    }
+    Ok(())
+}
+
+/// New replicas per partition to reach `factor`, leaving out partitions that already have it.
+/// Replicas are dropped from the end so the preferred leader stays, and added on the brokers
+/// holding the fewest replicas, preferring racks the partition is not on yet.
+fn plan_replication_change(
+    partitions: &[(i32, Vec<i32>)],
+    brokers: &[admin::ClusterNode],
+    mut load: HashMap<i32, usize>,
+    factor: usize,
+) -> Result<Vec<(i32, Vec<i32>)>, KafkaError> {
+    if factor == 0 || factor > brokers.len() {
+        return Err(KafkaError::Generic(format!(
+            "Replication factor must be between 1 and the number of brokers, {}",
+            brokers.len()
+        )));
+    }
+    let rack = |id: i32| {
+        brokers
+            .iter()
+            .find(|b| b.id == id)
+            .and_then(|b| b.rack.as_deref())
+    };
+    let mut changes = vec![];
+    for (partition, replicas) in partitions {
+        if replicas.len() == factor {
+            continue;
+        }
+        let mut target = replicas.clone();
+        for removed in target.drain(factor.min(replicas.len())..) {
+            load.entry(removed)
+                .and_modify(|count| *count = count.saturating_sub(1));
+        }
+        while target.len() < factor {
+            let racks: BTreeSet<Option<&str>> = target.iter().map(|id| rack(*id)).collect();
+            let Some(broker) = brokers
+                .iter()
+                .filter(|b| !target.contains(&b.id))
+                .min_by_key(|b| {
+                    let rack_used = b.rack.is_some() && racks.contains(&b.rack.as_deref());
+                    (rack_used, load.get(&b.id).copied().unwrap_or(0), b.id)
+                })
+            else {
+                break;
+            };
+            *load.entry(broker.id).or_default() += 1;
+            target.push(broker.id);
+        }
+        changes.push((*partition, target));
+    }
+    Ok(changes)
+}
+
+/// Changes the replication factor of a topic by reassigning its partitions, after showing the
+/// new replicas and asking for confirmation.
+pub fn set_replication_factor(
+    bootstrap_servers: &str,
+    topic: &str,
+    factor: usize,
+    assume_yes: bool,
+) -> Result<(), KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let metadata = consumer
+        .fetch_metadata(None, Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::MetadataFetch("Error while fetching topic metadata".to_string(), er)
+        })?;
+    let mut load: HashMap<i32, usize> = HashMap::new();
+    for partition in metadata.topics().iter().flat_map(|t| t.partitions()) {
+        for replica in partition.replicas() {
+            *load.entry(*replica).or_default() += 1;
+        }
+    }
+    let partitions: Vec<(i32, Vec<i32>)> = metadata
+        .topics()
+        .iter()
+        .filter(|t| t.name() == topic)
+        .flat_map(|t| t.partitions())
+        .map(|p| (p.id(), p.replicas().to_vec()))
+        .collect();
+    if partitions.is_empty() {
+        return Err(KafkaError::TopicNotExists(format!(
+            "Topic {} does not exist",
+            topic
+        )));
+    }
+
+    admin::check_feature(&consumer, admin::Feature::PARTITION_REASSIGNMENTS)?;
+    let brokers = admin::describe_cluster_nodes(&consumer)?;
+    let changes = plan_replication_change(&partitions, &brokers, load, factor)?;
+    if changes.is_empty() {
+        output::info(format!(
+            "Topic {} already has a replication factor of {}",
+            topic, factor
+        ));
+        return Ok(());
+    }
+
+    let current: HashMap<i32, &Vec<i32>> = partitions.iter().map(|(p, r)| (*p, r)).collect();
+    let mut table = Table::new();
+    table.add_row(row!["Partition", "Replicas", "New Replicas"]);
+    for (partition, target) in &changes {
+        table.add_row(row![
+            partition,
+            join_ids(current[partition]),
+            join_ids(target)
+        ]);
+    }
+    output::print_table(&table);
+
+    confirm_change(
+        format!(
+            "Reassign {} partitions of {} to a replication factor of {}?",
+            changes.len(),
+            topic,
+            factor
+        ),
+        assume_yes,
+        "No partitions were reassigned",
+    )?;
+    let controller = admin::describe_controller(&consumer)?;
+    let failures = admin::alter_partition_reassignments(&controller, topic, &changes)?;
+    for (partition, reason) in &failures {
+        eprintln!("Could not reassign partition {}: {}", partition, reason);
+    }
+    output::info(format!(
+        "Started reassigning {} partitions of {}; the brokers copy the data in the background",
+        changes.len() - failures.len(),
+        topic
+    ));
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(KafkaError::Admin(format!(
+            "{} partitions could not be reassigned",
+            failures.len()
+        )))
+    }
+}
+
+/// Names of the topics `pattern` matches in full, sorted. Internal topics are never matched.
+fn matching_topics<'a>(
+    names: impl IntoIterator<Item = &'a str>,
+    pattern: &str,
+) -> Result<Vec<&'a str>, KafkaError> {
+    let regex = Regex::new(&format!("^(?:{})$", pattern))
+        .map_err(|er| KafkaError::Generic(format!("Invalid pattern {}: {}", pattern, er)))?;
+    let mut matching: Vec<&str> = names
+        .into_iter()
+        .filter(|name| !name.starts_with("__") && regex.is_match(name))
+        .collect();
+    matching.sort();
+    Ok(matching)
+}
+
+/// Deletes every topic whose whole name matches `pattern`, `batch_size` topics per request,
+/// after listing them and asking for confirmation.
+pub fn delete_topics_matching(
+    bootstrap_servers: &str,
+    pattern: &str,
+    batch_size: usize,
+    assume_yes: bool,
+) -> Result<(), KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let metadata = consumer
+        .fetch_metadata(None, Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::MetadataFetch("Error while fetching topic metadata".to_string(), er)
+        })?;
+    let topics = matching_topics(metadata.topics().iter().map(|t| t.name()), pattern)?;
+    if topics.is_empty() {
+        output::info(format!("No topics match {}", pattern));
+        return Ok(());
+    }
+
+    let mut table = Table::new();
+    table.add_row(row!["Topic", "Partitions"]);
+    for topic in metadata.topics() {
+        if topics.contains(&topic.name()) {
+            table.add_row(row![topic.name(), topic.partitions().len()]);
+        }
+    }
+    output::print_table(&table);
+    confirm_change(
+        format!("Delete these {} topics?", topics.len()),
+        assume_yes,
+        "No topics were deleted",
+    )?;
+
+    let admin_client = admin::get_admin_client(bootstrap_servers)?;
+    let progress = output::Progress::new("Deleting topics", Some(topics.len() as u64));
+    let mut results = Table::new();
+    results.add_row(row!["Topic", "Result"]);
+    let mut failed = 0;
+    for batch in topics.chunks(batch_size.max(1)) {
+        let outcome = admin::delete_topics(&admin_client, batch);
+        progress.inc(batch.len() as u64);
+        for result in outcome? {
+            match result {
+                Ok(topic) => results.add_row(row![topic, Fg->"deleted"]),
+                Err((topic, code)) => {
+                    failed += 1;
+                    results.add_row(row![topic, Fr->code.to_string()])
+                }
+            };
+        }
+    }
+    progress.finish();
+    output::print_table(&results);
+    if failed > 0 {
+        return Err(KafkaError::Admin(format!(
+            "{} of {} topics could not be deleted",
+            failed,
+            topics.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Configs set on the topic itself, leaving out sensitive ones whose value is not returned.
+fn topic_overrides(entries: &[ConfigEntry]) -> BTreeMap<String, String> {
+    entries
+        .iter()
+        .filter(|e| e.source == ConfigSource::DynamicTopic && !e.is_sensitive)
+        .filter_map(|e| Some((e.name.clone(), e.value.clone()?)))
+        .collect()
+}
+
+/// Partition count, replication factor and configs of every topic that is not internal.
+fn read_topic_backups(
+    bootstrap_servers: &str,
+    consumer: &BaseConsumer<ClientAuth>,
+) -> Result<Vec<TopicBackup>, KafkaError> {
+    let metadata = consumer
+        .fetch_metadata(None, Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::MetadataFetch("Error while fetching topic metadata".to_string(), er)
+        })?;
+    let topics: Vec<_> = metadata
+        .topics()
+        .iter()
+        .filter(|t| !t.name().starts_with("__"))
+        .collect();
+    let names: Vec<&str> = topics.iter().map(|t| t.name()).collect();
+    let admin_client = admin::get_admin_client(bootstrap_servers)?;
+    let configs = admin::describe_topic_configs(&admin_client, &names)?;
+
+    let mut backups: Vec<TopicBackup> = topics
+        .iter()
+        .map(|topic| TopicBackup {
+            name: topic.name().to_string(),
+            partitions: topic.partitions().len() as i32,
+            replication_factor: topic
+                .partitions()
+                .first()
+                .map_or(0, |p| p.replicas().len() as i32),
+            configs: configs
+                .get(topic.name())
+                .map(|entries| topic_overrides(entries))
+                .unwrap_or_default(),
+        })
+        .collect();
+    backups.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(backups)
+}
+
+/// Writes the topics, their configs and the ACLs of the cluster to `file`. ACLs that cannot be
+/// described, such as on a cluster without an authorizer, fail the backup unless `skip_acls`.
+pub fn backup_cluster(
+    bootstrap_servers: &str,
+    file: &Path,
+    skip_acls: bool,
+) -> Result<(), KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let topics = read_topic_backups(bootstrap_servers, &consumer)?;
+    let acls = if skip_acls {
+        vec![]
+    } else {
+        admin::describe_acls(&consumer).map_err(|er| {
+            KafkaError::Admin(format!(
+                "Failed to read the ACLs, pass --skip-acls to back up the topics only: {}",
+                er
+            ))
+        })?
+    };
+    let backup = ClusterBackup {
+        created: chrono::Utc::now().timestamp_millis(),
+        topics,
+        acls,
+    };
+    let content = serde_json::to_string_pretty(&backup).expect("Cluster backups serialize");
+    std::fs::write(file, content)
+        .map_err(|er| KafkaError::Io(format!("Failed to write backup file: {:?}", file), er))?;
+    output::info(format!(
+        "Backed up {} topics and {} ACLs to {:?}",
+        backup.topics.len(),
+        backup.acls.len(),
+        file
+    ));
+    Ok(())
+}
+
+fn print_restore_plan(steps: &[RestoreStep]) {
+    let mut table = Table::new();
+    table.add_row(row!["Change", "Resource", "Details"]);
+    for step in steps {
+        match step {
+            RestoreStep::CreateTopic(topic) => table.add_row(row![
+                "create topic",
+                topic.name,
+                format!(
+                    "{} partitions, replication factor {}, {} configs",
+                    topic.partitions,
+                    topic.replication_factor,
+                    topic.configs.len()
+                )
+            ]),
+            RestoreStep::AddPartitions { topic, from, to } => {
+                table.add_row(row!["add partitions", topic, format!("{} -> {}", from, to)])
+            }
+            RestoreStep::SetConfigs { topic, configs } => {
+                let configs: Vec<String> = configs
+                    .iter()
+                    .map(|(name, value)| format!("{}={}", name, output::config_value(name, value)))
+                    .collect();
+                table.add_row(row!["set configs", topic, configs.join(", ")])
+            }
+            RestoreStep::CreateAcl(acl) => {
+                table.add_row(row!["create acl", acl.resource_name, acl.to_string()])
+            }
+            RestoreStep::Skip { topic, reason } => {
+                table.add_row(row![Fy->"skip", topic, Fy->reason])
+            }
+        };
+    }
+    output::print_table(&table);
+}
+
+/// Recreates the topics, configs and ACLs of a backup on the cluster, after showing the changes
+/// and asking for confirmation. What the cluster has on top of the backup is left alone.
+pub fn restore_cluster(
+    bootstrap_servers: &str,
+    file: &Path,
+    dry_run: bool,
+    assume_yes: bool,
+) -> Result<(), KafkaError> {
+    let content = std::fs::read_to_string(file)
+        .map_err(|er| KafkaError::Io(format!("Failed to read backup file: {:?}", file), er))?;
+    let backup: ClusterBackup = serde_json::from_str(&content)
+        .map_err(|er| KafkaError::Generic(format!("Invalid backup file {:?}: {}", file, er)))?;
+    let consumer = get_consumer(bootstrap_servers)?;
+    let current = ClusterBackup {
+        created: 0,
+        topics: read_topic_backups(bootstrap_servers, &consumer)?,
+        acls: if backup.acls.is_empty() {
+            vec![]
+        } else {
+            admin::describe_acls(&consumer)?
+        },
+    };
+
+    let steps = plan_restore(&backup, &current);
+    if steps.is_empty() {
+        output::info("The cluster already matches the backup");
+        return Ok(());
+    }
+    print_restore_plan(&steps);
+    let changes = steps
+        .iter()
+        .filter(|step| !matches!(step, RestoreStep::Skip { .. }))
+        .count();
+    if dry_run || changes == 0 {
+        return Ok(());
+    }
+    if steps
+        .iter()
+        .any(|step| matches!(step, RestoreStep::SetConfigs { .. }))
+    {
+        admin::check_feature(&consumer, admin::Feature::INCREMENTAL_ALTER_CONFIGS)?;
+    }
+    confirm_change(
+        format!("Apply these {} changes?", changes),
+        assume_yes,
+        "No changes were made",
+    )?;
+
+    let admin_client = admin::get_admin_client(bootstrap_servers)?;
+    let mut failures = vec![];
+    let new_topics: Vec<NewTopic> = steps
+        .iter()
+        .filter_map(|step| match step {
+            RestoreStep::CreateTopic(topic) => Some(topic.configs.iter().fold(
+                NewTopic::new(
+                    &topic.name,
+                    topic.partitions,
+                    TopicReplication::Fixed(topic.replication_factor),
+                ),
+                |new_topic, (name, value)| new_topic.set(name, value),
+            )),
+            _ => None,
+        })
+        .collect();
+    if !new_topics.is_empty() {
+        for result in admin::create_topics(&admin_client, &new_topics)? {
+            match result {
+                Ok(topic) => output::info(format!("Created topic {}", topic)),
+                Err((topic, code)) => failures.push(format!("create topic {}: {}", topic, code)),
+            }
+        }
+    }
+    let new_partitions: Vec<NewPartitions> = steps
+        .iter()
+        .filter_map(|step| match step {
+            RestoreStep::AddPartitions { topic, to, .. } => {
+                Some(NewPartitions::new(topic, *to as usize))
+            }
+            _ => None,
+        })
+        .collect();
+    if !new_partitions.is_empty() {
+        for result in admin::create_partitions(&admin_client, &new_partitions)? {
+            match result {
+                Ok(topic) => output::info(format!("Added partitions to {}", topic)),
+                Err((topic, code)) => {
+                    failures.push(format!("add partitions to {}: {}", topic, code))
+                }
+            }
+        }
+    }
+    for step in &steps {
+        if let RestoreStep::SetConfigs { topic, configs } = step {
+            match admin::set_topic_configs(&consumer, topic, configs) {
+                Ok(()) => output::info(format!("Set {} configs of {}", configs.len(), topic)),
+                Err(er) => failures.push(format!("set configs of {}: {}", topic, er)),
+            }
+        }
+    }
+    let acls: Vec<AclBinding> = steps
+        .iter()
+        .filter_map(|step| match step {
+            RestoreStep::CreateAcl(acl) => Some((*acl).clone()),
+            _ => None,
+        })
+        .collect();
+    if !acls.is_empty() {
+        let failed = admin::create_acls(&consumer, &acls)?;
+        output::info(format!("Created {} ACLs", acls.len() - failed.len()));
+        failures.extend(
+            failed
+                .into_iter()
+                .map(|(acl, reason)| format!("create acl {}: {}", acl, reason)),
+        );
+    }
+
+    report_failures(&failures, changes)
+}
+
+/// Prints each change in `failures` that could not be made, and fails if there is any.
+fn report_failures(failures: &[String], changes: usize) -> Result<(), KafkaError> {
+    for failure in failures {
+        eprintln!("Could not {}", failure);
+    }
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(KafkaError::Admin(format!(
+            "{} of {} changes failed",
+            failures.len(),
+            changes
+        )))
+    }
+}
+
+/// Brings the ACLs of the cluster in line with an ACL file: creates the missing ones and with
+/// `prune` deletes the extra ones of the principals in the file.
+pub fn apply_acls(
+    bootstrap_servers: &str,
+    file: &Path,
+    prune: bool,
+    dry_run: bool,
+    assume_yes: bool,
+) -> Result<(), KafkaError> {
+    let content = std::fs::read_to_string(file)
+        .map_err(|er| KafkaError::Io(format!("Failed to read ACL file: {:?}", file), er))?;
+    let acl_file: AclFile = serde_yaml::from_str(&content)
+        .map_err(|er| KafkaError::Generic(format!("Invalid ACL file {:?}: {}", file, er)))?;
+    let desired = acl_file.bindings();
+    let consumer = get_consumer(bootstrap_servers)?;
+    let current = admin::describe_acls(&consumer)?;
+
+    let (create, delete) = plan_acls(&desired, &current, prune);
+    if create.is_empty() && delete.is_empty() {
+        output::info(format!(
+            "The cluster already has the {} ACLs of {:?}",
+            desired.len(),
+            file
+        ));
+        return Ok(());
+    }
+    let mut table = Table::new();
+    table.add_row(row!["Change", "ACL"]);
+    for acl in &create {
+        table.add_row(row![Fg->"create", acl]);
+    }
+    for acl in &delete {
+        table.add_row(row![Fr->"delete", acl]);
+    }
+    output::print_table(&table);
+    let changes = create.len() + delete.len();
+    if dry_run {
+        return Ok(());
+    }
+    confirm_change(
+        format!("Apply these {} changes?", changes),
+        assume_yes,
+        "No changes were made",
+    )?;
+
+    let mut failures = vec![];
+    if !create.is_empty() {
+        let acls: Vec<AclBinding> = create.into_iter().cloned().collect();
+        let failed = admin::create_acls(&consumer, &acls)?;
+        output::info(format!("Created {} ACLs", acls.len() - failed.len()));
+        failures.extend(
+            failed
+                .into_iter()
+                .map(|(acl, reason)| format!("create acl {}: {}", acl, reason)),
+        );
+    }
+    if !delete.is_empty() {
+        let acls: Vec<AclBinding> = delete.into_iter().cloned().collect();
+        let failed = admin::delete_acls(&consumer, &acls)?;
+        output::info(format!("Deleted {} ACLs", acls.len() - failed.len()));
+        failures.extend(
+            failed
+                .into_iter()
+                .map(|(acl, reason)| format!("delete acl {}: {}", acl, reason)),
+        );
+    }
+
+    report_failures(&failures, changes)
+}
+
+/// Lists the ACLs that apply to `principal`, including `User:*` ones, and what they let it do
+/// on the cluster and on each topic and group that exists.
+pub fn show_principal_acls(bootstrap_servers: &str, principal: &str) -> Result<(), KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let all_acls = admin::describe_acls(&consumer)?;
+    let acls: Vec<&AclBinding> = all_acls
+        .iter()
+        .filter(|acl| applies_to(acl, principal))
+        .collect();
+    if acls.is_empty() {
+        output::info(format!("No ACLs apply to {}", principal));
+        return Ok(());
+    }
+
+    let mut table = Table::new();
+    table.add_row(row![
+        "Permission",
+        "Principal",
+        "Operation",
+        "Resource",
+        "Name",
+        "Pattern",
+        "Host"
+    ]);
+    for acl in &acls {
+        if acl.permission == AclPermission::Deny {
+            table.add_row(row![Fr->"deny", acl.principal, acl.operation, acl.resource_type, acl.resource_name, acl.pattern_type, acl.host]);
+        } else {
+            table.add_row(row![Fg->"allow", acl.principal, acl.operation, acl.resource_type, acl.resource_name, acl.pattern_type, acl.host]);
+        }
+    }
+    output::print_table(&table);
+
+    let metadata = consumer
+        .fetch_metadata(None, Duration::from_secs(10))
+        .map_err(|er| KafkaError::MetadataFetch("Error while fetching metadata".to_string(), er))?;
+    let groups = consumer
+        .fetch_group_list(None, Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::GroupListFetch("Error while fetching consumer groups".to_string(), er)
+        })?;
+    let mut topics: Vec<&str> = metadata
+        .topics()
+        .iter()
+        .map(|topic| topic.name())
+        .filter(|name| !name.starts_with("__"))
+        .collect();
+    topics.sort();
+    let mut group_names: Vec<&str> = groups.groups().iter().map(|group| group.name()).collect();
+    group_names.sort();
+    let resources = std::iter::once((AclResource::Cluster, "kafka-cluster"))
+        .chain(topics.into_iter().map(|name| (AclResource::Topic, name)))
+        .chain(
+            group_names
+                .into_iter()
+                .map(|name| (AclResource::Group, name)),
+        );
+
+    let mut table = Table::new();
+    table.add_row(row!["Resource", "Name", "Can"]);
+    for (resource_type, name) in resources {
+        let operations = effective_operations(&acls, resource_type, name);
+        if !operations.is_empty() {
+            let operations: Vec<String> = operations.iter().map(|o| o.to_string()).collect();
+            table.add_row(row![resource_type, name, operations.join(", ")]);
+        }
+    }
+    if table.len() == 1 {
+        output::info(format!(
+            "The ACLs of {} cover no existing topic or group",
+            principal
+        ));
+    } else {
+        output::print_table(&table);
+    }
+    Ok(())
+}
+
+/// Keeps the longest throttle time any broker reported in the statistics of a client.
+#[derive(Default)]
+struct ThrottleMonitor {
+    max_throttle_ms: AtomicI64,
+}
+
+impl ClientContext for ThrottleMonitor {
+    const ENABLE_REFRESH_OAUTH_TOKEN: bool = ClientAuth::ENABLE_REFRESH_OAUTH_TOKEN;
+
+    fn generate_oauth_token(
+        &self,
+        config: Option<&str>,
+    ) -> Result<OAuthToken, Box<dyn std::error::Error>> {
+        ClientAuth.generate_oauth_token(config)
+    }
+
+    fn stats(&self, statistics: Statistics) {
+        let throttle = statistics
+            .brokers
+            .values()
+            .filter_map(|broker| broker.throttle.as_ref())
+            .map(|window| window.max)
+            .max()
+            .unwrap_or(0);
+        self.max_throttle_ms.fetch_max(throttle, Ordering::Relaxed);
+    }
+}
+
+impl ConsumerContext for ThrottleMonitor {}
+
+/// Fetches from the end of `topic` as `client_id` for `duration` and returns the longest
+/// throttle the brokers applied. Quotas are tracked per client id across all its connections,
+/// so a client id already over its quota gets even these empty fetches throttled.
+fn probe_throttle(
+    bootstrap_servers: &str,
+    client_id: &str,
+    topic: &str,
+    partitions: &[i32],
+    duration: Duration,
+) -> Result<i64, KafkaError> {
+    let consumer: BaseConsumer<ThrottleMonitor> = client::config(bootstrap_servers)
+        .set("client.id", client_id)
+        .set("group.id", client::group_id())
+        .set("enable.auto.commit", "false")
+        .set("statistics.interval.ms", "1000")
+        .create_with_context(ThrottleMonitor::default())
+        .map_err(client::creation_error)?;
+    client::authenticate(consumer.client())?;
+    let mut assignment = TopicPartitionList::new();
+    for &partition in partitions {
+        assignment
+            .add_partition_offset(topic, partition, Offset::End)
+            .map_err(|er| {
+                KafkaError::OffsetFetch("Error while preparing offsets".to_string(), er)
+            })?;
+    }
+    consumer
+        .assign(&assignment)
+        .map_err(|er| KafkaError::Generic(format!("Error while assigning partitions: {:?}", er)))?;
+    let started = Instant::now();
+    while started.elapsed() < duration {
+        if let Some(Err(er)) = consumer.poll(Duration::from_millis(100)) {
+            return Err(KafkaError::Generic(format!(
+                "Error while polling: {:?}",
+                er
+            )));
+        }
+    }
+    Ok(consumer.context().max_throttle_ms.load(Ordering::Relaxed))
+}
+
+/// Shows a quota value, byte rates as a size per second.
+fn format_quota_value(name: &str, value: f64) -> String {
+    if name.ends_with("_byte_rate") {
+        format!("{}/s", output::bytes(value as u64))
+    } else {
+        value.to_string()
+    }
+}
+
+/// The partitions of `topic`, or of the first topic of the cluster when none is given, to fetch
+/// from while probing throttling.
+fn quota_probe_topic(
+    consumer: &BaseConsumer<ClientAuth>,
+    topic: Option<&str>,
+) -> Result<(String, Vec<i32>), KafkaError> {
+    let metadata = consumer
+        .fetch_metadata(topic, Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::MetadataFetch("Error while fetching topic metadata".to_string(), er)
+        })?;
+    metadata
+        .topics()
+        .iter()
+        .filter(|t| !t.name().starts_with("__") && !t.partitions().is_empty())
+        .min_by_key(|t| t.name())
+        .map(|t| {
+            (
+                t.name().to_string(),
+                t.partitions().iter().map(|p| p.id()).collect(),
+            )
+        })
+        .ok_or_else(|| match topic {
+            Some(topic) => KafkaError::TopicNotExists(format!("Topic {} does not exist", topic)),
+            None => KafkaError::Generic(
+                "The cluster has no topic to fetch from, give one with --topic".to_string(),
+            ),
+        })
+}
+
+/// Lists the configured client quotas and, with `impersonate`, whether the brokers throttle
+/// each quota'd client id right now. Quotas on users or IPs cannot be probed, since the probe
+/// connects with kfcli's own credentials.
+pub fn get_quota_usage(
+    bootstrap_servers: &str,
+    impersonate: bool,
+    topic: Option<&str>,
+    duration: Duration,
+) -> Result<(), KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    admin::check_feature(&consumer, admin::Feature::CLIENT_QUOTAS)?;
+    let brokers = admin::describe_cluster_nodes(&consumer)?;
+    let broker = brokers
+        .first()
+        .ok_or_else(|| KafkaError::Admin("The cluster has no brokers".to_string()))?;
+    let quotas = admin::describe_client_quotas(broker)?;
+    if quotas.is_empty() {
+        output::info("No client quotas are configured");
+        return Ok(());
+    }
+
+    let probe_topic = if impersonate {
+        let probe_topic = quota_probe_topic(&consumer, topic)?;
+        output::status(format!(
+            "Warning: fetching from {} as each quota'd client id, which counts against the quotas \
+             of the clients using it",
+            probe_topic.0
+        ));
+        Some(probe_topic)
+    } else {
+        None
+    };
+
+    let probed = match probe_topic {
+        Some(_) => quotas.iter().filter(|q| q.client_id().is_some()).count(),
+        None => 0,
+    };
+    let progress = output::Progress::new("Probing client ids", Some(probed as u64));
+    let mut table = Table::new();
+    table.add_row(row!["Entity", "Quotas", "Max Throttle", "Status"]);
+    let mut throttled = 0;
+    for quota in &quotas {
+        let values: Vec<String> = quota
+            .values
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, format_quota_value(name, *value)))
+            .collect();
+        let throttle = match (quota.client_id(), &probe_topic) {
+            (Some(client_id), Some((topic, partitions))) => {
+                let throttle =
+                    probe_throttle(bootstrap_servers, client_id, topic, partitions, duration)?;
+                progress.inc(1);
+                Some(throttle)
+            }
+            _ => None,
+        };
+        match throttle {
+            Some(ms) if ms > 0 => {
+                throttled += 1;
+                table
+                    .add_row(row![quota, values.join(", "), format!("{} ms", ms), Fr->"throttled"]);
+            }
+            Some(_) => {
+                table.add_row(row![quota, values.join(", "), "0 ms", Fg->"ok"]);
+            }
+            None => {
+                table.add_row(row![quota, values.join(", "), "-", "not probed"]);
+            }
+        }
+    }
+    progress.finish();
+    output::print_table(&table);
+    if probe_topic.is_some() {
+        output::info(format!("{} client ids are being throttled", throttled));
+    } else {
+        output::info("Throttling was not probed, pass --impersonate to probe each client id");
+    }
+    Ok(())
+}
+
+/// Compares the topics of the cluster with a desired-state file and fails when they differ, so
+/// that a scheduled job notices drift.
+pub fn plan_topics(bootstrap_servers: &str, file: &Path) -> Result<(), KafkaError> {
+    let content = std::fs::read_to_string(file).map_err(|er| {
+        KafkaError::Io(format!("Failed to read desired-state file: {:?}", file), er)
+    })?;
+    let desired: DesiredState = serde_yaml::from_str(&content).map_err(|er| {
+        KafkaError::Generic(format!("Invalid desired-state file {:?}: {}", file, er))
+    })?;
+    let consumer = get_consumer(bootstrap_servers)?;
+    let actual = read_topic_backups(bootstrap_servers, &consumer)?;
+    let drift = detect_drift(&desired, &actual);
+    if drift.is_empty() {
+        output::info(format!(
+            "The {} topics of {:?} match the cluster",
+            desired.topics.len(),
+            file
+        ));
+        return Ok(());
+    }
+
+    let config = |name: &str, value: &Option<String>, unset: &str| match value {
+        Some(value) => output::config_value(name, value),
+        None => unset.to_string(),
+    };
+    let mut table = Table::new();
+    table.add_row(row!["Topic", "Drift", "Desired", "Actual"]);
+    for difference in &drift {
+        let (desired, actual) = match difference {
+            Drift::MissingTopic(_) => ("exists".to_string(), "missing".to_string()),
+            Drift::Partitions {
+                desired, actual, ..
+            }
+            | Drift::ReplicationFactor {
+                desired, actual, ..
+            } => (desired.to_string(), actual.to_string()),
+            Drift::Config {
+                name,
+                desired,
+                actual,
+                ..
+            } => (
+                config(name, desired, "(not set)"),
+                config(name, actual, "(default)"),
+            ),
+        };
+        table.add_row(row![difference.topic(), difference, desired, Fr->actual]);
+    }
+    output::print_table(&table);
+    Err(KafkaError::Generic(format!(
+        "Found {} differences from {:?}",
+        drift.len(),
+        file
+    )))
+}
+
+pub fn get_broker_loggers(
+    bootstrap_servers: &str,
+    broker_id: i32,
+    levels: &[(String, String)],
+) -> Result<(), KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    if !levels.is_empty() {
+        admin::check_feature(&consumer, admin::Feature::INCREMENTAL_ALTER_CONFIGS)?;
+        admin::set_broker_loggers(&consumer, broker_id, levels)?;
+        for (logger, level) in levels {
+            output::info(format!(
+                "Set {} to {} on broker {}",
+                logger, level, broker_id
+            ));
+        }
+    }
+
+    let mut loggers = admin::describe_broker_loggers(&consumer, broker_id)?;
+    loggers.sort();
+    let mut table = Table::new();
+    table.add_row(row!["Logger", "Level"]);
+    for (logger, level) in loggers {
+        if levels.iter().any(|(changed, _)| *changed == logger) {
+            table.add_row(row![Fg->logger, Fg->level]);
+        } else {
+            table.add_row(row![logger, level]);
+        }
+    }
+    output::print_table(&table);
+    Ok(())
+}
+
+/// A topic as the HTTP API lists it.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TopicSummary {
+    name: String,
+    partitions: usize,
+    replication_factor: usize,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct PartitionSummary {
+    partition: i32,
+    leader: i32,
+    replicas: Vec<i32>,
+    isr: Vec<i32>,
+    low: i64,
+    high: i64,
+}
+
+/// Partitions of a topic with their replicas and watermarks, as the HTTP API returns them.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TopicPartitions {
+    name: String,
+    partitions: Vec<PartitionSummary>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct GroupPartitionLag {
+    group: String,
+    lag: i64,
+    partitions: Vec<PartitionLag>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ClusterHealth {
+    brokers: usize,
+    topics: usize,
+    /// Partitions with fewer in-sync replicas than replicas
+    under_replicated: usize,
+    /// Partitions without a leader
+    offline: usize,
+}
+
+fn fetch_all_metadata(consumer: &BaseConsumer<ClientAuth>) -> Result<Metadata, KafkaError> {
+    consumer
+        .fetch_metadata(None, Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::MetadataFetch("Error while fetching topic metadata".to_string(), er)
+        })
+}
+
+pub fn list_topic_summaries(bootstrap_servers: &str) -> Result<Vec<TopicSummary>, KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let metadata = fetch_all_metadata(&consumer)?;
+    let mut topics: Vec<TopicSummary> = metadata
+        .topics()
+        .iter()
+        .map(|t| TopicSummary {
+            name: t.name().to_string(),
+            partitions: t.partitions().len(),
+            replication_factor: t.partitions().first().map_or(0, |p| p.replicas().len()),
+        })
+        .collect();
+    topics.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(topics)
+}
+
+pub fn get_topic_partitions(
+    bootstrap_servers: &str,
+    topic: &str,
+) -> Result<TopicPartitions, KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let watermarks: HashMap<i32, (i64, i64)> = topic_watermarks(&consumer, topic)?
+        .into_iter()
+        .map(|(partition, low, high)| (partition, (low, high)))
+        .collect();
+    let metadata = consumer
+        .fetch_metadata(Some(topic), Duration::from_secs(10))
+        .map_err(|er| {
+            KafkaError::MetadataFetch("Error while fetching topic metadata".to_string(), er)
+        })?;
+    let partitions = metadata.topics()[0]
+        .partitions()
+        .iter()
+        .map(|p| {
+            let (low, high) = watermarks.get(&p.id()).copied().unwrap_or((-1, -1));
+            PartitionSummary {
+                partition: p.id(),
+                leader: p.leader(),
+                replicas: p.replicas().to_vec(),
+                isr: p.isr().to_vec(),
+                low,
+                high,
+            }
+        })
+        .collect();
+    Ok(TopicPartitions {
+        name: topic.to_string(),
+        partitions,
+    })
+}
+
+pub fn get_group_partition_lag(
+    bootstrap_servers: &str,
+    group: &str,
+) -> Result<GroupPartitionLag, KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let tpl = all_topic_partitions(&fetch_all_metadata(&consumer)?);
+    let end_offsets = fetch_end_offsets(&consumer, &tpl)?;
+    let partitions = fetch_group_lag(bootstrap_servers, group, &tpl, &end_offsets)?;
+    Ok(GroupPartitionLag {
+        group: group.to_string(),
+        lag: partitions.iter().map(|p| p.lag).sum(),
+        partitions,
+    })
+}
+
+pub fn get_cluster_health(bootstrap_servers: &str) -> Result<ClusterHealth, KafkaError> {
+    let consumer = get_consumer(bootstrap_servers)?;
+    let metadata = fetch_all_metadata(&consumer)?;
+    let partitions = metadata.topics().iter().flat_map(|t| t.partitions());
+    Ok(ClusterHealth {
+        brokers: metadata.brokers().len(),
+        topics: metadata.topics().len(),
+        under_replicated: partitions
+            .clone()
+            .filter(|p| p.isr().len() < p.replicas().len())
+            .count(),
+        offline: partitions.filter(|p| p.leader() < 0).count(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use rdkafka::metadata::MetadataTopic;
+
+    use std::collections::{BTreeMap, HashMap};
+
+    use crate::kafka::{get_consumer, get_topic_detail_inner, KafkaError};
+
+    #[test]
+    fn test_get_topics_inner() {
+        let bootstrap_servers = "localhost:9092";
+        let metadata = super::get_topics_inner(bootstrap_servers, None);
+        assert!(metadata.is_ok());
+        let metadata = metadata.unwrap();
+        let topics = metadata
+            .topics()
+            .iter()
+            .filter(|topic| topic.name() != "__consumer_offsets")
+            .collect::<Vec<&MetadataTopic>>();
+
+        assert_eq!(topics.len(), 3);
+        topics
+            .iter()
+            .filter(|topic| topic.name() == "topic-one")
+            .for_each(|topic| {
+                assert_eq!(topic.partitions().len(), 3);
+            });
+    }
+
+    #[test]
+    fn test_get_topic_not_exists_detail_inner() {
+        let bootstrap_servers = "localhost:9092";
+        let topic = "topic-not-exists";
+        let consumer = get_consumer(bootstrap_servers).unwrap();
+        let result = get_topic_detail_inner(&consumer, topic, &HashMap::new());
+        assert!(result.is_err());
+        if let KafkaError::TopicNotExists(err) = result.unwrap_err() {
+            assert_eq!(err, "Topic topic-not-exists does not exist");
+        } else {
+            panic!("Error should be TopicNotExists");
+        }
+    }
+
+    #[test]
+    fn test_get_topic_detail_inner() {
+        let bootstrap_servers = "localhost:9092";
+        let topic = "topic-one";
+        let consumer = get_consumer(bootstrap_servers).unwrap();
+        let (overall_header, overall_detail, partition_detail_header, partition_detail) =
+            get_topic_detail_inner(&consumer, topic, &HashMap::new()).unwrap();
+        assert_eq!(
+            overall_header,
+            ["Partitions", "Partition IDs", "Total Messages"]
+        );
+        assert_eq!(overall_detail, ["3", "0, 1, 2, ", "0"]);
+        assert_eq!(
+            partition_detail_header,
+            [
+                "Partition ID",
+                "Leader",
+                "Offset",
+                "Replicas",
+                "ISR",
+                "Health",
+                "Rack Spread"
+            ]
+        );
+        assert_eq!(
+            partition_detail,
+            [
+                ["0", "1", "0", "1", "1", "in-sync", "-"],
+                ["1", "1", "0", "1", "1", "in-sync", "-"],
+                ["2", "1", "0", "1", "1", "in-sync", "-"]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_error_deserialize_assignment() {
+        let data = vec![
+            0, 1, 0, 0, 0, 1, 0, 9, 116, 111, 45, 111, 110, 101, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0,
+            1, 0, 0, 0, 2,
+        ];
+        let result = super::deserialize_assignment(&data);
+        assert!(result.is_err());
+        if let KafkaError::Deserialize(err, _) = result.unwrap_err() {
+            assert_eq!(err, "Error while reading partition:");
+        } else {
+            panic!("Error should be Deserialize");
+        }
+    }
+
+    #[test]
+    fn test_deserialize_assignment() {
+        let data = vec![
+            0, 1, 0, 0, 0, 1, 0, 9, 116, 111, 112, 105, 99, 45, 111, 110, 101, 0, 0, 0, 3, 0, 0, 0,
+            0, 0, 0, 0, 1, 0, 0, 0, 2, 255, 255, 255, 255,
+        ];
+        let result = super::deserialize_assignment(&data);
+        assert!(result.is_ok());
+        let result = result.unwrap();
+        assert_eq!(result.len(), 1);
+        assert!(result.contains_key("topic-one"));
+        let partitions = result.get("topic-one").unwrap();
+        assert_eq!(partitions.len(), 3);
+        assert_eq!(partitions[0], 0);
+        assert_eq!(partitions[1], 1);
+        assert_eq!(partitions[2], 2);
+    }
+
+    fn member(client_id: &str, assignment: &[(&str, &[i32])]) -> super::GroupMember {
+        super::GroupMember {
+            client_id: client_id.to_string(),
+            host: "/127.0.0.1".to_string(),
+            assignment: assignment
+                .iter()
+                .map(|(topic, partitions)| (topic.to_string(), partitions.to_vec()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_diff_group_members() {
+        use super::MemberEvent;
+        use std::collections::BTreeMap;
+
+        let mut old = BTreeMap::new();
+        old.insert(
+            "m-1".to_string(),
+            member("c-1", &[("topic-one", &[0, 1, 2])]),
+        );
+        old.insert("m-2".to_string(), member("c-2", &[]));
+
+        let mut new = BTreeMap::new();
+        new.insert("m-1".to_string(), member("c-1", &[("topic-one", &[0, 1])]));
+        new.insert("m-3".to_string(), member("c-3", &[("topic-one", &[2])]));
+
+        let events = super::diff_group_members("Stable", "PreparingRebalance", &old, &new);
+        assert_eq!(events.len(), 4);
+        assert_eq!(
+            events[0],
+            MemberEvent::StateChanged("Stable".to_string(), "PreparingRebalance".to_string())
+        );
+        assert!(matches!(&events[1], MemberEvent::AssignmentChanged(id, _, _) if id == "m-1"));
+        assert!(matches!(&events[2], MemberEvent::Joined(id, _) if id == "m-3"));
+        assert!(matches!(&events[3], MemberEvent::Left(id, _) if id == "m-2"));
+
+        assert!(super::diff_group_members("Stable", "Stable", &new, &new).is_empty());
+    }
+
+    #[test]
+    fn test_diff_topic_meta() {
+        use super::{MetaEvent, PartitionState, TopicMeta};
+
+        let partition = |leader: i32, isr: &[i32]| PartitionState {
+            leader,
+            isr: isr.to_vec(),
+        };
+        let old = TopicMeta {
+            partitions: [(0, partition(1, &[1, 2, 3])), (1, partition(2, &[1, 2, 3]))].into(),
+            configs: [("retention.ms".to_string(), "86400000".to_string())].into(),
+        };
+        let new = TopicMeta {
+            partitions: [
+                (0, partition(2, &[2, 3])),
+                (1, partition(2, &[1, 2, 3])),
+                (2, partition(3, &[3, 1, 2])),
+            ]
+            .into(),
+            configs: [("cleanup.policy".to_string(), "compact".to_string())].into(),
+        };
+
+        let events = super::diff_topic_meta(&old, &new);
+        assert_eq!(
+            events,
+            vec![
+                MetaEvent::PartitionCount(2, 3),
+                MetaEvent::LeaderChanged(0, 1, 2),
+                MetaEvent::IsrChanged(0, vec![1, 2, 3], vec![2, 3]),
+                MetaEvent::ConfigChanged(
+                    "cleanup.policy".to_string(),
+                    None,
+                    Some("compact".to_string())
+                ),
+                MetaEvent::ConfigChanged(
+                    "retention.ms".to_string(),
+                    Some("86400000".to_string()),
+                    None
+                ),
+            ]
+        );
+        assert_eq!(
+            super::format_meta_event(&events[2]),
+            "partition 0 ISR shrank: [1, 2, 3] -> [2, 3]"
+        );
+        assert_eq!(
+            super::format_meta_event(&events[4]),
+            "config retention.ms changed: 86400000 -> (default)"
+        );
+        assert!(super::diff_topic_meta(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn test_format_assignment() {
+        let m = member("c-1", &[("topic-one", &[0, 1]), ("topic-two", &[0])]);
+        assert_eq!(
+            super::format_assignment(&m.assignment),
+            "topic-one[0, 1] topic-two[0]"
+        );
+        assert_eq!(
+            super::format_assignment(&member("c-1", &[]).assignment),
+            "-"
+        );
+    }
+
+    #[test]
+    fn test_compute_partition_lag() {
+        use rdkafka::{Offset, TopicPartitionList};
+        use std::collections::HashMap;
+
+        let mut committed = TopicPartitionList::new();
+        committed
+            .add_partition_offset("topic-one", 0, Offset::Offset(10))
+            .unwrap();
+        committed
+            .add_partition_offset("topic-one", 1, Offset::Invalid)
+            .unwrap();
+        committed
+            .add_partition_offset("topic-two", 0, Offset::Offset(5))
+            .unwrap();
+
+        let mut end_offsets = HashMap::new();
+        end_offsets.insert(("topic-one".to_string(), 0), 25);
+        end_offsets.insert(("topic-one".to_string(), 1), 7);
+        end_offsets.insert(("topic-two".to_string(), 0), 5);
+
+        let lags = super::compute_partition_lag(&committed, &end_offsets);
+        assert_eq!(lags.len(), 2);
+        assert_eq!(lags[0].lag, 15);
+        assert_eq!(lags[1].lag, 0);
+    }
+
+    #[test]
+    fn test_committed_topics() {
+        use rdkafka::{Offset, TopicPartitionList};
+
+        let mut committed = TopicPartitionList::new();
+        committed
+            .add_partition_offset("orders", 0, Offset::Invalid)
+            .unwrap();
+        committed
+            .add_partition_offset("orders", 1, Offset::Offset(3))
+            .unwrap();
+        committed
+            .add_partition_offset("payments", 0, Offset::Invalid)
+            .unwrap();
+
+        let topics = super::committed_topics(&committed);
+        assert_eq!(topics.into_iter().collect::<Vec<_>>(), vec!["orders"]);
+    }
+
+    #[test]
+    fn test_orphan_reason() {
+        use super::{orphan_reason, OrphanReason};
+
+        let day = 86_400_000;
+        let now = 100 * day;
+        assert_eq!(
+            orphan_reason(0, 0, false, None, now, 30 * day),
+            Some(OrphanReason::NoOffsets)
+        );
+        assert_eq!(
+            orphan_reason(2, 0, false, None, now, 30 * day),
+            Some(OrphanReason::TopicsDeleted)
+        );
+        assert_eq!(
+            orphan_reason(2, 1, true, None, now, 30 * day),
+            Some(OrphanReason::Expired)
+        );
+        assert_eq!(
+            orphan_reason(2, 1, false, Some(now - 45 * day), now, 30 * day),
+            Some(OrphanReason::Idle)
+        );
+        assert_eq!(
+            orphan_reason(2, 1, false, Some(now - day), now, 30 * day),
+            None
+        );
+        // The record before the committed offset is a transaction marker or was compacted
+        assert_eq!(orphan_reason(2, 1, false, None, now, 30 * day), None);
+    }
+
+    #[test]
+    fn test_time_behind() {
+        let now = 1_714_557_600_000;
+        assert_eq!(super::time_behind(0, None, now), Some(0));
+        assert_eq!(
+            super::time_behind(3_000_000, Some(now - 2_832_000), now),
+            Some(2_832_000)
+        );
+        assert_eq!(super::time_behind(5, Some(now + 10), now), Some(0));
+        assert_eq!(super::time_behind(5, None, now), None);
+    }
+
+    #[test]
+    fn test_translate_offsets() {
+        let key = |partition: i32| ("orders".to_string(), partition);
+        let committed = || (0..4).map(|p| ("orders".to_string(), p, 100)).collect();
+        // Partition 2 is caught up, partition 3 does not exist on the target
+        let source_ends =
+            HashMap::from([(key(0), 200), (key(1), 200), (key(2), 100), (key(3), 200)]);
+        let timestamps = HashMap::from([(key(0), 1_000), (key(1), 2_000)]);
+        let target_offsets = HashMap::from([(key(0), 97)]);
+        let target_ends = HashMap::from([(key(0), 150), (key(1), 120), (key(2), 130)]);
+
+        let translations = super::translate_offsets(
+            committed(),
+            &source_ends,
+            &timestamps,
+            &target_offsets,
+            &target_ends,
+        )
+        .unwrap();
+        let targets: Vec<Option<i64>> = translations.iter().map(|t| t.target_offset).collect();
+        assert_eq!(targets, vec![Some(97), Some(120), Some(130), None]);
+        assert_eq!(translations[0].csv_line(), "orders,0,100,1000,97");
+        assert_eq!(translations[3].csv_line(), "orders,3,100,,");
+
+        // Partition 1 has unread records, but the next one could not be read
+        let timestamps = HashMap::from([(key(0), 1_000)]);
+        assert!(super::translate_offsets(
+            committed(),
+            &source_ends,
+            &timestamps,
+            &target_offsets,
+            &target_ends,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_lag_row_csv_line() {
+        let row = super::LagRow {
+            time: 1714557600000,
+            group: "billing,eu".to_string(),
+            topic: "orders".to_string(),
+            partition: 3,
+            committed: 10,
+            end: 25,
+            lag: 15,
+            time_behind_ms: Some(60_000),
+        };
+        assert_eq!(
+            row.csv_line(),
+            "1714557600000,\"billing,eu\",orders,3,10,25,15"
+        );
+    }
+
+    #[test]
+    fn test_rank_group_lags() {
+        let lag = |group: &str, lag: i64| super::GroupLag {
+            group: group.to_string(),
+            state: "Stable".to_string(),
+            topics: std::collections::BTreeSet::from(["topic-one".to_string()]),
+            lag,
+        };
+        let ranked = super::rank_group_lags(vec![lag("a", 5), lag("b", 50), lag("c", 20)], 2);
+        assert_eq!(ranked, vec![lag("b", 50), lag("c", 20)]);
+    }
+
+    #[test]
+    fn test_summarize_topic_sizes() {
+        let replica =
+            |broker: i32, topic: &str, partition: i32, size: i64| crate::admin::ReplicaSize {
+                broker,
+                log_dir: "/var/lib/kafka".to_string(),
+                topic: topic.to_string(),
+                partition,
+                size,
+            };
+        let sizes = super::summarize_topic_sizes(&[
+            replica(1, "small", 0, 10),
+            replica(1, "big", 0, 100),
+            replica(2, "big", 0, 90),
+            replica(2, "big", 1, 40),
+        ]);
+        assert_eq!(sizes.len(), 2);
+        assert_eq!(sizes[0].topic, "big");
+        assert_eq!((sizes[0].size, sizes[0].on_disk), (140, 230));
+        assert_eq!(
+            sizes[0].partitions[0],
+            super::PartitionSize {
+                partition: 0,
+                replicas: 2,
+                size: 100,
+                on_disk: 190,
+            }
+        );
+        assert_eq!((sizes[1].size, sizes[1].on_disk), (10, 10));
+    }
+
+    #[test]
+    fn test_plan_replication_change() {
+        use std::collections::HashMap;
+
+        let broker = |id: i32, rack: &str| crate::admin::ClusterNode {
+            id,
+            host: "localhost".to_string(),
+            port: 9092,
+            rack: Some(rack.to_string()),
+        };
+        let brokers = [
+            broker(1, "a"),
+            broker(2, "a"),
+            broker(3, "b"),
+            broker(4, "b"),
+        ];
+        let load = HashMap::from([(1, 5), (2, 1), (3, 4), (4, 2)]);
+        let partitions = [(0, vec![1]), (1, vec![2]), (2, vec![3, 4])];
+
+        // Broker 4 is on another rack and holds fewer replicas than 3
+        assert_eq!(
+            super::plan_replication_change(&partitions, &brokers, load.clone(), 2).unwrap(),
+            vec![(0, vec![1, 4]), (1, vec![2, 4])]
+        );
+        assert_eq!(
+            super::plan_replication_change(&partitions, &brokers, load.clone(), 1).unwrap(),
+            vec![(2, vec![3])]
+        );
+        assert!(super::plan_replication_change(&partitions, &brokers, load.clone(), 5).is_err());
+        assert!(super::plan_replication_change(&partitions, &brokers, load, 0).is_err());
+    }
+
+    #[test]
+    fn test_handle_topic_result() {
+        use rdkafka::types::RDKafkaErrorCode;
+
+        let exists = || Err(("orders".to_string(), RDKafkaErrorCode::TopicAlreadyExists));
+        assert_eq!(
+            super::handle_topic_result(Ok("orders".to_string()), "create", None).unwrap(),
+            Some("orders".to_string())
+        );
+        assert_eq!(
+            super::handle_topic_result(
+                exists(),
+                "create",
+                Some(RDKafkaErrorCode::TopicAlreadyExists)
+            )
+            .unwrap(),
+            None
+        );
+        assert!(super::handle_topic_result(exists(), "create", None).is_err());
+        assert!(super::handle_topic_result(
+            exists(),
+            "create",
+            Some(RDKafkaErrorCode::UnknownTopicOrPartition)
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_matching_topics() {
+        let names = [
+            "loadtest-2",
+            "orders",
+            "loadtest-1",
+            "old-loadtest-1",
+            "__loadtest-internal",
+        ];
+        assert_eq!(
+            super::matching_topics(names, "loadtest-.*").unwrap(),
+            vec!["loadtest-1", "loadtest-2"]
+        );
+        assert_eq!(
+            super::matching_topics(names, "orders|loadtest-1").unwrap(),
+            vec!["loadtest-1", "orders"]
+        );
+        assert!(super::matching_topics(names, "loadtest-(").is_err());
+    }
+
+    #[test]
+    fn test_count_broker_partitions() {
+        let partitions: [(i32, &[i32], &[i32]); 3] = [
+            (1, &[1, 2], &[1, 2]),
+            (2, &[2, 1], &[2]),
+            (2, &[2, 3], &[2, 3]),
+        ];
+        assert_eq!(
+            super::count_broker_partitions(1, &partitions),
+            super::BrokerPartitions {
+                leaders: 1,
+                replicas: 2,
+                out_of_sync: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_summarize_log_dirs() {
+        let replica = |log_dir: &str, size: i64| crate::admin::ReplicaSize {
+            broker: 1,
+            log_dir: log_dir.to_string(),
+            topic: "orders".to_string(),
+            partition: 0,
+            size,
+        };
+        assert_eq!(
+            super::summarize_log_dirs(&[replica("/b", 5), replica("/a", 10), replica("/b", 7)]),
+            vec![("/a".to_string(), 1, 10), ("/b".to_string(), 2, 12)]
+        );
+    }
+
+    #[test]
+    fn test_idle_reason() {
+        let cutoff = 1_714_557_600_000;
+        assert_eq!(super::idle_reason(0, None, cutoff), Some("empty"));
+        assert_eq!(
+            super::idle_reason(12, Some(cutoff - 1), cutoff),
+            Some("idle")
+        );
+        assert_eq!(super::idle_reason(12, Some(cutoff + 1), cutoff), None);
+        assert_eq!(super::idle_reason(12, None, cutoff), None);
+    }
+
+    #[test]
+    fn test_retention_flag() {
+        assert_eq!(
+            super::retention_flag(-1, -1, false, false),
+            Some("retention is unlimited")
+        );
+        assert_eq!(
+            super::retention_flag(604800000, -1, false, true),
+            Some("retention never reached")
+        );
+        assert_eq!(super::retention_flag(604800000, -1, false, false), None);
+        assert_eq!(super::retention_flag(-1, -1, true, true), None);
+    }
+
+    #[test]
+    fn test_retention_used() {
+        assert_eq!(super::retention_used(50, 200), "25%");
+        assert_eq!(super::retention_used(300, 200), "150%");
+        assert_eq!(super::retention_used(50, -1), "-");
+    }
+
+    #[test]
+    fn test_most_lagging_partition() {
+        let lags = vec![
+            partition_lag(0, 10, 25),
+            partition_lag(1, 0, 40),
+            partition_lag(2, 5, 45),
+        ];
+        assert_eq!(super::most_lagging_partition(&lags).unwrap().partition, 1);
+        assert!(super::most_lagging_partition(&[]).is_none());
+    }
+
+    #[test]
+    fn test_topic_consumer_status() {
+        let lags = vec![partition_lag(0, 10, 25)];
+        assert_eq!(super::topic_consumer_status(true, &[]), Some("Active"));
+        assert_eq!(super::topic_consumer_status(true, &lags), Some("Active"));
+        assert_eq!(super::topic_consumer_status(false, &lags), Some("Inactive"));
+        assert_eq!(super::topic_consumer_status(false, &[]), None);
+    }
+
+    fn partition_lag(partition: i32, committed: i64, end: i64) -> super::PartitionLag {
+        super::PartitionLag {
+            topic: "topic-one".to_string(),
+            partition,
+            committed,
+            end,
+            lag: end - committed,
+        }
+    }
+
+    #[test]
+    fn test_compute_lag_trends() {
+        use std::time::Duration;
+
+        let first = vec![
+            partition_lag(0, 0, 100),
+            partition_lag(1, 50, 60),
+            partition_lag(2, 10, 10),
+        ];
+        let last = vec![
+            partition_lag(0, 60, 110),
+            partition_lag(1, 50, 80),
+            partition_lag(2, 10, 10),
+        ];
+        let trends = super::compute_lag_trends(&first, &last, Duration::from_secs(10));
+        assert_eq!(trends.len(), 3);
+
+        assert_eq!(trends[0].lag, 50);
+        assert_eq!(trends[0].delta, -50);
+        assert_eq!(trends[0].rate, 6.0);
+        assert_eq!(trends[0].eta, Some(Duration::from_secs(10)));
+
+        assert_eq!(trends[1].delta, 20);
+        assert_eq!(trends[1].eta, None);
+
+        assert_eq!(trends[2].eta, Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_format_eta() {
+        use std::time::Duration;
+
+        assert_eq!(super::format_eta(None), "never");
+        assert_eq!(super::format_eta(Some(Duration::from_secs(42))), "42s");
+        assert_eq!(super::format_eta(Some(Duration::from_secs(125))), "2m 5s");
+        assert_eq!(super::format_eta(Some(Duration::from_secs(7380))), "2h 3m");
+    }
+
+    #[test]
+    fn test_compute_skew() {
+        let skew = super::compute_skew(&[(0, 100), (1, 100), (2, 400)]);
+        assert_eq!(skew.len(), 3);
+        assert_eq!(skew[0].share, 100.0 * 100.0 / 600.0);
+        assert_eq!(skew[0].deviation, -50.0);
+        assert_eq!(skew[2].deviation, 100.0);
+
+        let empty = super::compute_skew(&[(0, 0), (1, 0)]);
+        assert_eq!(empty[0].share, 0.0);
+        assert_eq!(empty[0].deviation, 0.0);
+    }
+
+    #[test]
+    fn test_summarize_keys() {
+        let key = |k: &str| Some(k.to_string());
+        let records = vec![
+            (key("a"), 0),
+            (key("b"), 1),
+            (key("a"), 0),
+            (None, 2),
+            (key("a"), 1),
+        ];
+        let stats = super::summarize_keys(&records);
+        assert_eq!(stats.len(), 3);
+        assert_eq!(stats[0].key, key("a"));
+        assert_eq!(stats[0].records, 3);
+        assert_eq!(
+            stats[0].partitions.iter().copied().collect::<Vec<i32>>(),
+            [0, 1]
+        );
+        assert_eq!(stats[1].key, None);
+        assert_eq!(stats[2].key, key("b"));
+    }
+
+    #[test]
+    fn test_rank_large_records() {
+        let record = |partition: i32, offset: i64, size: usize| super::LargeRecord {
+            partition,
+            offset,
+            timestamp: None,
+            size,
+        };
+        let ranked = super::rank_large_records(
+            vec![
+                record(0, 5, 950_000),
+                record(1, 2, 1_200_000),
+                record(0, 9, 950_000),
+                record(2, 7, 910_000),
+            ],
+            3,
+        );
+        assert_eq!(
+            ranked,
+            vec![
+                record(1, 2, 1_200_000),
+                record(0, 5, 950_000),
+                record(0, 9, 950_000)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compute_size_stats() {
+        assert_eq!(super::compute_size_stats(&mut []), None);
+
+        let mut sizes: Vec<usize> = (1..=100).rev().collect();
+        let stats = super::compute_size_stats(&mut sizes).unwrap();
+        assert_eq!(stats.min, 1);
+        assert_eq!(stats.avg, 50);
+        assert_eq!(stats.p95, 95);
+        assert_eq!(stats.max, 100);
+
+        let stats = super::compute_size_stats(&mut [42]).unwrap();
+        assert_eq!(stats.p95, 42);
+    }
+
+    #[test]
+    fn test_apply_to_state() {
+        use std::collections::BTreeMap;
+
+        let mut state = BTreeMap::new();
+        super::apply_to_state(&mut state, "a".to_string(), Some("1".to_string()), 0, 0);
+        super::apply_to_state(&mut state, "b".to_string(), Some("2".to_string()), 1, 0);
+        super::apply_to_state(&mut state, "a".to_string(), Some("3".to_string()), 0, 1);
+        super::apply_to_state(&mut state, "b".to_string(), None, 1, 1);
+
+        assert_eq!(state.len(), 1);
+        let a = state.get("a").unwrap();
+        assert_eq!(a.value, "3");
+        assert_eq!(a.offset, 1);
+    }
+
+    #[test]
+    fn test_redact_field() {
+        use serde_json::json;
+
+        let mut record: toml::Value = serde_json::from_value(json!({
+            "data": {
+                "customer": {"email": "a@example.com", "name": "Ada"},
+                "card": {"number": "4111", "expiry": "12/30"},
+                "items": [{"email": "b@example.com"}, {"sku": "x"}]
+            }
+        }))
+        .unwrap();
+        for path in [
+            "data.customer.email",
+            "data.card.*",
+            "data.items.email",
+            "data.missing",
+        ] {
+            let path: Vec<&str> = path.split('.').collect();
+            super::redact_field(&mut record, &path);
+        }
+        let record: serde_json::Value = serde_json::to_value(&record).unwrap();
+        assert_eq!(
+            record,
+            json!({
+                "data": {
+                    "customer": {"email": "***", "name": "Ada"},
+                    "card": {"number": "***", "expiry": "***"},
+                    "items": [{"email": "***"}, {"sku": "x"}]
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_highlight_matches() {
+        let patterns = super::highlight_patterns(
+            Some("data.status=paid"),
+            Some(&regex::Regex::new("ord-[0-9]+").unwrap()),
+        );
+        assert_eq!(
+            super::highlight_matches(r#"{"id": "ord-42", "status": "paid"}"#, &patterns),
+            "{\"id\": \"\x1b[7mord-42\x1b[27m\", \x1b[7m\"status\"\x1b[27m: \x1b[7m\"paid\"\x1b[27m}"
+        );
+        assert_eq!(
+            super::highlight_matches("data.status=paid total=3", &patterns),
+            "\x1b[7mdata.status\x1b[27m=\x1b[7mpaid\x1b[27m total=3"
+        );
+        // Escape codes are kept whole, even when a pattern would match inside them
+        let digits = vec![regex::Regex::new("[0-9]+").unwrap()];
+        assert_eq!(
+            super::highlight_matches("\x1b[1;34m7\x1b[0m", &digits),
+            "\x1b[1;34m\x1b[7m7\x1b[27m\x1b[0m"
+        );
+        assert_eq!(super::highlight_matches("plain", &[]), "plain");
+    }
+
+    #[test]
+    fn test_paused_records() {
+        let mut paused = super::PausedRecords::default();
+        for offset in 0..super::PAUSE_BUFFER + 2 {
+            paused.push((None, offset.to_string()));
+        }
+        assert_eq!(paused.records.len(), super::PAUSE_BUFFER);
+        assert_eq!(paused.dropped, 2);
+        assert_eq!(paused.records.front().unwrap().1, "2");
+    }
+
+    #[test]
+    fn test_format_tail_stats() {
+        let partitions = BTreeMap::from([(0, 1_200), (3, 7)]);
+        assert_eq!(
+            super::format_tail_stats(42.25, 1_180, 27, &partitions),
+            "42.2 msg/s | shown 1180 | filtered out 27 | p0=1200 p3=7"
+        );
+    }
+
+    #[test]
+    fn test_flatten_json() {
+        use serde_json::json;
+
+        let record: toml::Value = serde_json::from_value(json!({
+            "data": {
+                "attributes": {"name": "Ada Lovelace", "id": "c-1"},
+                "items": [{"sku": "x", "qty": 2}],
+                "tags": [],
+                "paid": true
+            }
+        }))
+        .unwrap();
+        assert_eq!(
+            super::flatten_json(&record),
+            "data.attributes.id=c-1 data.attributes.name=\"Ada Lovelace\" data.items.0.qty=2 \
+             data.items.0.sku=x data.paid=true data.tags=[]"
+        );
+    }
+
+    #[test]
+    fn test_resume_session() {
+        use std::{collections::BTreeMap, time::Instant};
+
+        let tracker = super::SessionTracker {
+            name: "debug",
+            session: crate::session::TailSession {
+                topic: "orders".to_string(),
+                offsets: BTreeMap::from([(0, 42), (1, 3), (2, 900)]),
+            },
+            resumed: true,
+            last_save: Instant::now(),
+        };
+        // Partition 1 was truncated past the session, partition 3 is new
+        let watermarks = vec![(0, 0, 100), (1, 10, 20), (2, 0, 500), (3, 5, 8)];
+        assert_eq!(
+            tracker.resume(watermarks),
+            vec![(0, 42, 100), (1, 10, 20), (2, 500, 500), (3, 5, 8)]
+        );
     }
 
-    Ok(())
-}
+    #[test]
+    fn test_summarize_validation() {
+        let failures = vec![
+            ("Invalid Avro payload: a".to_string(), 0, 10),
+            ("Invalid Avro payload: b".to_string(), 1, 4),
+            ("Invalid Avro payload: b".to_string(), 1, 5),
+            ("Invalid Avro payload: b".to_string(), 2, 7),
+            ("Invalid Avro payload: b".to_string(), 2, 8),
+        ];
+        let summary = super::summarize_validation(failures);
+        assert_eq!(summary.len(), 2);
+        assert_eq!(summary[0].error, "Invalid Avro payload: b");
+        assert_eq!(summary[0].records, 4);
+        assert_eq!(summary[0].examples, vec![(1, 4), (1, 5), (2, 7)]);
+        assert_eq!(summary[1].records, 1);
+    }
 
-fn get_consumers_group_details_inner<'a>(
-    bootstrap_servers: &str,
-    group: &'a str,
-) -> Result<([&'a str; 4], [String; 4], [&'a str; 5], [String; 5]), KafkaError> {
-    let consumer = get_consumer(bootstrap_servers);
-    let groups = consumer
-        .fetch_group_list(Some(&group), std::time::Duration::from_secs(10))
-        .map_err(|er| {
-            if let rdkafka::error::KafkaError::GroupListFetch(_) = er {
-                KafkaError::GroupListFetch("Error while fetching consumer groups".to_string(), er)
-            } else {
-                KafkaError::Generic("Error while fetching consumer groups".to_string())
-            }
-        })?;
+    #[test]
+    fn test_collect_transactions() {
+        use super::TransactionRecord;
+        use crate::transaction::TransactionMetadata;
 
-    let group_header = ["Group ID", "State", "Protocol Type", "Protocol"];
-    let mut group_detail = [
-        String::from(""),
-        String::from(""),
-        String::from(""),
-        String::from(""),
-    ];
+        let record = |id: &str, offset: i64, state: Option<&'static str>| TransactionRecord {
+            transactional_id: id.to_string(),
+            partition: 0,
+            offset,
+            metadata: state.map(|state| TransactionMetadata {
+                producer_id: 1,
+                producer_epoch: 0,
+                timeout_ms: 60000,
+                state,
+                topics: vec![],
+                last_update: 0,
+                start: -1,
+            }),
+        };
+        let records = vec![
+            record("billing", 0, Some("Ongoing")),
+            record("orders", 1, Some("Ongoing")),
+            record("billing", 2, Some("CompleteCommit")),
+            record("expired", 3, Some("Empty")),
+            record("expired", 4, None),
+        ];
 
-    let member_header = ["Member ID", "Client ID", "Host", "Topoc", "Partitions"];
-    let mut member_detail = [
-        String::from(""),
-        String::from(""),
-        String::from(""),
-        String::from(""),
-        String::from(""),
-    ];
+        let latest = super::collect_transactions(records.clone(), None, false);
+        let offsets: Vec<i64> = latest.iter().map(|r| r.offset).collect();
+        assert_eq!(offsets, vec![2, 1]);
 
-    for group in groups.groups() {
-        group_detail = [
-            group.name().to_string(),
-            group.state().to_string(),
-            group.protocol_type().to_string(),
-            group.protocol().to_string(),
-        ];
+        let ongoing = super::collect_transactions(records.clone(), None, true);
+        assert_eq!(ongoing.len(), 1);
+        assert_eq!(ongoing[0].transactional_id, "orders");
 
-        if group.state() == "Stable" {
-            for member in group.members() {
-                let assignment = member.assignment();
-                if assignment.is_none() {
-                    continue;
-                }
-                let assignment = deserialize_assignment(member.assignment().unwrap())?;
+        let history = super::collect_transactions(records, Some("expired"), false);
+        let offsets: Vec<i64> = history.iter().map(|r| r.offset).collect();
+        assert_eq!(offsets, vec![3, 4]);
+    }
 
-                for (topic, partitions) in assignment {
-                    let partitions = partitions
-                        .iter()
-                        .map(|p| p.to_string())
-                        .collect::<Vec<String>>();
-                    member_detail = [
-                        member.id().to_string(),
-                        member.client_id().to_string(),
-                        member.client_host().to_string(),
-                        topic,
-                        partitions.join(", "),
-                    ];
+    #[test]
+    fn test_matches_filter() {
+        let payload = br#"{"data":{"status":"failed"}}"#.as_slice();
+        assert!(super::matches_filter(Some(payload), None));
+        assert!(super::matches_filter(
+            Some(payload),
+            Some("data.status=failed")
+        ));
+        assert!(!super::matches_filter(
+            Some(payload),
+            Some("data.status=ok")
+        ));
+        assert!(!super::matches_filter(
+            Some(b"not json"),
+            Some("data.status")
+        ));
+        assert!(!super::matches_filter(None, Some("data.status")));
+    }
 
-                    // get_topic_detail_inner(&consumer, &topic);
-                }
-            }
-        }
+    #[test]
+    fn test_decode_partition_header() {
+        assert_eq!(super::decode_partition_header(b"3"), Some(3));
+        assert_eq!(super::decode_partition_header(&[0, 0, 0, 7]), Some(7));
+        assert_eq!(super::decode_partition_header(b"abc"), None);
     }
-    Ok((group_header, group_detail, member_header, member_detail))
-}
 
-fn calculate_consumer_lag(bootstrap_servers: &str, group_id: &str) -> Result<(), KafkaError> {
-    let consumer = get_given_consumer(bootstrap_servers, group_id);
+    #[test]
+    fn test_dlq_origin() {
+        use rdkafka::message::{Header, OwnedHeaders, OwnedMessage};
+        use rdkafka::Timestamp;
 
-    let subscription = consumer.subscription().map_err(|er| {
-        KafkaError::Generic(format!("Error while fetching subscription: {:?}", er))
-    })?;
+        let headers = OwnedHeaders::new()
+            .insert(Header {
+                key: "kafka_dlt-exception-fqcn",
+                value: Some("java.lang.IllegalStateException"),
+            })
+            .insert(Header {
+                key: "kafka_dlt-original-topic",
+                value: Some("orders"),
+            })
+            .insert(Header {
+                key: "kafka_dlt-original-partition",
+                value: Some([0u8, 0, 0, 2].as_slice()),
+            });
+        let message = OwnedMessage::new(
+            None,
+            None,
+            "orders.DLQ".to_string(),
+            Timestamp::NotAvailable,
+            0,
+            0,
+            Some(headers),
+        );
 
-    // #TODO: This should be implemented
-    // let subscription = subscription.unwrap();
-    // let topics: Vec<String> = subscription
-    //     .elements()
-    //     .iter()
-    //     .map(|tp| tp.topic().to_string())
-    //     .collect();
+        let origin = super::dlq_origin(&message, None);
+        assert_eq!(origin.error, "java.lang.IllegalStateException");
+        assert_eq!(origin.topic, "orders");
+        assert_eq!(origin.partition, Some(2));
 
-    // Get metadata for topic partitions
-    let metadata = consumer
-        .fetch_metadata(None, Duration::from_secs(10))
-        .map_err(|er| {
-            if let rdkafka::error::KafkaError::MetadataFetch(_) = er {
-                KafkaError::MetadataFetch("Error while fetching topic metadata".to_string(), er)
-            } else {
-                KafkaError::Generic("Error while fetching topics".to_string())
-            }
-        })?;
+        let origin = super::dlq_origin(&message, Some("x-error"));
+        assert_eq!(origin.error, "<unknown>");
+    }
 
-    for topic in metadata.topics() {
-        let topic_metadata = topic;
-        println!("Topic: {}", topic_metadata.name());
+    #[test]
+    fn test_check_delivered() {
+        use std::sync::atomic::Ordering;
 
-        // Create partition list for committed offsets
-        let mut tpl = TopicPartitionList::new();
-        for partition in topic_metadata.partitions() {
-            tpl.add_partition(topic_metadata.name(), partition.id());
-        }
+        // A DLQ replay whose records all reached the destination succeeds
+        let counter = super::DeliveryCounter::default();
+        counter.delivered.store(3, Ordering::Relaxed);
+        assert!(counter.check_delivered("orders").is_ok());
 
-        let mut table = Table::new();
-        table.add_row(row!["Partition", "Current Offset", "Latest Offset", "Lag"]);
+        // and one with an undelivered record fails, so the command exits non-zero
+        counter.failed.store(1, Ordering::Relaxed);
+        let error = counter.check_delivered("orders").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "1 of 4 records were not delivered to orders"
+        );
+    }
 
-        let mut partition_details: Vec<[String; 3]> = vec![];
+    #[test]
+    fn test_jsonl_record() {
+        use rdkafka::message::{Header, OwnedHeaders, OwnedMessage};
+        use rdkafka::Timestamp;
 
-        for partition in topic_metadata.partitions() {
-            let partition_id = partition.id();
+        let headers = OwnedHeaders::new()
+            .insert(Header {
+                key: "trace-id",
+                value: Some("abc"),
+            })
+            .insert(Header {
+                key: "empty",
+                value: None::<&str>,
+            })
+            .insert(Header {
+                key: "trace-id",
+                value: Some(&b"\xffdef"[..]),
+            });
+        let message = OwnedMessage::new(
+            None,
+            Some(b"order-1".to_vec()),
+            "orders".to_string(),
+            Timestamp::CreateTime(1714557600000),
+            2,
+            42,
+            Some(headers),
+        );
+        let line = super::jsonl_record(&message, serde_json::json!({"amount": 5}));
+        assert!(!line.contains('\n'));
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&line).unwrap(),
+            serde_json::json!({
+                "topic": "orders",
+                "partition": 2,
+                "offset": 42,
+                "timestamp": 1714557600000_i64,
+                "key": "order-1",
+                "headers": [["trace-id", "abc"], ["empty", null], ["trace-id", "\u{fffd}def"]],
+                "value": {"amount": 5},
+            })
+        );
+        let line = super::jsonl_record(&message, "not json");
+        assert!(line.ends_with(r#""value":"not json"}"#));
+    }
 
-            let (_, partition_detail, _) =
-                partition_detail_inner(partition, topic_metadata.name(), &consumer)?;
+    #[test]
+    fn test_compute_broker_balance() {
+        let partitions = vec![
+            (1, vec![1, 2]),
+            (1, vec![1, 3]),
+            (1, vec![1, 2]),
+            (2, vec![2, 3]),
+        ];
+        let balance = super::compute_broker_balance(&[1, 2, 3], &partitions);
+        assert_eq!(balance.len(), 3);
+        assert_eq!(balance[0].leaders, 3);
+        assert_eq!(balance[0].replicas, 3);
+        assert!((balance[0].leader_deviation - 125.0).abs() < 1e-9);
+        assert_eq!(balance[2].leaders, 0);
+        assert!((balance[2].leader_deviation + 100.0).abs() < 1e-9);
+        assert_eq!(balance[2].replicas, 2);
 
-            partition_details.extend(partition_detail);
+        // An offline partition adds no phantom broker -1 and no leader to share out
+        let mut with_offline = partitions.clone();
+        with_offline.push((-1, vec![3]));
+        let balance = super::compute_broker_balance(&[1, 2, 3], &with_offline);
+        assert_eq!(balance.len(), 3);
+        assert!(balance.iter().all(|b| b.id >= 0));
+        assert!((balance[0].leader_deviation - 125.0).abs() < 1e-9);
+        assert_eq!(balance[2].replicas, 3);
+    }
 
-            // Get latest offset
-            let (_, high_watermark) = consumer
-                .fetch_watermarks(topic_metadata.name(), partition_id, Duration::from_secs(5))
-                .map_err(|er| {
-                    KafkaError::Generic(format!("Error while fetching watermarks: {:?}", er))
-                })?;
+    #[test]
+    fn test_describe_rack_spread() {
+        let mut racks = HashMap::new();
+        assert_eq!(super::describe_rack_spread(&[1, 2], &racks), "-");
 
-            let mut topic_partition_list = TopicPartitionList::new();
-            topic_partition_list.add_partition(topic_metadata.name(), partition_id);
-            let committed_offsets = consumer
-                .committed_offsets(topic_partition_list, std::time::Duration::from_secs(5))
-                .expect("Failed to fetch committed offsets");
+        racks.insert(1, "eu-west-1a".to_string());
+        racks.insert(2, "eu-west-1a".to_string());
+        racks.insert(3, "eu-west-1b".to_string());
+        assert_eq!(
+            super::describe_rack_spread(&[1, 2], &racks),
+            "no (eu-west-1a)"
+        );
+        assert_eq!(
+            super::describe_rack_spread(&[1, 2, 3], &racks),
+            "yes (eu-west-1a, eu-west-1b)"
+        );
+    }
 
-            // Get committed offset
-            let committed_offset = committed_offsets
-                .find_partition(topic_metadata.name(), partition_id)
-                .and_then(|p| Some(p.offset().to_raw()))
-                .unwrap_or(Some(0))
-                .unwrap_or(0);
+    #[test]
+    fn test_partition_health() {
+        assert_eq!(
+            super::partition_health(1, &[1, 2, 3], &[1, 2, 3]),
+            "in-sync"
+        );
+        assert_eq!(
+            super::partition_health(1, &[1, 2, 3], &[1, 3]),
+            "under-replicated"
+        );
+        assert_eq!(super::partition_health(-1, &[1, 2, 3], &[]), "offline");
+    }
 
-            // Calculate lag
-            let lag = high_watermark - committed_offset;
+    #[test]
+    fn test_min_isr_risk() {
+        assert_eq!(super::min_isr_risk(3, 3, 2), None);
+        assert_eq!(
+            super::min_isr_risk(3, 2, 2),
+            Some("one more replica failure blocks acks=all")
+        );
+        assert_eq!(
+            super::min_isr_risk(3, 1, 2),
+            Some("unavailable for acks=all")
+        );
+        assert_eq!(
+            super::min_isr_risk(1, 1, 2),
+            Some("min.insync.replicas exceeds the replication factor")
+        );
+        assert_eq!(super::min_isr_risk(1, 1, 1), None);
+    }
+
+    #[test]
+    fn test_slow_brokers() {
+        let ms = std::time::Duration::from_millis;
+        assert_eq!(super::median(&[ms(9), ms(2), ms(4)]), ms(4));
+        assert_eq!(super::median(&[]), ms(0));
+
+        let medians: BTreeMap<i32, std::time::Duration> =
+            [(1, ms(4)), (2, ms(5)), (3, ms(40)), (4, ms(6))].into();
+        assert_eq!(super::slow_brokers(&medians), [3].into());
+        let fast: BTreeMap<i32, std::time::Duration> = [(1, ms(1)), (2, ms(1)), (3, ms(5))].into();
+        assert!(super::slow_brokers(&fast).is_empty());
+    }
 
-            table.add_row(row![partition_id, committed_offset, high_watermark, lag]);
+    fn config_entry(name: &str, value: &str, is_default: bool) -> rdkafka::admin::ConfigEntry {
+        rdkafka::admin::ConfigEntry {
+            name: name.to_string(),
+            value: Some(value.to_string()),
+            source: rdkafka::admin::ConfigSource::StaticBroker,
+            is_read_only: false,
+            is_default,
+            is_sensitive: false,
         }
-        table.printstd();
     }
 
-    Ok(())
-}
+    #[test]
+    fn test_diff_configs() {
+        let broker_1 = super::non_default_configs(
+            &[
+                config_entry("broker.id", "1", false),
+                config_entry("num.io.threads", "8", false),
+                config_entry("log.retention.hours", "168", false),
+            ],
+            &super::BROKER_IDENTITY_CONFIGS,
+        );
+        let broker_2 = super::non_default_configs(
+            &[
+                config_entry("broker.id", "2", false),
+                config_entry("num.io.threads", "16", false),
+                config_entry("log.retention.hours", "168", false),
+                config_entry("message.max.bytes", "1048588", true),
+            ],
+            &super::BROKER_IDENTITY_CONFIGS,
+        );
+        let broker_3 = super::non_default_configs(
+            &[
+                config_entry("broker.id", "3", false),
+                config_entry("num.io.threads", "8", false),
+            ],
+            &super::BROKER_IDENTITY_CONFIGS,
+        );
 
-#[cfg(test)]
-mod test {
-    use rdkafka::metadata::MetadataTopic;
+        let differences = super::diff_configs(&[broker_1, broker_2, broker_3]);
+        assert_eq!(
+            differences,
+            vec![
+                (
+                    "log.retention.hours".to_string(),
+                    vec![Some("168".to_string()), Some("168".to_string()), None]
+                ),
+                (
+                    "num.io.threads".to_string(),
+                    vec![
+                        Some("8".to_string()),
+                        Some("16".to_string()),
+                        Some("8".to_string())
+                    ]
+                ),
+            ]
+        );
+    }
 
-    use crate::kafka::{get_consumer, get_topic_detail_inner, KafkaError};
+    #[test]
+    fn test_topic_overrides() {
+        let mut retention = config_entry("retention.ms", "86400000", false);
+        retention.source = rdkafka::admin::ConfigSource::DynamicTopic;
+        let mut secret = config_entry("sasl.jaas.config", "", false);
+        secret.source = rdkafka::admin::ConfigSource::DynamicTopic;
+        secret.is_sensitive = true;
+        let overrides = super::topic_overrides(&[
+            retention,
+            secret,
+            config_entry("min.insync.replicas", "2", false),
+        ]);
+        assert_eq!(
+            overrides.into_iter().collect::<Vec<_>>(),
+            vec![("retention.ms".to_string(), "86400000".to_string())]
+        );
+    }
 
     #[test]
-    fn test_get_topics_inner() {
-        let bootstrap_servers = "localhost:9092";
-        let metadata = super::get_topics_inner(bootstrap_servers, None);
-        assert!(metadata.is_ok());
-        let metadata = metadata.unwrap();
-        let topics = metadata
-            .topics()
-            .iter()
-            .filter(|topic| topic.name() != "__consumer_offsets")
-            .collect::<Vec<&MetadataTopic>>();
+    fn test_sampler_keeps_fraction() {
+        let mut sampler = super::Sampler::new(0.25);
+        let kept = (0..100).filter(|_| sampler.keep()).count();
+        assert_eq!(kept, 25);
+    }
 
-        assert_eq!(topics.len(), 3);
-        topics
-            .iter()
-            .filter(|topic| topic.name() == "topic-one")
-            .for_each(|topic| {
-                assert_eq!(topic.partitions().len(), 3);
-            });
+    #[test]
+    fn test_deduper_keeps_latest_per_key() {
+        let mut deduper = super::Deduper::new(5);
+        assert!(!deduper.push(Some(&b"a"[..]), 1));
+        assert!(!deduper.push(Some(&b"b"[..]), 2));
+        assert!(!deduper.push(None, 3));
+        assert!(!deduper.push(Some(&b"a"[..]), 4));
+        assert!(deduper.push(None, 5));
+        assert_eq!(deduper.flush(), vec![2, 3, 4, 5]);
+        assert!(!deduper.push(Some(&b"a"[..]), 6));
+        assert_eq!(deduper.flush(), vec![6]);
     }
 
     #[test]
-    fn test_get_topic_not_exists_detail_inner() {
-        let bootstrap_servers = "localhost:9092";
-        let topic = "topic-not-exists";
-        let consumer = get_consumer(bootstrap_servers);
-        let result = get_topic_detail_inner(&consumer, topic);
-        assert!(result.is_err());
-        if let KafkaError::TopicNotExists(err) = result.unwrap_err() {
-            assert_eq!(err, "Topic topic-not-exists does not exist");
-        } else {
-            panic!("Error should be TopicNotExists");
-        }
+    fn test_window_ranges() {
+        let starts = vec![(0, 10, 50), (1, 30, 30), (2, 5, 40)];
+        let ends = vec![(0, 20, 50), (1, 30, 30), (2, 40, 40)];
+        assert_eq!(
+            super::window_ranges(&starts, &ends),
+            vec![(0, 10, 20), (1, 30, 30), (2, 5, 40)]
+        );
     }
 
     #[test]
-    fn test_get_topic_detail_inner() {
-        let bootstrap_servers = "localhost:9092";
-        let topic = "topic-one";
-        let consumer = get_consumer(bootstrap_servers);
-        let (overall_header, overall_detail, partition_detail_header, partition_detail) =
-            get_topic_detail_inner(&consumer, topic).unwrap();
+    fn test_plan_offset_commit() {
+        let watermarks = [(0, 10, 100), (1, 0, 50)];
         assert_eq!(
-            overall_header,
-            ["Partitions", "Partition IDs", "Total Messages"]
+            super::plan_offset_commit(&watermarks, Some(1), 50).unwrap(),
+            vec![(1, 50, 50)]
         );
-        assert_eq!(overall_detail, ["3", "0, 1, 2, ", "0"]);
         assert_eq!(
-            partition_detail_header,
-            ["Partition ID", "Leader", "Offset"]
+            super::plan_offset_commit(&watermarks, None, 20).unwrap(),
+            vec![(0, 20, 100), (1, 20, 50)]
         );
         assert_eq!(
-            partition_detail,
-            [["0", "1", "0"], ["1", "1", "0"], ["2", "1", "0"]]
+            super::plan_offset_commit(&watermarks, None, 5)
+                .unwrap_err()
+                .to_string(),
+            "Offset 5 is outside the watermarks [10, 100] of partition 0"
+        );
+        assert_eq!(
+            super::plan_offset_commit(&watermarks, Some(2), 5)
+                .unwrap_err()
+                .to_string(),
+            "Partition 2 does not exist"
         );
     }
 
     #[test]
-    fn test_error_deserialize_assignment() {
-        let data = vec![
-            0, 1, 0, 0, 0, 1, 0, 9, 116, 111, 45, 111, 110, 101, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0,
-            1, 0, 0, 0, 2,
-        ];
-        let result = super::deserialize_assignment(&data);
-        assert!(result.is_err());
-        if let KafkaError::Deserialize(err, _) = result.unwrap_err() {
-            assert_eq!(err, "Error while reading partition:");
-        } else {
-            panic!("Error should be Deserialize");
-        }
+    fn test_describe_offset_changes() {
+        let committed = HashMap::from([(0, 80)]);
+        let changes = super::describe_offset_changes(&[(0, 10, 100), (1, 0, 50)], &committed);
+        assert_eq!(
+            changes,
+            vec![
+                super::OffsetChange {
+                    partition: 0,
+                    committed: Some(80),
+                    target: 10,
+                    lag: 90
+                },
+                super::OffsetChange {
+                    partition: 1,
+                    committed: None,
+                    target: 0,
+                    lag: 50
+                },
+            ]
+        );
+        assert_eq!(changes[0].shift(), Some(-70));
+        assert_eq!(changes[1].shift(), None);
     }
 
     #[test]
-    fn test_deserialize_assignment() {
-        let data = vec![
-            0, 1, 0, 0, 0, 1, 0, 9, 116, 111, 112, 105, 99, 45, 111, 110, 101, 0, 0, 0, 3, 0, 0, 0,
-            0, 0, 0, 0, 1, 0, 0, 0, 2, 255, 255, 255, 255,
-        ];
-        let result = super::deserialize_assignment(&data);
-        assert!(result.is_ok());
-        let result = result.unwrap();
-        assert_eq!(result.len(), 1);
-        assert!(result.contains_key("topic-one"));
-        let partitions = result.get("topic-one").unwrap();
-        assert_eq!(partitions.len(), 3);
-        assert_eq!(partitions[0], 0);
-        assert_eq!(partitions[1], 1);
-        assert_eq!(partitions[2], 2);
+    fn test_gap_detector() {
+        let mut detector = super::GapDetector::default();
+        assert_eq!(detector.observe(0, 10), None);
+        assert_eq!(detector.observe(0, 11), None);
+        assert_eq!(detector.observe(1, 5), None);
+        assert_eq!(detector.observe(0, 15), Some((12, 14)));
+        assert_eq!(detector.observe(1, 7), Some((6, 6)));
+        // A rebalance or seek back is not a gap
+        assert_eq!(detector.observe(0, 3), None);
+        assert_eq!(detector.gaps, vec![(0, 12, 14), (1, 6, 6)]);
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        use std::time::{Duration, Instant};
+
+        let start = Instant::now();
+        let mut limiter = super::RateLimiter::new(2.0, start);
+        assert!(limiter.allow(start));
+        assert!(limiter.allow(start));
+        assert!(!limiter.allow(start));
+        assert!(limiter.allow(start + Duration::from_millis(500)));
+        assert!(!limiter.allow(start + Duration::from_millis(600)));
+        // Idle time does not build up more than a second's worth
+        let later = start + Duration::from_secs(10);
+        assert!(limiter.allow(later));
+        assert!(limiter.allow(later));
+        assert!(!limiter.allow(later));
     }
 }
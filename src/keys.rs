@@ -0,0 +1,120 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+
+/// What a key pressed during `topics tail --follow` asks for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyAction {
+    /// Space stops printing records, or prints the ones held since
+    TogglePause,
+    /// `f` prompts for a new filter
+    EditFilter,
+    /// `q` ends the tail as Ctrl-C does
+    Quit,
+}
+
+fn key_action(key: u8) -> Option<KeyAction> {
+    match key {
+        b' ' => Some(KeyAction::TogglePause),
+        b'f' | b'F' => Some(KeyAction::EditFilter),
+        b'q' | b'Q' => Some(KeyAction::Quit),
+        _ => None,
+    }
+}
+
+/// Reads single key presses from the terminal without waiting for Enter and without echoing
+/// them. The terminal is set back the way it was when this is dropped.
+pub struct Keyboard {
+    #[cfg(unix)]
+    original: libc::termios,
+}
+
+impl Keyboard {
+    /// Takes over the keyboard, `None` unless both stdin and stderr are terminals.
+    #[cfg(unix)]
+    pub fn open() -> Option<Keyboard> {
+        if !io::stdin().is_terminal() || !io::stderr().is_terminal() {
+            return None;
+        }
+        // SAFETY: termios is plain data that tcgetattr fills in
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return None;
+        }
+        let keyboard = Keyboard { original };
+        keyboard.set_single_keys().then_some(keyboard)
+    }
+
+    #[cfg(not(unix))]
+    pub fn open() -> Option<Keyboard> {
+        None
+    }
+
+    /// Turns off line buffering and echo, leaving Ctrl-C and output processing alone. Reads
+    /// return at once, with nothing when no key was pressed.
+    #[cfg(unix)]
+    fn set_single_keys(&self) -> bool {
+        let mut single = self.original;
+        single.c_lflag &= !(libc::ICANON | libc::ECHO);
+        single.c_cc[libc::VMIN] = 0;
+        single.c_cc[libc::VTIME] = 0;
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &single) == 0 }
+    }
+
+    #[cfg(unix)]
+    fn restore(&self) {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+    }
+
+    /// The action of the next key pressed so far, skipping keys that have none.
+    #[cfg(unix)]
+    pub fn poll(&mut self) -> Option<KeyAction> {
+        let mut key = 0u8;
+        while unsafe { libc::read(libc::STDIN_FILENO, (&mut key as *mut u8).cast(), 1) } == 1 {
+            if let Some(action) = key_action(key) {
+                return Some(action);
+            }
+        }
+        None
+    }
+
+    #[cfg(not(unix))]
+    pub fn poll(&mut self) -> Option<KeyAction> {
+        None
+    }
+
+    /// Asks for a line of text with the terminal back in its usual mode, `None` if stdin closed.
+    pub fn prompt(&mut self, prompt: &str) -> Option<String> {
+        #[cfg(unix)]
+        self.restore();
+        eprint!("{}", prompt);
+        let _ = io::stderr().flush();
+        let mut line = String::new();
+        let read = io::stdin().lock().read_line(&mut line);
+        #[cfg(unix)]
+        self.set_single_keys();
+        match read {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line.trim().to_string()),
+        }
+    }
+}
+
+impl Drop for Keyboard {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        self.restore();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{key_action, KeyAction};
+
+    #[test]
+    fn test_key_action() {
+        assert_eq!(key_action(b' '), Some(KeyAction::TogglePause));
+        assert_eq!(key_action(b'f'), Some(KeyAction::EditFilter));
+        assert_eq!(key_action(b'Q'), Some(KeyAction::Quit));
+        assert_eq!(key_action(0x1b), None);
+        assert_eq!(key_action(b'x'), None);
+    }
+}
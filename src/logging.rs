@@ -0,0 +1,87 @@
+use std::{
+    fs::OpenOptions,
+    io::{self, IsTerminal},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use crate::{cli::LogLevel, kafka::KafkaError};
+
+/// Set when logging at trace level, so that clients also turn on the debug logs of librdkafka.
+static LIBRDKAFKA_DEBUG: AtomicBool = AtomicBool::new(false);
+
+/// The level to log at: the one asked for, debug when only a log file is given, and nothing
+/// when neither is given.
+fn effective_level(level: Option<LogLevel>, file: Option<&Path>) -> Option<LogLevel> {
+    match (level, file) {
+        (Some(level), _) => Some(level),
+        (None, Some(_)) => Some(LogLevel::Debug),
+        (None, None) => None,
+    }
+}
+
+/// Starts logging to `file`, appended to, or to stderr without one. The logs of librdkafka,
+/// which go through the `log` crate, end up in the same place.
+pub fn init(level: Option<LogLevel>, file: Option<&Path>) -> Result<(), KafkaError> {
+    let Some(level) = effective_level(level, file) else {
+        return Ok(());
+    };
+    LIBRDKAFKA_DEBUG.store(level == LogLevel::Trace, Ordering::Relaxed);
+    let builder = tracing_subscriber::fmt()
+        .with_max_level(level.level())
+        .with_target(true);
+    match file {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|er| KafkaError::Io(format!("Failed to open log file {:?}", path), er))?;
+            builder
+                .with_ansi(false)
+                .with_writer(Mutex::new(file))
+                .init();
+        }
+        None => builder
+            .with_ansi(io::stderr().is_terminal())
+            .with_writer(io::stderr)
+            .init(),
+    }
+    // The arguments are left out, as `config add` takes secrets
+    tracing::info!(version = env!("CARGO_PKG_VERSION"), "kfcli started");
+    Ok(())
+}
+
+/// The `debug` setting for new clients when logging at trace level: the librdkafka contexts
+/// that explain why a client cannot reach or authenticate with a broker.
+pub fn librdkafka_debug() -> Option<&'static str> {
+    LIBRDKAFKA_DEBUG
+        .load(Ordering::Relaxed)
+        .then_some("broker,topic,metadata,security,protocol")
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::effective_level;
+    use crate::cli::LogLevel;
+
+    #[test]
+    fn test_effective_level() {
+        let file = Some(Path::new("kfcli.log"));
+        assert_eq!(effective_level(None, None), None);
+        assert_eq!(effective_level(None, file), Some(LogLevel::Debug));
+        assert_eq!(
+            effective_level(Some(LogLevel::Warn), file),
+            Some(LogLevel::Warn)
+        );
+        assert_eq!(
+            effective_level(Some(LogLevel::Trace), None),
+            Some(LogLevel::Trace)
+        );
+    }
+}
@@ -1,27 +1,104 @@
-use std::error::Error;
+use std::{error::Error, io::IsTerminal};
 
 use clap::Parser;
 use cli::{generate_completion, Cli};
 use config::{
-    activate_environment, configure, get_active_environment, get_config_file, read_config,
+    activate_environment, add_environment, configure, get_active_environment, get_config_file,
+    list_environments, print_current_environment, read_config, read_environments,
+    read_project_config, select_environments, with_variables, EnvironmentConfig,
 };
 
+mod acl;
+mod admin;
+mod advice;
+mod audit;
+mod avro;
+mod backup;
 mod cli;
+mod client;
 mod config;
+mod desired;
+mod history;
 mod kafka;
+mod keys;
+mod logging;
+mod output;
+mod picker;
+mod produce;
+mod proto;
+mod registry;
+mod serve;
+mod session;
+mod snapshot;
+mod template;
+mod transaction;
+mod xml;
 
 fn main() {
     if let Err(e) = handle_command() {
+        tracing::error!(error = %e, "Command failed");
         eprintln!("Error: {}", e);
+        if let Some(hint) = advice::hint(e.as_ref()) {
+            eprintln!("Hint: {}", hint);
+        }
         std::process::exit(1);
     }
 }
 
 fn handle_command() -> Result<(), Box<dyn Error>> {
     let config = Cli::parse();
+    logging::init(config.log_level, config.log_file.as_deref())?;
+    let project = read_project_config()?;
+    let defaults = project
+        .as_ref()
+        .map(|(_, project)| project.defaults.clone())
+        .unwrap_or_default();
+    output::init(output::OutputOptions {
+        no_pager: config.no_pager || defaults.no_pager.unwrap_or(false),
+        columns: config.columns.clone(),
+        sort_by: config.sort_by.clone(),
+        descending: config.desc,
+        human: !config.no_human
+            && (config.human
+                || defaults
+                    .human
+                    .unwrap_or_else(|| std::io::stdout().is_terminal())),
+        time_format: config.time_format.or(defaults.time_format),
+        quiet: config.quiet || defaults.quiet.unwrap_or(false),
+    });
+    // A project's environment stands in for the active one, -e still wins
+    let project_env = project.and_then(|(path, project)| project.environment.map(|e| (path, e)));
     match config.command {
         cli::Command::Config(args) => {
-            if let Some(conf_command) = args.activate {
+            if let Some(cli::ConfigCommand::Add(add)) = &args.command {
+                add_environment(add)?;
+            } else if let Some(cli::ConfigCommand::Current(current)) = &args.command {
+                let environments = match &project_env {
+                    Some((path, selector)) => {
+                        if !current.short {
+                            output::status(format!("Environment set by {:?}", path));
+                        }
+                        select_environments(read_environments()?, selector)?
+                    }
+                    None => vec![get_active_environment(read_environments()?)?],
+                };
+                for (name, environment) in environments {
+                    let environment = (name, with_variables(environment)?);
+                    print_current_environment(&environment, current.short, !current.no_color);
+                }
+            } else if args.list {
+                let config_file = get_config_file()?;
+                let environments = read_config(&config_file)?;
+                let environments = match &config.env {
+                    Some(selector) => select_environments(environments, selector)?,
+                    None => {
+                        let mut all: Vec<_> = environments.into_iter().collect();
+                        all.sort_by(|a, b| a.0.cmp(&b.0));
+                        all
+                    }
+                };
+                list_environments(&environments);
+            } else if let Some(conf_command) = args.activate {
                 let config_file = get_config_file()?;
                 let environment = read_config(&config_file)?;
                 activate_environment(&conf_command, &config_file, environment)?;
@@ -29,56 +106,610 @@ fn handle_command() -> Result<(), Box<dyn Error>> {
                 configure()?;
             }
         }
-        cli::Command::Topics(topic_args) => {
-            let config_file = get_config_file()?;
-            match topic_args.command {
-                cli::TopicCommand::List => {
-                    let env = get_active_environment(config_file)?;
-                    kafka::get_topics(&env.brokers)?;
-                }
-                cli::TopicCommand::Details(topic_args) => {
-                    let env = get_active_environment(config_file)?;
-                    kafka::get_topic_detail(&env.brokers, &topic_args.topic)?;
-                }
-                cli::TopicCommand::Tail(tail_args) => {
-                    let env = get_active_environment(config_file)?;
-                    kafka::tail_topic(&env.brokers, &tail_args.topic, tail_args.filter)?;
+        cli::Command::Completion(args) => match generate_completion(args.shell) {
+            Ok(_) => {
+                output::info("Completion generated successfully");
+            }
+            Err(e) => eprintln!("Error generating completion: {}", e),
+        },
+        cli::Command::Audit(args) => audit::show_audit_log(args.limit)?,
+        // Charts read a recorded file and need no cluster
+        cli::Command::Consumer(cli::ConsumerCommandArgs {
+            command: Some(cli::ConsumerCommand::Chart(args)),
+            ..
+        }) => history::chart_lag_history(&args.file, args.group.as_deref(), args.width)?,
+        mut command => {
+            // Without -e a project file picks the environment, which is said on every run
+            let project_file = project_env
+                .as_ref()
+                .filter(|_| config.env.is_none() && !config.all_envs)
+                .map(|(path, selector)| {
+                    output::status(format!("Environment {} set by {:?}", selector, path));
+                    path.display().to_string()
+                });
+            let selector = config.env.or(project_env.map(|(_, selector)| selector));
+            let fan_out =
+                config.all_envs || selector.as_deref().is_some_and(|e| e.starts_with('@'));
+            if fan_out && !command.is_read_only() {
+                return Err("Only read-only commands can run against several environments".into());
+            }
+            if config.all_envs {
+                let mut environments: Vec<_> = read_environments()?.into_iter().collect();
+                environments.sort_by(|a, b| a.0.cmp(&b.0));
+                run_on_environments(&command, &environments)?;
+            } else if let Some(selector) = &selector {
+                let environments = select_environments(read_environments()?, selector)?;
+                if fan_out {
+                    run_on_environments(&command, &environments)?;
+                } else {
+                    let (name, env) = environments.into_iter().next().expect("One is selected");
+                    run_audited_command(&mut command, &name, project_file, &with_variables(env)?)?;
                 }
+            } else {
+                let (name, env) = get_active_environment(read_environments()?)?;
+                run_audited_command(&mut command, &name, None, &with_variables(env)?)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Runs `command` against each of `environments`, prefixing the output with the environment
+/// name. A failing environment does not stop the others.
+fn run_on_environments(
+    command: &cli::Command,
+    environments: &[(String, EnvironmentConfig)],
+) -> Result<(), Box<dyn Error>> {
+    let mut failed = 0;
+    for (name, env) in environments {
+        println!("[{}]", name);
+        let result = with_variables(env.clone())
+            .map_err(|e| e.into())
+            .and_then(|env| {
+                tracing::info!(environment = name, brokers = %env.brokers, "Running command");
+                client::use_environment(&env)?;
+                run_kafka_command(command, &env.brokers)
+            });
+        if let Err(e) = result {
+            tracing::error!(environment = name, error = %e, "Command failed");
+            eprintln!("[{}] Error: {}", name, e);
+            if let Some(hint) = advice::hint(e.as_ref()) {
+                eprintln!("[{}] Hint: {}", name, hint);
             }
+            failed += 1;
         }
+    }
+    if failed > 0 {
+        return Err(format!("{} of {} environments failed", failed, environments.len()).into());
+    }
+    Ok(())
+}
+
+/// Runs `command` and, if it changes the cluster, records it with its outcome in the audit log.
+fn run_audited_command(
+    command: &mut cli::Command,
+    environment: &str,
+    environment_source: Option<String>,
+    config: &EnvironmentConfig,
+) -> Result<(), Box<dyn Error>> {
+    tracing::info!(environment, brokers = %config.brokers, "Running command");
+    client::use_environment(config)?;
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    args.extend(pick_missing(command, &config.brokers)?);
+    let result = run_kafka_command(command, &config.brokers);
+    if command.is_audited() {
+        let entry = audit::AuditEntry::new(
+            environment,
+            environment_source,
+            args,
+            result.as_ref().map(|_| ()).map_err(|e| e.to_string()),
+        );
+        if let Err(e) = audit::record(&entry) {
+            eprintln!("Warning: the command was not audited: {}", e);
+        }
+    }
+    result
+}
+
+/// Lets the user pick the topic or consumer group of a command run without one, returning the
+/// flags that would have given the picked value. Off a terminal nothing is picked and the
+/// command reports what is missing.
+fn pick_missing(command: &mut cli::Command, brokers: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    if !picker::is_interactive() {
+        return Ok(vec![]);
+    }
+    if let Some(topic) = command.missing_topic() {
+        let picked =
+            picker::pick("Topic", &kafka::topic_names(brokers)?)?.ok_or("No topic picked")?;
+        *topic = Some(picked.clone());
+        return Ok(vec!["--topic".to_string(), picked]);
+    }
+    if let Some(args) = command.missing_group() {
+        let picked = picker::pick("Consumer group", &kafka::group_names(brokers)?)?
+            .ok_or("No consumer group picked")?;
+        args.consumer = Some(picked.clone());
+        let mut flags = vec!["--consumer".to_string(), picked];
+        // A group is picked to see how it is doing, so its lag is shown too
+        if !args.pending {
+            args.pending = true;
+            flags.push("--pending".to_string());
+        }
+        return Ok(flags);
+    }
+    Ok(vec![])
+}
+
+/// The value of a flag that is picked from a list when left out on a terminal.
+fn required<'a>(value: &'a Option<String>, flag: &str) -> Result<&'a str, Box<dyn Error>> {
+    value
+        .as_deref()
+        .ok_or_else(|| format!("{} is required when not run on a terminal", flag).into())
+}
+
+fn run_kafka_command(command: &cli::Command, brokers: &str) -> Result<(), Box<dyn Error>> {
+    match command {
+        cli::Command::Topics(topic_args) => match &topic_args.command {
+            cli::TopicCommand::List => {
+                kafka::get_topics(brokers)?;
+            }
+            cli::TopicCommand::Details(topic_args) => {
+                kafka::get_topic_detail(
+                    brokers,
+                    required(&topic_args.topic, "--topic")?,
+                    kafka::DetailOptions {
+                        configs: topic_args.configs,
+                        lag: topic_args.lag,
+                        acls: topic_args.acls,
+                        time_range: topic_args.time_range,
+                    },
+                )?;
+            }
+            cli::TopicCommand::Tail(tail_args) => {
+                let decoder: Option<Box<dyn kafka::PayloadDecoder>> =
+                    match (&tail_args.proto_descriptor, &tail_args.proto_message) {
+                        (Some(descriptor), Some(message)) => {
+                            Some(Box::new(proto::ProtoDecoder::load(descriptor, message)?))
+                        }
+                        _ => match (&tail_args.avro_schema, &tail_args.schema_registry) {
+                            (Some(schema), _) => Some(Box::new(avro::AvroDecoder::load(schema)?)),
+                            (None, Some(url)) => {
+                                Some(Box::new(registry::RegistryDecoder::new(url)))
+                            }
+                            (None, None) => None,
+                        },
+                    };
+                kafka::tail_topic(
+                    brokers,
+                    required(&tail_args.topic, "--topic")?,
+                    kafka::TailOptions {
+                        filter: tail_args.filter.as_deref(),
+                        decoder: decoder.as_deref(),
+                        start: if tail_args.from_beginning {
+                            Some(kafka::StartPosition::Beginning)
+                        } else {
+                            tail_args.since.map(kafka::StartPosition::Timestamp)
+                        },
+                        follow: !tail_args.no_follow,
+                        max_rate: tail_args.max_rate,
+                        sample: tail_args.sample,
+                        format: tail_args.format,
+                        dedupe_window: tail_args.dedupe_by_key.then_some(tail_args.window),
+                        detect_gaps: tail_args.detect_gaps,
+                        redact: &tail_args.redact,
+                        flatten: tail_args.flatten,
+                        output: tail_args.output,
+                        session: tail_args.session.as_deref(),
+                        highlight: tail_args.highlight.as_ref(),
+                        stats: tail_args.stats,
+                        commit: tail_args.commit,
+                    },
+                )?;
+            }
+            cli::TopicCommand::Validate(args) => {
+                let decoder: Box<dyn kafka::PayloadDecoder> = match &args.schema {
+                    Some(schema) => Box::new(avro::AvroDecoder::load(schema)?),
+                    None => Box::new(registry::RegistryDecoder::new(
+                        args.schema_registry.as_deref().unwrap_or_default(),
+                    )),
+                };
+                let range = args
+                    .since
+                    .map(|since| (since, args.until.unwrap_or(i64::MAX)));
+                kafka::validate_topic(brokers, &args.topic, decoder.as_ref(), args.sample, range)?;
+            }
+            cli::TopicCommand::Transactions(args) => {
+                kafka::get_transactions(brokers, args.id.as_deref(), args.ongoing, args.output)?;
+            }
+            cli::TopicCommand::Skew(skew_args) => {
+                kafka::get_topic_skew(brokers, &skew_args.topic, skew_args.threshold)?;
+            }
+            cli::TopicCommand::Keys(keys_args) => {
+                kafka::get_key_distribution(
+                    brokers,
+                    &keys_args.topic,
+                    keys_args.sample,
+                    keys_args.top,
+                )?;
+            }
+            cli::TopicCommand::Sizes(sizes_args) => {
+                kafka::get_message_sizes(brokers, &sizes_args.topic, sizes_args.sample)?;
+            }
+            cli::TopicCommand::Size(size_args) => {
+                kafka::get_topic_disk_usage(brokers, size_args.topic.as_deref(), size_args.top)?;
+            }
+            cli::TopicCommand::RetentionReport => {
+                kafka::get_retention_report(brokers)?;
+            }
+            cli::TopicCommand::State(state_args) => {
+                kafka::get_topic_state(
+                    brokers,
+                    &state_args.topic,
+                    state_args.key.as_deref(),
+                    state_args.output,
+                )?;
+            }
+            cli::TopicCommand::Replay(replay_args) => {
+                let start = match (replay_args.since, replay_args.from_offset) {
+                    (Some(timestamp), _) => kafka::StartPosition::Timestamp(timestamp),
+                    (_, Some(offset)) => kafka::StartPosition::Offset(offset),
+                    _ => kafka::StartPosition::Beginning,
+                };
+                kafka::replay_topic(
+                    brokers,
+                    &replay_args.from,
+                    &replay_args.to,
+                    start,
+                    replay_args.transform_filter.as_deref(),
+                    &replay_args.headers,
+                    &replay_args.producer,
+                )?;
+            }
+            cli::TopicCommand::Dump(dump_args) => {
+                kafka::dump_topic(brokers, &dump_args.topic, &dump_args.file)?;
+            }
+            cli::TopicCommand::Restore(restore_args) => {
+                kafka::restore_topic(
+                    brokers,
+                    &restore_args.file,
+                    &restore_args.topic,
+                    restore_args.keep_partitions,
+                    &restore_args.producer,
+                )?;
+            }
+            cli::TopicCommand::Seed(seed_args) => {
+                kafka::seed_topic(
+                    brokers,
+                    &seed_args.topic,
+                    &seed_args.template,
+                    seed_args.key.as_deref(),
+                    seed_args.count,
+                    &seed_args.headers,
+                    &seed_args.producer,
+                )?;
+            }
+            cli::TopicCommand::LargeMessages(args) => {
+                kafka::find_large_messages(
+                    brokers,
+                    &args.topic,
+                    args.threshold,
+                    args.sample,
+                    args.from,
+                    args.limit,
+                )?;
+            }
+            cli::TopicCommand::Idle(idle_args) => {
+                kafka::get_idle_topics(brokers, idle_args.since)?;
+            }
+            cli::TopicCommand::WatchMeta(watch_args) => {
+                kafka::watch_topic_meta(brokers, &watch_args.topic, watch_args.interval)?;
+            }
+            cli::TopicCommand::Window(window_args) => {
+                kafka::window_topic(
+                    brokers,
+                    &window_args.topic,
+                    window_args.from,
+                    window_args.to,
+                    window_args.output.as_deref(),
+                )?;
+            }
+            cli::TopicCommand::Produce(produce_args) => {
+                let mut record = produce::DraftRecord {
+                    key: produce_args.key.clone(),
+                    headers: produce_args.headers.clone(),
+                    value: produce_args
+                        .value
+                        .clone()
+                        .map_or_else(|| serde_json::json!({}), serde_json::Value::String),
+                };
+                if produce_args.edit {
+                    match produce::edit_draft(&record)? {
+                        Some(edited) => record = edited,
+                        None => {
+                            output::info("Nothing was produced");
+                            return Ok(());
+                        }
+                    }
+                }
+                kafka::produce_record(
+                    brokers,
+                    &produce_args.topic,
+                    &record,
+                    produce_args.partition,
+                    produce_args.repeat,
+                    produce_args.interval,
+                    &produce_args.producer,
+                )?;
+            }
+            cli::TopicCommand::Watermarks(watermarks_args) => {
+                kafka::get_topic_watermarks(
+                    brokers,
+                    &watermarks_args.topic,
+                    watermarks_args.output,
+                )?;
+            }
+            cli::TopicCommand::DiffConfigs(diff_args) => {
+                kafka::get_topic_config_diff(brokers, &diff_args.topics)?;
+            }
+        },
         cli::Command::Brokers(args) => {
-            let config_file = get_config_file()?;
-            let env = get_active_environment(config_file)?;
-            if args.list {
-                kafka::get_broker_detail(&env.brokers)?;
+            if let Some(command) = &args.command {
+                match command {
+                    cli::BrokerCommand::Balance(balance_args) => {
+                        kafka::get_broker_balance(brokers, balance_args.threshold)?;
+                    }
+                    cli::BrokerCommand::DiffConfigs(diff_args) => {
+                        kafka::get_broker_config_diff(brokers, &diff_args.brokers)?;
+                    }
+                    cli::BrokerCommand::Details(details_args) => {
+                        kafka::get_single_broker_detail(brokers, details_args.id)?;
+                    }
+                }
+            } else if args.list {
+                kafka::get_broker_detail(brokers)?;
             } else {
                 //#FIXME: Should return an error here
                 eprintln!("Invalid command, use -l flag to list brokers");
             }
         }
         cli::Command::Consumer(group_command) => {
-            let config_file = get_config_file()?;
-            let env = get_active_environment(config_file)?;
+            if let Some(command) = &group_command.command {
+                match command {
+                    cli::ConsumerCommand::Lag(args) => {
+                        kafka::export_group_lag(
+                            brokers,
+                            args.group.as_deref(),
+                            args.output,
+                            args.file.as_deref(),
+                        )?;
+                    }
+                    cli::ConsumerCommand::Record(args) => {
+                        kafka::record_group_lag(brokers, &args.group, args.interval, &args.file)?;
+                    }
+                    cli::ConsumerCommand::Chart(_) => {}
+                    cli::ConsumerCommand::TranslateOffsets(args) => {
+                        if args.target_env.starts_with('@') {
+                            return Err("The target has to be a single environment".into());
+                        }
+                        // KFCLI_ variables describe the source cluster, so the target is taken
+                        // from the config file as it is
+                        let environments =
+                            select_environments(read_environments()?, &args.target_env)?;
+                        let target = environments[0].1.clone();
+                        if target.brokers == brokers {
+                            return Err(format!(
+                                "The target environment {} uses the same brokers as the source",
+                                args.target_env
+                            )
+                            .into());
+                        }
+                        kafka::translate_group_offsets(brokers, &args.group, &target, args.output)?;
+                    }
+                    cli::ConsumerCommand::Members(args) => {
+                        if args.watch {
+                            kafka::watch_group_members(brokers, &args.group, args.interval)?;
+                        } else {
+                            kafka::get_group_members(brokers, &args.group)?;
+                        }
+                    }
+                    cli::ConsumerCommand::Top(args) => {
+                        kafka::get_top_lagging_groups(brokers, args.limit)?;
+                    }
+                    cli::ConsumerCommand::SetOffset(args) => {
+                        kafka::set_group_offset(
+                            brokers,
+                            &args.group,
+                            &args.topic,
+                            args.partition,
+                            args.offset,
+                            args.yes,
+                        )?;
+                    }
+                    cli::ConsumerCommand::RemoveMember(args) => {
+                        kafka::remove_group_members(brokers, &args.group, &args.instance_id)?;
+                    }
+                    cli::ConsumerCommand::Orphans(args) => {
+                        kafka::find_orphan_groups(brokers, args.older_than, args.delete, args.yes)?;
+                    }
+                    cli::ConsumerCommand::Create(args) => {
+                        let start = match args.start {
+                            cli::GroupStart::Earliest => kafka::StartPosition::Beginning,
+                            cli::GroupStart::Latest => kafka::StartPosition::End,
+                            cli::GroupStart::Timestamp(timestamp) => {
+                                kafka::StartPosition::Timestamp(timestamp)
+                            }
+                        };
+                        kafka::create_group(brokers, &args.group, &args.topic, start, args.yes)?;
+                    }
+                    cli::ConsumerCommand::ResetOffsets(args) => {
+                        let target = match args.to_datetime {
+                            Some(timestamp) => kafka::StartPosition::Timestamp(timestamp),
+                            None if args.to_latest => kafka::StartPosition::End,
+                            None => kafka::StartPosition::Beginning,
+                        };
+                        kafka::reset_group_offsets(
+                            brokers,
+                            &args.group,
+                            &args.topic,
+                            target,
+                            args.yes,
+                        )?;
+                    }
+                }
+                return Ok(());
+            }
             if group_command.list {
-                kafka::get_consumer_groups(&env.brokers)?;
+                kafka::get_consumer_groups(
+                    brokers,
+                    kafka::GroupFilter {
+                        state: group_command.state,
+                        topic: group_command.topic.as_deref(),
+                        with_lag: group_command.with_lag,
+                        show_kfcli_groups: group_command.show_kfcli_groups,
+                    },
+                )?;
                 return Ok(());
             }
-            match group_command.consumer {
+            match &group_command.consumer {
                 Some(group) => {
-                    kafka::get_consumers_group_details(&env.brokers, group, false)?;
+                    kafka::get_consumers_group_details(
+                        brokers,
+                        group.clone(),
+                        group_command.pending,
+                        group_command.samples,
+                        group_command.interval,
+                    )?;
                 }
                 None => {
-                    //#FIXME: Should return an error here
-                    eprintln!("Either specify -g or -l flag");
+                    return Err(
+                        "Either specify -c or -l, or run on a terminal to pick a group".into(),
+                    );
                 }
             }
         }
-        cli::Command::Completion(args) => match generate_completion(args.shell) {
-            Ok(_) => {
-                println!("Completion generated successfully");
+        cli::Command::Health(args) => match &args.command {
+            cli::HealthCommand::MinIsr => kafka::get_min_isr_report(brokers)?,
+        },
+        cli::Command::Dlq(args) => match &args.command {
+            cli::DlqCommand::Inspect(inspect_args) => {
+                let start = match inspect_args.since {
+                    Some(timestamp) => kafka::StartPosition::Timestamp(timestamp),
+                    None => kafka::StartPosition::Beginning,
+                };
+                kafka::inspect_dlq(
+                    brokers,
+                    &inspect_args.topic,
+                    start,
+                    inspect_args.error_header.as_deref(),
+                )?;
+            }
+            cli::DlqCommand::Replay(replay_args) => {
+                let start = match replay_args.since {
+                    Some(timestamp) => kafka::StartPosition::Timestamp(timestamp),
+                    None => kafka::StartPosition::Beginning,
+                };
+                let selection = kafka::DlqSelection {
+                    error: replay_args.error.as_deref(),
+                    error_header: replay_args.error_header.as_deref(),
+                    source_partition: replay_args.source_partition,
+                    filter: replay_args.filter.as_deref(),
+                };
+                kafka::replay_dlq(
+                    brokers,
+                    &replay_args.topic,
+                    &replay_args.to,
+                    start,
+                    selection,
+                    &replay_args.headers,
+                    &replay_args.producer,
+                )?;
+            }
+        },
+        cli::Command::Admin(args) => match &args.command {
+            cli::AdminCommand::Loggers(loggers_args) => {
+                kafka::get_broker_loggers(brokers, loggers_args.broker, &loggers_args.set)?
+            }
+            cli::AdminCommand::CreateTopic(create_args) => kafka::create_topic(
+                brokers,
+                &create_args.topic,
+                create_args.partitions,
+                create_args.replication_factor,
+                create_args.assignment.as_ref().map(|a| a.0.as_slice()),
+                &create_args.config,
+                create_args.if_not_exists,
+            )?,
+            cli::AdminCommand::DeleteTopic(delete_args) => kafka::delete_topic(
+                brokers,
+                required(&delete_args.topic, "--topic")?,
+                delete_args.if_exists,
+                delete_args.yes,
+            )?,
+            cli::AdminCommand::SetReplication(replication_args) => kafka::set_replication_factor(
+                brokers,
+                required(&replication_args.topic, "--topic")?,
+                replication_args.factor,
+                replication_args.yes,
+            )?,
+            cli::AdminCommand::DeleteTopics(delete_args) => kafka::delete_topics_matching(
+                brokers,
+                &delete_args.regex,
+                delete_args.batch_size,
+                delete_args.yes,
+            )?,
+            cli::AdminCommand::Plan(plan_args) => kafka::plan_topics(brokers, &plan_args.file)?,
+            cli::AdminCommand::Quotas(quotas_args) => match &quotas_args.command {
+                cli::QuotasCommand::Usage(usage_args) => kafka::get_quota_usage(
+                    brokers,
+                    usage_args.impersonate,
+                    usage_args.topic.as_deref(),
+                    usage_args.duration,
+                )?,
+            },
+        },
+        cli::Command::Cluster(args) => match &args.command {
+            cli::ClusterCommand::Backup(backup_args) => {
+                kafka::backup_cluster(brokers, &backup_args.file, backup_args.skip_acls)?
+            }
+            cli::ClusterCommand::Restore(restore_args) => kafka::restore_cluster(
+                brokers,
+                &restore_args.file,
+                restore_args.dry_run,
+                restore_args.yes,
+            )?,
+        },
+        cli::Command::Acl(args) => match &args.command {
+            cli::AclCommand::Apply(apply_args) => kafka::apply_acls(
+                brokers,
+                &apply_args.file,
+                apply_args.prune,
+                apply_args.dry_run,
+                apply_args.yes,
+            )?,
+            cli::AclCommand::Whoami(whoami_args) => {
+                kafka::show_principal_acls(brokers, &whoami_args.principal)?
+            }
+        },
+        cli::Command::Serve(args) => serve::serve(brokers, &args.address)?,
+        cli::Command::Probe(args) => match &args.command {
+            cli::ProbeCommand::Brokers(probe_args) => kafka::probe_brokers(
+                brokers,
+                &probe_args.topic,
+                probe_args.count,
+                probe_args.acks,
+                probe_args.timeout,
+            )?,
+        },
+        cli::Command::Simulate(args) => match &args.command {
+            cli::SimulateCommand::Consume(consume_args) => {
+                kafka::simulate_consume(
+                    brokers,
+                    &consume_args.topic,
+                    &consume_args.group,
+                    consume_args.rate,
+                    consume_args.commit_interval,
+                )?;
             }
-            Err(e) => eprintln!("Error generating completion: {}", e),
         },
+        cli::Command::Config(_) | cli::Command::Completion(_) | cli::Command::Audit(_) => {}
     }
     Ok(())
 }
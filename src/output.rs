@@ -0,0 +1,695 @@
+use std::{
+    env,
+    fmt::Display,
+    io::{self, IsTerminal, Write},
+    process::{Command, Stdio},
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local, Utc};
+use prettytable::{Cell, Row, Table};
+use terminal_size::{terminal_size, Height, Width};
+
+use crate::cli::TimeFormat;
+
+/// How command output is rendered, set once from the global CLI flags.
+#[derive(Debug, Clone, Default)]
+pub struct OutputOptions {
+    pub no_pager: bool,
+    pub columns: Vec<String>,
+    pub sort_by: Option<String>,
+    pub descending: bool,
+    pub human: bool,
+    pub time_format: Option<TimeFormat>,
+    pub quiet: bool,
+}
+
+static OPTIONS: OnceLock<OutputOptions> = OnceLock::new();
+
+pub fn init(options: OutputOptions) {
+    let _ = OPTIONS.set(options);
+}
+
+fn options() -> &'static OutputOptions {
+    OPTIONS.get_or_init(OutputOptions::default)
+}
+
+/// Prints a confirmation or note that scripts can do without. Silenced by `--quiet`.
+pub fn info(message: impl Display) {
+    if !options().quiet {
+        println!("{}", message);
+    }
+}
+
+/// Prints a status line to stderr. Silenced by `--quiet`.
+pub fn status(message: impl Display) {
+    if !options().quiet {
+        eprintln!("{}", message);
+    }
+}
+
+/// Asks a yes/no question on stderr and reads the answer from stdin. Anything but `y` or `yes`
+/// declines, as does a closed stdin.
+pub fn confirm(question: impl Display) -> bool {
+    eprint!("{} (y/n) ", question);
+    let _ = io::stderr().flush();
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Progress of a long scan, redrawn on stderr at most a few times a second with a bar and ETA
+/// when the total is known. Nothing is drawn when stderr is not a terminal or with `--quiet`.
+pub struct Progress {
+    label: String,
+    total: Option<u64>,
+    started: Instant,
+    visible: bool,
+    state: Mutex<ProgressState>,
+}
+
+struct ProgressState {
+    done: u64,
+    last_draw: Option<Instant>,
+}
+
+impl Progress {
+    pub fn new(label: &str, total: Option<u64>) -> Self {
+        Progress {
+            label: label.to_string(),
+            total,
+            started: Instant::now(),
+            visible: !options().quiet && io::stderr().is_terminal(),
+            state: Mutex::new(ProgressState {
+                done: 0,
+                last_draw: None,
+            }),
+        }
+    }
+
+    pub fn inc(&self, amount: u64) {
+        let mut state = self.state.lock().expect("Progress lock poisoned");
+        state.done += amount;
+        let due = state
+            .last_draw
+            .is_none_or(|last| last.elapsed() >= Duration::from_millis(200));
+        if self.visible && due {
+            eprint!("\r{}", self.render(state.done));
+            state.last_draw = Some(Instant::now());
+        }
+    }
+
+    /// Draws the final state and moves to a new line.
+    pub fn finish(&self) {
+        let state = self.state.lock().expect("Progress lock poisoned");
+        if self.visible {
+            eprintln!("\r{}", self.render(state.done));
+        }
+    }
+
+    fn render(&self, done: u64) -> String {
+        render_progress(&self.label, done, self.total, self.started.elapsed())
+    }
+}
+
+/// A status line kept at the bottom of the terminal on stderr while output scrolls above it.
+/// Callers clear it before printing and draw it again afterwards. Nothing is drawn when stderr
+/// is not a terminal or with `--quiet`.
+pub struct Footer {
+    visible: bool,
+    text: String,
+    drawn: bool,
+}
+
+impl Footer {
+    pub fn new() -> Self {
+        Footer {
+            visible: !options().quiet && io::stderr().is_terminal(),
+            text: String::new(),
+            drawn: false,
+        }
+    }
+
+    /// Erases the line, so that what is printed next takes its place.
+    pub fn clear(&mut self) {
+        if self.drawn {
+            eprint!("\r\x1b[2K");
+            self.drawn = false;
+        }
+    }
+
+    /// Draws `text`, cut to the terminal width so it stays on one line.
+    pub fn update(&mut self, text: String) {
+        let width = terminal_size().map_or(usize::MAX, |(Width(width), _)| width as usize);
+        self.clear();
+        self.text = text.chars().take(width.saturating_sub(1)).collect();
+        self.redraw();
+    }
+
+    /// Draws the last text again below what was printed since it was cleared.
+    pub fn redraw(&mut self) {
+        if self.visible && !self.text.is_empty() {
+            let _ = io::stdout().flush();
+            eprint!("\r\x1b[2K{}", self.text);
+            let _ = io::stderr().flush();
+            self.drawn = true;
+        }
+    }
+}
+
+fn render_progress(label: &str, done: u64, total: Option<u64>, elapsed: Duration) -> String {
+    const WIDTH: usize = 20;
+    match total {
+        Some(total) if total > 0 => {
+            let ratio = (done as f64 / total as f64).min(1.0);
+            let filled = (ratio * WIDTH as f64).round() as usize;
+            let eta = if done > 0 && done < total {
+                let remaining = elapsed.as_secs_f64() * (total - done) as f64 / done as f64;
+                format!(" ETA {}s", remaining.ceil() as u64)
+            } else {
+                String::new()
+            };
+            format!(
+                "{} [{}{}] {}/{} {:.0}%{}",
+                label,
+                "#".repeat(filled),
+                " ".repeat(WIDTH - filled),
+                done,
+                total,
+                ratio * 100.0,
+                eta
+            )
+        }
+        _ => format!("{} {} ({}s)", label, done, elapsed.as_secs()),
+    }
+}
+
+/// A message count, shortened to `1.2M` in human mode.
+pub fn count(value: i64) -> String {
+    if options().human {
+        format_count(value)
+    } else {
+        value.to_string()
+    }
+}
+
+/// A span of time given in milliseconds, shown as `47.2m` in human mode.
+pub fn elapsed(millis: i64) -> String {
+    if options().human {
+        format_elapsed(millis)
+    } else {
+        millis.to_string()
+    }
+}
+
+/// A size in bytes, shown as `3.4 GiB` in human mode.
+pub fn bytes(value: u64) -> String {
+    if options().human {
+        format_bytes(value)
+    } else {
+        value.to_string()
+    }
+}
+
+/// A config value, with `*.ms` durations and `*.bytes` sizes made readable in human mode.
+pub fn config_value(name: &str, value: &str) -> String {
+    if options().human {
+        format_config_value(name, value)
+    } else {
+        value.to_string()
+    }
+}
+
+/// A point in time given in epoch milliseconds, in the chosen `--time-format`.
+pub fn timestamp(millis: i64) -> String {
+    format_timestamp(
+        millis,
+        options().time_format.unwrap_or_default(),
+        Utc::now().timestamp_millis(),
+    )
+}
+
+/// The current time, for prefixing lines of commands that keep running.
+pub fn now() -> String {
+    timestamp(Utc::now().timestamp_millis())
+}
+
+/// Whether record timestamps were asked for with `--time-format`.
+pub fn show_record_time() -> bool {
+    options().time_format.is_some()
+}
+
+fn format_timestamp(millis: i64, format: TimeFormat, now_millis: i64) -> String {
+    let Some(time) = DateTime::<Utc>::from_timestamp_millis(millis) else {
+        return millis.to_string();
+    };
+    match format {
+        TimeFormat::Utc => time.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
+        TimeFormat::Local => time
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string(),
+        TimeFormat::Epoch => millis.to_string(),
+        TimeFormat::Relative => {
+            const UNITS: [(i64, &str); 3] = [(86_400, "d"), (3_600, "h"), (60, "m")];
+            let seconds = (now_millis - millis) / 1000;
+            let magnitude = seconds.abs();
+            let amount = UNITS
+                .iter()
+                .find(|(size, _)| magnitude >= *size)
+                .map(|(size, unit)| format!("{}{}", magnitude / size, unit))
+                .unwrap_or_else(|| format!("{}s", magnitude));
+            if seconds < 0 {
+                format!("in {}", amount)
+            } else {
+                format!("{} ago", amount)
+            }
+        }
+    }
+}
+
+fn format_count(value: i64) -> String {
+    const UNITS: [(f64, &str); 3] = [(1e9, "B"), (1e6, "M"), (1e3, "K")];
+    let magnitude = value.unsigned_abs() as f64;
+    UNITS
+        .iter()
+        .find(|(size, _)| magnitude >= *size)
+        .map(|(size, unit)| format!("{:.1}{}", value as f64 / size, unit))
+        .unwrap_or_else(|| value.to_string())
+}
+
+fn format_bytes(value: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if value < 1024 {
+        return format!("{} B", value);
+    }
+    let mut size = value as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Shows milliseconds in the largest unit they reach, to one decimal, or in whole seconds under
+/// a minute.
+fn format_elapsed(value: i64) -> String {
+    const UNITS: [(f64, &str); 3] = [(86_400e3, "d"), (3_600e3, "h"), (60e3, "m")];
+    let magnitude = value.unsigned_abs() as f64;
+    UNITS
+        .iter()
+        .find(|(size, _)| magnitude >= *size)
+        .map(|(size, unit)| format!("{:.1}{}", value as f64 / size, unit))
+        .unwrap_or_else(|| format!("{}s", value / 1000))
+}
+
+/// Shows milliseconds in the largest unit that divides them evenly, e.g. `7d` or `90s`.
+fn format_duration_ms(value: i64) -> String {
+    const UNITS: [(i64, &str); 4] = [
+        (24 * 60 * 60 * 1000, "d"),
+        (60 * 60 * 1000, "h"),
+        (60 * 1000, "m"),
+        (1000, "s"),
+    ];
+    if value <= 0 {
+        return value.to_string();
+    }
+    UNITS
+        .iter()
+        .find(|(size, _)| value % size == 0)
+        .map(|(size, unit)| format!("{}{}", value / size, unit))
+        .unwrap_or_else(|| format!("{}ms", value))
+}
+
+fn format_config_value(name: &str, value: &str) -> String {
+    if name.ends_with(".ms") {
+        if let Ok(ms) = value.parse() {
+            return format_duration_ms(ms);
+        }
+    }
+    if name.ends_with(".bytes") {
+        if let Ok(size) = value.parse() {
+            return format_bytes(size);
+        }
+    }
+    value.to_string()
+}
+
+/// Reads back a number written by the human formatters so sorting stays numeric. Byte sizes
+/// are set apart from their unit, as in `512 B`, while counts and durations are not, as in
+/// `1.2B` for 1.2 billion.
+fn parse_number(text: &str) -> Option<f64> {
+    let text = text.trim();
+    if let Ok(value) = text.parse() {
+        return Some(value);
+    }
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-' || c == '+'))
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let multiplier = if unit.starts_with(' ') {
+        match unit.trim() {
+            "B" => 1.0,
+            "KiB" => 1024f64,
+            "MiB" => 1024f64.powi(2),
+            "GiB" => 1024f64.powi(3),
+            "TiB" => 1024f64.powi(4),
+            "PiB" => 1024f64.powi(5),
+            _ => return None,
+        }
+    } else {
+        match unit {
+            "K" => 1e3,
+            "M" => 1e6,
+            "B" => 1e9,
+            "ms" => 1.0,
+            "s" => 1e3,
+            "m" => 60e3,
+            "h" => 3600e3,
+            "d" => 86400e3,
+            _ => return None,
+        }
+    };
+    Some(number * multiplier)
+}
+
+/// One CSV line without the line break, quoting fields that hold a comma, quote or line break.
+pub fn csv_line<S: AsRef<str>>(fields: &[S]) -> String {
+    fields
+        .iter()
+        .map(|field| {
+            let field = field.as_ref();
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+/// Prints a table, through a pager when it does not fit on the terminal.
+pub fn print_table(table: &Table) {
+    let options = options();
+    let sorted = options
+        .sort_by
+        .as_ref()
+        .and_then(|column| sort_rows(table, column, options.descending));
+    let table = sorted.as_ref().unwrap_or(table);
+    match select_columns(table, &options.columns) {
+        Some(selected) => print_rendered(&selected),
+        None => print_rendered(table),
+    }
+}
+
+fn print_rendered(table: &Table) {
+    if options().no_pager || !io::stdout().is_terminal() {
+        table.printstd();
+        return;
+    }
+    let rendered = table.to_string();
+    match terminal_size() {
+        Some((_, Height(height))) if exceeds_height(&rendered, height) => {
+            if page(&rendered).is_err() {
+                table.printstd();
+            }
+        }
+        _ => {
+            table.printstd();
+        }
+    }
+}
+
+/// Whether a column name given on the command line names a header: the header in lower case
+/// with dashes for spaces (`partition-id`), one of its words (`id`), or the initials of a header
+/// of several words (`rf`).
+fn column_matches(selector: &str, header: &str) -> bool {
+    let selector = selector.trim().to_lowercase();
+    let words: Vec<String> = header
+        .split_whitespace()
+        .map(|w| w.to_lowercase())
+        .collect();
+    if selector == words.join("-") || words.contains(&selector) {
+        return true;
+    }
+    words.len() > 1
+        && selector
+            == words
+                .iter()
+                .filter_map(|w| w.chars().next())
+                .collect::<String>()
+}
+
+/// Keeps the columns named in `columns`, in that order, using the first row as header. Returns
+/// `None` when nothing is selected or none of the columns belong to this table.
+fn select_columns(table: &Table, columns: &[String]) -> Option<Table> {
+    let header = table.get_row(0)?;
+    let indices: Vec<usize> = columns
+        .iter()
+        .filter_map(|column| {
+            (0..header.len()).find(|&i| {
+                header
+                    .get_cell(i)
+                    .is_some_and(|cell| column_matches(column, &cell.get_content()))
+            })
+        })
+        .collect();
+    if indices.is_empty() {
+        return None;
+    }
+
+    let mut selected = Table::new();
+    for row in table.row_iter() {
+        let cells = indices
+            .iter()
+            .map(|&i| row.get_cell(i).cloned().unwrap_or_else(|| Cell::new("")))
+            .collect();
+        selected.add_row(Row::new(cells));
+    }
+    Some(selected)
+}
+
+/// Sorts the rows below the header by `column`, numerically when both values are numbers.
+/// Returns `None` when the table has no such column.
+fn sort_rows(table: &Table, column: &str, descending: bool) -> Option<Table> {
+    let header = table.get_row(0)?;
+    let index = (0..header.len()).find(|&i| {
+        header
+            .get_cell(i)
+            .is_some_and(|cell| column_matches(column, &cell.get_content()))
+    })?;
+
+    let content = |row: &Row| {
+        row.get_cell(index)
+            .map(|c| c.get_content())
+            .unwrap_or_default()
+    };
+    let mut rows: Vec<Row> = table.row_iter().skip(1).cloned().collect();
+    rows.sort_by(|a, b| {
+        let (a, b) = (content(a), content(b));
+        let ordering = match (parse_number(&a), parse_number(&b)) {
+            (Some(a), Some(b)) => a.total_cmp(&b),
+            _ => a.cmp(&b),
+        };
+        if descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+
+    let mut sorted = Table::new();
+    sorted.add_row(header.clone());
+    for row in rows {
+        sorted.add_row(row);
+    }
+    Some(sorted)
+}
+
+fn exceeds_height(text: &str, height: u16) -> bool {
+    // Keep a line free for the shell prompt
+    text.lines().count() >= height as usize
+}
+
+/// Hands `text` to `$PAGER`, or `less` when it is not set.
+fn page(text: &str) -> io::Result<()> {
+    let pager = env::var("PAGER")
+        .ok()
+        .filter(|p| !p.trim().is_empty())
+        .unwrap_or_else(|| "less -FRX".to_string());
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(&pager)
+        .stdin(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // The user may quit the pager before reading everything
+        let _ = stdin.write_all(text.as_bytes());
+    }
+    child.wait()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use prettytable::{row, Table};
+
+    use super::exceeds_height;
+
+    #[test]
+    fn test_csv_line() {
+        assert_eq!(super::csv_line(&["orders", "12"]), "orders,12");
+        assert_eq!(
+            super::csv_line(&["a,b", "say \"hi\"", ""]),
+            "\"a,b\",\"say \"\"hi\"\"\","
+        );
+    }
+
+    #[test]
+    fn test_select_columns() {
+        let mut table = Table::new();
+        table.add_row(row!["Topic", "Partitions", "Replication Factor"]);
+        table.add_row(row!["orders", 12, 3]);
+
+        let columns = vec!["rf".to_string(), "topic".to_string()];
+        let selected = super::select_columns(&table, &columns).unwrap();
+        let cell = |row: usize, column: usize| {
+            selected
+                .get_row(row)
+                .unwrap()
+                .get_cell(column)
+                .unwrap()
+                .get_content()
+        };
+        assert_eq!(cell(0, 0), "Replication Factor");
+        assert_eq!(cell(1, 0), "3");
+        assert_eq!(cell(1, 1), "orders");
+
+        assert!(super::select_columns(&table, &["lag".to_string()]).is_none());
+        assert!(super::column_matches(
+            "replication-factor",
+            "Replication Factor"
+        ));
+        assert!(super::column_matches("partition-id", "Partition ID"));
+        assert!(super::column_matches("id", "Broker ID"));
+        assert!(!super::column_matches("p", "Partitions"));
+    }
+
+    #[test]
+    fn test_sort_rows() {
+        let mut table = Table::new();
+        table.add_row(row!["Group ID", "State", "Lag"]);
+        table.add_row(row!["billing", "Stable", 120]);
+        table.add_row(row!["audit", "Empty", 9]);
+        table.add_row(row!["orders", "Stable", 4500]);
+
+        let first_column = |table: &Table| -> Vec<String> {
+            table
+                .row_iter()
+                .skip(1)
+                .map(|row| row.get_cell(0).unwrap().get_content())
+                .collect()
+        };
+        let by_lag = super::sort_rows(&table, "lag", true).unwrap();
+        assert_eq!(first_column(&by_lag), vec!["orders", "billing", "audit"]);
+        let by_name = super::sort_rows(&table, "group-id", false).unwrap();
+        assert_eq!(first_column(&by_name), vec!["audit", "billing", "orders"]);
+        assert!(super::sort_rows(&table, "host", false).is_none());
+
+        let mut sizes = Table::new();
+        sizes.add_row(row!["Topic", "Size"]);
+        sizes.add_row(row!["orders", "1.2 KiB"]);
+        sizes.add_row(row!["audit", "512 B"]);
+        sizes.add_row(row!["events", "3.4 GiB"]);
+        let by_size = super::sort_rows(&sizes, "size", false).unwrap();
+        assert_eq!(first_column(&by_size), vec!["audit", "orders", "events"]);
+        assert_eq!(super::parse_number("1.2B"), Some(1.2e9));
+    }
+
+    #[test]
+    fn test_human_formats() {
+        assert_eq!(super::format_count(999), "999");
+        assert_eq!(super::format_count(1_234_567), "1.2M");
+        assert_eq!(super::format_count(-45_000), "-45.0K");
+        assert_eq!(super::format_bytes(512), "512 B");
+        assert_eq!(super::format_bytes(3_650_722_202), "3.4 GiB");
+        assert_eq!(super::format_duration_ms(604_800_000), "7d");
+        assert_eq!(super::format_duration_ms(90_000), "90s");
+        assert_eq!(super::format_duration_ms(-1), "-1");
+        assert_eq!(super::format_elapsed(2_832_000), "47.2m");
+        assert_eq!(super::format_elapsed(5_400_000), "1.5h");
+        assert_eq!(super::format_elapsed(42_500), "42s");
+        assert_eq!(super::format_config_value("retention.ms", "86400000"), "1d");
+        assert_eq!(
+            super::format_config_value("segment.bytes", "1073741824"),
+            "1.0 GiB"
+        );
+        assert_eq!(
+            super::format_config_value("cleanup.policy", "delete"),
+            "delete"
+        );
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        use crate::cli::TimeFormat;
+
+        let millis = 1_714_557_600_000;
+        assert_eq!(
+            super::format_timestamp(millis, TimeFormat::Utc, millis),
+            "2024-05-01T10:00:00.000Z"
+        );
+        assert_eq!(
+            super::format_timestamp(millis, TimeFormat::Epoch, millis),
+            "1714557600000"
+        );
+        assert_eq!(
+            super::format_timestamp(millis, TimeFormat::Relative, millis + 5_400_000),
+            "1h ago"
+        );
+        assert_eq!(
+            super::format_timestamp(millis, TimeFormat::Relative, millis - 30_000),
+            "in 30s"
+        );
+    }
+
+    #[test]
+    fn test_parse_number() {
+        assert_eq!(super::parse_number("42"), Some(42.0));
+        assert_eq!(super::parse_number("1.5K"), Some(1500.0));
+        assert_eq!(super::parse_number("2.0 KiB"), Some(2048.0));
+        assert_eq!(super::parse_number("7d"), Some(604_800_000.0));
+        assert_eq!(super::parse_number("Stable"), None);
+    }
+
+    #[test]
+    fn test_render_progress() {
+        use std::time::Duration;
+
+        assert_eq!(
+            super::render_progress("Dumping", 25, Some(100), Duration::from_secs(10)),
+            "Dumping [#####               ] 25/100 25% ETA 30s"
+        );
+        assert_eq!(
+            super::render_progress("Dumping", 100, Some(100), Duration::from_secs(40)),
+            "Dumping [####################] 100/100 100%"
+        );
+        assert_eq!(
+            super::render_progress("Restoring", 7, None, Duration::from_secs(3)),
+            "Restoring 7 (3s)"
+        );
+    }
+
+    #[test]
+    fn test_exceeds_height() {
+        assert!(!exceeds_height("a\nb\nc\n", 10));
+        assert!(exceeds_height("a\nb\nc\n", 3));
+        assert!(!exceeds_height("", 1));
+    }
+}
@@ -0,0 +1,31 @@
+use std::io::{self, IsTerminal};
+
+use dialoguer::{theme::ColorfulTheme, FuzzySelect};
+
+use crate::kafka::KafkaError;
+
+/// Rows of the picker shown at once, the rest scroll.
+const VISIBLE_ITEMS: usize = 15;
+
+/// Whether there is someone at a terminal to pick from a list.
+pub fn is_interactive() -> bool {
+    io::stdin().is_terminal() && io::stderr().is_terminal()
+}
+
+/// Lets the user narrow `items` down by typing parts of a name and pick one, `None` when they
+/// press Esc. The picker is drawn on stderr so that it does not end up in piped output.
+pub fn pick(prompt: &str, items: &[String]) -> Result<Option<String>, KafkaError> {
+    if items.is_empty() {
+        return Err(KafkaError::Generic(format!(
+            "Nothing to pick a {} from",
+            prompt.to_lowercase()
+        )));
+    }
+    let picked = FuzzySelect::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .items(items)
+        .max_length(VISIBLE_ITEMS)
+        .interact_opt()
+        .map_err(|er| KafkaError::Generic(format!("Error while picking a {}: {}", prompt, er)))?;
+    Ok(picked.map(|index| items[index].clone()))
+}
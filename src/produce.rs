@@ -0,0 +1,278 @@
+use std::{env, fs, io::Write, process};
+
+use rdkafka::message::{Header, Headers, OwnedHeaders};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    kafka::KafkaError,
+    output,
+    template::{RecordTemplate, TextTemplate},
+};
+
+/// A record to produce. `topics produce --edit` opens it in the editor as JSON; headers are
+/// `[name, value]` pairs, a string value is sent as it is and any other JSON value is sent
+/// serialized, `null` being a tombstone.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct DraftRecord {
+    pub key: Option<String>,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    pub value: Value,
+}
+
+impl DraftRecord {
+    pub fn value_bytes(&self) -> Option<Vec<u8>> {
+        match &self.value {
+            Value::Null => None,
+            Value::String(value) => Some(value.as_bytes().to_vec()),
+            value => Some(value.to_string().into_bytes()),
+        }
+    }
+
+    pub fn owned_headers(&self) -> OwnedHeaders {
+        owned_headers(&self.headers)
+    }
+}
+
+/// The headers of a replayed record: those of the source record, unless `source` is `None`,
+/// followed by `extra`, which replace source headers of the same name.
+pub fn replay_headers<H: Headers>(source: Option<&H>, extra: &[(String, String)]) -> OwnedHeaders {
+    let mut headers = OwnedHeaders::new();
+    for header in source.iter().flat_map(|source| source.iter()) {
+        if !extra.iter().any(|(name, _)| name == header.key) {
+            headers = headers.insert(header);
+        }
+    }
+    insert_headers(headers, extra)
+}
+
+/// `headers` as the headers of a new record.
+pub fn owned_headers(headers: &[(String, String)]) -> OwnedHeaders {
+    insert_headers(OwnedHeaders::new_with_capacity(headers.len()), headers)
+}
+
+fn insert_headers(headers: OwnedHeaders, extra: &[(String, String)]) -> OwnedHeaders {
+    extra.iter().fold(headers, |headers, (key, value)| {
+        headers.insert(Header {
+            key,
+            value: Some(value.as_bytes()),
+        })
+    })
+}
+
+/// A record whose key, header values and value strings may hold the placeholders of
+/// `template`, such as `{{seq}}` and `{{now}}`, rendered anew for each record produced.
+pub struct DraftTemplate {
+    key: Option<TextTemplate>,
+    headers: Vec<(String, TextTemplate)>,
+    value: RecordTemplate,
+}
+
+impl DraftTemplate {
+    pub fn parse(draft: &DraftRecord) -> Result<DraftTemplate, String> {
+        Ok(DraftTemplate {
+            key: draft.key.as_deref().map(TextTemplate::parse).transpose()?,
+            headers: draft
+                .headers
+                .iter()
+                .map(|(name, value)| Ok((name.clone(), TextTemplate::parse(value)?)))
+                .collect::<Result<_, String>>()?,
+            value: RecordTemplate::from_value(draft.value.clone())?,
+        })
+    }
+
+    /// The record numbered `seq`, created at `now` in epoch milliseconds.
+    pub fn render(&self, rng: &mut fastrand::Rng, now: i64, seq: u64) -> DraftRecord {
+        DraftRecord {
+            key: self.key.as_ref().map(|key| key.render(rng, now, seq)),
+            headers: self
+                .headers
+                .iter()
+                .map(|(name, value)| (name.clone(), value.render(rng, now, seq)))
+                .collect(),
+            value: self.value.render(rng, now, seq),
+        }
+    }
+}
+
+/// Reads the record back from the editor, `Ok(None)` when the file was emptied to cancel.
+fn parse_draft(text: &str) -> Result<Option<DraftRecord>, String> {
+    if text.trim().is_empty() {
+        return Ok(None);
+    }
+    serde_json::from_str(text)
+        .map(Some)
+        .map_err(|er| er.to_string())
+}
+
+/// `$VISUAL` or `$EDITOR`, which may carry arguments such as `code --wait`, falling back to vi.
+fn editor_command() -> Vec<String> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_default();
+    let command: Vec<String> = editor.split_whitespace().map(String::from).collect();
+    if command.is_empty() {
+        vec!["vi".to_string()]
+    } else {
+        command
+    }
+}
+
+/// Opens `draft` in the editor until it is saved as a valid record. Returns `None` when the
+/// file is emptied or the user gives up on an invalid record.
+pub fn edit_draft(draft: &DraftRecord) -> Result<Option<DraftRecord>, KafkaError> {
+    // A new file only this user can read, removed when it is dropped
+    let mut file = tempfile::Builder::new()
+        .prefix("kfcli-record-")
+        .suffix(".json")
+        .tempfile()
+        .map_err(|er| KafkaError::Io("Failed to create a file for the record".to_string(), er))?;
+    let path = file.path().to_path_buf();
+    let content = serde_json::to_string_pretty(draft).expect("Records serialize");
+    writeln!(file, "{}", content)
+        .map_err(|er| KafkaError::Io(format!("Failed to write {:?}", path), er))?;
+
+    let command = editor_command();
+    let result = loop {
+        let status = process::Command::new(&command[0])
+            .args(&command[1..])
+            .arg(&path)
+            .status()
+            .map_err(|er| KafkaError::Io(format!("Failed to start editor {}", command[0]), er));
+        match status {
+            Ok(status) if status.success() => {}
+            Ok(status) => {
+                break Err(KafkaError::Generic(format!(
+                    "Editor {} exited with {}, nothing was produced",
+                    command[0], status
+                )))
+            }
+            Err(er) => break Err(er),
+        }
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(er) => break Err(KafkaError::Io(format!("Failed to read {:?}", path), er)),
+        };
+        match parse_draft(&text) {
+            Ok(draft) => break Ok(draft),
+            Err(er) => {
+                eprintln!("Invalid record: {}", er);
+                if !output::confirm("Edit it again?") {
+                    break Ok(None);
+                }
+            }
+        }
+    };
+    drop(file);
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use rdkafka::message::{Header, Headers, OwnedHeaders};
+
+    use super::{parse_draft, replay_headers, DraftRecord, DraftTemplate};
+
+    #[test]
+    fn test_parse_draft() {
+        let draft = parse_draft(
+            r#"{"key": "order-1", "headers": [["source", "cli"], ["source", "retry"]], "value": 5}"#,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(draft.key.as_deref(), Some("order-1"));
+        assert_eq!(
+            draft.headers,
+            vec![
+                ("source".to_string(), "cli".to_string()),
+                ("source".to_string(), "retry".to_string())
+            ]
+        );
+        assert_eq!(draft.owned_headers().count(), 2);
+        assert_eq!(draft.value_bytes().unwrap(), b"5");
+
+        let raw = parse_draft(r#"{"key": null, "value": "plain text"}"#)
+            .unwrap()
+            .unwrap();
+        assert_eq!(raw.value_bytes().unwrap(), b"plain text");
+        let tombstone = DraftRecord {
+            key: Some("order-1".to_string()),
+            headers: Default::default(),
+            value: json!(null),
+        };
+        assert_eq!(tombstone.value_bytes(), None);
+
+        assert_eq!(parse_draft("  \n"), Ok(None));
+        assert!(parse_draft(r#"{"value": 1, "partition": 2}"#).is_err());
+        assert!(parse_draft(r#"{"key": "k", "value": 1"#).is_err());
+    }
+
+    #[test]
+    fn test_render_draft() {
+        let draft = DraftRecord {
+            key: Some("order-{{seq}}".to_string()),
+            headers: [("sent".to_string(), "{{now}}".to_string())].into(),
+            value: json!({"id": "{{seq}}", "lines": ["{{seq}}/{{now}}", 3], "note": "plain"}),
+        };
+        let mut rng = fastrand::Rng::with_seed(7);
+        let rendered = DraftTemplate::parse(&draft)
+            .unwrap()
+            .render(&mut rng, 1714557600000, 7);
+        assert_eq!(rendered.key.as_deref(), Some("order-7"));
+        assert_eq!(rendered.headers[0].1, "1714557600000");
+        assert_eq!(
+            rendered.value,
+            json!({"id": 7, "lines": ["7/1714557600000", 3], "note": "plain"})
+        );
+        let raw = DraftRecord {
+            key: None,
+            headers: Default::default(),
+            value: json!("tick {{seq}}"),
+        };
+        let rendered = DraftTemplate::parse(&raw).unwrap().render(&mut rng, 0, 1);
+        assert_eq!(rendered.value_bytes().unwrap(), b"tick 1");
+
+        let unknown = DraftRecord {
+            key: None,
+            headers: Default::default(),
+            value: json!({"note": "{{other}}"}),
+        };
+        assert!(DraftTemplate::parse(&unknown).is_err());
+    }
+
+    #[test]
+    fn test_replay_headers() {
+        let source = OwnedHeaders::new()
+            .insert(Header {
+                key: "trace-id",
+                value: Some("old"),
+            })
+            .insert(Header {
+                key: "route",
+                value: None::<&str>,
+            });
+        let extra = vec![("trace-id".to_string(), "abc".to_string())];
+        let headers = |headers: OwnedHeaders| {
+            headers
+                .iter()
+                .map(|h| (h.key.to_string(), h.value.map(<[u8]>::to_vec)))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            headers(replay_headers(Some(&source), &extra)),
+            vec![
+                ("route".to_string(), None),
+                ("trace-id".to_string(), Some(b"abc".to_vec())),
+            ]
+        );
+        assert_eq!(
+            headers(replay_headers(None::<&OwnedHeaders>, &extra)),
+            vec![("trace-id".to_string(), Some(b"abc".to_vec()))]
+        );
+        assert_eq!(headers(replay_headers(Some(&source), &[])).len(), 2);
+    }
+}
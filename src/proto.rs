@@ -0,0 +1,111 @@
+use std::{fs, path::Path};
+
+use prost_reflect::{DescriptorPool, DynamicMessage, MessageDescriptor};
+use serde_json::Value;
+
+use crate::kafka::{KafkaError, PayloadDecoder};
+
+/// Decodes protobuf payloads into JSON with a message type from a compiled descriptor set, as
+/// written by `protoc --include_imports --descriptor_set_out`.
+pub struct ProtoDecoder {
+    message: MessageDescriptor,
+}
+
+impl ProtoDecoder {
+    pub fn new(message: MessageDescriptor) -> Self {
+        ProtoDecoder { message }
+    }
+
+    pub fn load(descriptor_set: &Path, message: &str) -> Result<Self, KafkaError> {
+        let bytes = fs::read(descriptor_set).map_err(|er| {
+            KafkaError::Io(
+                format!("Failed to read descriptor set: {:?}", descriptor_set),
+                er,
+            )
+        })?;
+        Self::from_bytes(&bytes, message)
+    }
+
+    fn from_bytes(descriptor_set: &[u8], message: &str) -> Result<Self, KafkaError> {
+        let pool = DescriptorPool::decode(descriptor_set)
+            .map_err(|er| KafkaError::Generic(format!("Invalid descriptor set: {}", er)))?;
+        let message = pool.get_message_by_name(message).ok_or_else(|| {
+            KafkaError::Generic(format!("Message {} is not in the descriptor set", message))
+        })?;
+        Ok(Self::new(message))
+    }
+}
+
+impl PayloadDecoder for ProtoDecoder {
+    fn to_json(&self, payload: &[u8]) -> Result<Value, String> {
+        let message = DynamicMessage::decode(self.message.clone(), payload)
+            .map_err(|er| format!("Invalid {} payload: {}", self.message.full_name(), er))?;
+        serde_json::to_value(&message).map_err(|er| er.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use prost_reflect::{
+        prost::Message,
+        prost_types::{
+            field_descriptor_proto::{Label, Type},
+            DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet,
+        },
+    };
+    use serde_json::json;
+
+    use super::ProtoDecoder;
+    use crate::kafka::PayloadDecoder;
+
+    fn order_descriptor_set() -> Vec<u8> {
+        let field = |name: &str, number: i32, kind: Type| FieldDescriptorProto {
+            name: Some(name.to_string()),
+            json_name: Some(name.to_string()),
+            number: Some(number),
+            label: Some(Label::Optional as i32),
+            r#type: Some(kind as i32),
+            ..Default::default()
+        };
+        FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("order.proto".to_string()),
+                package: Some("com.acme".to_string()),
+                syntax: Some("proto3".to_string()),
+                message_type: vec![DescriptorProto {
+                    name: Some("Order".to_string()),
+                    field: vec![
+                        field("id", 1, Type::String),
+                        field("amount", 2, Type::Int32),
+                    ],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+        }
+        .encode_to_vec()
+    }
+
+    #[test]
+    fn test_proto_to_json() {
+        let decoder = ProtoDecoder::from_bytes(&order_descriptor_set(), "com.acme.Order").unwrap();
+        // id = "o-1", amount = 150
+        let payload = [0x0a, 0x03, b'o', b'-', b'1', 0x10, 0x96, 0x01];
+        assert_eq!(
+            decoder.to_json(&payload).unwrap(),
+            json!({"id": "o-1", "amount": 150})
+        );
+        assert!(decoder.to_json(&[0x0a, 0x09]).is_err());
+    }
+
+    #[test]
+    fn test_proto_unknown_message() {
+        let error = ProtoDecoder::from_bytes(&order_descriptor_set(), "com.acme.Invoice")
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Message com.acme.Invoice is not in the descriptor set"
+        );
+    }
+}
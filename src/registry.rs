@@ -0,0 +1,294 @@
+use std::{cell::RefCell, collections::HashMap};
+
+use prost_reflect::{prost::encoding::decode_varint, FileDescriptor, MessageDescriptor};
+use protox::{
+    file::{ChainFileResolver, File, FileResolver, GoogleFileResolver},
+    Compiler,
+};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{avro::AvroDecoder, kafka::PayloadDecoder, proto::ProtoDecoder};
+
+/// First byte of the Schema Registry wire format, followed by the schema id as a big-endian u32
+const MAGIC_BYTE: u8 = 0;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RegisteredSchema {
+    schema: String,
+    /// Missing for Avro schemas
+    schema_type: Option<String>,
+    #[serde(default)]
+    references: Vec<SchemaReference>,
+}
+
+#[derive(Deserialize)]
+struct SchemaReference {
+    /// Import path for protobuf, full name of the type for Avro
+    name: String,
+    subject: String,
+    version: i32,
+}
+
+enum SchemaDecoder {
+    Avro(AvroDecoder),
+    Protobuf(FileDescriptor),
+    Json,
+}
+
+impl SchemaDecoder {
+    fn to_json(&self, payload: &[u8]) -> Result<Value, String> {
+        match self {
+            SchemaDecoder::Avro(decoder) => decoder.to_json(payload),
+            SchemaDecoder::Protobuf(file) => {
+                let (indexes, payload) = read_message_indexes(payload)?;
+                let message = message_at(file, &indexes).ok_or_else(|| {
+                    format!("No message at index {:?} in {}", indexes, file.name())
+                })?;
+                ProtoDecoder::new(message).to_json(payload)
+            }
+            SchemaDecoder::Json => serde_json::from_slice(payload)
+                .map_err(|er| format!("Invalid JSON payload: {}", er)),
+        }
+    }
+}
+
+/// Decodes payloads in the Schema Registry wire format, looking up each schema id once and
+/// picking Avro, Protobuf or JSON Schema decoding from its registered type.
+pub struct RegistryDecoder {
+    url: String,
+    schemas: RefCell<HashMap<u32, Result<SchemaDecoder, String>>>,
+}
+
+impl RegistryDecoder {
+    pub fn new(url: &str) -> Self {
+        RegistryDecoder {
+            url: url.trim_end_matches('/').to_string(),
+            schemas: RefCell::new(HashMap::new()),
+        }
+    }
+
+    fn get(&self, path: &str) -> Result<RegisteredSchema, String> {
+        let url = format!("{}{}", self.url, path);
+        let body = ureq::get(&url)
+            .call()
+            .and_then(|response| response.into_body().read_to_string())
+            .map_err(|er| format!("Failed to fetch {}: {}", url, er))?;
+        serde_json::from_str(&body).map_err(|er| format!("Invalid response from {}: {}", url, er))
+    }
+
+    fn fetch(&self, id: u32) -> Result<SchemaDecoder, String> {
+        let registered = self.get(&format!("/schemas/ids/{}", id))?;
+        let mut references = HashMap::new();
+        self.fetch_references(&registered.references, &mut references)?;
+        match registered.schema_type.as_deref().unwrap_or("AVRO") {
+            "AVRO" => {
+                let references: Vec<String> = references.into_values().collect();
+                AvroDecoder::with_references(&registered.schema, &references)
+                    .map(SchemaDecoder::Avro)
+                    .map_err(|er| er.to_string())
+            }
+            "PROTOBUF" => compile_proto(
+                &format!("schema-{}.proto", id),
+                &registered.schema,
+                references,
+            )
+            .map(SchemaDecoder::Protobuf),
+            "JSON" => Ok(SchemaDecoder::Json),
+            other => Err(format!("Unsupported schema type {}", other)),
+        }
+    }
+
+    /// Collects the schemas `references` point to, and the ones those point to, by name.
+    fn fetch_references(
+        &self,
+        references: &[SchemaReference],
+        fetched: &mut HashMap<String, String>,
+    ) -> Result<(), String> {
+        for reference in references {
+            if fetched.contains_key(&reference.name) {
+                continue;
+            }
+            let registered = self.get(&format!(
+                "/subjects/{}/versions/{}",
+                reference.subject, reference.version
+            ))?;
+            fetched.insert(reference.name.clone(), registered.schema);
+            self.fetch_references(&registered.references, fetched)?;
+        }
+        Ok(())
+    }
+}
+
+impl PayloadDecoder for RegistryDecoder {
+    fn to_json(&self, payload: &[u8]) -> Result<Value, String> {
+        if payload.len() < 5 || payload[0] != MAGIC_BYTE {
+            return Err("Payload is not in the Schema Registry wire format".to_string());
+        }
+        let id = u32::from_be_bytes([payload[1], payload[2], payload[3], payload[4]]);
+        let mut schemas = self.schemas.borrow_mut();
+        // Failed lookups are kept too, so a missing schema is not fetched again for every record
+        match schemas.entry(id).or_insert_with(|| self.fetch(id)) {
+            Ok(schema) => schema.to_json(&payload[5..]),
+            Err(er) => Err(format!("Schema {}: {}", id, er)),
+        }
+    }
+}
+
+/// Serves the schema and its references from memory, by import path.
+struct RegistryFiles(HashMap<String, String>);
+
+impl FileResolver for RegistryFiles {
+    fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+        match self.0.get(name) {
+            Some(source) => File::from_source(name, source),
+            None => Err(protox::Error::file_not_found(name)),
+        }
+    }
+}
+
+fn compile_proto(
+    name: &str,
+    schema: &str,
+    mut imports: HashMap<String, String>,
+) -> Result<FileDescriptor, String> {
+    imports.insert(name.to_string(), schema.to_string());
+    let mut resolver = ChainFileResolver::new();
+    resolver.add(RegistryFiles(imports));
+    resolver.add(GoogleFileResolver::new());
+    let mut compiler = Compiler::with_file_resolver(resolver);
+    compiler
+        .open_file(name)
+        .map_err(|er| format!("Invalid protobuf schema: {}", er))?;
+    compiler
+        .descriptor_pool()
+        .get_file_by_name(name)
+        .ok_or_else(|| format!("Protobuf schema {} did not compile", name))
+}
+
+/// Reads the zigzag varint encoded path to the message type that follows the schema id. An
+/// empty path is written as a single 0 and means the first message of the file.
+fn read_message_indexes(mut payload: &[u8]) -> Result<(Vec<usize>, &[u8]), String> {
+    let mut read = || {
+        decode_varint(&mut payload)
+            .map(|n| ((n >> 1) as i64 ^ -((n & 1) as i64)) as usize)
+            .map_err(|er| format!("Invalid message indexes: {}", er))
+    };
+    let count = read()?;
+    let indexes = if count == 0 {
+        vec![0]
+    } else {
+        (0..count).map(|_| read()).collect::<Result<_, _>>()?
+    };
+    Ok((indexes, payload))
+}
+
+fn message_at(file: &FileDescriptor, indexes: &[usize]) -> Option<MessageDescriptor> {
+    let (first, nested) = indexes.split_first()?;
+    nested
+        .iter()
+        .try_fold(file.messages().nth(*first)?, |message, index| {
+            message.child_messages().nth(*index)
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::{compile_proto, read_message_indexes, RegistryDecoder, SchemaDecoder};
+    use crate::{avro::AvroDecoder, kafka::PayloadDecoder};
+
+    const ORDER_PROTO: &str = r#"
+        syntax = "proto3";
+        package com.acme;
+        import "line.proto";
+        message Order {
+            string id = 1;
+            Line line = 2;
+            message Note { string text = 1; }
+        }
+    "#;
+
+    fn decoder() -> RegistryDecoder {
+        let imports = HashMap::from([(
+            "line.proto".to_string(),
+            "syntax = \"proto3\"; package com.acme; message Line { int32 amount = 1; }".to_string(),
+        )]);
+        let file = compile_proto("schema-2.proto", ORDER_PROTO, imports).unwrap();
+        let avro = AvroDecoder::from_str(
+            r#"{"type": "record", "name": "Id", "fields": [{"name": "id", "type": "string"}]}"#,
+        )
+        .unwrap();
+        let decoder = RegistryDecoder::new("http://localhost:8081/");
+        decoder.schemas.borrow_mut().extend([
+            (1, Ok(SchemaDecoder::Avro(avro))),
+            (2, Ok(SchemaDecoder::Protobuf(file))),
+            (3, Ok(SchemaDecoder::Json)),
+            (4, Err("Failed to fetch".to_string())),
+        ]);
+        decoder
+    }
+
+    #[test]
+    fn test_registry_to_json() {
+        let decoder = decoder();
+        assert_eq!(
+            decoder
+                .to_json(&[0, 0, 0, 0, 1, 0x06, b'o', b'-', b'1'])
+                .unwrap(),
+            json!({"id": "o-1"})
+        );
+        // Order { id = "o-1", line { amount = 75 } }
+        assert_eq!(
+            decoder
+                .to_json(&[0, 0, 0, 0, 2, 0, 0x0a, 0x03, b'o', b'-', b'1', 0x12, 0x02, 0x08, 0x4b])
+                .unwrap(),
+            json!({"id": "o-1", "line": {"amount": 75}})
+        );
+        // Order.Note { text = "hi" }
+        assert_eq!(
+            decoder
+                .to_json(&[0, 0, 0, 0, 2, 0x04, 0, 0, 0x0a, 0x02, b'h', b'i'])
+                .unwrap(),
+            json!({"text": "hi"})
+        );
+        assert_eq!(
+            decoder.to_json(b"\0\0\0\0\x03{\"id\": \"o-1\"}").unwrap(),
+            json!({"id": "o-1"})
+        );
+    }
+
+    #[test]
+    fn test_registry_errors() {
+        let decoder = decoder();
+        assert_eq!(
+            decoder.to_json(b"{\"id\": \"o-1\"}").unwrap_err(),
+            "Payload is not in the Schema Registry wire format"
+        );
+        assert_eq!(
+            decoder.to_json(&[0, 0, 0, 0, 4, 0]).unwrap_err(),
+            "Schema 4: Failed to fetch"
+        );
+        assert_eq!(
+            decoder.to_json(&[0, 0, 0, 0, 2, 0x02, 0x04]).unwrap_err(),
+            "No message at index [2] in schema-2.proto"
+        );
+    }
+
+    #[test]
+    fn test_read_message_indexes() {
+        assert_eq!(
+            read_message_indexes(&[0, 0x0a]).unwrap(),
+            (vec![0], &[0x0a][..])
+        );
+        assert_eq!(
+            read_message_indexes(&[0x04, 0x02, 0x06]).unwrap(),
+            (vec![1, 3], &[][..])
+        );
+        assert!(read_message_indexes(&[0x04, 0x02]).is_err());
+    }
+}
@@ -0,0 +1,275 @@
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use serde::Serialize;
+use serde_json::json;
+
+use crate::{
+    kafka::{self, KafkaError},
+    output,
+};
+
+/// Time a client gets to send its request before the connection is dropped
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Longest request line or header accepted, in bytes
+const MAX_LINE_BYTES: usize = 8 * 1024;
+/// Most bytes of headers accepted per request
+const MAX_HEADER_BYTES: usize = 32 * 1024;
+/// Requests answered at once, further connections get a 503
+const MAX_CONNECTIONS: usize = 16;
+
+/// The read-only endpoints of `kfcli serve`.
+#[derive(Debug, Clone, PartialEq)]
+enum Route {
+    Health,
+    Topics,
+    Topic(String),
+    GroupLag(String),
+}
+
+/// Matches the request line to an endpoint, or gives the status and message to answer with.
+fn route(method: &str, target: &str) -> Result<Route, (u16, String)> {
+    if method != "GET" {
+        return Err((405, format!("Method {} is not allowed", method)));
+    }
+    let path = target.split('?').next().unwrap_or_default();
+    let segments: Vec<String> = path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(percent_decode)
+        .collect::<Option<_>>()
+        .ok_or_else(|| (400, format!("Invalid path {}", path)))?;
+    match segments.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["health"] => Ok(Route::Health),
+        ["topics"] => Ok(Route::Topics),
+        ["topics", topic] => Ok(Route::Topic(topic.to_string())),
+        ["groups", group, "lag"] => Ok(Route::GroupLag(group.to_string())),
+        _ => Err((404, format!("No endpoint at {}", path))),
+    }
+}
+
+/// Decodes the `%XX` escapes of a path segment, `None` when an escape is malformed or the
+/// result is not UTF-8.
+fn percent_decode(segment: &str) -> Option<String> {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+fn status_text(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        431 => "Request Header Fields Too Large",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+fn ok_body(value: impl Serialize) -> (u16, String) {
+    (
+        200,
+        serde_json::to_string(&value).expect("API responses serialize"),
+    )
+}
+
+fn error_body(status: u16, message: &str) -> (u16, String) {
+    (status, json!({ "error": message }).to_string())
+}
+
+/// Answers a request against the cluster. A missing topic is a 404 and other cluster errors
+/// are a 502, except for `/health` which reports them as 503.
+fn respond(bootstrap_servers: &str, route: Route) -> (u16, String) {
+    let failed = |er: KafkaError| match er {
+        KafkaError::TopicNotExists(message) => error_body(404, &message),
+        er => error_body(502, &er.to_string()),
+    };
+    match route {
+        Route::Health => match kafka::get_cluster_health(bootstrap_servers) {
+            Ok(health) => ok_body(health),
+            Err(er) => error_body(503, &er.to_string()),
+        },
+        Route::Topics => {
+            kafka::list_topic_summaries(bootstrap_servers).map_or_else(failed, ok_body)
+        }
+        Route::Topic(topic) => {
+            kafka::get_topic_partitions(bootstrap_servers, &topic).map_or_else(failed, ok_body)
+        }
+        Route::GroupLag(group) => {
+            kafka::get_group_partition_lag(bootstrap_servers, &group).map_or_else(failed, ok_body)
+        }
+    }
+}
+
+/// Reads a line of at most `limit` bytes, failing with `InvalidData` on a longer one.
+fn read_line(reader: &mut impl BufRead, limit: usize, line: &mut String) -> io::Result<usize> {
+    let read = reader.by_ref().take(limit as u64).read_line(line)?;
+    if read == limit && !line.ends_with('\n') {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Line too long"));
+    }
+    Ok(read)
+}
+
+/// Reads the request line. The headers are not used, but are read so the client is not reset
+/// while sending them.
+fn read_request(reader: &mut impl BufRead) -> io::Result<String> {
+    let mut request_line = String::new();
+    read_line(reader, MAX_LINE_BYTES, &mut request_line)?;
+    let mut header = String::new();
+    let mut header_bytes = 0;
+    loop {
+        header.clear();
+        let read = read_line(reader, MAX_LINE_BYTES, &mut header)?;
+        header_bytes += read;
+        if header_bytes > MAX_HEADER_BYTES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Headers too long",
+            ));
+        }
+        if read == 0 || header.trim_end().is_empty() {
+            return Ok(request_line);
+        }
+    }
+}
+
+fn handle(stream: TcpStream, bootstrap_servers: &str) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let (status, body) = match read_request(&mut BufReader::new(&stream)) {
+        Ok(request_line) => {
+            let mut parts = request_line.split_whitespace();
+            match (parts.next(), parts.next()) {
+                (Some(method), Some(target)) => match route(method, target) {
+                    Ok(route) => respond(bootstrap_servers, route),
+                    Err((status, message)) => error_body(status, &message),
+                },
+                _ => error_body(400, "Invalid request"),
+            }
+        }
+        Err(er) if er.kind() == io::ErrorKind::InvalidData => {
+            error_body(431, "The request line or headers are too long")
+        }
+        Err(er) => return Err(er),
+    };
+    write_response(&stream, status, &body)
+}
+
+fn write_response(mut stream: &TcpStream, status: u16, body: &str) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        status_text(status),
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// Serves read-only JSON endpoints about the cluster on `address` until Ctrl-C. Each request is
+/// answered on a thread of its own with fresh metadata, up to `MAX_CONNECTIONS` at once.
+pub fn serve(bootstrap_servers: &str, address: &str) -> Result<(), KafkaError> {
+    let listener = TcpListener::bind(address)
+        .map_err(|er| KafkaError::Io(format!("Failed to listen on {}", address), er))?;
+    listener
+        .set_nonblocking(true)
+        .map_err(|er| KafkaError::Io(format!("Failed to listen on {}", address), er))?;
+    output::info(format!(
+        "Serving /health, /topics, /topics/<topic> and /groups/<group>/lag on http://{}",
+        address
+    ));
+
+    let stopped = kafka::stop_on_ctrl_c()?;
+    let active = Arc::new(AtomicUsize::new(0));
+    while !stopped.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, _)) if active.load(Ordering::SeqCst) >= MAX_CONNECTIONS => {
+                let (status, body) = error_body(503, "Too many requests at once");
+                let _ = write_response(&stream, status, &body);
+            }
+            Ok((stream, _)) => {
+                active.fetch_add(1, Ordering::SeqCst);
+                let active = Arc::clone(&active);
+                let bootstrap_servers = bootstrap_servers.to_string();
+                thread::spawn(move || {
+                    if let Err(er) = handle(stream, &bootstrap_servers) {
+                        output::status(format!("Failed to answer a request: {}", er));
+                    }
+                    active.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+            Err(er) if er.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(100))
+            }
+            Err(er) => output::status(format!("Failed to accept a connection: {}", er)),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::{percent_decode, read_request, route, Route, MAX_LINE_BYTES};
+
+    #[test]
+    fn test_route() {
+        assert_eq!(route("GET", "/health"), Ok(Route::Health));
+        assert_eq!(route("GET", "/topics/"), Ok(Route::Topics));
+        assert_eq!(
+            route("GET", "/topics/orders.v1?pretty"),
+            Ok(Route::Topic("orders.v1".to_string()))
+        );
+        assert_eq!(
+            route("GET", "/groups/billing%20service/lag"),
+            Ok(Route::GroupLag("billing service".to_string()))
+        );
+        assert_eq!(route("GET", "/groups/billing").unwrap_err().0, 404);
+        assert_eq!(route("DELETE", "/topics/orders").unwrap_err().0, 405);
+        assert_eq!(route("GET", "/topics/%ff").unwrap_err().0, 400);
+    }
+
+    #[test]
+    fn test_read_request() {
+        let request = "GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        assert_eq!(
+            read_request(&mut Cursor::new(request)).unwrap(),
+            "GET /health HTTP/1.1\r\n"
+        );
+        let long_line = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_LINE_BYTES));
+        assert!(read_request(&mut Cursor::new(long_line)).is_err());
+        let many_headers = format!("GET / HTTP/1.1\r\n{}", "X-Filler: 1\r\n".repeat(4096));
+        assert!(read_request(&mut Cursor::new(many_headers)).is_err());
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("a%2Fb").as_deref(), Some("a/b"));
+        assert_eq!(percent_decode("plain").as_deref(), Some("plain"));
+        assert_eq!(percent_decode("bad%2"), None);
+    }
+}
@@ -0,0 +1,83 @@
+use std::{collections::BTreeMap, fs, io};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{config_path, ConfigError};
+
+const SESSIONS_FOLDER: &str = "sessions";
+
+/// Where a named tail left off, so that the next `tail --session` continues from there.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TailSession {
+    pub topic: String,
+    /// Next offset to read, by partition
+    pub offsets: BTreeMap<i32, i64>,
+}
+
+/// Session names become file names, so they are kept to letters, digits, `-` and `_`.
+pub fn check_name(name: &str) -> Result<(), String> {
+    if !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        Ok(())
+    } else {
+        Err(format!(
+            "Invalid session name {:?}: use letters, digits, - and _",
+            name
+        ))
+    }
+}
+
+fn session_file(name: &str) -> Result<std::path::PathBuf, ConfigError> {
+    Ok(config_path(SESSIONS_FOLDER)?.join(format!("{}.json", name)))
+}
+
+/// The saved session, `None` when there is none by that name yet.
+pub fn load(name: &str) -> Result<Option<TailSession>, ConfigError> {
+    let path = session_file(name)?;
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(er) if er.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(er) => {
+            return Err(ConfigError::ConfigRead(
+                format!("Failed to read {:?}", path),
+                er,
+            ))
+        }
+    };
+    serde_json::from_str(&content).map(Some).map_err(|er| {
+        ConfigError::ConfigRead(
+            format!("Failed to read {:?}", path),
+            io::Error::new(io::ErrorKind::InvalidData, er),
+        )
+    })
+}
+
+/// Saves the session, replacing the file in one step so an interrupted save keeps the last one.
+pub fn save(name: &str, session: &TailSession) -> Result<(), ConfigError> {
+    let path = session_file(name)?;
+    let write_error = |er| ConfigError::ConfigWrite(format!("Failed to write {:?}", path), er);
+    if let Some(folder) = path.parent() {
+        fs::create_dir_all(folder).map_err(write_error)?;
+    }
+    let temporary = path.with_extension("json.tmp");
+    let content = serde_json::to_string(session).expect("Tail sessions serialize");
+    fs::write(&temporary, content)
+        .and_then(|_| fs::rename(&temporary, &path))
+        .map_err(write_error)
+}
+
+#[cfg(test)]
+mod test {
+    use super::check_name;
+
+    #[test]
+    fn test_check_name() {
+        assert!(check_name("my-debug_2").is_ok());
+        assert!(check_name("").is_err());
+        assert!(check_name("../config").is_err());
+        assert!(check_name("a b").is_err());
+    }
+}
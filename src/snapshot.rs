@@ -0,0 +1,118 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rdkafka::{
+    message::{BorrowedMessage, Header, Headers, OwnedHeaders},
+    Message, Timestamp,
+};
+use serde::{Deserialize, Serialize};
+
+/// One record of a topic snapshot. Snapshots are JSON Lines files with one record per line;
+/// keys, values and header values are base64 encoded so binary payloads survive the round trip.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotRecord {
+    pub partition: i32,
+    pub offset: i64,
+    pub timestamp: Option<i64>,
+    pub key: Option<String>,
+    pub value: Option<String>,
+    #[serde(default)]
+    pub headers: Vec<SnapshotHeader>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotHeader {
+    pub key: String,
+    pub value: Option<String>,
+}
+
+impl SnapshotRecord {
+    pub fn from_message(message: &BorrowedMessage) -> Self {
+        let timestamp = match message.timestamp() {
+            Timestamp::NotAvailable => None,
+            Timestamp::CreateTime(ts) | Timestamp::LogAppendTime(ts) => Some(ts),
+        };
+        let headers = message
+            .headers()
+            .map(|headers| {
+                headers
+                    .iter()
+                    .map(|h| SnapshotHeader {
+                        key: h.key.to_string(),
+                        value: h.value.map(|v| STANDARD.encode(v)),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        SnapshotRecord {
+            partition: message.partition(),
+            offset: message.offset(),
+            timestamp,
+            key: message.key().map(|k| STANDARD.encode(k)),
+            value: message.payload().map(|v| STANDARD.encode(v)),
+            headers,
+        }
+    }
+
+    pub fn key_bytes(&self) -> Result<Option<Vec<u8>>, base64::DecodeError> {
+        self.key.as_ref().map(|k| STANDARD.decode(k)).transpose()
+    }
+
+    pub fn value_bytes(&self) -> Result<Option<Vec<u8>>, base64::DecodeError> {
+        self.value.as_ref().map(|v| STANDARD.decode(v)).transpose()
+    }
+
+    pub fn owned_headers(&self) -> Result<OwnedHeaders, base64::DecodeError> {
+        let mut headers = OwnedHeaders::new_with_capacity(self.headers.len());
+        for header in &self.headers {
+            let value = header
+                .value
+                .as_ref()
+                .map(|v| STANDARD.decode(v))
+                .transpose()?;
+            headers = headers.insert(Header {
+                key: &header.key,
+                value: value.as_deref(),
+            });
+        }
+        Ok(headers)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rdkafka::message::Headers;
+
+    use super::{SnapshotHeader, SnapshotRecord};
+
+    #[test]
+    fn test_snapshot_record_round_trip() {
+        let record = SnapshotRecord {
+            partition: 1,
+            offset: 42,
+            timestamp: Some(1714557600000),
+            key: Some("a2V5".to_string()),
+            value: None,
+            headers: vec![SnapshotHeader {
+                key: "trace-id".to_string(),
+                value: Some("AP8=".to_string()),
+            }],
+        };
+        let line = serde_json::to_string(&record).unwrap();
+        let parsed: SnapshotRecord = serde_json::from_str(&line).unwrap();
+        assert_eq!(parsed, record);
+
+        assert_eq!(parsed.key_bytes().unwrap(), Some(b"key".to_vec()));
+        assert_eq!(parsed.value_bytes().unwrap(), None);
+        let headers = parsed.owned_headers().unwrap();
+        assert_eq!(headers.count(), 1);
+        assert_eq!(headers.get(0).value, Some([0u8, 255].as_slice()));
+    }
+
+    #[test]
+    fn test_snapshot_record_without_headers() {
+        let line = r#"{"partition":0,"offset":0,"timestamp":null,"key":null,"value":"e30="}"#;
+        let parsed: SnapshotRecord = serde_json::from_str(line).unwrap();
+        assert!(parsed.headers.is_empty());
+        assert_eq!(parsed.value_bytes().unwrap(), Some(b"{}".to_vec()));
+    }
+}
@@ -0,0 +1,257 @@
+use serde_json::{Map, Value};
+
+/// A value generated anew for every record.
+#[derive(Debug, Clone, PartialEq)]
+enum Placeholder {
+    /// `{{uuid}}`, a random version 4 UUID
+    Uuid,
+    /// `{{int 1 100}}`, a random integer in the inclusive range
+    Int(i64, i64),
+    /// `{{timestamp}}` or `{{now}}`, the current time in epoch milliseconds
+    Timestamp,
+    /// `{{seq}}`, the number of the record, counting from 1
+    Seq,
+    /// `{{choice red green blue}}`, one of the words picked at random
+    Choice(Vec<String>),
+}
+
+impl Placeholder {
+    fn parse(expression: &str) -> Result<Placeholder, String> {
+        let words: Vec<&str> = expression.split_whitespace().collect();
+        match words[..] {
+            ["uuid"] => Ok(Placeholder::Uuid),
+            ["timestamp"] | ["now"] => Ok(Placeholder::Timestamp),
+            ["seq"] => Ok(Placeholder::Seq),
+            ["int", min, max] => {
+                let bound = |word: &str| {
+                    word.parse::<i64>()
+                        .map_err(|_| format!("Invalid bound {} in {{{{{}}}}}", word, expression))
+                };
+                let (min, max) = (bound(min)?, bound(max)?);
+                if min > max {
+                    return Err(format!("Empty range in {{{{{}}}}}", expression));
+                }
+                Ok(Placeholder::Int(min, max))
+            }
+            ["choice", ..] if words.len() > 1 => Ok(Placeholder::Choice(
+                words[1..].iter().map(|w| w.to_string()).collect(),
+            )),
+            _ => Err(format!(
+                "Unknown placeholder {{{{{}}}}}, use uuid, int <min> <max>, timestamp, now, seq or choice <a> <b> ...",
+                expression
+            )),
+        }
+    }
+
+    fn render(&self, rng: &mut fastrand::Rng, now: i64, seq: u64) -> Value {
+        match self {
+            Placeholder::Uuid => {
+                let mut bytes = rng.u128(..).to_be_bytes();
+                bytes[6] = (bytes[6] & 0x0f) | 0x40;
+                bytes[8] = (bytes[8] & 0x3f) | 0x80;
+                let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                Value::String(format!(
+                    "{}-{}-{}-{}-{}",
+                    &hex[..8],
+                    &hex[8..12],
+                    &hex[12..16],
+                    &hex[16..20],
+                    &hex[20..]
+                ))
+            }
+            Placeholder::Int(min, max) => Value::from(rng.i64(*min..=*max)),
+            Placeholder::Timestamp => Value::from(now),
+            Placeholder::Seq => Value::from(seq),
+            Placeholder::Choice(words) => Value::String(words[rng.usize(..words.len())].clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Placeholder(Placeholder),
+}
+
+/// Splits a string at its `{{...}}` placeholders.
+fn parse_text(text: &str) -> Result<Vec<Part>, String> {
+    let mut parts = vec![];
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let end = rest[start..]
+            .find("}}")
+            .ok_or_else(|| format!("Unclosed placeholder in {:?}", text))?;
+        if start > 0 {
+            parts.push(Part::Text(rest[..start].to_string()));
+        }
+        parts.push(Part::Placeholder(Placeholder::parse(
+            rest[start + 2..start + end].trim(),
+        )?));
+        rest = &rest[start + end + 2..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Text(rest.to_string()));
+    }
+    Ok(parts)
+}
+
+fn render_text(parts: &[Part], rng: &mut fastrand::Rng, now: i64, seq: u64) -> Value {
+    if let [Part::Placeholder(placeholder)] = parts {
+        return placeholder.render(rng, now, seq);
+    }
+    let mut text = String::new();
+    for part in parts {
+        match part {
+            Part::Text(t) => text.push_str(t),
+            Part::Placeholder(placeholder) => match placeholder.render(rng, now, seq) {
+                Value::String(s) => text.push_str(&s),
+                value => text.push_str(&value.to_string()),
+            },
+        }
+    }
+    Value::String(text)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Literal(Value),
+    Text(Vec<Part>),
+    Array(Vec<Node>),
+    Object(Vec<(String, Node)>),
+}
+
+impl Node {
+    fn parse(value: Value) -> Result<Node, String> {
+        Ok(match value {
+            Value::String(text) if text.contains("{{") => Node::Text(parse_text(&text)?),
+            Value::Array(items) => Node::Array(
+                items
+                    .into_iter()
+                    .map(Node::parse)
+                    .collect::<Result<_, _>>()?,
+            ),
+            Value::Object(fields) => Node::Object(
+                fields
+                    .into_iter()
+                    .map(|(name, value)| Node::parse(value).map(|node| (name, node)))
+                    .collect::<Result<_, _>>()?,
+            ),
+            value => Node::Literal(value),
+        })
+    }
+
+    fn render(&self, rng: &mut fastrand::Rng, now: i64, seq: u64) -> Value {
+        match self {
+            Node::Literal(value) => value.clone(),
+            Node::Text(parts) => render_text(parts, rng, now, seq),
+            Node::Array(items) => Value::Array(
+                items
+                    .iter()
+                    .map(|item| item.render(rng, now, seq))
+                    .collect(),
+            ),
+            Node::Object(fields) => Value::Object(
+                fields
+                    .iter()
+                    .map(|(name, node)| (name.clone(), node.render(rng, now, seq)))
+                    .collect::<Map<_, _>>(),
+            ),
+        }
+    }
+}
+
+/// A JSON document whose strings may hold placeholders, used by `topics seed` and `topics
+/// produce --repeat` to generate records. A string that is a single `{{int ...}}`,
+/// `{{timestamp}}` or `{{seq}}` becomes a JSON number.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordTemplate {
+    root: Node,
+}
+
+impl RecordTemplate {
+    pub fn parse(json: &str) -> Result<RecordTemplate, String> {
+        let value: Value = serde_json::from_str(json).map_err(|er| er.to_string())?;
+        RecordTemplate::from_value(value)
+    }
+
+    pub fn from_value(value: Value) -> Result<RecordTemplate, String> {
+        Ok(RecordTemplate {
+            root: Node::parse(value)?,
+        })
+    }
+
+    /// The document for the record numbered `seq`, created at `now`.
+    pub fn render(&self, rng: &mut fastrand::Rng, now: i64, seq: u64) -> Value {
+        self.root.render(rng, now, seq)
+    }
+}
+
+/// Plain text with placeholders, such as a record key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextTemplate {
+    parts: Vec<Part>,
+}
+
+impl TextTemplate {
+    pub fn parse(text: &str) -> Result<TextTemplate, String> {
+        Ok(TextTemplate {
+            parts: parse_text(text)?,
+        })
+    }
+
+    pub fn render(&self, rng: &mut fastrand::Rng, now: i64, seq: u64) -> String {
+        match render_text(&self.parts, rng, now, seq) {
+            Value::String(text) => text,
+            value => value.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{RecordTemplate, TextTemplate};
+
+    #[test]
+    fn test_render_template() {
+        let template = RecordTemplate::parse(
+            r#"{
+                "id": "{{uuid}}",
+                "amount": "{{int 5 5}}",
+                "at": "{{ timestamp }}",
+                "status": "{{choice paid}}",
+                "note": "order {{int 7 7}} of {{choice eu}}",
+                "items": [{"sku": "A-{{int 1 1}}", "count": 2}]
+            }"#,
+        )
+        .unwrap();
+        let mut rng = fastrand::Rng::with_seed(7);
+        let value = template.render(&mut rng, 1714557600000, 1);
+        let id = value["id"].as_str().unwrap();
+        assert_eq!(id.len(), 36);
+        assert_eq!(&id[14..15], "4");
+        assert_ne!(value["id"], template.render(&mut rng, 0, 2)["id"]);
+        assert_eq!(value["amount"], json!(5));
+        assert_eq!(value["at"], json!(1714557600000_i64));
+        assert_eq!(value["status"], json!("paid"));
+        assert_eq!(value["note"], json!("order 7 of eu"));
+        assert_eq!(value["items"], json!([{"sku": "A-1", "count": 2}]));
+
+        let key = TextTemplate::parse("{{int 3 3}}").unwrap();
+        assert_eq!(key.render(&mut rng, 0, 1), "3");
+        let key = TextTemplate::parse("order-{{seq}}-{{now}}").unwrap();
+        assert_eq!(
+            key.render(&mut rng, 1714557600000, 7),
+            "order-7-1714557600000"
+        );
+    }
+
+    #[test]
+    fn test_invalid_template() {
+        assert!(RecordTemplate::parse(r#"{"id": "{{guid}}"}"#).is_err());
+        assert!(RecordTemplate::parse(r#"{"n": "{{int 9 1}}"}"#).is_err());
+        assert!(RecordTemplate::parse(r#"{"n": "{{int 1"}"#).is_err());
+        assert!(RecordTemplate::parse(r#"{"c": "{{choice}}"}"#).is_err());
+    }
+}
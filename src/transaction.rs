@@ -0,0 +1,225 @@
+use std::io::{self, Cursor};
+
+use byteorder::{BigEndian, ReadBytesExt};
+use serde::Serialize;
+
+use crate::admin::{read_compact_len, read_compact_string, read_string, skip_tagged_fields};
+
+/// Internal topic where transaction coordinators log the state of every transactional id
+pub const TRANSACTION_STATE_TOPIC: &str = "__transaction_state";
+
+/// Partitions of one topic that a transaction has written to.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TransactionTopic {
+    pub topic: String,
+    pub partitions: Vec<i32>,
+}
+
+/// A TransactionLogValue record: where a transactional id stood when the coordinator logged it.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TransactionMetadata {
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+    pub timeout_ms: i32,
+    pub state: &'static str,
+    pub topics: Vec<TransactionTopic>,
+    /// Epoch milliseconds
+    pub last_update: i64,
+    /// Epoch milliseconds, -1 when no transaction is open
+    pub start: i64,
+}
+
+impl TransactionMetadata {
+    /// Whether a transaction is open or has not finished committing or aborting.
+    pub fn is_ongoing(&self) -> bool {
+        matches!(
+            self.state,
+            "Ongoing" | "PrepareCommit" | "PrepareAbort" | "PrepareEpochFence"
+        )
+    }
+}
+
+fn state_name(status: i8) -> &'static str {
+    match status {
+        0 => "Empty",
+        1 => "Ongoing",
+        2 => "PrepareCommit",
+        3 => "PrepareAbort",
+        4 => "CompleteCommit",
+        5 => "CompleteAbort",
+        6 => "Dead",
+        7 => "PrepareEpochFence",
+        _ => "Unknown",
+    }
+}
+
+fn invalid(er: io::Error) -> String {
+    format!("Truncated transaction record: {}", er)
+}
+
+/// Reads the transactional id out of a TransactionLogKey.
+pub fn decode_key(key: &[u8]) -> Result<String, String> {
+    let mut cursor = Cursor::new(key);
+    let version = cursor.read_i16::<BigEndian>().map_err(invalid)?;
+    if version != 0 {
+        return Err(format!("Unsupported transaction key version {}", version));
+    }
+    read_string(&mut cursor)
+        .map_err(invalid)?
+        .ok_or_else(|| "Transaction key without a transactional id".to_string())
+}
+
+/// Decodes a TransactionLogValue, version 0 or the flexible version 1.
+pub fn decode_value(value: &[u8]) -> Result<TransactionMetadata, String> {
+    let mut cursor = Cursor::new(value);
+    let version = cursor.read_i16::<BigEndian>().map_err(invalid)?;
+    if !(0..=1).contains(&version) {
+        return Err(format!("Unsupported transaction value version {}", version));
+    }
+    let flexible = version >= 1;
+    decode_value_fields(&mut cursor, flexible).map_err(invalid)
+}
+
+fn decode_value_fields(
+    cursor: &mut Cursor<&[u8]>,
+    flexible: bool,
+) -> io::Result<TransactionMetadata> {
+    let producer_id = cursor.read_i64::<BigEndian>()?;
+    let producer_epoch = cursor.read_i16::<BigEndian>()?;
+    let timeout_ms = cursor.read_i32::<BigEndian>()?;
+    let state = state_name(cursor.read_i8()?);
+    // The counts come from the record, so nothing is allocated up front on their word
+    let count = read_array_len(cursor, flexible)?.unwrap_or(0);
+    let mut topics = Vec::new();
+    for _ in 0..count {
+        let topic = if flexible {
+            read_compact_string(cursor)?
+        } else {
+            read_string(cursor)?
+        };
+        let partition_count = read_array_len(cursor, flexible)?.unwrap_or(0);
+        let partitions = (0..partition_count)
+            .map(|_| cursor.read_i32::<BigEndian>())
+            .collect::<io::Result<_>>()?;
+        if flexible {
+            skip_tagged_fields(cursor)?;
+        }
+        topics.push(TransactionTopic {
+            topic: topic.unwrap_or_default(),
+            partitions,
+        });
+    }
+    let last_update = cursor.read_i64::<BigEndian>()?;
+    let start = cursor.read_i64::<BigEndian>()?;
+    // The previous and next producer ids of version 1 are tagged fields, which are not shown
+    if flexible {
+        skip_tagged_fields(cursor)?;
+    }
+    Ok(TransactionMetadata {
+        producer_id,
+        producer_epoch,
+        timeout_ms,
+        state,
+        topics,
+        last_update,
+        start,
+    })
+}
+
+fn read_array_len(cursor: &mut Cursor<&[u8]>, flexible: bool) -> io::Result<Option<usize>> {
+    if flexible {
+        read_compact_len(cursor)
+    } else {
+        let len = cursor.read_i32::<BigEndian>()?;
+        Ok((len >= 0).then_some(len as usize))
+    }
+}
+
+/// Topics and partitions of a transaction, e.g. `orders[0,3] payments[1]`.
+pub fn format_topics(topics: &[TransactionTopic]) -> String {
+    topics
+        .iter()
+        .map(|t| {
+            let partitions: Vec<String> = t.partitions.iter().map(|p| p.to_string()).collect();
+            format!("{}[{}]", t.topic, partitions.join(","))
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use byteorder::{BigEndian, WriteBytesExt};
+
+    use super::{decode_key, decode_value, format_topics, TransactionTopic};
+
+    fn write_header(value: &mut Vec<u8>, version: i16, status: i8) {
+        value.write_i16::<BigEndian>(version).unwrap();
+        value.write_i64::<BigEndian>(4001).unwrap();
+        value.write_i16::<BigEndian>(7).unwrap();
+        value.write_i32::<BigEndian>(60000).unwrap();
+        value.write_i8(status).unwrap();
+    }
+
+    #[test]
+    fn test_decode_key() {
+        let mut key = vec![0, 0, 0, 9];
+        key.extend_from_slice(b"billing-1");
+        assert_eq!(decode_key(&key).unwrap(), "billing-1");
+        assert!(decode_key(&[0, 1, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_decode_value_v0() {
+        let mut value = Vec::new();
+        write_header(&mut value, 0, 1);
+        value.write_i32::<BigEndian>(1).unwrap();
+        value.write_i16::<BigEndian>(6).unwrap();
+        value.extend_from_slice(b"orders");
+        value.write_i32::<BigEndian>(2).unwrap();
+        value.write_i32::<BigEndian>(0).unwrap();
+        value.write_i32::<BigEndian>(3).unwrap();
+        value.write_i64::<BigEndian>(1714557660000).unwrap();
+        value.write_i64::<BigEndian>(1714557600000).unwrap();
+
+        let metadata = decode_value(&value).unwrap();
+        assert_eq!(metadata.producer_id, 4001);
+        assert_eq!(metadata.producer_epoch, 7);
+        assert_eq!(metadata.state, "Ongoing");
+        assert!(metadata.is_ongoing());
+        assert_eq!(
+            metadata.topics,
+            vec![TransactionTopic {
+                topic: "orders".to_string(),
+                partitions: vec![0, 3],
+            }]
+        );
+        assert_eq!(metadata.start, 1714557600000);
+        assert_eq!(format_topics(&metadata.topics), "orders[0,3]");
+        assert!(decode_value(&value[..value.len() - 4]).is_err());
+
+        let mut huge = Vec::new();
+        write_header(&mut huge, 0, 1);
+        huge.write_i32::<BigEndian>(i32::MAX).unwrap();
+        assert!(decode_value(&huge).is_err());
+    }
+
+    #[test]
+    fn test_decode_value_v1() {
+        let mut value = Vec::new();
+        write_header(&mut value, 1, 4);
+        // An empty compact array, then the timestamps
+        value.push(1);
+        value.write_i64::<BigEndian>(1714557660000).unwrap();
+        value.write_i64::<BigEndian>(-1).unwrap();
+        // One tagged field: the previous producer id
+        value.extend_from_slice(&[1, 0, 8]);
+        value.write_i64::<BigEndian>(4000).unwrap();
+
+        let metadata = decode_value(&value).unwrap();
+        assert_eq!(metadata.state, "CompleteCommit");
+        assert!(!metadata.is_ongoing());
+        assert!(metadata.topics.is_empty());
+        assert_eq!(metadata.start, -1);
+    }
+}
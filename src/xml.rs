@@ -0,0 +1,151 @@
+use quick_xml::{
+    events::{BytesStart, Event},
+    Reader,
+};
+
+const TAG: &str = "34";
+const ATTRIBUTE: &str = "36";
+const VALUE: &str = "32";
+const COMMENT: &str = "90";
+
+fn paint(text: &str, color: &str, colored: bool) -> String {
+    if colored {
+        format!("\x1b[{}m{}\x1b[0m", color, text)
+    } else {
+        text.to_string()
+    }
+}
+
+fn open_tag(tag: &BytesStart, empty: bool, colored: bool) -> Result<String, String> {
+    let mut rendered = paint(&format!("<{}", tag.name().as_ref()), TAG, colored);
+    for attribute in tag.attributes() {
+        let attribute = attribute.map_err(|er| er.to_string())?;
+        rendered.push(' ');
+        rendered.push_str(&paint(attribute.key.as_ref(), ATTRIBUTE, colored));
+        rendered.push('=');
+        rendered.push_str(&paint(&format!("\"{}\"", attribute.value), VALUE, colored));
+    }
+    rendered.push_str(&paint(if empty { "/>" } else { ">" }, TAG, colored));
+    Ok(rendered)
+}
+
+/// Re-indents an XML document, one element per line with two spaces per level. Elements that
+/// only hold text stay on one line. Text and attribute values are kept escaped as they were.
+pub fn pretty_print(xml: &str, colored: bool) -> Result<String, String> {
+    let mut reader = Reader::from_str(xml);
+
+    let mut lines: Vec<String> = Vec::new();
+    let mut depth = 0;
+    // Whether the current line holds an open tag whose content has not been broken out yet
+    let mut inline = false;
+    // Text arrives in pieces split at entity references, so it is trimmed once it is complete
+    let mut text = String::new();
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|er| format!("Invalid XML at {}: {}", reader.error_position(), er))?;
+        match event {
+            Event::Text(ref piece) => {
+                text.push_str(piece);
+                continue;
+            }
+            Event::GeneralRef(ref reference) => {
+                text.push_str(&format!("&{};", &**reference));
+                continue;
+            }
+            Event::CData(ref data) => {
+                text.push_str(&format!("<![CDATA[{}]]>", &**data));
+                continue;
+            }
+            _ => push_text(&mut lines, text.trim()),
+        }
+        text.clear();
+        let indent = "  ".repeat(depth);
+        match event {
+            Event::Start(tag) => {
+                lines.push(format!("{}{}", indent, open_tag(&tag, false, colored)?));
+                depth += 1;
+                inline = true;
+            }
+            Event::End(tag) => {
+                depth = depth.saturating_sub(1);
+                let close = paint(&format!("</{}>", tag.name().as_ref()), TAG, colored);
+                match lines.last_mut() {
+                    Some(line) if inline => line.push_str(&close),
+                    _ => lines.push(format!("{}{}", "  ".repeat(depth), close)),
+                }
+                inline = false;
+            }
+            Event::Empty(tag) => {
+                lines.push(format!("{}{}", indent, open_tag(&tag, true, colored)?));
+                inline = false;
+            }
+            Event::Text(_) | Event::GeneralRef(_) | Event::CData(_) => {}
+            Event::Comment(comment) => {
+                let comment = format!("<!--{}-->", &*comment);
+                lines.push(format!("{}{}", indent, paint(&comment, COMMENT, colored)));
+                inline = false;
+            }
+            Event::Decl(decl) => {
+                lines.push(format!("<?{}?>", &*decl));
+            }
+            Event::PI(instruction) => {
+                lines.push(format!("{}<?{}?>", indent, &*instruction));
+            }
+            Event::DocType(doctype) => {
+                lines.push(format!("<!DOCTYPE {}>", &*doctype));
+            }
+            Event::Eof => break,
+        }
+    }
+    if depth > 0 {
+        return Err("Invalid XML: unclosed element".to_string());
+    }
+    Ok(lines.join("\n"))
+}
+
+fn push_text(lines: &mut Vec<String>, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    match lines.last_mut() {
+        Some(line) => line.push_str(text),
+        None => lines.push(text.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::pretty_print;
+
+    #[test]
+    fn test_pretty_print() {
+        let xml = r#"<?xml version="1.0"?><order id="42"><item sku="a&amp;b">Tea &amp; milk</item><gift/><!-- note --></order>"#;
+        assert_eq!(
+            pretty_print(xml, false).unwrap(),
+            [
+                r#"<?xml version="1.0"?>"#,
+                r#"<order id="42">"#,
+                r#"  <item sku="a&amp;b">Tea &amp; milk</item>"#,
+                r#"  <gift/>"#,
+                r#"  <!-- note -->"#,
+                r#"</order>"#,
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_pretty_print_colored() {
+        assert_eq!(
+            pretty_print("<a>1</a>", true).unwrap(),
+            "\x1b[34m<a\x1b[0m\x1b[34m>\x1b[0m1\x1b[34m</a>\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_pretty_print_invalid() {
+        assert!(pretty_print("<order><item></order>", false).is_err());
+        assert!(pretty_print("<order>", false).is_err());
+    }
+}